@@ -0,0 +1,103 @@
+//! Derive macro for bilevel-aggregator.
+//! 
+//! Use this through the derive feature of bilevel-aggregator, which
+//! re-exports the macro alongside the BilevelKey trait it implements.
+
+use proc_macro::TokenStream;
+use proc_macro2::TokenStream as TokenStream2;
+use quote::quote;
+use syn::{Data, DeriveInput, Error, Fields, Index, Member, Meta, Type, parse_macro_input, spanned::Spanned};
+
+/// Implement BilevelKey for a struct, taking the group key from the fields
+/// marked #[group] and the aggregation key from those marked #[agg].
+/// 
+/// A key of one field is that field's type, and a key of several fields
+/// is a tuple of their types, in the order the fields are declared.
+/// The fields are cloned for each row projected.
+/// 
+/// A field can be marked #[group] or #[agg] but not both, and neither
+/// attribute takes arguments.
+#[proc_macro_derive(BilevelKey, attributes(group, agg))]
+pub fn derive_bilevel_key(input: TokenStream) -> TokenStream {
+    let input = parse_macro_input!(input as DeriveInput);
+    expand(input).unwrap_or_else(Error::into_compile_error).into()
+}
+
+fn expand(input: DeriveInput) -> Result<TokenStream2, Error> {
+    let fields = match &input.data {
+        Data::Struct(data) => &data.fields,
+        _ => return Err(Error::new(input.span(), "BilevelKey can only be derived for structs")),
+    };
+    let mut group = Vec::new();
+    let mut agg = Vec::new();
+    let members: Vec<(Member, &Type, _)> = match fields {
+        Fields::Named(named) => named.named.iter()
+            .map(|f| (Member::Named(f.ident.clone().unwrap()), &f.ty, &f.attrs))
+            .collect(),
+        Fields::Unnamed(unnamed) => unnamed.unnamed.iter().enumerate()
+            .map(|(i, f)| (Member::Unnamed(Index::from(i)), &f.ty, &f.attrs))
+            .collect(),
+        Fields::Unit => Vec::new(),
+    };
+    for (member, ty, attrs) in members {
+        let mut marked = None;
+        for attr in attrs {
+            let name = match attr.path().get_ident() {
+                Some(ident) if ident == "group" || ident == "agg" => ident.to_string(),
+                _ => continue,
+            };
+            if !matches!(attr.meta, Meta::Path(_)) {
+                return Err(Error::new_spanned(attr, format!("#[{}] takes no arguments", name)));
+            }
+            if let Some(first) = marked.replace(name.clone()) {
+                let message = if first == name {
+                    format!("the field is already marked #[{}]", name)
+                } else {
+                    "a field can be marked #[group] or #[agg] but not both".to_owned()
+                };
+                return Err(Error::new_spanned(attr, message));
+            }
+            if name == "group" {
+                group.push((member.clone(), ty));
+            } else {
+                agg.push((member.clone(), ty));
+            }
+        }
+    }
+    if group.is_empty() {
+        return Err(Error::new(input.ident.span(), "no field is marked #[group]"));
+    }
+    if agg.is_empty() {
+        return Err(Error::new(input.ident.span(), "no field is marked #[agg]"));
+    }
+    let (group_type, group_value) = projection(&group);
+    let (agg_type, agg_value) = projection(&agg);
+    let name = &input.ident;
+    let (impl_generics, type_generics, where_clause) = input.generics.split_for_impl();
+    Ok(quote! {
+        impl #impl_generics ::bilevel_aggregator::BilevelKey for #name #type_generics #where_clause {
+            type Group = #group_type;
+            type Agg = #agg_type;
+
+            fn group(&self) -> Self::Group {
+                #group_value
+            }
+
+            fn agg(&self) -> Self::Agg {
+                #agg_value
+            }
+        }
+    })
+}
+
+/// Get the type of a key made of the specified fields, and the expression
+/// that projects a row onto it.
+fn projection(fields: &[(Member, &Type)]) -> (TokenStream2, TokenStream2) {
+    let types = fields.iter().map(|(_, ty)| ty);
+    let values = fields.iter().map(|(member, _)| quote! { ::std::clone::Clone::clone(&self.#member) });
+    if fields.len() == 1 {
+        (quote! { #(#types)* }, quote! { #(#values)* })
+    } else {
+        (quote! { (#(#types),*) }, quote! { (#(#values),*) })
+    }
+}
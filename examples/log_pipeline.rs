@@ -0,0 +1,42 @@
+//! Count the requests in a log file by window of time, host and status.
+//!
+//! The log file is read as CSV, with a header, and the fields time, host
+//! and status, where time is in seconds since the epoch. The counts are
+//! written to standard output in the Prometheus text format, or as CSV
+//! if --csv is given.
+//!
+//! cargo run --example log_pipeline --features pipeline -- access.csv
+
+use std::{env, io, process};
+use bilevel_aggregator::pipeline::{
+    Pipeline, PipelineConfig, PipelineError, Window, write_csv, write_prometheus,
+};
+
+fn main() {
+    let args: Vec<String> = env::args().skip(1).collect();
+    let csv = args.iter().any(|a| a == "--csv");
+    let Some(path) = args.iter().find(|a| !a.starts_with("--")) else {
+        eprintln!("usage: log_pipeline [--csv] <log.csv>");
+        process::exit(2);
+    };
+    if let Err(e) = run(path, csv) {
+        eprintln!("{}", e);
+        process::exit(1);
+    }
+}
+
+fn run(path: &str, csv: bool) -> Result<(), PipelineError> {
+    let pipeline = Pipeline::new(PipelineConfig {
+        skip_header: true,
+        group_fields: vec![1],
+        key_field: 2,
+        window: Some(Window { field: 0, width: 60 }),
+        ..PipelineConfig::default()
+    });
+    let results = pipeline.run_file(path)?;
+    if csv {
+        write_csv(&results, io::stdout().lock())
+    } else {
+        write_prometheus(&results, "requests_per_minute", io::stdout().lock())
+    }
+}
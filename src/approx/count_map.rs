@@ -0,0 +1,236 @@
+use std::{borrow::Borrow, cmp::Ordering, collections::HashMap, hash::Hash};
+use super::CountMinSketch;
+
+/// The number of rows of the sketch of each group.
+const DEPTH: usize = 4;
+
+/// A group of a BilevelCountMin: the sketch of the counts of all its
+/// aggregation keys, and the keys with the greatest estimated counts.
+struct Group<K> {
+    sketch: CountMinSketch,
+    /// The heavy hitters, with their estimated counts, in no order.
+    heavy: Vec<(K, u64)>,
+}
+
+/// A collection of pairs (g, k) grouped by g, counting the occurrences of
+/// each pair in fixed memory per group.
+/// 
+/// Each group keeps a count-min sketch of the counts of its aggregation
+/// keys, and a list of the few keys with the greatest estimated counts, the
+/// heavy hitters. However many distinct aggregation keys are found, such
+/// as user agents or query strings chosen by an adversary, the memory taken
+/// by a group does not grow, at the cost of estimated counts that may
+/// exceed the true counts. Only the heavy hitters can be listed.
+/// 
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+pub struct BilevelCountMin<G, K> {
+    groups: HashMap<G, Group<K>>,
+    width: usize,
+    heavy_hitters: usize,
+}
+
+impl<G: Hash + Eq, K: Hash + Eq> BilevelCountMin<G, K> {
+    /// Create a new collection.
+    /// 
+    /// width: the number of counters in each of the four rows of the sketch
+    ///     of each group. The estimated count of a key exceeds its true
+    ///     count by at most about 2.7 / width of the total count of its
+    ///     group, in all but about 2% of cases.
+    /// heavy_hitters: the number of keys with the greatest estimated counts
+    ///     to keep for each group.
+    pub fn new(width: usize, heavy_hitters: usize) -> Self {
+        Self { groups: HashMap::new(), width: width.max(1), heavy_hitters }
+    }
+
+    /// Create a new collection whose sketches take at most the specified
+    /// memory per group.
+    /// 
+    /// memory_per_group: the number of bytes for the sketch of each group,
+    ///     not counting the heavy hitters.
+    /// heavy_hitters: the number of keys with the greatest estimated counts
+    ///     to keep for each group.
+    pub fn with_memory(memory_per_group: usize, heavy_hitters: usize) -> Self {
+        Self::new(memory_per_group / (DEPTH * std::mem::size_of::<u64>()), heavy_hitters)
+    }
+
+    /// Add count occurrences of a key pair to the collection.
+    /// 
+    /// g: the group key.
+    /// k: the remaining key.
+    /// 
+    /// Return the new estimate of the count of the pair.
+    pub fn add<GRef, KRef>(&mut self, g: &GRef, k: &KRef, count: u64) -> u64
+    where
+        G: Borrow<GRef>,
+        GRef: ToOwned<Owned = G> + Hash + Eq + ?Sized,
+        K: Borrow<KRef>,
+        KRef: ToOwned<Owned = K> + Hash + Eq + ?Sized,
+    {
+        let (width, heavy_hitters) = (self.width, self.heavy_hitters);
+        let group = match self.groups.get_mut(g) {
+            Some(group) => group,
+            None => self.groups.entry(g.to_owned()).or_insert_with(|| Group {
+                sketch: CountMinSketch::new(width, DEPTH),
+                heavy: Vec::with_capacity(heavy_hitters),
+            }),
+        };
+        let estimate = group.sketch.add(k, count);
+        group.offer(k, estimate, heavy_hitters);
+        estimate
+    }
+
+    /// Estimate the count of the specified key pair, which is 0 if the
+    /// group was not found.
+    pub fn estimate<GRef, KRef>(&self, g: &GRef, k: &KRef) -> u64
+    where
+        G: Borrow<GRef>,
+        GRef: Hash + Eq + ?Sized,
+        KRef: Hash + ?Sized,
+    {
+        self.groups.get(g).map_or(0, |group| group.sketch.estimate(k))
+    }
+
+    /// Get the total count of the pairs found for the specified group.
+    pub fn total<GRef>(&self, g: &GRef) -> u64
+    where
+        G: Borrow<GRef>,
+        GRef: Hash + Eq + ?Sized,
+    {
+        self.groups.get(g).map_or(0, |group| group.sketch.total())
+    }
+
+    /// List the heavy hitters of the specified group, with their estimated
+    /// counts, greatest first.
+    pub fn heavy_hitters<GRef>(&self, g: &GRef) -> Vec<(&K, u64)>
+    where
+        G: Borrow<GRef>,
+        GRef: Hash + Eq + ?Sized,
+        K: Ord,
+    {
+        let mut heavy: Vec<_> = self.groups.get(g).into_iter()
+            .flat_map(|group| group.heavy.iter().map(|(k, n)| (k, *n)))
+            .collect();
+        heavy.sort_unstable_by(|a, b| b.1.cmp(&a.1).then_with(|| a.0.cmp(b.0)));
+        heavy
+    }
+
+    /// List the heavy hitters of every group, with their estimated counts.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn iter(&self) -> impl Iterator<Item = (&G, &K, u64)> {
+        self.groups.iter()
+            .flat_map(|(g, group)| group.heavy.iter().map(move |(k, n)| (g, k, *n)))
+    }
+
+    /// List the heavy hitters of every group, sorted using the specified
+    /// comparison function.
+    pub fn iter_sorted_by<F>(&self, compare: F) -> impl Iterator<Item = (&G, &K, u64)>
+    where
+        F: FnMut(&(&G, &K, u64), &(&G, &K, u64)) -> Ordering,
+    {
+        let mut pairs: Vec<_> = self.iter().collect();
+        pairs.sort_unstable_by(compare);
+        pairs.into_iter()
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Merge the counts of another collection into this one, consuming it,
+    /// such as one gathered on another worker.
+    /// 
+    /// The heavy hitters of both are kept as candidates, with their counts
+    /// estimated again from the merged sketch.
+    /// 
+    /// The sketches of a collection of a different width cannot be merged;
+    /// return false, leaving this collection unchanged, in that case,
+    /// otherwise true.
+    pub fn merge(&mut self, other: Self) -> bool {
+        if other.width != self.width {
+            return false;
+        }
+        for (g, other_group) in other.groups {
+            match self.groups.get_mut(&g) {
+                Some(group) => {
+                    group.sketch.merge(&other_group.sketch);
+                    let mut candidates = std::mem::take(&mut group.heavy);
+                    candidates.extend(other_group.heavy);
+                    for (k, _) in candidates {
+                        let estimate = group.sketch.estimate(&k);
+                        group.offer_owned(k, estimate, self.heavy_hitters);
+                    }
+                },
+                None => { self.groups.insert(g, other_group); },
+            }
+        }
+        true
+    }
+
+    /// Estimate the memory allocated by the sketches of the collection, in
+    /// bytes, not counting the heavy hitters.
+    pub fn memory_usage(&self) -> usize {
+        self.groups.values().map(|group| group.sketch.memory_usage()).sum()
+    }
+}
+
+impl<K: Eq> Group<K> {
+    /// Record the estimated count of a key, keeping it as a heavy hitter
+    /// if it is one already, or if there is room, or if its count exceeds
+    /// that of the least heavy hitter, which it then replaces.
+    fn offer<KRef>(&mut self, k: &KRef, estimate: u64, limit: usize)
+    where
+        K: Borrow<KRef>,
+        KRef: ToOwned<Owned = K> + Eq + ?Sized,
+    {
+        if let Some(entry) = self.heavy.iter_mut().find(|(h, _)| h.borrow() == k) {
+            entry.1 = estimate;
+        } else if let Some(i) = self.replaceable(estimate, limit) {
+            self.insert_at(i, k.to_owned(), estimate);
+        }
+    }
+
+    /// Record the estimated count of an owned key, as offer does.
+    fn offer_owned(&mut self, k: K, estimate: u64, limit: usize) {
+        if let Some(entry) = self.heavy.iter_mut().find(|(h, _)| *h == k) {
+            entry.1 = entry.1.max(estimate);
+        } else if let Some(i) = self.replaceable(estimate, limit) {
+            self.insert_at(i, k, estimate);
+        }
+    }
+
+    /// Find where a new heavy hitter with the specified count may be kept:
+    /// the end of the list if there is room, or the place of the least
+    /// heavy hitter if its count is less.
+    fn replaceable(&self, estimate: u64, limit: usize) -> Option<usize> {
+        if self.heavy.len() < limit {
+            return Some(self.heavy.len());
+        }
+        self.heavy.iter().enumerate()
+            .min_by_key(|(_, (_, n))| *n)
+            .filter(|(_, (_, n))| *n < estimate)
+            .map(|(i, _)| i)
+    }
+
+    /// Keep a heavy hitter at the specified place.
+    fn insert_at(&mut self, i: usize, k: K, estimate: u64) {
+        if i == self.heavy.len() {
+            self.heavy.push((k, estimate));
+        } else {
+            self.heavy[i] = (k, estimate);
+        }
+    }
+}
+
+impl<G: std::fmt::Debug, K: std::fmt::Debug> std::fmt::Debug for BilevelCountMin<G, K> {
+    /// Format the collection as a map from each group key to a map of its
+    /// heavy hitters and their estimated counts, listing only the first
+    /// few groups of a large collection.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::debug::debug_map(f, self.groups.iter().map(|(g, group)| {
+            (g, group.heavy.iter().map(|(k, n)| (k, n)))
+        }))
+    }
+}
@@ -0,0 +1,83 @@
+use std::hash::Hash;
+
+/// A sketch of the counts of a multiset of keys, from which the count of
+/// any key can be estimated, in memory that does not grow with the number
+/// of keys.
+/// 
+/// The sketch has depth rows of width counters. Each key is counted in one
+/// counter of each row, and its count is estimated by the least of them,
+/// so an estimate is never less than the true count, and exceeds it by at
+/// most about e / width of the total count, in all but about e^-depth of
+/// cases.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CountMinSketch {
+    width: usize,
+    depth: usize,
+    counters: Box<[u64]>,
+    total: u64,
+}
+
+impl CountMinSketch {
+    /// Create a sketch of an empty multiset.
+    /// 
+    /// width: the number of counters in each row, at least 1.
+    /// depth: the number of rows, at least 1.
+    pub fn new(width: usize, depth: usize) -> Self {
+        let (width, depth) = (width.max(1), depth.max(1));
+        Self { width, depth, counters: vec![0; width * depth].into_boxed_slice(), total: 0 }
+    }
+
+    /// Add count occurrences of a key, returning the new estimate of its
+    /// count.
+    pub fn add<K: Hash + ?Sized>(&mut self, k: &K, count: u64) -> u64 {
+        self.total += count;
+        let mut estimate = u64::MAX;
+        for i in self.indexes(k) {
+            self.counters[i] += count;
+            estimate = estimate.min(self.counters[i]);
+        }
+        estimate
+    }
+
+    /// Estimate the count of a key.
+    pub fn estimate<K: Hash + ?Sized>(&self, k: &K) -> u64 {
+        self.indexes(k).map(|i| self.counters[i]).min().unwrap_or(0)
+    }
+
+    /// Get the total count of all the keys added.
+    pub fn total(&self) -> u64 {
+        self.total
+    }
+
+    /// Combine the sketch of another multiset, as if its keys had been
+    /// added here.
+    /// 
+    /// Return false, leaving this sketch unchanged, if the sketches have
+    /// different dimensions, otherwise true.
+    pub fn merge(&mut self, other: &CountMinSketch) -> bool {
+        if (other.width, other.depth) != (self.width, self.depth) {
+            return false;
+        }
+        for (c, &o) in self.counters.iter_mut().zip(other.counters.iter()) {
+            *c += o;
+        }
+        self.total += other.total;
+        true
+    }
+
+    /// Get the number of bytes taken by the counters of the sketch.
+    pub fn memory_usage(&self) -> usize {
+        self.counters.len() * std::mem::size_of::<u64>()
+    }
+
+    /// Find the counter of a key in each row.
+    fn indexes<K: Hash + ?Sized>(&self, k: &K) -> impl Iterator<Item = usize> {
+        // Derive a hash for each row from two halves of one hash.
+        let h = crate::hash(k);
+        let (h1, h2) = (h & 0xffff_ffff, (h >> 32) | 1);
+        let width = self.width as u64;
+        (0..self.depth as u64).map(move |row| {
+            (row * width + h1.wrapping_add(row.wrapping_mul(h2)) % width) as usize
+        })
+    }
+}
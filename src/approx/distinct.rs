@@ -0,0 +1,156 @@
+use std::{borrow::Borrow, cmp::Ordering, collections::HashMap, hash::Hash, marker::PhantomData};
+use super::HyperLogLog;
+
+/// The default precision of the sketch of each group, taking 16 KiB
+/// with a standard error of about 0.8%.
+const DEFAULT_PRECISION: u8 = 14;
+
+/// A collection of pairs (g, k) grouped by g, keeping for each group only
+/// a HyperLogLog sketch of its aggregation keys, from which the number of
+/// distinct aggregation keys can be estimated.
+/// 
+/// The memory taken by each group is fixed by the precision, however many
+/// aggregation keys are found, so this suits groups with hundreds of
+/// millions of distinct aggregation keys, where an exact BilevelSet would
+/// not fit in memory. The keys themselves cannot be listed.
+/// 
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+pub struct BilevelDistinct<G, K: ?Sized> {
+    groups: HashMap<G, HyperLogLog>,
+    precision: u8,
+    _keys: PhantomData<fn(&K)>,
+}
+
+impl<G: Hash + Eq, K: Hash + ?Sized> BilevelDistinct<G, K> {
+    /// Create a new collection, with the default precision.
+    pub fn new() -> Self {
+        Self::with_precision(DEFAULT_PRECISION)
+    }
+
+    /// Create a new collection with the specified precision.
+    /// 
+    /// precision: the precision of the sketch of each group, as for
+    ///     HyperLogLog::new.
+    pub fn with_precision(precision: u8) -> Self {
+        Self {
+            groups: HashMap::new(),
+            precision: HyperLogLog::new(precision).precision(),
+            _keys: PhantomData,
+        }
+    }
+
+    /// Insert a key pair found into the collection.
+    /// 
+    /// g: the group key.
+    /// k: the remaining key.
+    pub fn insert<GRef, KRef>(&mut self, g: &GRef, k: &KRef)
+    where
+        G: Borrow<GRef>,
+        GRef: ToOwned<Owned = G> + Hash + Eq + ?Sized,
+        K: Borrow<KRef>,
+        KRef: Hash + ?Sized,
+    {
+        match self.groups.get_mut(g) {
+            Some(sketch) => sketch.insert(k),
+            None => {
+                let mut sketch = HyperLogLog::new(self.precision);
+                sketch.insert(k);
+                self.groups.insert(g.to_owned(), sketch);
+            },
+        }
+    }
+
+    /// Estimate the number of distinct aggregation keys found for the
+    /// specified group, which is 0 if the group was not found.
+    pub fn estimate<GRef>(&self, g: &GRef) -> u64
+    where
+        G: Borrow<GRef>,
+        GRef: Hash + Eq + ?Sized,
+    {
+        self.groups.get(g).map_or(0, HyperLogLog::estimate)
+    }
+
+    /// Get the sketch of the specified group, if it was found.
+    pub fn sketch<GRef>(&self, g: &GRef) -> Option<&HyperLogLog>
+    where
+        G: Borrow<GRef>,
+        GRef: Hash + Eq + ?Sized,
+    {
+        self.groups.get(g)
+    }
+
+    /// List the groups currently in the collection, with the estimated
+    /// number of distinct aggregation keys of each.
+    pub fn iter(&self) -> impl Iterator<Item = (&G, u64)> {
+        self.groups.iter().map(|(g, sketch)| (g, sketch.estimate()))
+    }
+
+    /// List the groups currently in the collection, with their estimates,
+    /// sorted by the group key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&G, u64)>
+    where
+        G: Ord,
+    {
+        self.iter_sorted_by(|a, b| a.0.cmp(b.0))
+    }
+
+    /// List the groups currently in the collection, with their estimates,
+    /// sorted using the specified comparison function.
+    pub fn iter_sorted_by<F>(&self, compare: F) -> impl Iterator<Item = (&G, u64)>
+    where
+        F: FnMut(&(&G, u64), &(&G, u64)) -> Ordering,
+    {
+        let mut groups: Vec<_> = self.iter().collect();
+        groups.sort_unstable_by(compare);
+        groups.into_iter()
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Merge the sketches of another collection into this one, consuming
+    /// it, such as one gathered on another worker.
+    /// 
+    /// The sketches of a collection with a different precision cannot be
+    /// merged; return false, leaving this collection unchanged, in that
+    /// case, otherwise true.
+    pub fn merge(&mut self, other: Self) -> bool {
+        if other.precision != self.precision {
+            return false;
+        }
+        for (g, sketch) in other.groups {
+            match self.groups.get_mut(&g) {
+                Some(existing) => { existing.merge(&sketch); },
+                None => { self.groups.insert(g, sketch); },
+            }
+        }
+        true
+    }
+
+    /// Get the number of bytes taken by the sketches of the collection.
+    pub fn memory_usage(&self) -> usize {
+        self.groups.len() * (1 << self.precision)
+    }
+}
+
+impl<G: Hash + Eq, K: Hash + ?Sized> Default for BilevelDistinct<G, K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G, K> std::fmt::Debug for BilevelDistinct<G, K>
+where
+    G: Hash + Eq + std::fmt::Debug,
+    K: Hash + ?Sized,
+{
+    /// Format the collection as a map from each group key to its estimated
+    /// number of distinct aggregation keys, listing only the first few
+    /// groups of a large collection.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::debug::debug_entries(f, self.iter())
+    }
+}
@@ -0,0 +1,96 @@
+use std::hash::Hash;
+
+/// The least precision of a HyperLogLog.
+pub const MIN_PRECISION: u8 = 4;
+/// The greatest precision of a HyperLogLog.
+pub const MAX_PRECISION: u8 = 18;
+
+/// A sketch of a set of keys, from which the number of distinct keys
+/// can be estimated, in memory that does not grow with the number of keys.
+/// 
+/// Each key is hashed to one of 2^precision registers, which keeps the
+/// greatest number of leading zero bits seen in the rest of the hashes.
+/// The standard error of the estimate is about 1.04 / √(2^precision).
+/// 
+/// The hash is the same in every process, so sketches built on different
+/// workers can be merged.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct HyperLogLog {
+    precision: u8,
+    registers: Box<[u8]>,
+}
+
+impl HyperLogLog {
+    /// Create a sketch of an empty set.
+    /// 
+    /// precision: the number of bits of the hash used to select a
+    ///     register, clamped to the range MIN_PRECISION to MAX_PRECISION.
+    ///     Each sketch takes 2^precision bytes.
+    pub fn new(precision: u8) -> Self {
+        let precision = precision.clamp(MIN_PRECISION, MAX_PRECISION);
+        Self { precision, registers: vec![0; 1 << precision].into_boxed_slice() }
+    }
+
+    /// Add a key to the set.
+    pub fn insert<K: Hash + ?Sized>(&mut self, k: &K) {
+        self.insert_hash(crate::hash(k))
+    }
+
+    /// Add a key to the set by its 64-bit hash.
+    pub fn insert_hash(&mut self, hash: u64) {
+        let p = self.precision as u32;
+        let index = (hash >> (64 - p)) as usize;
+        // Mark the end of the remaining bits, so that the rank is bounded.
+        let rest = (hash << p) | (1 << (p - 1));
+        let rank = rest.leading_zeros() as u8 + 1;
+        if rank > self.registers[index] {
+            self.registers[index] = rank;
+        }
+    }
+
+    /// Estimate the number of distinct keys in the set.
+    pub fn estimate(&self) -> u64 {
+        let m = self.registers.len() as f64;
+        let alpha = match self.registers.len() {
+            16 => 0.673,
+            32 => 0.697,
+            64 => 0.709,
+            _ => 0.7213 / (1.0 + 1.079 / m),
+        };
+        let sum: f64 = self.registers.iter().map(|&r| (-(r as f64)).exp2()).sum();
+        let raw = alpha * m * m / sum;
+        let zeros = self.registers.iter().filter(|&&r| r == 0).count();
+        // Small sets are better estimated by counting the empty registers.
+        let estimate = if raw <= 2.5 * m && zeros > 0 {
+            m * (m / zeros as f64).ln()
+        } else {
+            raw
+        };
+        estimate.round() as u64
+    }
+
+    /// Combine the sketch of another set, as if its keys had been
+    /// inserted here.
+    /// 
+    /// Return false, leaving this sketch unchanged, if the sketches have
+    /// different precisions, otherwise true.
+    pub fn merge(&mut self, other: &HyperLogLog) -> bool {
+        if other.precision != self.precision {
+            return false;
+        }
+        for (r, &o) in self.registers.iter_mut().zip(other.registers.iter()) {
+            *r = (*r).max(o);
+        }
+        true
+    }
+
+    /// Get the precision of the sketch.
+    pub fn precision(&self) -> u8 {
+        self.precision
+    }
+
+    /// Get the number of bytes taken by the registers of the sketch.
+    pub fn memory_usage(&self) -> usize {
+        self.registers.len()
+    }
+}
@@ -0,0 +1,82 @@
+use super::*;
+
+#[test]
+pub fn test_hyperloglog() {
+    let mut sketch = HyperLogLog::new(14);
+    assert_eq!(sketch.estimate(), 0);
+    for i in 0..100_000u64 {
+        sketch.insert(&i);
+        // Duplicates do not change the estimate.
+        sketch.insert(&i);
+    }
+    let estimate = sketch.estimate() as f64;
+    assert!((estimate - 100_000.0).abs() < 0.03 * 100_000.0, "estimated {}", estimate);
+
+    let mut small = HyperLogLog::new(14);
+    for i in 0..100u64 {
+        small.insert(&i);
+    }
+    assert!((small.estimate() as i64 - 100).abs() <= 2);
+    assert!(!small.merge(&HyperLogLog::new(10)));
+    assert!(small.merge(&sketch));
+    assert_eq!(small.estimate(), sketch.estimate());
+}
+
+#[test]
+pub fn test_distinct() {
+    let mut a: BilevelDistinct<String, str> = BilevelDistinct::new();
+    let mut b: BilevelDistinct<String, str> = BilevelDistinct::new();
+    for i in 0..20_000 {
+        let target = if i % 2 == 0 { &mut a } else { &mut b };
+        target.insert("users", &format!("user{}", i % 10_000));
+        target.insert("pages", &format!("page{}", i % 50));
+    }
+    assert_eq!(a.group_count(), 2);
+    assert!(a.merge(b));
+    let users = a.estimate("users") as f64;
+    assert!((users - 10_000.0).abs() < 0.03 * 10_000.0, "estimated {}", users);
+    let result: Vec<_> = a.iter_sorted().map(|(g, n)| (g.as_str(), n)).collect();
+    assert_eq!(result[0], ("pages", 50));
+    assert_eq!(a.estimate("missing"), 0);
+    assert_eq!(a.memory_usage(), 2 << 14);
+    assert!(!a.merge(BilevelDistinct::with_precision(8)));
+}
+
+#[test]
+pub fn test_count_min_sketch() {
+    let mut sketch = CountMinSketch::new(1000, 4);
+    for i in 0..10_000u64 {
+        sketch.add(&i, 1);
+    }
+    assert_eq!(sketch.add("hot", 500), sketch.estimate("hot"));
+    assert_eq!(sketch.total(), 10_500);
+    // Estimates are never less than the true counts.
+    assert!(sketch.estimate("hot") >= 500);
+    assert!(sketch.estimate("hot") < 500 + 100);
+    assert_eq!(sketch.memory_usage(), 1000 * 4 * 8);
+    assert!(!sketch.merge(&CountMinSketch::new(10, 4)));
+}
+
+#[test]
+pub fn test_count_min() {
+    let mut a: BilevelCountMin<String, String> = BilevelCountMin::with_memory(32 * 1024, 3);
+    let mut b: BilevelCountMin<String, String> = BilevelCountMin::with_memory(32 * 1024, 3);
+    for i in 0..20_000 {
+        let target = if i % 2 == 0 { &mut a } else { &mut b };
+        // A long tail of distinct keys, with a few frequent ones.
+        target.add("agents", &format!("bot{}", i), 1);
+        let hot = ["curl", "firefox", "chrome", "safari"][i % 10 % 4];
+        if i % 10 < 4 {
+            target.add("agents", hot, (4 - i % 10) as u64);
+        }
+    }
+    assert_eq!(a.memory_usage(), 32 * 1024);
+    assert!(a.merge(b));
+    assert_eq!(a.total("agents"), 20_000 + 2_000 * (4 + 3 + 2 + 1));
+    let heavy: Vec<_> = a.heavy_hitters("agents").into_iter().map(|(k, _)| k.as_str()).collect();
+    assert_eq!(heavy, ["curl", "firefox", "chrome"]);
+    assert!(a.estimate("agents", "curl") >= 8_000);
+    assert!(a.estimate("agents", "curl") < 8_000 + 200);
+    assert_eq!(a.estimate("missing", "curl"), 0);
+    assert_eq!(a.iter().count(), 3);
+}
@@ -0,0 +1,107 @@
+use hashbrown::HashTable;
+
+use crate::hash;
+
+/// Byte strings, such as the aggregation keys of a bytes collection, kept
+/// end to end in a single buffer.
+///
+/// Each distinct key is kept once, and is given an index as its id when
+/// first found, which does not change for the life of the arena. Keeping
+/// the keys in one buffer, rather than in an allocation each, saves the
+/// allocator the work and the fragmentation of millions of short keys.
+#[derive(Clone)]
+pub(crate) struct Arena {
+    bytes: Vec<u8>,
+    /// The offset of the end of each key in bytes, indexed by id.
+    ends: Vec<usize>,
+    table: HashTable<usize>,
+}
+
+impl Arena {
+    /// Create an empty arena with space for the specified number of
+    /// distinct keys.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            bytes: Vec::new(),
+            ends: Vec::with_capacity(capacity),
+            table: HashTable::with_capacity(capacity),
+        }
+    }
+
+    /// Find the id of a key, adding the key if it is new.
+    pub fn intern(&mut self, k: &[u8]) -> usize {
+        let h = hash(k);
+        if let Some(&id) = self.table.find(h, |&i| slice(&self.bytes, &self.ends, i) == k) {
+            return id;
+        }
+        let id = self.ends.len();
+        self.bytes.extend_from_slice(k);
+        self.ends.push(self.bytes.len());
+        let (bytes, ends) = (&self.bytes, &self.ends);
+        self.table.insert_unique(h, id, |&i| hash(slice(bytes, ends, i)));
+        id
+    }
+
+    /// Get the key with the specified id.
+    pub fn key(&self, id: usize) -> &[u8] {
+        slice(&self.bytes, &self.ends, id)
+    }
+
+    /// Count the distinct keys.
+    pub fn len(&self) -> usize {
+        self.ends.len()
+    }
+
+    /// Estimate the memory allocated by the arena, in bytes, including
+    /// the contents of the keys.
+    #[cfg(any(feature = "tenant", feature = "text"))]
+    pub fn memory_usage(&self) -> usize {
+        self.bytes.capacity()
+            + self.ends.capacity() * std::mem::size_of::<usize>()
+            + crate::table_size::<usize>(self.table.capacity())
+    }
+}
+
+/// Strings, such as the string components of the keys of a text
+/// collection, kept end to end in an Arena.
+#[cfg(feature = "text")]
+#[derive(Clone)]
+pub(crate) struct StrArena(Arena);
+
+#[cfg(feature = "text")]
+impl StrArena {
+    /// Create an empty arena with space for the specified number of
+    /// distinct strings.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self(Arena::with_capacity(capacity))
+    }
+
+    /// Find the id of a string, adding the string if it is new.
+    pub fn intern(&mut self, s: &str) -> usize {
+        self.0.intern(s.as_bytes())
+    }
+
+    /// Get the string with the specified id.
+    pub fn key(&self, id: usize) -> &str {
+        // SAFETY: Only whole strings are interned, so each key is valid
+        // UTF-8.
+        unsafe { std::str::from_utf8_unchecked(self.0.key(id)) }
+    }
+
+    /// List the distinct strings, in order of their ids.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &str> {
+        (0..self.0.len()).map(|id| self.key(id))
+    }
+
+    /// Estimate the memory allocated by the arena, in bytes, including
+    /// the contents of the strings.
+    pub fn memory_usage(&self) -> usize {
+        self.0.memory_usage()
+    }
+}
+
+/// Get the key with the specified id from the buffer.
+fn slice<'a>(bytes: &'a [u8], ends: &[usize], id: usize) -> &'a [u8] {
+    let start = if id == 0 { 0 } else { ends[id - 1] };
+    &bytes[start..ends[id]]
+}
@@ -0,0 +1,147 @@
+use std::sync::Arc;
+use arrow_array::{ArrayRef, BooleanArray, PrimitiveArray, RecordBatch, StringArray, types::*};
+use arrow_schema::{ArrowError, DataType, Field, Schema, SchemaRef};
+use crate::core::{AggKeyId, GroupId};
+
+/// A type whose values can be written to an Arrow column.
+pub trait ArrowValue {
+    /// The Arrow type of the column.
+    fn data_type() -> DataType;
+
+    /// Build a column of the values.
+    fn to_array(values: Vec<&Self>) -> ArrayRef;
+}
+
+macro_rules! primitive_arrow_value {
+    ($($t:ty => $arrow:ty, $data_type:expr;)*) => {
+        $(
+            impl ArrowValue for $t {
+                fn data_type() -> DataType {
+                    $data_type
+                }
+
+                fn to_array(values: Vec<&Self>) -> ArrayRef {
+                    Arc::new(PrimitiveArray::<$arrow>::from_iter_values(values.into_iter().copied()))
+                }
+            }
+        )*
+    };
+}
+
+primitive_arrow_value! {
+    i8 => Int8Type, DataType::Int8;
+    i16 => Int16Type, DataType::Int16;
+    i32 => Int32Type, DataType::Int32;
+    i64 => Int64Type, DataType::Int64;
+    u8 => UInt8Type, DataType::UInt8;
+    u16 => UInt16Type, DataType::UInt16;
+    u32 => UInt32Type, DataType::UInt32;
+    u64 => UInt64Type, DataType::UInt64;
+    f32 => Float32Type, DataType::Float32;
+    f64 => Float64Type, DataType::Float64;
+}
+
+impl ArrowValue for usize {
+    fn data_type() -> DataType {
+        DataType::UInt64
+    }
+
+    fn to_array(values: Vec<&Self>) -> ArrayRef {
+        Arc::new(PrimitiveArray::<UInt64Type>::from_iter_values(values.into_iter().map(|&v| v as u64)))
+    }
+}
+
+impl ArrowValue for bool {
+    fn data_type() -> DataType {
+        DataType::Boolean
+    }
+
+    fn to_array(values: Vec<&Self>) -> ArrayRef {
+        Arc::new(BooleanArray::from(values.into_iter().copied().collect::<Vec<_>>()))
+    }
+}
+
+impl ArrowValue for String {
+    fn data_type() -> DataType {
+        DataType::Utf8
+    }
+
+    fn to_array(values: Vec<&Self>) -> ArrayRef {
+        Arc::new(StringArray::from_iter_values(values))
+    }
+}
+
+impl ArrowValue for &str {
+    fn data_type() -> DataType {
+        DataType::Utf8
+    }
+
+    fn to_array(values: Vec<&Self>) -> ArrayRef {
+        Arc::new(StringArray::from_iter_values(values.into_iter().copied()))
+    }
+}
+
+/// Build a record batch with group, key and value columns from the rows
+/// listed.
+pub(crate) fn record_batch<'a, G, K, V>(
+    rows: impl Iterator<Item = (&'a G, &'a K, &'a V)>,
+) -> Result<RecordBatch, ArrowError>
+where
+    G: ArrowValue + 'a,
+    K: ArrowValue + 'a,
+    V: ArrowValue + 'a,
+{
+    let (mut groups, mut keys, mut values) = (Vec::new(), Vec::new(), Vec::new());
+    for (g, k, v) in rows {
+        groups.push(g);
+        keys.push(k);
+        values.push(v);
+    }
+    RecordBatch::try_new(schema::<G, K, V>(), vec![G::to_array(groups), K::to_array(keys), V::to_array(values)])
+}
+
+/// Describe the group, key and value columns of a record batch.
+pub(crate) fn schema<G: ArrowValue, K: ArrowValue, V: ArrowValue>() -> SchemaRef {
+    Arc::new(Schema::new(vec![
+        Field::new("group", G::data_type(), false),
+        Field::new("key", K::data_type(), false),
+        Field::new("value", V::data_type(), false),
+    ]))
+}
+
+/// Build a record batch from the rows listed, with the ids of the group
+/// and aggregation key of each in group_id, group_generation and key_id
+/// columns, before the group, key and value columns.
+pub(crate) fn record_batch_with_ids<'a, G, K, V>(
+    rows: impl Iterator<Item = (GroupId, &'a G, AggKeyId, &'a K, &'a V)>,
+) -> Result<RecordBatch, ArrowError>
+where
+    G: ArrowValue + 'a,
+    K: ArrowValue + 'a,
+    V: ArrowValue + 'a,
+{
+    let (mut group_ids, mut generations, mut key_ids) = (Vec::new(), Vec::new(), Vec::new());
+    let (mut groups, mut keys, mut values) = (Vec::new(), Vec::new(), Vec::new());
+    for (group_id, g, key_id, k, v) in rows {
+        group_ids.push(group_id.index() as u64);
+        generations.push(group_id.generation());
+        key_ids.push(key_id.index() as u64);
+        groups.push(g);
+        keys.push(k);
+        values.push(v);
+    }
+    let mut fields = vec![
+        Field::new("group_id", DataType::UInt64, false),
+        Field::new("group_generation", DataType::UInt32, false),
+        Field::new("key_id", DataType::UInt64, false),
+    ];
+    fields.extend(schema::<G, K, V>().fields().iter().map(|f| f.as_ref().clone()));
+    RecordBatch::try_new(Arc::new(Schema::new(fields)), vec![
+        Arc::new(PrimitiveArray::<UInt64Type>::from(group_ids)),
+        Arc::new(PrimitiveArray::<UInt32Type>::from(generations)),
+        Arc::new(PrimitiveArray::<UInt64Type>::from(key_ids)),
+        G::to_array(groups),
+        K::to_array(keys),
+        V::to_array(values),
+    ])
+}
@@ -0,0 +1,143 @@
+//! Aggregate the rows of a delimited file, such as a tab-separated log,
+//! by group, listing the distinct keys of each group, or counting the rows
+//! of each key with --count.
+//!
+//! The group key and the aggregation key are each made of the columns
+//! chosen, numbered from 1. The pairs are written to standard output,
+//! sorted, with the same delimiter as the input.
+//!
+//! bilevel --group 1 --key 3,4 --count access.tsv
+
+use std::{env, error::Error, fs::File, io::{self, BufRead, BufReader, BufWriter, Write}, process};
+use bilevel_aggregator::text::{BilevelMapDyn, BilevelSetDyn, Normalizer};
+
+const USAGE: &str = "\
+usage: bilevel [options] --group <columns> --key <columns> [file]
+
+Reads standard input if no file, or -, is given.
+
+options:
+  -g, --group <columns>    the columns of the group key, such as 1,2
+  -k, --key <columns>      the columns of the aggregation key
+  -d, --delimiter <char>   the field delimiter, tab by default
+  -c, --count              count the rows of each pair
+      --skip-header        skip the first line
+      --trim               trim whitespace from each field
+      --ignore-case        convert each field to lowercase
+  -h, --help               print this message";
+
+/// The options given on the command line.
+#[derive(Default)]
+struct Options {
+    group: Vec<usize>,
+    key: Vec<usize>,
+    delimiter: Option<char>,
+    count: bool,
+    skip_header: bool,
+    normalizer: Normalizer,
+    path: Option<String>,
+}
+
+fn main() {
+    let options = match parse(env::args().skip(1)) {
+        Ok(Some(options)) => options,
+        Ok(None) => {
+            println!("{}", USAGE);
+            return;
+        },
+        Err(e) => {
+            eprintln!("bilevel: {}\n\n{}", e, USAGE);
+            process::exit(2);
+        },
+    };
+    if let Err(e) = run(&options) {
+        eprintln!("bilevel: {}", e);
+        process::exit(1);
+    }
+}
+
+/// Parse the arguments, returning None if help was asked for.
+fn parse(mut args: impl Iterator<Item = String>) -> Result<Option<Options>, String> {
+    let mut options = Options::default();
+    while let Some(arg) = args.next() {
+        let mut value = || args.next().ok_or_else(|| format!("{} needs a value", arg));
+        match arg.as_str() {
+            "-g" | "--group" => options.group = columns(&value()?)?,
+            "-k" | "--key" => options.key = columns(&value()?)?,
+            "-d" | "--delimiter" => {
+                let value = value()?;
+                let mut chars = value.chars();
+                match (chars.next(), chars.next()) {
+                    (Some(c), None) => options.delimiter = Some(c),
+                    _ => return Err(format!("the delimiter must be a single character, not {:?}", value)),
+                }
+            },
+            "-c" | "--count" => options.count = true,
+            "--skip-header" => options.skip_header = true,
+            "--trim" => options.normalizer.trim = true,
+            "--ignore-case" => options.normalizer.case_fold = true,
+            "-h" | "--help" => return Ok(None),
+            _ if arg.starts_with('-') && arg != "-" => return Err(format!("unknown option {}", arg)),
+            _ if options.path.is_some() => return Err(format!("unexpected argument {}", arg)),
+            _ => options.path = Some(arg),
+        }
+    }
+    if options.group.is_empty() || options.key.is_empty() {
+        return Err("both --group and --key must be given".to_owned());
+    }
+    Ok(Some(options))
+}
+
+/// Parse a comma-separated list of column numbers, counting from 1, into
+/// indices counting from 0.
+fn columns(list: &str) -> Result<Vec<usize>, String> {
+    list.split(',')
+        .map(|c| match c.trim().parse::<usize>() {
+            Ok(n) if n > 0 => Ok(n - 1),
+            _ => Err(format!("invalid column {:?}: columns are numbered from 1", c)),
+        })
+        .collect()
+}
+
+fn run(options: &Options) -> Result<(), Box<dyn Error>> {
+    let input: Box<dyn BufRead> = match options.path.as_deref() {
+        None | Some("-") => Box::new(io::stdin().lock()),
+        Some(path) => Box::new(BufReader::new(File::open(path).map_err(|e| format!("{}: {}", path, e))?)),
+    };
+    let delimiter = options.delimiter.unwrap_or('\t');
+    let width = options.group.iter().chain(&options.key).max().map_or(0, |&c| c + 1);
+    let mut set = BilevelSetDyn::new(options.group.len(), options.key.len());
+    let mut counts: BilevelMapDyn<usize> = BilevelMapDyn::new(options.group.len(), options.key.len());
+    set.set_normalizer(options.normalizer);
+    counts.set_normalizer(options.normalizer);
+    for (i, line) in input.lines().enumerate().skip(options.skip_header as usize) {
+        let line = line?;
+        if line.is_empty() {
+            continue;
+        }
+        let fields: Vec<_> = line.split(delimiter).collect();
+        if fields.len() < width {
+            return Err(format!("line {}: expected at least {} fields, found {}", i + 1, width, fields.len()).into());
+        }
+        let select = |columns: &[usize]| -> Vec<&str> { columns.iter().map(|&c| fields[c]).collect() };
+        let (g, k) = (select(&options.group), select(&options.key));
+        if options.count {
+            *counts.add_or_get(&g, &k)? += 1;
+        } else {
+            set.insert(&g, &k)?;
+        }
+    }
+    let mut output = BufWriter::new(io::stdout().lock());
+    let separator = delimiter.to_string();
+    if options.count {
+        for (g, k, count) in counts.iter_sorted() {
+            writeln!(output, "{}{}{}{}{}", g.join(&separator), delimiter, k.join(&separator), delimiter, count)?;
+        }
+    } else {
+        for (g, k) in set.iter_sorted() {
+            writeln!(output, "{}{}{}", g.join(&separator), delimiter, k.join(&separator))?;
+        }
+    }
+    output.flush()?;
+    Ok(())
+}
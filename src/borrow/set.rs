@@ -1,7 +1,7 @@
-use std::{collections::HashSet, hash::Hash};
-use hashbrown::HashTable;
+use std::{cmp::Ordering, collections::{HashMap, HashSet}, hash::Hash, mem::size_of};
 
-use crate::{Capacity, hash};
+use crate::{Capacity, Derived, HeapSize, core::{self, AggKeyId, GroupId, Interned}};
+use super::BilevelMap;
 
 
 /// A collection of distinct pairs (g, k) grouped by g.
@@ -11,13 +11,11 @@ use crate::{Capacity, hash};
 /// 
 /// G is the type of the group key.
 /// K is the type of the remaining key.
-pub struct BilevelSet<G, K> {
-    per_group: usize,
-    /// Keep a single copy of each key here, rather than one in each group
+pub struct BilevelSet<G: Hash, K: Hash> {
+    /// Keep a single copy of each key, rather than one in each group
     /// where it appears.
-    keys: Vec<K>,
-    groups: HashTable<(G, HashSet<usize>)>,
-    key_table: HashTable<usize>,
+    inner: core::BilevelSet<G, K, Interned<K>>,
+    pivoted: Derived<Box<BilevelSet<K, G>>>,
 }
 
 impl<G: Hash, K: Hash> BilevelSet<G, K> {
@@ -26,23 +24,17 @@ impl<G: Hash, K: Hash> BilevelSet<G, K> {
     /// No initial capacity is allocated, and capacity for a few items
     /// is allocated for each new group key found.
     pub fn new() -> Self {
-        Self {
-            per_group: 4,
-            keys: Vec::new(),
-            groups: HashTable::new(),
-            key_table: HashTable::new(),
-        }
+        Self::from_core(core::BilevelSet::new())
     }
 
     /// Create a new collection with the specified capacity.
     pub fn with_capacity(capacity: Capacity) -> Self {
-        let Capacity { groups, per_group, agg_keys } = capacity;
-        Self {
-            per_group,
-            keys: Vec::with_capacity(agg_keys),
-            groups: HashTable::with_capacity(groups),
-            key_table: HashTable::with_capacity(agg_keys),
-        }
+        Self::from_core(core::BilevelSet::with_capacity(capacity))
+    }
+
+    /// Wrap a generic collection.
+    fn from_core(inner: core::BilevelSet<G, K, Interned<K>>) -> Self {
+        Self { inner, pivoted: Derived::new() }
     }
 
     /// Insert a key pair found into the collection.
@@ -56,32 +48,306 @@ impl<G: Hash, K: Hash> BilevelSet<G, K> {
         GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
         KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
     {
-        // Find the index of k in the key list, 
-        // adding it if it is new.
-        let &i = self.key_table.entry(
-            hash(&k),
-            |&i| k.eq(&self.keys[i]),
-            |&i| hash(&self.keys[i])
-        ).or_insert_with(||{
-            let i = self.keys.len();
-            self.keys.push(k.to_owned());
-            i
-        }).get();
-        // Add the index found to the group.
-        self.groups.entry(
-            hash(g),
-            |(o, _)| g.eq(o),
-            |(o, _)| hash(o)
-        ).or_insert_with(|| (g.to_owned(), HashSet::with_capacity(self.per_group)))
-        .get_mut().1.insert(i)
+        self.invalidate();
+        self.inner.insert(g, k)
+    }
+
+    /// Insert a key pair found into the collection, taking ownership of
+    /// the keys, so that a new key is stored without being copied and a
+    /// key already present is dropped.
+    /// 
+    /// Return false if the key was already present, otherwise true.
+    pub fn insert_owned(&mut self, g: G, k: K) -> bool
+    where
+        G: Eq,
+        K: Eq,
+    {
+        self.invalidate();
+        self.inner.insert_owned(g, k)
     }
 
     /// List the pairs currently in the collection without consuming
     /// the collection.
     /// 
     /// Pairs are grouped by g.
-    pub fn iter(&self) -> Iter<'_, G, K> {
-        Iter::new(self)
+    pub fn iter(&self) -> SetIter<'_, G, K> {
+        SetIter { inner: self.inner.iter() }
+    }
+
+    /// Call f once for each group, with the group key and the aggregation
+    /// keys of the group, as iter lists them.
+    pub fn for_each_group<F>(&self, mut f: F)
+    where
+        F: FnMut(&G, GroupKeys<'_, K>),
+    {
+        self.inner.for_each_group(|g, keys| f(g, GroupKeys { inner: keys }));
+    }
+
+    /// List the pairs currently in the collection, as iter does, along
+    /// with the ids of the group and aggregation key of each.
+    pub fn iter_with_ids(&self) -> impl Iterator<Item = (GroupId, &G, AggKeyId, &K)> {
+        self.inner.iter_with_ids()
+    }
+
+    /// List the aggregation keys with their ids, in the order of the ids.
+    /// 
+    /// Each distinct aggregation key is given an id when first found,
+    /// which does not change for the life of the collection.
+    pub fn id_mapping(&self) -> impl ExactSizeIterator<Item = (AggKeyId, &K)> {
+        self.inner.id_mapping()
+    }
+
+    /// Find the id of a group, or None if the group is not present.
+    /// 
+    /// A group keeps its id for as long as it is in the collection.
+    pub fn group_id<GRef>(&self, g: &GRef) -> Option<GroupId>
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+    {
+        self.inner.group_id(g)
+    }
+
+    /// Get the key of the group with the specified id, or None if no
+    /// group has the id.
+    pub fn resolve_group(&self, id: GroupId) -> Option<&G> {
+        self.inner.resolve_group(id)
+    }
+
+    /// Find the id of an aggregation key, or None if the key has not been
+    /// found in any group.
+    pub fn agg_key_id<KRef>(&self, k: &KRef) -> Option<AggKeyId>
+    where
+        KRef: PartialEq<K> + Hash + ?Sized,
+    {
+        self.inner.agg_key_id(k)
+    }
+
+    /// Get the aggregation key with the specified id, or None if no key
+    /// has the id.
+    pub fn resolve_agg_key(&self, id: AggKeyId) -> Option<&K> {
+        self.inner.resolve_agg_key(id)
+    }
+
+    /// Insert a pair given by the ids of its group and aggregation key,
+    /// such as ids passed from another system, without copying either key.
+    /// 
+    /// Return false if the pair was already present, otherwise true, or
+    /// None if either id is not of this collection, such as the id of a
+    /// group removed since.
+    pub fn insert_by_id(&mut self, g: GroupId, k: AggKeyId) -> Option<bool> {
+        self.invalidate();
+        self.inner.insert_by_id(g, k)
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&G, &K)>
+    where
+        G: Ord,
+        K: Ord,
+    {
+        self.inner.iter_sorted()
+    }
+
+    /// List the pairs currently in the collection, sorted using the
+    /// specified comparison function.
+    pub fn iter_sorted_by<'a, F>(&'a self, compare: F) -> impl Iterator<Item = (&'a G, &'a K)> + 'a
+    where
+        F: FnMut(&(&'a G, &'a K), &(&'a G, &'a K)) -> Ordering + 'a,
+    {
+        self.inner.iter_sorted_by(compare)
+    }
+
+    /// List the pairs in the collection and consume the collection.
+    /// 
+    /// Pairs are grouped by g. Each group key is cloned for every pair
+    /// in its group, and each aggregation key for every group where it
+    /// appears.
+    pub fn into_iter(self) -> SetIntoIter<G, K>
+    where
+        G: Clone,
+        K: Clone,
+    {
+        SetIntoIter { inner: self.inner.into_iter() }
+    }
+
+    /// List the pairs in the collection, sorted by the group key and then
+    /// by the aggregation key, consuming the collection.
+    /// 
+    /// Each group key is cloned for every pair in its group, and each
+    /// aggregation key for every group where it appears.
+    pub fn into_iter_sorted(self) -> impl Iterator<Item = (G, K)>
+    where
+        G: Ord + Clone,
+        K: Ord + Clone,
+    {
+        self.inner.into_iter_sorted()
+    }
+
+    /// List the pairs currently in the collection, as iter does, but also
+    /// list each empty group once, with no aggregation key.
+    pub fn iter_with_empty(&self) -> impl Iterator<Item = (&G, Option<&K>)> {
+        self.inner.iter_with_empty()
+    }
+    /// Declare group keys that are expected to appear, so that they are
+    /// listed by iter_with_empty even if no pairs are found for them.
+    pub fn declare_groups<'a, GRef>(&mut self, groups: impl IntoIterator<Item = &'a GRef>)
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized + 'a,
+    {
+        self.invalidate();
+        self.inner.declare_groups(groups);
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// groups.
+    pub fn reserve_groups(&mut self, additional: usize) {
+        self.inner.reserve_groups(additional);
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// distinct aggregation keys.
+    pub fn reserve_agg_keys(&mut self, additional: usize) {
+        self.inner.reserve_agg_keys(additional);
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// pairs in a group, declaring the group if new.
+    pub fn reserve_in_group<GRef>(&mut self, g: &GRef, additional: usize)
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+    {
+        self.invalidate();
+        self.inner.reserve_in_group(g, additional);
+    }
+
+    /// Choose whether each new group is given capacity for the average
+    /// size of the groups found so far, rather than the per_group
+    /// capacity alone.
+    /// 
+    /// The per_group capacity is still the least given. The choice is
+    /// not kept in snapshots.
+    pub fn adapt_per_group(&mut self, adaptive: bool) {
+        self.inner.adapt_per_group(adaptive);
+    }
+
+    /// Estimate the memory held by the collection, in bytes, including
+    /// the memory owned by the keys, such as the contents of strings.
+    /// 
+    /// This walks every group, so takes time in proportion to the number
+    /// of pairs.
+    pub fn estimated_heap_bytes(&self) -> usize
+    where
+        G: HeapSize,
+        K: HeapSize,
+    {
+        // The pivoted view is counted too, while it is held.
+        let pivoted = self.pivoted.get().map_or(0, |p| size_of::<BilevelSet<K, G>>() + p.estimated_heap_bytes());
+        self.inner.estimated_heap_bytes() + pivoted
+    }
+
+    /// Count the pairs currently in the collection.
+    /// 
+    /// The count is kept as pairs are added, so this takes constant time.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Check whether the collection holds no pairs, although it may hold
+    /// empty groups.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Count the pairs of the specified group, which is 0 if the group is
+    /// not present.
+    pub fn group_len<GRef>(&self, g: &GRef) -> usize
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+    {
+        self.inner.group_len(g)
+    }
+
+    /// Count the distinct aggregation keys found in any group.
+    /// 
+    /// Each distinct key is stored once, so this takes constant time.
+    pub fn distinct_agg_keys(&self) -> usize {
+        self.inner.distinct_agg_keys()
+    }
+
+    /// List the group keys currently in the collection, with the number
+    /// of aggregation keys in each group.
+    pub fn group_sizes(&self) -> impl Iterator<Item = (&G, usize)> + '_ {
+        self.inner.group_sizes()
+    }
+
+    /// Count the groups by their number of aggregation keys, such as to
+    /// choose the capacity per group.
+    /// 
+    /// buckets: the greatest size counted in each bucket, in ascending
+    ///     order. A final bucket counts the groups larger than the last of
+    ///     these.
+    /// 
+    /// Return the number of groups in each bucket.
+    pub fn size_histogram(&self, buckets: &[usize]) -> Vec<usize> {
+        self.inner.size_histogram(buckets)
+    }
+
+    /// Render the collection as a text table, with a heading for each
+    /// group followed by its aggregation keys in aligned columns,
+    /// such as for debugging or the output of a command.
+    /// 
+    /// The groups, and the keys of each group, are listed in the order of
+    /// their text, so that the same contents are always rendered the same
+    /// way.
+    pub fn render_table(&self, options: &crate::TableOptions) -> String
+    where
+        G: std::fmt::Display,
+        K: std::fmt::Display,
+    {
+        self.inner.render_table(options)
+    }
+
+    /// Measure the overlap of the aggregation keys of two groups, as their
+    /// Jaccard similarity: the number of keys they share divided by the
+    /// number of keys in either.
+    /// 
+    /// Return None if either group is not present.
+    pub fn similarity<GRef>(&self, g1: &GRef, g2: &GRef) -> Option<f64>
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+    {
+        self.inner.similarity(g1, g2)
+    }
+
+    /// List the n groups whose aggregation keys overlap most with those of
+    /// the specified group, with their Jaccard similarity, greatest first.
+    /// 
+    /// Groups that share no keys with the group are not listed, nor is the
+    /// group itself.
+    pub fn most_similar<GRef>(&self, g: &GRef, n: usize) -> Vec<(&G, f64)>
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+    {
+        self.inner.most_similar(g, n)
+    }
+
+    /// Find groups with identical sets of aggregation keys.
+    /// 
+    /// Return each class of two or more equivalent groups.
+    pub fn dedup_groups(&self) -> Vec<Vec<&G>> {
+        self.inner.dedup_groups()
+    }
+
+    /// Discard the values derived from the current contents.
+    fn invalidate(&mut self) {
+        self.pivoted.invalidate();
+    }
+}
+
+impl<G: Hash, K: Hash> Default for BilevelSet<G, K> {
+    fn default() -> Self {
+        Self::new()
     }
 }
 
@@ -92,53 +358,396 @@ where
 {
     /// Copy the data into a new collection that groups by the aggregation key.
     pub fn pivot(&self) -> BilevelSet<K, G> {
-        let capacity = Capacity {
-            groups: self.keys.len(),
-            agg_keys: self.groups.len(),
-            per_group: self.per_group,
-        };
-        let mut pivoted = BilevelSet::with_capacity(capacity);
-        for (g, k) in self.iter() {
-            pivoted.insert(k, g);
-        }
-        pivoted
+        BilevelSet::from_core(self.inner.pivot())
+    }
+
+    /// Get a view of the data grouped by the aggregation key.
+    /// 
+    /// The view is created on first use, and kept until the collection
+    /// is next modified.
+    pub fn pivoted(&self) -> &BilevelSet<K, G> {
+        self.pivoted.get_or_init(|| Box::new(self.pivot()))
+    }
+
+    /// Count how often each two aggregation keys appear in the same group,
+    /// as in a basket analysis.
+    /// 
+    /// Each two distinct keys found together are listed both ways, so that
+    /// the keys found with a key a are those of the group a. A key is not
+    /// counted with itself.
+    pub fn cooccurrence(&self) -> BilevelMap<K, K, usize> {
+        BilevelMap::from_core(self.inner.cooccurrence())
+    }
+}
+
+impl<G, K> BilevelSet<G, K>
+where
+    G: Hash + Eq,
+    K: Hash + Eq + Clone,
+{
+    /// Convert into a map of the groups, consuming the collection.
+    pub fn into_grouped(self) -> HashMap<G, HashSet<K>> {
+        self.inner.into_grouped()
     }
 }
 
-pub struct Iter<'a, G, K> {
-    keys: &'a Vec<K>,
-    outer: hashbrown::hash_table::Iter<'a, (G, HashSet<usize>)>,
-    inner: Option<(&'a G, std::collections::hash_set::Iter<'a, usize>)>,
+impl<G, K> From<HashMap<G, HashSet<K>>> for BilevelSet<G, K>
+where
+    G: Hash + Eq,
+    K: Hash + Eq + Clone,
+{
+    fn from(grouped: HashMap<G, HashSet<K>>) -> Self {
+        Self::from_core(grouped.into())
+    }
+}
+
+impl<G, K> std::fmt::Debug for BilevelSet<G, K>
+where
+    G: Hash + std::fmt::Debug,
+    K: Hash + std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<G, K> Clone for BilevelSet<G, K>
+where
+    G: Hash + Clone,
+    K: Hash + Clone,
+{
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone(), pivoted: Derived::new() }
+    }
 }
 
-impl<'a, G, K> Iter<'a, G, K> {
-    fn new(set: &'a BilevelSet<G, K>) -> Self {
-        let mut outer = set.groups.iter();
-        let inner = outer.next().map(wrap_inner);
-        Self { keys: &set.keys, outer, inner }
+impl<G, K> PartialEq for BilevelSet<G, K>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
     }
 }
 
-impl<'a, G, K> Iterator for Iter<'a, G, K> {
+impl<G, K> Eq for BilevelSet<G, K>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+{
+}
+
+#[cfg(feature = "derive")]
+impl<G, K> BilevelSet<G, K>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+{
+    /// Insert the key pair of a row into the collection.
+    /// 
+    /// Return false if the key was already present, otherwise true.
+    pub fn insert_row<R: crate::BilevelKey<Group = G, Agg = K>>(&mut self, row: &R) -> bool {
+        self.insert(&row.group(), &row.agg())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<G: Hash + serde::Serialize, K: Hash + serde::Serialize> serde::Serialize for BilevelSet<G, K> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.inner.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, G, K> serde::Deserialize<'de> for BilevelSet<G, K>
+where
+    G: Hash + Eq + serde::Deserialize<'de>,
+    K: Hash + Eq + Clone + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashMap::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(feature = "csv")]
+impl<G, K> BilevelSet<G, K>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+{
+    /// Write the collection as delimited text, such as CSV or TSV, with a
+    /// row for each pair. The value column, if listed, is left empty.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn write_csv(&self, writer: impl std::io::Write, options: &crate::CsvOptions) -> std::io::Result<()>
+    where
+        G: std::fmt::Display,
+        K: std::fmt::Display,
+    {
+        self.inner.write_csv(writer, options)
+    }
+}
+
+#[cfg(feature = "frozen")]
+impl<G, K> BilevelSet<G, K>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+{
+    /// Write the collection in a frozen format, to be read by FrozenMap
+    /// with payloads of () without being deserialized, such as from a file
+    /// mapped into memory.
+    /// 
+    /// The groups are written in order of the bytes of their keys, and the
+    /// pairs of each group in order of the bytes of their aggregation keys.
+    pub fn write_frozen(&self, writer: impl std::io::Write) -> std::io::Result<()>
+    where
+        G: AsRef<[u8]>,
+        K: AsRef<[u8]>,
+    {
+        self.inner.write_frozen(writer)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<G, K> BilevelSet<G, K>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+{
+    /// Write the collection as a JSON object, from each group key to an
+    /// array of its aggregation keys.
+    /// 
+    /// The groups, and the keys of each group, are written in order, so
+    /// that the same contents are always written the same way.
+    pub fn to_json_grouped(&self, writer: impl std::io::Write) -> Result<(), serde_json::Error>
+    where
+        G: Ord + serde::Serialize,
+        K: Ord + serde::Serialize,
+    {
+        self.inner.to_json_grouped(writer)
+    }
+
+    /// Write the collection as a JSON array, with an object for each pair
+    /// holding the ids of its group and aggregation key along with the
+    /// keys, such as {"group_id":[0,0],"group":"web1","key_id":0,"key":"200"}.
+    /// 
+    /// The pairs are listed as iter_with_ids lists them.
+    pub fn to_json_with_ids(&self, writer: impl std::io::Write) -> Result<(), serde_json::Error>
+    where
+        G: serde::Serialize,
+        K: serde::Serialize,
+    {
+        self.inner.to_json_with_ids(writer)
+    }
+}
+
+#[cfg(feature = "snapshot")]
+impl<G, K> BilevelSet<G, K>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+{
+    /// Write a binary snapshot of the collection, including the table of
+    /// aggregation keys.
+    pub fn write_snapshot(&self, writer: impl std::io::Write) -> Result<(), crate::SnapshotError>
+    where
+        G: serde::Serialize,
+        K: serde::Serialize,
+    {
+        self.inner.write_snapshot(writer)
+    }
+
+    /// Read a collection from a binary snapshot written by write_snapshot.
+    /// 
+    /// The snapshot does not keep the GroupIds: the groups are given new
+    /// ids, so ids taken before the snapshot do not carry over.
+    pub fn read_snapshot(reader: impl std::io::Read) -> Result<Self, crate::SnapshotError>
+    where
+        G: serde::de::DeserializeOwned,
+        K: serde::de::DeserializeOwned,
+    {
+        core::BilevelSet::read_snapshot(reader).map(Self::from_core)
+    }
+
+    /// Read a collection from a binary snapshot written by write_snapshot,
+    /// keeping the groups read before any error in reading the groups.
+    /// 
+    /// Return the groups that could be read, with a report of those that
+    /// could not, if any. A snapshot whose header or other contents cannot
+    /// be read is still an error.
+    pub fn read_snapshot_partial(
+        reader: impl std::io::Read,
+    ) -> Result<(Self, Option<crate::MissingGroups>), crate::SnapshotError>
+    where
+        G: serde::de::DeserializeOwned,
+        K: serde::de::DeserializeOwned,
+    {
+        let (inner, missing) = core::BilevelSet::read_snapshot_partial(reader)?;
+        Ok((Self::from_core(inner), missing))
+    }
+}
+
+#[cfg(feature = "replay")]
+impl<G, K> crate::Replay<G, K, ()> for BilevelSet<G, K>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+{
+    fn apply(&mut self, mutation: crate::Mutation<&G, &K, &()>) {
+        self.invalidate();
+        crate::Replay::apply(&mut self.inner, mutation)
+    }
+}
+
+#[cfg(feature = "tenant")]
+impl<G: Hash, K: Hash> crate::TenantData for BilevelSet<G, K> {
+    type Item<'a> = (&'a G, &'a K) where Self: 'a;
+
+    fn pairs(&self) -> impl Iterator<Item = (&G, &K)> {
+        self.iter()
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        crate::TenantData::memory_usage(&self.inner)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<G: Hash + Send + Sync, K: Hash + Send + Sync> BilevelSet<G, K> {
+    /// List the pairs currently in the collection in parallel, splitting
+    /// the work between threads by group.
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (&G, &K)> {
+        self.inner.par_iter()
+    }
+}
+
+impl<'a, G: Hash, K: Hash> IntoIterator for &'a BilevelSet<G, K> {
+    type Item = (&'a G, &'a K);
+    type IntoIter = SetIter<'a, G, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<G: Hash + Clone, K: Hash + Clone> IntoIterator for BilevelSet<G, K> {
+    type Item = (G, K);
+    type IntoIter = SetIntoIter<G, K>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        BilevelSet::into_iter(self)
+    }
+}
+
+/// Lists the pairs of a BilevelSet, grouped by g.
+/// 
+/// This is returned by BilevelSet::iter.
+pub struct SetIter<'a, G, K: Hash> {
+    inner: core::SetIter<'a, G, K, Interned<K>>,
+}
+
+impl<'a, G, K: Hash> Iterator for SetIter<'a, G, K> {
     type Item = (&'a G, &'a K);
 
     fn next(&mut self) -> Option<Self::Item> {
-        loop {
-            if let Some(inner) = &mut self.inner {
-                if let Some(&i) = inner.1.next() {
-                    return Some((inner.0, &self.keys[i]));
-                } else {
-                    self.inner = self.outer.next().map(wrap_inner);
-                }
-            } else {
-                return  None;
-            }
-        }
-    }
-}
-
-fn wrap_inner<G>(inner: &(G, HashSet<usize>))
-    -> (&G, std::collections::hash_set::Iter<'_, usize>)
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<G, K: Hash> ExactSizeIterator for SetIter<'_, G, K> {}
+
+impl<G, K: Hash> std::iter::FusedIterator for SetIter<'_, G, K> {}
+
+/// Lists the pairs of a BilevelSet, grouped by g, consuming the
+/// collection.
+/// 
+/// This is returned by BilevelSet::into_iter.
+pub struct SetIntoIter<G, K: Hash> {
+    inner: core::SetIntoIter<G, K, Interned<K>>,
+}
+
+impl<G: Clone, K: Hash + Clone> Iterator for SetIntoIter<G, K> {
+    type Item = (G, K);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<G: Clone, K: Hash + Clone> ExactSizeIterator for SetIntoIter<G, K> {}
+
+impl<G: Clone, K: Hash + Clone> std::iter::FusedIterator for SetIntoIter<G, K> {}
+
+/// Lists the aggregation keys of one group of a BilevelSet.
+/// 
+/// This is given to the closure passed to BilevelSet::for_each_group.
+pub struct GroupKeys<'a, K: Hash> {
+    inner: core::GroupKeys<'a, K, Interned<K>>,
+}
+
+impl<'a, K: Hash> Iterator for GroupKeys<'a, K> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K: Hash> ExactSizeIterator for GroupKeys<'_, K> {}
+
+impl<K: Hash> std::iter::FusedIterator for GroupKeys<'_, K> {}
+
+impl<G, K> crate::BilevelSetOps for BilevelSet<G, K>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
 {
-    (&inner.0, inner.1.iter())
-}
\ No newline at end of file
+    type Group = G;
+    type Key = K;
+
+    fn insert_pair(&mut self, g: G, k: K) -> bool {
+        self.insert_owned(g, k)
+    }
+
+    fn iter_pairs(&self) -> impl Iterator<Item = (&G, &K)> {
+        self.inner.iter()
+    }
+
+    fn group_sizes(&self) -> impl Iterator<Item = (&G, usize)> {
+        self.inner.group_sizes()
+    }
+
+    fn group_len(&self, g: &G) -> usize {
+        self.inner.group_len(g)
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
@@ -197,4 +197,439 @@ pub fn test_map() {
             }
             assert!(!set.contains(g));
         }
-}
\ No newline at end of file
+}
+#[test]
+pub fn test_regroup() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    for (g, k) in [("a.x", "1"), ("b.x", "1"), ("a.y", "2"), ("b.y", "1"), ("b.y", "2")] {
+        *map.add_or_get(g, k) += 1;
+    }
+    // Collapse the groups by prefix, summing the counts.
+    let regrouped = map.regroup_by(|g| g[..1].to_owned(), |v, w| *v += w);
+    let mut result: Vec<_> = regrouped.iter()
+        .map(|(g, k, &v)| (g.as_str(), k.as_str(), v))
+        .collect();
+    result.sort();
+    assert_eq!(result, [("a", "1", 1), ("a", "2", 1), ("b", "1", 2), ("b", "2", 1)]);
+}
+
+#[test]
+pub fn test_dedup_groups() {
+    let mut set: BilevelSet<String, String> = BilevelSet::new();
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    for (g, k) in [("a", "1"), ("a", "2"), ("b", "2"), ("b", "1"), ("c", "1"), ("d", "1"), ("d", "2"), ("d", "2")] {
+        set.insert(g, k);
+        *map.add_or_get(g, k) += 1;
+    }
+    let mut classes = set.dedup_groups();
+    classes.iter_mut().for_each(|class| class.sort());
+    assert_eq!(classes, [vec!["a", "b", "d"]]);
+    // Group d has a different count for key 2.
+    let mut classes = map.dedup_groups_with_values();
+    classes.iter_mut().for_each(|class| class.sort());
+    assert_eq!(classes, [vec!["a", "b"]]);
+}
+
+#[test]
+pub fn test_map_values() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    for (g, k) in [("a", "1"), ("a", "1"), ("a", "2"), ("b", "1")] {
+        *map.add_or_get(g, k) += 1;
+    }
+    let mapped = map.map_values(|g, k, v| format!("{}:{}={}", g, k, v));
+    let mut result: Vec<_> = mapped.iter().map(|(_, _, v)| v.as_str()).collect();
+    result.sort();
+    assert_eq!(result, ["a:1=2", "a:2=1", "b:1=1"]);
+}
+
+#[test]
+pub fn test_flat() {
+    let flat = std::collections::HashMap::from([
+        (("a".to_owned(), "1".to_owned()), 2),
+        (("a".to_owned(), "2".to_owned()), 1),
+        (("b".to_owned(), "1".to_owned()), 1),
+    ]);
+    let map: BilevelMap<String, String, u32> = BilevelMap::from_flat(flat.clone());
+    let mut result: Vec<_> = map.iter()
+        .map(|(g, k, &v)| (g.as_str(), k.as_str(), v))
+        .collect();
+    result.sort();
+    assert_eq!(result, [("a", "1", 2), ("a", "2", 1), ("b", "1", 1)]);
+    assert_eq!(map.into_flat(), flat);
+}
+
+#[test]
+pub fn test_expand() {
+    let mut map: BilevelMap<String, String, u8> = BilevelMap::new();
+    *map.add_or_get("b", "1") += 2;
+    *map.add_or_get("a", "2") += 1;
+    *map.add_or_get("a", "1") += 3;
+    assert_eq!(map.expand().count(), 6);
+    let result: Vec<_> = map.expand_sorted()
+        .map(|(g, k)| (g.as_str(), k.as_str()))
+        .collect();
+    assert_eq!(result, [("a", "1"), ("a", "1"), ("a", "1"), ("a", "2"), ("b", "1"), ("b", "1")]);
+}
+
+#[test]
+pub fn test_iter_with_empty() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    map.declare_groups(["a", "b"]);
+    *map.add_or_get("a", "1") += 1;
+    *map.add_or_get("c", "1") += 1;
+    let mut result: Vec<_> = map.iter_with_empty()
+        .map(|(g, p)| (g.as_str(), p.map(|(k, &v)| (k.as_str(), v))))
+        .collect();
+    result.sort();
+    assert_eq!(result, [("a", Some(("1", 1))), ("b", None), ("c", Some(("1", 1)))]);
+    assert_eq!(map.iter().count(), 2);
+}
+
+#[test]
+pub fn test_nested() {
+    use std::collections::HashMap;
+    let grouped = HashMap::from([
+        ("a".to_owned(), HashSet::from(["1".to_owned(), "2".to_owned()])),
+        ("b".to_owned(), HashSet::from(["1".to_owned()])),
+    ]);
+    let set = BilevelSet::from(grouped.clone());
+    assert_eq!(set.iter().count(), 3);
+    assert_eq!(set.into_grouped(), grouped);
+    let nested = HashMap::from([
+        ("a".to_owned(), HashMap::from([("1".to_owned(), 2), ("2".to_owned(), 1)])),
+        ("b".to_owned(), HashMap::from([("1".to_owned(), 1)])),
+    ]);
+    let map: BilevelMap<String, String, u32> = BilevelMap::from(nested.clone());
+    assert_eq!(map.iter().count(), 3);
+    assert_eq!(map.into_nested(), nested);
+}
+
+#[test]
+pub fn test_derived() {
+    let mut set: BilevelSet<String, String> = BilevelSet::new();
+    set.insert("a", "1");
+    set.insert("b", "1");
+    assert_eq!(set.pivoted().iter().count(), 2);
+    assert_eq!(set.dedup_groups().len(), 1);
+    // Modifying the set discards the derived values.
+    set.insert("a", "2");
+    assert_eq!(set.pivoted().iter().count(), 3);
+    assert!(set.dedup_groups().is_empty());
+}
+
+#[test]
+pub fn test_sorted() {
+    let mut set: BilevelSet<String, String> = BilevelSet::new();
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    for (g, k) in [("y", "b"), ("x", "c"), ("x", "b"), ("y", "a")] {
+        set.insert(g, k);
+        *map.add_or_get(g, k) += 1;
+    }
+    let expected = [("x", "b"), ("x", "c"), ("y", "a"), ("y", "b")];
+    assert!(set.iter_sorted().map(|(g, k)| (g.as_str(), k.as_str())).eq(expected));
+    assert!(map.iter_sorted().map(|(g, k, _)| (g.as_str(), k.as_str())).eq(expected));
+    let result: Vec<_> = set.into_iter_sorted().collect();
+    assert!(result.iter().map(|(g, k)| (g.as_str(), k.as_str())).eq(expected));
+    let result: Vec<_> = map.into_iter_sorted().collect();
+    assert!(result.iter().map(|(g, k, _)| (g.as_str(), k.as_str())).eq(expected));
+}
+
+#[test]
+pub fn test_send_sync() {
+    // Fails to compile if the collections cannot be shared with, or moved
+    // into, worker threads.
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<BilevelSet<String, String>>();
+    assert_send_sync::<BilevelMap<String, String, u64>>();
+}
+
+#[test]
+pub fn test_ids() {
+    let mut set: BilevelSet<String, String> = BilevelSet::new();
+    for (g, k) in [("x", "a"), ("y", "b"), ("y", "a")] {
+        set.insert(g, k);
+    }
+    assert!(set.id_mapping().map(|(i, k)| (i.index(), k.as_str())).eq([(0, "a"), (1, "b")]));
+    // Verify that each id resolves to the key listed with it.
+    for (id, g, i, k) in set.iter_with_ids() {
+        assert_eq!(set.resolve_group(id), Some(g));
+        assert_eq!(set.resolve_agg_key(i), Some(k));
+    }
+    assert_eq!(set.iter_with_ids().count(), 3);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+pub fn test_serde() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    *map.add_or_get("x", "a") += 3;
+    assert_eq!(serde_json::to_string(&map).unwrap(), r#"{"x":{"a":3}}"#);
+    let set: BilevelSet<String, String> = serde_json::from_str(r#"{"x":["a","b"],"y":["a"]}"#).unwrap();
+    assert_eq!(set.id_mapping().len(), 2);
+    let result: Vec<_> = set.iter_sorted().map(|(g, k)| (g.as_str(), k.as_str())).collect();
+    assert_eq!(result, [("x", "a"), ("x", "b"), ("y", "a")]);
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+pub fn test_snapshot() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    for (g, k) in [("x", "a"), ("x", "b"), ("y", "a"), ("x", "a")] {
+        *map.add_or_get(g, k) += 1;
+    }
+    let mut buf = Vec::new();
+    map.write_snapshot(&mut buf).unwrap();
+    let mut restored: BilevelMap<String, String, u32> = BilevelMap::read_snapshot(&buf[..]).unwrap();
+    assert!(restored.iter_sorted().eq(map.iter_sorted()));
+    // The interned keys are restored, so known pairs are found again.
+    assert_eq!(*restored.add_or_get("x", "a"), 2);
+    // A snapshot from an unknown format version is rejected.
+    buf[4] = 99;
+    assert!(matches!(
+        BilevelMap::<String, String, u32>::read_snapshot(&buf[..]),
+        Err(crate::SnapshotError::UnsupportedVersion(99))
+    ));
+}
+
+#[cfg(feature = "replay")]
+#[test]
+pub fn test_replay() {
+    use crate::{Logged, Mutation, MutationSink, Replay};
+    // A sink that only counts the updates, without copying the keys.
+    struct Updates(usize);
+    impl MutationSink<String, String, u32> for Updates {
+        fn record(&mut self, mutation: Mutation<&String, &String, &u32>) {
+            if let Mutation::Update(..) = mutation {
+                self.0 += 1;
+            }
+        }
+    }
+    let mut logged = Logged::new(BilevelMap::<String, String, u32>::new(), Updates(0));
+    for (g, k) in [("x", "a"), ("x", "b"), ("y", "a"), ("x", "a")] {
+        logged.update(g.to_string(), k.to_string(), 1);
+    }
+    logged.insert("y".to_string(), "c".to_string());
+    assert_eq!(logged.sink().0, 4);
+    assert_eq!(logged.get().iter().count(), 4);
+    let mut rebuilt = BilevelMap::new();
+    rebuilt.replay([
+        Mutation::Update("x".to_string(), "a".to_string(), 2),
+        Mutation::Insert("y".to_string(), "c".to_string()),
+    ]);
+    assert_eq!(*rebuilt.add_or_get("x", "a"), 2);
+    assert_eq!(*rebuilt.add_or_get("y", "c"), 0);
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+pub fn test_snapshot_partial() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    for (g, k) in [("x", "a"), ("y", "b"), ("z", "a")] {
+        *map.add_or_get(g, k) += 1;
+    }
+    let mut buf = Vec::new();
+    map.write_snapshot(&mut buf).unwrap();
+    buf.truncate(buf.len() - 1);
+    let (restored, missing) = BilevelMap::<String, String, u32>::read_snapshot_partial(&buf[..]).unwrap();
+    let missing = missing.unwrap();
+    assert_eq!(missing.missing(), 1);
+    assert!(matches!(missing.error, crate::SnapshotError::Encoding(_)));
+    assert_eq!(restored.iter().count(), 2);
+    // A snapshot cut off before the groups cannot be read at all.
+    buf.truncate(8);
+    assert!(BilevelMap::<String, String, u32>::read_snapshot_partial(&buf[..]).is_err());
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+pub fn test_par_iter() {
+    use rayon::prelude::*;
+    let mut set: BilevelSet<String, String> = BilevelSet::new();
+    for (g, k) in [("x", "a"), ("x", "b"), ("y", "a")] {
+        set.insert(g, k);
+    }
+    let mut pairs: Vec<_> = set.par_iter().collect();
+    pairs.sort();
+    assert!(pairs.into_iter().eq(set.iter_sorted()));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+pub fn test_par_extend() {
+    use rayon::prelude::*;
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    map.par_extend((0..1000).into_par_iter().map(|i| (format!("g{}", i % 4), format!("k{}", i % 5), 1)));
+    assert_eq!(map.iter().count(), 20);
+    assert!(map.iter().all(|(_, _, &v)| v == 50));
+}
+
+/// A payload with no default, counting values into buckets whose bounds
+/// are only known at run time.
+#[derive(Clone, Debug, PartialEq)]
+struct Histogram {
+    bounds: std::sync::Arc<[u32]>,
+    counts: Vec<u32>,
+}
+
+impl Histogram {
+    fn new(bounds: std::sync::Arc<[u32]>) -> Self {
+        Self { counts: vec![0; bounds.len() + 1], bounds }
+    }
+
+    fn record(&mut self, value: u32) {
+        let i = self.bounds.iter().take_while(|&&b| value >= b).count();
+        self.counts[i] += 1;
+    }
+}
+
+#[test]
+pub fn test_new_with() {
+    // The bounds are read from configuration.
+    let bounds: std::sync::Arc<[u32]> = "10,100,1000".split(',').map(|b| b.parse().unwrap()).collect();
+    let latency = move || Histogram::new(bounds.clone());
+    let mut a: BilevelMap<String, String, _> = BilevelMap::new_with(latency.clone());
+    let mut b: BilevelMap<String, String, _> = BilevelMap::with_capacity_and(Capacity { groups: 4, per_group: 4, agg_keys: 4 }, latency);
+    for (g, k, value) in [("x", "a", 5), ("x", "a", 50), ("x", "b", 5000), ("y", "a", 500)] {
+        a.add_or_get(g, k).record(value);
+        b.add_or_get(g, k).record(value);
+    }
+    assert_eq!(a.add_or_get("x", "a").counts, [1, 1, 0, 0]);
+    assert_eq!(b.add_or_get("x", "b").counts, [0, 0, 0, 1]);
+    // Derived collections keep the constructor.
+    let mut regrouped = b.regroup_by(|_| 0u8, |a, b| {
+        a.counts.iter_mut().zip(b.counts).for_each(|(a, b)| *a += b)
+    });
+    assert_eq!(regrouped.add_or_get(&0, "a").counts, [1, 1, 1, 0]);
+    regrouped.add_or_get(&0, "c").record(100);
+    assert_eq!(regrouped.add_or_get(&0, "c").counts, [0, 0, 1, 0]);
+}
+
+#[test]
+pub fn test_add_or_get_with_key() {
+    let mut map: BilevelMap<String, String, Vec<u8>> = BilevelMap::new();
+    for (g, k) in [("xy", "abc"), ("xy", "abc"), ("z", "d")] {
+        let buffer = map.add_or_get_with_key(g, k, |g, k| Vec::with_capacity(g.len() + k.len()));
+        buffer.push(0);
+    }
+    assert_eq!(map.add_or_get("xy", "abc").len(), 2);
+    assert!(map.add_or_get("z", "d").capacity() >= 2);
+}
+
+#[test]
+pub fn test_extend_with() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    *map.add_or_get("x", "a") += 1;
+    map.extend_with([("x".to_owned(), "a".to_owned(), 2), ("y".to_owned(), "a".to_owned(), 3)], |a, b| *a += b);
+    let result: Vec<_> = map.iter_sorted().map(|(g, k, v)| (g.as_str(), k.as_str(), *v)).collect();
+    assert_eq!(result, [("x", "a", 3), ("y", "a", 3)]);
+}
+
+#[test]
+pub fn test_top_k() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    for (k, v) in [("/", 10), ("/about", 2), ("/login", 7)] {
+        *map.add_or_get("customer", k) += v;
+    }
+    let top: Vec<_> = map.top_k("customer", 2, |v| *v).into_iter().map(|(k, v)| (k.as_str(), *v)).collect();
+    assert_eq!(top, [("/", 10), ("/login", 7)]);
+    let all: Vec<_> = map.top_k_all(1, |v| *v).map(|(g, k, _)| (g.as_str(), k.as_str())).collect();
+    assert_eq!(all, [("customer", "/")]);
+}
+
+#[test]
+pub fn test_group_sizes() {
+    let mut set: BilevelSet<String, String> = BilevelSet::new();
+    set.insert("x", "a");
+    set.insert("x", "b");
+    set.insert("y", "a");
+    let mut sizes: Vec<_> = set.group_sizes().map(|(g, n)| (g.as_str(), n)).collect();
+    sizes.sort();
+    assert_eq!(sizes, [("x", 2), ("y", 1)]);
+    assert_eq!(set.size_histogram(&[1, 4]), [1, 1, 0]);
+}
+
+#[test]
+pub fn test_len() {
+    let mut set: BilevelSet<String, String> = BilevelSet::new();
+    set.insert("x", "a");
+    set.insert("y", "a");
+    set.insert("y", "b");
+    assert_eq!((set.len(), set.group_len("y"), set.distinct_agg_keys()), (3, 2, 2));
+}
+
+#[test]
+pub fn test_owned() {
+    let mut set: BilevelSet<String, String> = BilevelSet::new();
+    assert!(set.insert_owned("u1".to_string(), "a".to_string()));
+    assert!(!set.insert_owned("u1".to_string(), "a".to_string()));
+    assert!(set.insert_owned("u1".to_string(), "b".to_string()));
+    // Owned and borrowed keys find the same groups and aggregation keys.
+    assert!(!set.insert("u1", "b"));
+    assert_eq!((set.len(), set.distinct_agg_keys()), (2, 2));
+
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    *map.add_or_get_owned("u1".to_string(), "a".to_string()) += 1;
+    *map.add_or_get("u1", "a") += 1;
+    *map.add_or_get_owned("u2".to_string(), "a".to_string()) += 1;
+    assert_eq!(map.len(), 2);
+    assert_eq!(map.iter().find(|(g, _, _)| *g == "u1").map(|(_, _, v)| *v), Some(2));
+}
+
+#[test]
+pub fn test_group_hashes_kept() {
+    use std::{hash::{Hash, Hasher}, sync::atomic::{AtomicUsize, Ordering}};
+
+    static HASHED: AtomicUsize = AtomicUsize::new(0);
+
+    /// A group key that counts how often it is hashed.
+    #[derive(Clone, PartialEq, Eq)]
+    struct Counted(String);
+
+    impl Hash for Counted {
+        fn hash<H: Hasher>(&self, state: &mut H) {
+            HASHED.fetch_add(1, Ordering::Relaxed);
+            self.0.hash(state)
+        }
+    }
+
+    let mut set: BilevelSet<Counted, String> = BilevelSet::new();
+    for i in 0..1000 {
+        set.insert(&Counted(format!("https://example.com/users/{i}")), "a");
+    }
+    // Each key is hashed once to find its group, and not again as the
+    // table of groups grows.
+    assert_eq!(HASHED.load(Ordering::Relaxed), 1000);
+    assert_eq!(set.group_sizes().count(), 1000);
+}
+
+#[cfg(feature = "csv")]
+#[test]
+pub fn test_write_csv() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    *map.add_or_get("x", "a") += 2;
+    let mut out = Vec::new();
+    map.write_csv(&mut out, &crate::CsvOptions::tsv()).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "group\tkey\tvalue\nx\ta\t2\n");
+}
+
+#[test]
+pub fn test_iterator_sizes() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    // One group small enough to keep its keys inline, and one large
+    // enough to move them into a hash table.
+    for i in 0..20 {
+        *map.add_or_get(if i < 3 { "small" } else { "large" }, &i.to_string()) += 1;
+    }
+    let mut pairs = map.iter();
+    for remaining in (0..20).rev() {
+        assert!(pairs.next().is_some());
+        assert_eq!(pairs.len(), remaining);
+        assert_eq!(pairs.size_hint(), (remaining, Some(remaining)));
+    }
+    assert!(pairs.next().is_none() && pairs.next().is_none());
+    assert_eq!(map.iter_mut().len(), 20);
+    let mut pairs = map.into_iter();
+    pairs.by_ref().take(5).for_each(drop);
+    assert_eq!(pairs.len(), 15);
+    assert_eq!(pairs.count(), 15);
+}
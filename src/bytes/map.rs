@@ -0,0 +1,200 @@
+use std::{cmp::Ordering, collections::HashMap, hash::Hash};
+use crate::{Capacity, core::{self, Inline}};
+use crate::arena::Arena;
+
+/// A collection of distinct pairs (g, k) grouped by g, with a payload
+/// associated with each pair, where the aggregation key is a byte string.
+/// 
+/// As pairs are found, they are added if not already present.
+/// When the collection is iterated over, the pairs are listed by group.
+/// 
+/// The aggregation keys are kept once each, end to end in a single
+/// buffer, and each group refers to them by id.
+/// 
+/// G is the type of the group key.
+/// V is the type of the payload.
+#[derive(Clone)]
+pub struct BilevelMap<G: Hash, V> {
+    arena: Arena,
+    inner: core::BilevelMap<G, usize, V, Inline>,
+}
+
+impl<G: Hash, V: Default> BilevelMap<G, V> {
+    /// Create a new collection, with the default payload for each new pair.
+    /// 
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    pub fn new() -> Self {
+        Self::with_capacity(Capacity::default())
+    }
+
+    /// Create a new collection with the specified capacity, with the
+    /// default payload for each new pair.
+    pub fn with_capacity(capacity: Capacity) -> Self {
+        Self {
+            arena: Arena::with_capacity(capacity.agg_keys),
+            inner: core::BilevelMap::with_capacity(capacity),
+        }
+    }
+}
+
+impl<G: Hash, V: Default> Default for BilevelMap<G, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: Hash, V> BilevelMap<G, V> {
+    /// Create a new collection.
+    /// 
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    /// 
+    /// constructor: A constructor for the payload of each new pair.
+    pub fn new_with(constructor: impl Fn() -> V + Send + Sync + 'static) -> Self {
+        Self::with_capacity_and(Capacity::default(), constructor)
+    }
+
+    /// Create a new collection with the specified capacity.
+    /// 
+    /// constructor: A constructor for the payload of each new pair.
+    pub fn with_capacity_and(capacity: Capacity, constructor: impl Fn() -> V + Send + Sync + 'static) -> Self {
+        Self {
+            arena: Arena::with_capacity(capacity.agg_keys),
+            inner: core::BilevelMap::with_capacity_and(capacity, constructor),
+        }
+    }
+
+    /// Get a mutable reference to the payload for the specified key pair.
+    /// 
+    /// If the key pair is currently not present, a new payload is inserted.
+    pub fn add_or_get<GRef>(&mut self, g: &GRef, k: &[u8]) -> &mut V
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+    {
+        let id = self.arena.intern(k);
+        self.inner.add_or_get(g, &id)
+    }
+
+    /// List the payloads for the pairs currently in the collection,
+    /// without consuming the collection or the payloads.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn iter(&self) -> impl Iterator<Item = (&G, &[u8], &V)> {
+        let arena = &self.arena;
+        self.inner.iter().map(move |(g, &id, v)| (g, arena.key(id), v))
+    }
+
+    /// List the pairs currently in the collection, as iter does, with the
+    /// id of each aggregation key.
+    /// 
+    /// The ids count from 0 in the order the keys were first found.
+    pub fn iter_with_ids(&self) -> impl Iterator<Item = (&G, usize, &[u8], &V)> {
+        let arena = &self.arena;
+        self.inner.iter().map(move |(g, &id, v)| (g, id, arena.key(id), v))
+    }
+
+    /// Get the aggregation key with the specified id.
+    pub fn key(&self, id: usize) -> &[u8] {
+        self.arena.key(id)
+    }
+
+    /// Count the distinct aggregation keys.
+    pub fn key_count(&self) -> usize {
+        self.arena.len()
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&G, &[u8], &V)>
+    where
+        G: Ord,
+    {
+        self.iter_sorted_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)))
+    }
+
+    /// List the pairs currently in the collection, sorted using the
+    /// specified comparison function.
+    pub fn iter_sorted_by<'a, F>(&'a self, compare: F) -> impl Iterator<Item = (&'a G, &'a [u8], &'a V)> + 'a
+    where
+        F: FnMut(&(&'a G, &'a [u8], &'a V), &(&'a G, &'a [u8], &'a V)) -> Ordering + 'a,
+    {
+        let mut pairs: Vec<_> = self.iter().collect();
+        pairs.sort_unstable_by(compare);
+        pairs.into_iter()
+    }
+
+    /// List the pairs currently in the collection, as iter does, but also
+    /// list each empty group once, with no aggregation key or payload.
+    pub fn iter_with_empty(&self) -> impl Iterator<Item = (&G, Option<(&[u8], &V)>)> {
+        let arena = &self.arena;
+        self.inner.iter_with_empty()
+            .map(move |(g, kv)| (g, kv.map(|(&id, v)| (arena.key(id), v))))
+    }
+
+    /// Declare group keys that are expected to appear, so that they are
+    /// listed by iter_with_empty even if no pairs are found for them.
+    pub fn declare_groups<'a, GRef>(&mut self, groups: impl IntoIterator<Item = &'a GRef>)
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized + 'a,
+    {
+        self.inner.declare_groups(groups);
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    /// Count the pairs currently in the collection.
+    pub fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+}
+
+impl<G: Hash + std::fmt::Debug, V: std::fmt::Debug> std::fmt::Debug for BilevelMap<G, V> {
+    /// Format the collection as a map from each group key to a map of its
+    /// aggregation keys, as byte strings, and payloads, listing only the
+    /// first few groups, and keys of each group, of a large collection.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let arena = &self.arena;
+        crate::debug::debug_map(f, self.inner.iter_groups().map(|(g, pairs)| {
+            (g, pairs.map(|(&id, v)| (arena.key(id), v)))
+        }))
+    }
+}
+
+impl<G: Hash + Eq, V: PartialEq> PartialEq for BilevelMap<G, V> {
+    /// Check whether two collections hold the same pairs and payloads,
+    /// whatever their order and the ids given to their aggregation keys.
+    fn eq(&self, other: &Self) -> bool {
+        if self.group_count() != other.group_count() || self.pair_count() != other.pair_count() {
+            return false;
+        }
+        let theirs: HashMap<_, _> = other.iter().map(|(g, k, v)| ((g, k), v)).collect();
+        self.iter().all(|(g, k, v)| theirs.get(&(g, k)) == Some(&v))
+    }
+}
+
+impl<G: Hash + Eq, V: Eq> Eq for BilevelMap<G, V> {}
+
+#[cfg(feature = "tenant")]
+impl<G: Hash, V> crate::TenantData for BilevelMap<G, V> {
+    type Item<'a> = (&'a G, &'a [u8], &'a V) where Self: 'a;
+
+    fn pairs(&self) -> impl Iterator<Item = (&G, &[u8], &V)> {
+        self.iter()
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.arena.memory_usage() + crate::TenantData::memory_usage(&self.inner)
+    }
+}
@@ -0,0 +1,178 @@
+use std::{cmp::Ordering, collections::HashSet, hash::Hash};
+use crate::{Capacity, core::{self, Inline}};
+use crate::arena::Arena;
+
+/// A collection of distinct pairs (g, k) grouped by g, where the
+/// aggregation key is a byte string.
+/// 
+/// As pairs are found, they are added if not already present.
+/// When the collection is iterated over, the pairs are listed by group.
+/// 
+/// The aggregation keys are kept once each, end to end in a single
+/// buffer, and each group refers to them by id.
+/// 
+/// G is the type of the group key.
+#[derive(Clone)]
+pub struct BilevelSet<G: Hash> {
+    arena: Arena,
+    inner: core::BilevelSet<G, usize, Inline>,
+}
+
+impl<G: Hash> BilevelSet<G> {
+    /// Create a new collection.
+    /// 
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    pub fn new() -> Self {
+        Self::with_capacity(Capacity::default())
+    }
+
+    /// Create a new collection with the specified capacity.
+    pub fn with_capacity(capacity: Capacity) -> Self {
+        Self {
+            arena: Arena::with_capacity(capacity.agg_keys),
+            inner: core::BilevelSet::with_capacity(capacity),
+        }
+    }
+
+    /// Insert a key pair found into the collection.
+    /// 
+    /// g: the group key.
+    /// k: the remaining key.
+    /// 
+    /// Return false if the key was already present, otherwise true.
+    pub fn insert<GRef>(&mut self, g: &GRef, k: &[u8]) -> bool
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+    {
+        let id = self.arena.intern(k);
+        self.inner.insert(g, &id)
+    }
+
+    /// List the pairs currently in the collection without consuming
+    /// the collection.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn iter(&self) -> impl Iterator<Item = (&G, &[u8])> {
+        let arena = &self.arena;
+        self.inner.iter().map(move |(g, &id)| (g, arena.key(id)))
+    }
+
+    /// List the pairs currently in the collection, as iter does, with the
+    /// id of each aggregation key.
+    /// 
+    /// The ids count from 0 in the order the keys were first found.
+    pub fn iter_with_ids(&self) -> impl Iterator<Item = (&G, usize, &[u8])> {
+        let arena = &self.arena;
+        self.inner.iter().map(move |(g, &id)| (g, id, arena.key(id)))
+    }
+
+    /// Get the aggregation key with the specified id.
+    pub fn key(&self, id: usize) -> &[u8] {
+        self.arena.key(id)
+    }
+
+    /// Count the distinct aggregation keys.
+    pub fn key_count(&self) -> usize {
+        self.arena.len()
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&G, &[u8])>
+    where
+        G: Ord,
+    {
+        self.iter_sorted_by(Ord::cmp)
+    }
+
+    /// List the pairs currently in the collection, sorted using the
+    /// specified comparison function.
+    pub fn iter_sorted_by<'a, F>(&'a self, compare: F) -> impl Iterator<Item = (&'a G, &'a [u8])> + 'a
+    where
+        F: FnMut(&(&'a G, &'a [u8]), &(&'a G, &'a [u8])) -> Ordering + 'a,
+    {
+        let mut pairs: Vec<_> = self.iter().collect();
+        pairs.sort_unstable_by(compare);
+        pairs.into_iter()
+    }
+
+    /// List the pairs currently in the collection, as iter does, but also
+    /// list each empty group once, with no aggregation key.
+    pub fn iter_with_empty(&self) -> impl Iterator<Item = (&G, Option<&[u8]>)> {
+        let arena = &self.arena;
+        self.inner.iter_with_empty().map(move |(g, k)| (g, k.map(|&id| arena.key(id))))
+    }
+
+    /// Declare group keys that are expected to appear, so that they are
+    /// listed by iter_with_empty even if no pairs are found for them.
+    pub fn declare_groups<'a, GRef>(&mut self, groups: impl IntoIterator<Item = &'a GRef>)
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized + 'a,
+    {
+        self.inner.declare_groups(groups);
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    /// Count the pairs currently in the collection.
+    pub fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+}
+
+impl<G: Hash> Default for BilevelSet<G> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: Hash + std::fmt::Debug> std::fmt::Debug for BilevelSet<G> {
+    /// Format the collection as a map from each group key to the set of
+    /// its aggregation keys, as byte strings, listing only the first few
+    /// groups, and keys of each group, of a large collection.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let arena = &self.arena;
+        crate::debug::debug_set(f, self.inner.iter_groups().map(|(g, keys)| {
+            (g, keys.map(|&id| arena.key(id)))
+        }))
+    }
+}
+
+impl<G: Hash + Eq> PartialEq for BilevelSet<G> {
+    /// Check whether two collections hold the same pairs, whatever their
+    /// order and the ids given to their aggregation keys.
+    fn eq(&self, other: &Self) -> bool {
+        if self.group_count() != other.group_count() || self.pair_count() != other.pair_count() {
+            return false;
+        }
+        let theirs: HashSet<_> = other.iter().collect();
+        self.iter().all(|pair| theirs.contains(&pair))
+    }
+}
+
+impl<G: Hash + Eq> Eq for BilevelSet<G> {}
+
+#[cfg(feature = "tenant")]
+impl<G: Hash> crate::TenantData for BilevelSet<G> {
+    type Item<'a> = (&'a G, &'a [u8]) where Self: 'a;
+
+    fn pairs(&self) -> impl Iterator<Item = (&G, &[u8])> {
+        self.iter()
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.arena.memory_usage() + crate::TenantData::memory_usage(&self.inner)
+    }
+}
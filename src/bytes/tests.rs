@@ -0,0 +1,49 @@
+use super::*;
+use crate::Capacity;
+
+#[test]
+pub fn test_set() {
+    let mut set: BilevelSet<String> = BilevelSet::new();
+    let test_data: [(&str, &[u8]); 5] = [
+        ("eth0", &[0x00, 0x1a, 0x2b]),
+        ("eth0", &[0xff, 0x00]),
+        ("eth1", &[0x00, 0x1a, 0x2b]),
+        ("eth0", &[0x00, 0x1a, 0x2b]),
+        ("eth1", &[]),
+    ];
+    for (i, (g, k)) in test_data.into_iter().enumerate() {
+        assert_eq!(set.insert(g, k), i != 3);
+    }
+    let expected: [(&str, &[u8]); 4] = [
+        ("eth0", &[0x00, 0x1a, 0x2b]),
+        ("eth0", &[0xff, 0x00]),
+        ("eth1", &[]),
+        ("eth1", &[0x00, 0x1a, 0x2b]),
+    ];
+    assert!(set.iter_sorted().map(|(g, k)| (g.as_str(), k)).eq(expected));
+    // Each distinct key is kept once, including the empty key.
+    assert_eq!(set.key_count(), 3);
+    assert_eq!(set.key(2), b"");
+    assert_eq!(set.pair_count(), 4);
+}
+
+#[test]
+pub fn test_map() {
+    let mut map: BilevelMap<u8, u32> = BilevelMap::with_capacity(Capacity {
+        groups: 2,
+        per_group: 2,
+        agg_keys: 4,
+    });
+    for (g, k) in [(1, &b"ab"[..]), (1, b"a"), (2, b"ab"), (1, b"ab"), (1, b"b")] {
+        *map.add_or_get(&g, k) += 1;
+    }
+    let expected: [(u8, &[u8], u32); 4] = [(1, b"a", 1), (1, b"ab", 2), (1, b"b", 1), (2, b"ab", 1)];
+    assert!(map.iter_sorted().map(|(g, k, v)| (*g, k, *v)).eq(expected));
+    // The keys are not confused by sharing a buffer.
+    let mut ids: Vec<_> = map.iter_with_ids().map(|(_, id, k, _)| (id, k)).collect();
+    ids.sort_unstable();
+    ids.dedup();
+    assert_eq!(ids, [(0, &b"ab"[..]), (1, b"a"), (2, b"b")]);
+    map.declare_groups([&3]);
+    assert_eq!(map.iter_with_empty().filter(|(_, kv)| kv.is_none()).count(), 1);
+}
@@ -0,0 +1,182 @@
+use std::{borrow::Borrow, cmp::Ordering, fmt, hash::{Hash, Hasher}, ops::Deref};
+use crate::HeapSize;
+
+/// The length of the longest string held within a CompactStr itself.
+const INLINE: usize = 22;
+
+/// An immutable string for keys, such as the aggregation keys of a borrow
+/// collection, that takes less memory than a String.
+///
+/// A string of up to 22 bytes is held within the value itself, with no
+/// allocation and no indirection to read it, and a longer string in a
+/// Box<str>, which has no spare capacity. Either way the value takes 24
+/// bytes, the size of a String alone.
+///
+/// It hashes, compares and orders as the str it holds, so a collection of
+/// CompactStr keys can be searched by &str.
+#[derive(Clone)]
+pub struct CompactStr(Repr);
+
+#[derive(Clone)]
+enum Repr {
+    Inline(u8, [u8; INLINE]),
+    Boxed(Box<str>),
+}
+
+impl CompactStr {
+    /// Copy a string, allocating only if it is longer than 22 bytes.
+    pub fn new(s: &str) -> Self {
+        if s.len() <= INLINE {
+            let mut bytes = [0; INLINE];
+            bytes[..s.len()].copy_from_slice(s.as_bytes());
+            Self(Repr::Inline(s.len() as u8, bytes))
+        } else {
+            Self(Repr::Boxed(s.into()))
+        }
+    }
+
+    /// Get the string.
+    pub fn as_str(&self) -> &str {
+        match &self.0 {
+            // SAFETY: The bytes were copied from a whole str.
+            Repr::Inline(len, bytes) => unsafe { std::str::from_utf8_unchecked(&bytes[..*len as usize]) },
+            Repr::Boxed(s) => s,
+        }
+    }
+
+    /// Check whether the string is held within the value, with no
+    /// allocation.
+    pub fn is_inline(&self) -> bool {
+        matches!(self.0, Repr::Inline(..))
+    }
+}
+
+impl Default for CompactStr {
+    fn default() -> Self {
+        Self::new("")
+    }
+}
+
+impl Deref for CompactStr {
+    type Target = str;
+
+    fn deref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<str> for CompactStr {
+    fn as_ref(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl AsRef<[u8]> for CompactStr {
+    fn as_ref(&self) -> &[u8] {
+        self.as_str().as_bytes()
+    }
+}
+
+impl Borrow<str> for CompactStr {
+    fn borrow(&self) -> &str {
+        self.as_str()
+    }
+}
+
+impl From<&str> for CompactStr {
+    fn from(s: &str) -> Self {
+        Self::new(s)
+    }
+}
+
+impl From<String> for CompactStr {
+    fn from(s: String) -> Self {
+        if s.len() <= INLINE { Self::new(&s) } else { Self(Repr::Boxed(s.into_boxed_str())) }
+    }
+}
+
+impl From<Box<str>> for CompactStr {
+    fn from(s: Box<str>) -> Self {
+        if s.len() <= INLINE { Self::new(&s) } else { Self(Repr::Boxed(s)) }
+    }
+}
+
+impl PartialEq for CompactStr {
+    fn eq(&self, other: &Self) -> bool {
+        self.as_str() == other.as_str()
+    }
+}
+
+impl Eq for CompactStr {}
+
+impl PartialEq<str> for CompactStr {
+    fn eq(&self, other: &str) -> bool {
+        self.as_str() == other
+    }
+}
+
+impl PartialEq<CompactStr> for str {
+    fn eq(&self, other: &CompactStr) -> bool {
+        self == other.as_str()
+    }
+}
+
+impl PartialEq<&str> for CompactStr {
+    fn eq(&self, other: &&str) -> bool {
+        self.as_str() == *other
+    }
+}
+
+impl PartialOrd for CompactStr {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl Ord for CompactStr {
+    fn cmp(&self, other: &Self) -> Ordering {
+        self.as_str().cmp(other.as_str())
+    }
+}
+
+impl Hash for CompactStr {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        // Hashed as the str is, so that a str can be looked up.
+        self.as_str().hash(state)
+    }
+}
+
+impl fmt::Debug for CompactStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Debug::fmt(self.as_str(), f)
+    }
+}
+
+impl fmt::Display for CompactStr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        fmt::Display::fmt(self.as_str(), f)
+    }
+}
+
+impl HeapSize for CompactStr {
+    fn heap_bytes(&self) -> usize {
+        match &self.0 {
+            Repr::Inline(..) => 0,
+            Repr::Boxed(s) => s.len(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for CompactStr {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_str(self.as_str())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for CompactStr {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        String::deserialize(deserializer).map(Self::from)
+    }
+}
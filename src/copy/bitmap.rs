@@ -0,0 +1,240 @@
+use std::{collections::{HashMap, HashSet}, hash::Hash};
+use roaring::RoaringBitmap;
+use crate::{HeapSize, table_size};
+use super::BilevelSet;
+
+/// A collection of distinct pairs (g, k) grouped by g, where the
+/// aggregation keys are u32 ids, holding the keys of each group in a
+/// roaring bitmap.
+/// 
+/// A bitmap holds dense runs of ids in a bit or less each, rather than in
+/// an entry of a hash table, so a set of millions of item ids for each of
+/// many users takes a small fraction of the memory of a BilevelSet, and
+/// the keys of two groups can be compared without hashing.
+/// 
+/// G is the type of the group key.
+pub struct BilevelBitmapSet<G>
+where
+    G: Hash + Eq,
+{
+    groups: HashMap<G, RoaringBitmap>,
+    pairs: usize,
+}
+
+impl<G> BilevelBitmapSet<G>
+where
+    G: Hash + Eq + Copy,
+{
+    /// Create a new collection.
+    /// 
+    /// No initial capacity is allocated.
+    pub fn new() -> Self {
+        Self { groups: HashMap::new(), pairs: 0 }
+    }
+
+    /// Create a new collection with capacity for the specified number of
+    /// groups.
+    pub fn with_capacity(groups: usize) -> Self {
+        Self { groups: HashMap::with_capacity(groups), pairs: 0 }
+    }
+
+    /// Add a pair to the collection, if not already present.
+    /// 
+    /// Return whether the pair was added.
+    pub fn insert(&mut self, g: G, k: u32) -> bool {
+        let added = self.groups.entry(g).or_default().insert(k);
+        self.pairs += added as usize;
+        added
+    }
+
+    /// Add the aggregation keys listed to a group, such as the ids of a
+    /// user's items read in bulk.
+    /// 
+    /// Return the number of pairs added.
+    pub fn extend_group(&mut self, g: G, keys: impl IntoIterator<Item = u32>) -> usize {
+        let bitmap = self.groups.entry(g).or_default();
+        let before = bitmap.len();
+        bitmap.extend(keys);
+        let added = (bitmap.len() - before) as usize;
+        self.pairs += added;
+        added
+    }
+
+    /// Check whether the collection holds a pair.
+    pub fn contains(&self, g: G, k: u32) -> bool {
+        self.groups.get(&g).is_some_and(|bitmap| bitmap.contains(k))
+    }
+
+    /// Get the bitmap of the aggregation keys of a group, or None if the
+    /// group is not present.
+    pub fn group(&self, g: G) -> Option<&RoaringBitmap> {
+        self.groups.get(&g)
+    }
+
+    /// List the pairs currently in the collection.
+    /// 
+    /// Pairs are grouped by g, and the keys of each group are listed in
+    /// ascending order.
+    pub fn iter(&self) -> impl Iterator<Item = (G, u32)> + '_ {
+        self.groups.iter().flat_map(|(g, bitmap)| bitmap.iter().map(move |k| (*g, k)))
+    }
+
+    /// List the pairs in the collection, consuming the collection.
+    /// 
+    /// Pairs are grouped by g, and the keys of each group are listed in
+    /// ascending order.
+    pub fn into_iter(self) -> impl Iterator<Item = (G, u32)> {
+        self.groups.into_iter().flat_map(|(g, bitmap)| bitmap.into_iter().map(move |k| (g, k)))
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (G, u32)> + '_
+    where
+        G: Ord,
+    {
+        let mut groups: Vec<_> = self.groups.iter().collect();
+        groups.sort_unstable_by_key(|(g, _)| **g);
+        groups.into_iter().flat_map(|(g, bitmap)| bitmap.iter().map(move |k| (*g, k)))
+    }
+
+    /// Add each group listed, if not already present, with no pairs.
+    pub fn declare_groups(&mut self, groups: impl IntoIterator<Item = G>) {
+        for g in groups {
+            self.groups.entry(g).or_default();
+        }
+    }
+
+    /// Remove a group, returning the bitmap of its aggregation keys, or
+    /// None if the group is not present.
+    pub fn remove_group(&mut self, g: G) -> Option<RoaringBitmap> {
+        let bitmap = self.groups.remove(&g)?;
+        self.pairs -= bitmap.len() as usize;
+        Some(bitmap)
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Count the pairs currently in the collection.
+    /// 
+    /// The count is kept as pairs are added, so this takes constant time.
+    pub fn len(&self) -> usize {
+        self.pairs
+    }
+
+    /// Check whether the collection holds no pairs, although it may hold
+    /// empty groups.
+    pub fn is_empty(&self) -> bool {
+        self.pairs == 0
+    }
+
+    /// Count the pairs of the specified group, which is 0 if the group is
+    /// not present.
+    pub fn group_len(&self, g: G) -> usize {
+        self.groups.get(&g).map_or(0, |bitmap| bitmap.len() as usize)
+    }
+
+    /// List the group keys currently in the collection, with the number
+    /// of aggregation keys in each group.
+    pub fn group_sizes(&self) -> impl Iterator<Item = (G, usize)> + '_ {
+        self.groups.iter().map(|(g, bitmap)| (*g, bitmap.len() as usize))
+    }
+
+    /// Measure the overlap of the aggregation keys of two groups, as their
+    /// Jaccard similarity: the number of keys they share divided by the
+    /// number of keys in either.
+    /// 
+    /// Return None if either group is not present.
+    pub fn similarity(&self, g1: G, g2: G) -> Option<f64> {
+        let (a, b) = (self.groups.get(&g1)?, self.groups.get(&g2)?);
+        let union = a.union_len(b);
+        Some(if union == 0 { 1.0 } else { a.intersection_len(b) as f64 / union as f64 })
+    }
+
+    /// Estimate the memory held by the collection, in bytes, taking the
+    /// memory of each bitmap to be its serialized size.
+    /// 
+    /// This walks every group, so takes time in proportion to the number
+    /// of groups.
+    pub fn estimated_heap_bytes(&self) -> usize
+    where
+        G: HeapSize,
+    {
+        table_size::<(G, RoaringBitmap)>(self.groups.capacity())
+            + self.groups.iter().map(|(g, bitmap)| g.heap_bytes() + bitmap.serialized_size()).sum::<usize>()
+    }
+
+    /// Convert into a BilevelSet, holding the keys of each group in a hash
+    /// table.
+    pub fn to_set(&self) -> BilevelSet<G, u32> {
+        let mut set = BilevelSet::with_capacity(self.groups.len(), 0);
+        set.declare_groups(self.groups.keys().copied());
+        for (g, k) in self.iter() {
+            set.insert(g, k);
+        }
+        set
+    }
+
+    /// Convert into a map from each group key to its aggregation keys.
+    pub fn into_grouped(self) -> HashMap<G, HashSet<u32>> {
+        self.groups.into_iter().map(|(g, bitmap)| (g, bitmap.into_iter().collect())).collect()
+    }
+}
+
+impl<G> Default for BilevelBitmapSet<G>
+where
+    G: Hash + Eq + Copy,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G> From<&BilevelSet<G, u32>> for BilevelBitmapSet<G>
+where
+    G: Hash + Eq + Copy,
+{
+    fn from(set: &BilevelSet<G, u32>) -> Self {
+        let mut bitmaps = Self::with_capacity(set.group_sizes().count());
+        bitmaps.declare_groups(set.group_sizes().map(|(g, _)| g));
+        for (g, k) in set.iter() {
+            bitmaps.insert(g, k);
+        }
+        bitmaps
+    }
+}
+
+impl<G> std::fmt::Debug for BilevelBitmapSet<G>
+where
+    G: Hash + Eq + std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::debug::debug_set(f, self.groups.iter().map(|(g, bitmap)| (g, bitmap.iter())))
+    }
+}
+
+impl<G> Clone for BilevelBitmapSet<G>
+where
+    G: Hash + Eq + Clone,
+{
+    fn clone(&self) -> Self {
+        Self { groups: self.groups.clone(), pairs: self.pairs }
+    }
+}
+
+impl<G> PartialEq for BilevelBitmapSet<G>
+where
+    G: Hash + Eq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.groups == other.groups
+    }
+}
+
+impl<G> Eq for BilevelBitmapSet<G>
+where
+    G: Hash + Eq,
+{}
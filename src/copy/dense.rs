@@ -0,0 +1,405 @@
+use std::{collections::HashMap, hash::Hash, mem::size_of};
+use crate::{HeapSize, table_size};
+
+/// An aggregation key drawn from a small domain of integers, 0 up to a
+/// size known in advance, such as an HTTP status code or a weekday, that
+/// can index the fixed-size storage of a dense collection.
+pub trait DenseKey: Copy {
+    /// Get the index of the key within the domain.
+    fn to_index(self) -> usize;
+
+    /// Get the key at an index within the domain.
+    fn from_index(index: usize) -> Self;
+}
+
+macro_rules! integer_dense_key {
+    ($($t:ty),*) => {
+        $(
+            impl DenseKey for $t {
+                fn to_index(self) -> usize {
+                    self as usize
+                }
+
+                fn from_index(index: usize) -> Self {
+                    index as $t
+                }
+            }
+        )*
+    };
+}
+
+integer_dense_key!(u8, u16, u32, u64, usize);
+
+/// The number of bits in each word of a bitset.
+const WORD: usize = u64::BITS as usize;
+
+/// Check whether the bit of a bitset at an index is set.
+fn has(bits: &[u64], i: usize) -> bool {
+    bits[i / WORD] & (1 << (i % WORD)) != 0
+}
+
+/// Set the bit of a bitset at an index, returning whether it was clear.
+fn set(bits: &mut [u64], i: usize) -> bool {
+    let word = &mut bits[i / WORD];
+    let was_clear = *word & (1 << (i % WORD)) == 0;
+    *word |= 1 << (i % WORD);
+    was_clear
+}
+
+/// List the indexes of the bits set in a bitset, in ascending order.
+fn ones(bits: &[u64]) -> impl Iterator<Item = usize> + '_ {
+    bits.iter().enumerate().flat_map(|(w, &word)| {
+        let mut word = word;
+        std::iter::from_fn(move || {
+            (word != 0).then(|| {
+                let bit = word.trailing_zeros() as usize;
+                word &= word - 1;
+                w * WORD + bit
+            })
+        })
+    })
+}
+
+/// A collection of distinct pairs (g, k) grouped by g, where the
+/// aggregation keys are drawn from a small domain known in advance,
+/// holding the keys of each group in a fixed-size bitset.
+/// 
+/// Each group takes a bit for each key of the domain, with no hash table
+/// of its own, so for a domain such as the HTTP status codes, the keys of
+/// each group take a few words, and adding a pair is a bit operation.
+/// 
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+pub struct BilevelDenseSet<G, K>
+where
+    G: Hash + Eq,
+    K: DenseKey,
+{
+    groups: HashMap<G, Box<[u64]>>,
+    domain: usize,
+    pairs: usize,
+    key: std::marker::PhantomData<K>,
+}
+
+impl<G, K> BilevelDenseSet<G, K>
+where
+    G: Hash + Eq + Copy,
+    K: DenseKey,
+{
+    /// Create a new collection, for aggregation keys whose indexes are
+    /// less than the specified size of the domain.
+    pub fn with_domain(domain: usize) -> Self {
+        Self { groups: HashMap::new(), domain, pairs: 0, key: std::marker::PhantomData }
+    }
+
+    /// Get the size of the domain of the aggregation keys.
+    pub fn domain(&self) -> usize {
+        self.domain
+    }
+
+    /// Add a pair to the collection, if not already present.
+    /// 
+    /// Return whether the pair was added.
+    /// 
+    /// Panics if the key lies outside the domain.
+    pub fn insert(&mut self, g: G, k: K) -> bool {
+        let i = self.index(k);
+        let words = self.domain.div_ceil(WORD);
+        let bits = self.groups.entry(g).or_insert_with(|| vec![0; words].into_boxed_slice());
+        let added = set(bits, i);
+        self.pairs += added as usize;
+        added
+    }
+
+    /// Check whether the collection holds a pair.
+    pub fn contains(&self, g: G, k: K) -> bool {
+        let i = k.to_index();
+        i < self.domain && self.groups.get(&g).is_some_and(|bits| has(bits, i))
+    }
+
+    /// List the pairs currently in the collection.
+    /// 
+    /// Pairs are grouped by g, and the keys of each group are listed in
+    /// order of their indexes.
+    pub fn iter(&self) -> impl Iterator<Item = (G, K)> + '_ {
+        self.groups.iter().flat_map(|(g, bits)| ones(bits).map(move |i| (*g, K::from_index(i))))
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the index of the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (G, K)> + '_
+    where
+        G: Ord,
+    {
+        let mut groups: Vec<_> = self.groups.iter().collect();
+        groups.sort_unstable_by_key(|(g, _)| **g);
+        groups.into_iter().flat_map(|(g, bits)| ones(bits).map(move |i| (*g, K::from_index(i))))
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Count the pairs currently in the collection.
+    /// 
+    /// The count is kept as pairs are added, so this takes constant time.
+    pub fn len(&self) -> usize {
+        self.pairs
+    }
+
+    /// Check whether the collection holds no pairs.
+    pub fn is_empty(&self) -> bool {
+        self.pairs == 0
+    }
+
+    /// Count the pairs of the specified group, which is 0 if the group is
+    /// not present.
+    pub fn group_len(&self, g: G) -> usize {
+        self.groups.get(&g).map_or(0, |bits| bits.iter().map(|w| w.count_ones() as usize).sum())
+    }
+
+    /// Estimate the memory held by the collection, in bytes.
+    pub fn estimated_heap_bytes(&self) -> usize
+    where
+        G: HeapSize,
+    {
+        table_size::<(G, Box<[u64]>)>(self.groups.capacity())
+            + self.groups.iter().map(|(g, bits)| g.heap_bytes() + bits.len() * size_of::<u64>()).sum::<usize>()
+    }
+
+    /// Get the index of a key, checking that it lies within the domain.
+    fn index(&self, k: K) -> usize {
+        let i = k.to_index();
+        assert!(i < self.domain, "key index {} is outside the domain of {} keys", i, self.domain);
+        i
+    }
+}
+
+impl<G, K> std::fmt::Debug for BilevelDenseSet<G, K>
+where
+    G: Hash + Eq + Copy + std::fmt::Debug,
+    K: DenseKey + std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::debug::debug_set(f, self.groups.iter().map(|(g, bits)| (g, ones(bits).map(K::from_index))))
+    }
+}
+
+impl<G, K> Clone for BilevelDenseSet<G, K>
+where
+    G: Hash + Eq + Clone,
+    K: DenseKey,
+{
+    fn clone(&self) -> Self {
+        Self { groups: self.groups.clone(), domain: self.domain, pairs: self.pairs, key: std::marker::PhantomData }
+    }
+}
+
+impl<G, K> PartialEq for BilevelDenseSet<G, K>
+where
+    G: Hash + Eq,
+    K: DenseKey,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.domain == other.domain && self.groups == other.groups
+    }
+}
+
+impl<G, K> Eq for BilevelDenseSet<G, K>
+where
+    G: Hash + Eq,
+    K: DenseKey,
+{}
+
+/// A collection of distinct pairs (g, k) grouped by g, with a payload
+/// for each pair, where the aggregation keys are drawn from a small domain
+/// known in advance, holding the payloads of each group in a fixed-size
+/// array.
+/// 
+/// Each group takes a payload, and a bit, for each key of the domain, with
+/// no hash table of its own, so finding the payload of a pair is indexing
+/// an array.
+/// 
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// V is the type of the payload.
+pub struct BilevelDenseMap<G, K, V>
+where
+    G: Hash + Eq,
+    K: DenseKey,
+{
+    /// The bitset of the keys present in each group, and the array of its
+    /// payloads, which are the default where no key is present.
+    groups: HashMap<G, DenseGroup<V>>,
+    domain: usize,
+    pairs: usize,
+    key: std::marker::PhantomData<K>,
+}
+
+/// The bitset of the keys present in a group of a BilevelDenseMap, and the
+/// array of its payloads.
+type DenseGroup<V> = (Box<[u64]>, Box<[V]>);
+
+impl<G, K, V> BilevelDenseMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: DenseKey,
+{
+    /// Create a new collection, with the default payload for each new pair,
+    /// for aggregation keys whose indexes are less than the specified size
+    /// of the domain.
+    pub fn with_domain(domain: usize) -> Self {
+        Self { groups: HashMap::new(), domain, pairs: 0, key: std::marker::PhantomData }
+    }
+
+    /// Get the size of the domain of the aggregation keys.
+    pub fn domain(&self) -> usize {
+        self.domain
+    }
+
+    /// Get a mutable reference to the payload for the specified key pair.
+    /// 
+    /// If the key pair is currently not present, the default payload is
+    /// inserted.
+    /// 
+    /// Panics if the key lies outside the domain.
+    pub fn add_or_get(&mut self, g: G, k: K) -> &mut V
+    where
+        V: Default,
+    {
+        let i = k.to_index();
+        assert!(i < self.domain, "key index {} is outside the domain of {} keys", i, self.domain);
+        let domain = self.domain;
+        let (bits, values) = self.groups.entry(g).or_insert_with(|| {
+            (vec![0; domain.div_ceil(WORD)].into_boxed_slice(), (0..domain).map(|_| V::default()).collect())
+        });
+        self.pairs += set(bits, i) as usize;
+        &mut values[i]
+    }
+
+    /// Get the payload for the specified key pair, or None if the pair is
+    /// not present.
+    pub fn get(&self, g: G, k: K) -> Option<&V> {
+        let i = k.to_index();
+        let (bits, values) = self.groups.get(&g)?;
+        (i < self.domain && has(bits, i)).then(|| &values[i])
+    }
+
+    /// Check whether the collection holds a pair.
+    pub fn contains(&self, g: G, k: K) -> bool {
+        self.get(g, k).is_some()
+    }
+
+    /// List the payloads for the pairs currently in the collection.
+    /// 
+    /// Pairs are grouped by g, and the keys of each group are listed in
+    /// order of their indexes.
+    pub fn iter(&self) -> impl Iterator<Item = (G, K, &V)> + '_ {
+        self.groups.iter().flat_map(|(g, (bits, values))| {
+            ones(bits).map(move |i| (*g, K::from_index(i), &values[i]))
+        })
+    }
+
+    /// List the payloads for the pairs currently in the collection, sorted
+    /// by the group key and then by the index of the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (G, K, &V)> + '_
+    where
+        G: Ord,
+    {
+        let mut groups: Vec<_> = self.groups.iter().collect();
+        groups.sort_unstable_by_key(|(g, _)| **g);
+        groups.into_iter().flat_map(|(g, (bits, values))| {
+            ones(bits).map(move |i| (*g, K::from_index(i), &values[i]))
+        })
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Count the pairs currently in the collection.
+    /// 
+    /// The count is kept as pairs are added, so this takes constant time.
+    pub fn len(&self) -> usize {
+        self.pairs
+    }
+
+    /// Check whether the collection holds no pairs.
+    pub fn is_empty(&self) -> bool {
+        self.pairs == 0
+    }
+
+    /// Count the pairs of the specified group, which is 0 if the group is
+    /// not present.
+    pub fn group_len(&self, g: G) -> usize {
+        self.groups.get(&g).map_or(0, |(bits, _)| bits.iter().map(|w| w.count_ones() as usize).sum())
+    }
+
+    /// Estimate the memory held by the collection, in bytes, including
+    /// the memory owned by the payloads of the pairs present.
+    pub fn estimated_heap_bytes(&self) -> usize
+    where
+        G: HeapSize,
+        V: HeapSize,
+    {
+        table_size::<(G, DenseGroup<V>)>(self.groups.capacity())
+            + self.groups.iter().map(|(g, (bits, values))| {
+                g.heap_bytes()
+                    + bits.len() * size_of::<u64>()
+                    + values.len() * size_of::<V>()
+                    + ones(bits).map(|i| values[i].heap_bytes()).sum::<usize>()
+            }).sum::<usize>()
+    }
+}
+
+impl<G, K, V> std::fmt::Debug for BilevelDenseMap<G, K, V>
+where
+    G: Hash + Eq + Copy + std::fmt::Debug,
+    K: DenseKey + std::fmt::Debug,
+    V: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::debug::debug_map(f, self.groups.iter().map(|(g, (bits, values))| {
+            (g, ones(bits).map(|i| (K::from_index(i), &values[i])))
+        }))
+    }
+}
+
+impl<G, K, V> Clone for BilevelDenseMap<G, K, V>
+where
+    G: Hash + Eq + Clone,
+    K: DenseKey,
+    V: Clone,
+{
+    fn clone(&self) -> Self {
+        Self { groups: self.groups.clone(), domain: self.domain, pairs: self.pairs, key: std::marker::PhantomData }
+    }
+}
+
+impl<G, K, V> PartialEq for BilevelDenseMap<G, K, V>
+where
+    G: Hash + Eq,
+    K: DenseKey,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        // The payloads where no key is present are not compared.
+        self.domain == other.domain
+            && self.pairs == other.pairs
+            && self.groups.len() == other.groups.len()
+            && self.groups.iter().all(|(g, (bits, values))| {
+                other.groups.get(g).is_some_and(|(other_bits, other_values)| {
+                    bits == other_bits && ones(bits).all(|i| values[i] == other_values[i])
+                })
+            })
+    }
+}
+
+impl<G, K, V> Eq for BilevelDenseMap<G, K, V>
+where
+    G: Hash + Eq,
+    K: DenseKey,
+    V: Eq,
+{}
@@ -1,4 +1,6 @@
-use std::{collections::{HashMap, HashSet}, hash::Hash};
+use std::{borrow::Borrow, cmp::Ordering, collections::{HashMap, HashSet}, hash::Hash, mem::size_of};
+use crate::{Capacity, Derived, HeapSize, core::{self, DefaultHashing, Inline, KeyHasher}};
+use super::{BilevelMap, map::Borrowed};
 
 /// A collection of distinct pairs (g, k) grouped by g.
 /// 
@@ -7,29 +9,28 @@ use std::{collections::{HashMap, HashSet}, hash::Hash};
 /// 
 /// G is the type of the group key.
 /// K is the type of the remaining key.
-pub struct BilevelSet<G, K>
+/// H is the hasher for both keys, such as IdentityHashing for integer ids.
+pub struct BilevelSet<G, K, H = DefaultHashing>
 where
 G: Hash + Eq,
 K: Hash + Eq,
+H: KeyHasher,
 {
-    data: HashMap<G, HashSet<K>>,
-    per_group: usize,
+    inner: core::BilevelSet<G, K, Inline<H>>,
+    pivoted: Derived<Box<BilevelSet<K, G, H>>>,
 }
 
 impl<G, K> BilevelSet<G, K>
 where
-    G: Hash + Eq + Copy + 'static,
-    K: Hash + Eq + Copy,
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
 {
     /// Create a new collection.
     /// 
     /// No initial capacity is allocated, and capacity for a few items
     /// is allocated for each new group key found.
     pub fn new() -> Self {
-        Self {
-            data: HashMap::new(),
-            per_group: 4,
-        }
+        Self::from_core(core::BilevelSet::new())
     }
 
     /// Create a new collection with the specified capacity.
@@ -38,10 +39,29 @@ where
     /// per_group: The number of items to allocate capacity for when a new
     ///     group key is found.
     pub fn with_capacity(groups: usize, per_group: usize) -> Self {
-        Self {
-            data: HashMap::with_capacity(groups),
-            per_group
-        }
+        Self::with_capacity_and_hashing(groups, per_group)
+    }
+}
+
+impl<G, K, H> BilevelSet<G, K, H>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+    /// Create a new collection with the specified capacity, hashing the
+    /// keys with H.
+    /// 
+    /// groups: The number of groups to allocate space for.
+    /// per_group: The number of items to allocate capacity for when a new
+    ///     group key is found.
+    pub fn with_capacity_and_hashing(groups: usize, per_group: usize) -> Self {
+        Self::from_core(core::BilevelSet::with_capacity(Capacity { groups, per_group, agg_keys: 0 }))
+    }
+
+    /// Wrap a generic collection.
+    fn from_core(inner: core::BilevelSet<G, K, Inline<H>>) -> Self {
+        Self { inner, pivoted: Derived::new() }
     }
 
     /// Insert a key pair found into the collection.
@@ -51,9 +71,8 @@ where
     /// 
     /// Return false if the key was already present, otherwise true.
     pub fn insert(&mut self, g: G, k: K) -> bool {
-        self.data.entry(g)
-            .or_insert(HashSet::with_capacity(self.per_group))
-            .insert(k)
+        self.invalidate();
+        self.inner.insert(&g, &k)
     }
 
     /// List the pairs currently in the collection without consuming
@@ -61,33 +80,685 @@ where
     /// 
     /// Pairs are grouped by g.
     /// 
-    /// Since G and K are Copy types, owned values are returned.
-    pub fn iter<'a>(&'a self) -> impl Iterator<Item = (G, K)> + 'a {
-        self.data.iter()
-            .flat_map(|(g, inner)| inner.iter().map(|k| (*g, *k)))
+    /// Since G and K are cheap to clone, owned values are returned.
+    pub fn iter(&self) -> SetIter<'_, G, K, H> {
+        SetIter { inner: self.inner.iter() }
+    }
+
+    /// Call f once for each group, with the group key and the aggregation
+    /// keys of the group, as iter lists them.
+    pub fn for_each_group<F>(&self, mut f: F)
+    where
+        F: FnMut(G, GroupKeys<'_, K, H>),
+    {
+        self.inner.for_each_group(|g, keys| f(g.clone(), GroupKeys { inner: keys }));
     }
 
     /// List the pairs in the collection and consume the collection.
     /// 
     /// Pairs are grouped by g.
-    pub fn into_iter(self) -> impl Iterator<Item = (G, K)>{
-        self.data.into_iter()
-            .flat_map(|(g, inner)| inner.into_iter().map(move |k| (g, k)))
+    pub fn into_iter(self) -> SetIntoIter<G, K, H> {
+        SetIntoIter { inner: self.inner.into_iter() }
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (G, K)>
+    where
+        G: Ord,
+        K: Ord,
+    {
+        self.iter_sorted_by(Ord::cmp)
+    }
+
+    /// List the pairs currently in the collection, sorted using the
+    /// specified comparison function.
+    pub fn iter_sorted_by<F>(&self, compare: F) -> impl Iterator<Item = (G, K)>
+    where
+        F: FnMut(&(G, K), &(G, K)) -> Ordering,
+    {
+        let mut pairs: Vec<_> = self.iter().collect();
+        pairs.sort_unstable_by(compare);
+        pairs.into_iter()
+    }
+
+    /// List the pairs in the collection, sorted by the group key and then
+    /// by the aggregation key, consuming the collection.
+    pub fn into_iter_sorted(self) -> impl Iterator<Item = (G, K)>
+    where
+        G: Ord,
+        K: Ord,
+    {
+        self.inner.into_iter_sorted()
+    }
+
+    /// Convert into a map of the groups, consuming the collection.
+    pub fn into_grouped(self) -> HashMap<G, HashSet<K>> {
+        self.inner.into_grouped()
+    }
+
+    /// List the pairs currently in the collection, as iter does, but also
+    /// list each empty group once, with no aggregation key.
+    pub fn iter_with_empty(&self) -> impl Iterator<Item = (G, Option<K>)> + '_ {
+        self.inner.iter_with_empty().map(|(g, k)| (g.clone(), k.cloned()))
+    }
+    /// Declare group keys that are expected to appear, so that they are
+    /// listed by iter_with_empty even if no pairs are found for them.
+    pub fn declare_groups(&mut self, groups: impl IntoIterator<Item = G>) {
+        self.invalidate();
+        for g in groups {
+            self.inner.declare_groups([&g]);
+        }
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// groups.
+    pub fn reserve_groups(&mut self, additional: usize) {
+        self.inner.reserve_groups(additional);
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// distinct aggregation keys.
+    pub fn reserve_agg_keys(&mut self, additional: usize) {
+        self.inner.reserve_agg_keys(additional);
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// pairs in a group, declaring the group if new.
+    /// 
+    /// The group key can be given in any form it borrows as, such as &str
+    /// for String keys.
+    pub fn reserve_in_group<Q>(&mut self, g: &Q, additional: usize)
+    where
+        G: Borrow<Q>,
+        Q: ToOwned<Owned = G> + Hash + Eq + ?Sized,
+    {
+        self.invalidate();
+        self.inner.reserve_in_group_with(&Borrowed(g), || g.to_owned(), additional);
+    }
+
+    /// Choose whether each new group is given capacity for the average
+    /// size of the groups found so far, rather than the per_group
+    /// capacity alone.
+    /// 
+    /// The per_group capacity is still the least given. The choice is
+    /// not kept in snapshots.
+    pub fn adapt_per_group(&mut self, adaptive: bool) {
+        self.inner.adapt_per_group(adaptive);
+    }
+
+    /// Estimate the memory held by the collection, in bytes, including
+    /// the memory owned by the keys, such as the contents of strings.
+    /// 
+    /// This walks every group, so takes time in proportion to the number
+    /// of pairs.
+    pub fn estimated_heap_bytes(&self) -> usize
+    where
+        G: HeapSize,
+        K: HeapSize,
+    {
+        // The pivoted view is counted too, while it is held.
+        let pivoted = self.pivoted.get().map_or(0, |p| size_of::<BilevelSet<K, G, H>>() + p.estimated_heap_bytes());
+        self.inner.estimated_heap_bytes() + pivoted
+    }
+
+    /// Count the pairs currently in the collection.
+    /// 
+    /// The count is kept as pairs are added, so this takes constant time.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Check whether the collection holds no pairs, although it may hold
+    /// empty groups.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Count the pairs of the specified group, which is 0 if the group is
+    /// not present.
+    /// 
+    /// The group key can be given in any form it borrows as.
+    pub fn group_len<Q>(&self, g: &Q) -> usize
+    where
+        G: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.inner.group_len(&Borrowed(g))
+    }
+
+    /// List the group keys currently in the collection, with the number
+    /// of aggregation keys in each group.
+    pub fn group_sizes(&self) -> impl Iterator<Item = (G, usize)> + '_ {
+        self.inner.group_sizes().map(|(g, n)| (g.clone(), n))
+    }
+
+    /// Count the groups by their number of aggregation keys, such as to
+    /// choose the capacity per group.
+    /// 
+    /// buckets: the greatest size counted in each bucket, in ascending
+    ///     order. A final bucket counts the groups larger than the last of
+    ///     these.
+    /// 
+    /// Return the number of groups in each bucket.
+    pub fn size_histogram(&self, buckets: &[usize]) -> Vec<usize> {
+        self.inner.size_histogram(buckets)
+    }
+
+    /// Render the collection as a text table, with a heading for each
+    /// group followed by its aggregation keys in aligned columns,
+    /// such as for debugging or the output of a command.
+    /// 
+    /// The groups, and the keys of each group, are listed in the order of
+    /// their text, so that the same contents are always rendered the same
+    /// way.
+    pub fn render_table(&self, options: &crate::TableOptions) -> String
+    where
+        G: std::fmt::Display,
+        K: std::fmt::Display,
+    {
+        self.inner.render_table(options)
+    }
+
+    /// Measure the overlap of the aggregation keys of two groups, as their
+    /// Jaccard similarity: the number of keys they share divided by the
+    /// number of keys in either.
+    /// 
+    /// Return None if either group is not present.
+    pub fn similarity(&self, g1: G, g2: G) -> Option<f64> {
+        self.inner.similarity(&g1, &g2)
+    }
+
+    /// List the n groups whose aggregation keys overlap most with those of
+    /// the specified group, with their Jaccard similarity, greatest first.
+    /// 
+    /// Groups that share no keys with the group are not listed, nor is the
+    /// group itself.
+    pub fn most_similar(&self, g: G, n: usize) -> Vec<(G, f64)> {
+        self.inner.most_similar(&g, n).into_iter().map(|(g, s)| (g.clone(), s)).collect()
+    }
+
+    /// Find groups with identical sets of aggregation keys.
+    /// 
+    /// Return each class of two or more equivalent groups.
+    pub fn dedup_groups(&self) -> Vec<Vec<G>> {
+        self.inner.dedup_groups().into_iter()
+            .map(|class| class.into_iter().cloned().collect())
+            .collect()
+    }
+
+    /// Discard the values derived from the current contents.
+    fn invalidate(&mut self) {
+        self.pivoted.invalidate();
     }
 }
 
-impl<G, K> BilevelSet<G, K>
+impl<G, K, H> Default for BilevelSet<G, K, H>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+    fn default() -> Self {
+        Self::from_core(core::BilevelSet::new())
+    }
+}
+
+impl<G, K, H> BilevelSet<G, K, H>
 where
-    G: Hash + Eq + Copy + 'static,
-    K: Hash + Eq + Copy + 'static,
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
 {
     /// Copy the data into a new collection that groups by the aggregation key.
-    pub fn pivot(&self) -> BilevelSet<K, G> {
-        // Pre-allocate capacity assuming approximate symmetry.
-        let mut pivoted = BilevelSet::with_capacity(self.data.len(), self.per_group);
-        for (g, k) in self.iter() {
-            pivoted.insert(k, g);
+    pub fn pivot(&self) -> BilevelSet<K, G, H> {
+        BilevelSet::from_core(self.inner.pivot())
+    }
+
+    /// Get a view of the data grouped by the aggregation key.
+    /// 
+    /// The view is created on first use, and kept until the collection
+    /// is next modified.
+    pub fn pivoted(&self) -> &BilevelSet<K, G, H> {
+        self.pivoted.get_or_init(|| Box::new(self.pivot()))
+    }
+
+    /// Count how often each two aggregation keys appear in the same group,
+    /// as in a basket analysis.
+    /// 
+    /// Each two distinct keys found together are listed both ways, so that
+    /// the keys found with a key a are those of the group a. A key is not
+    /// counted with itself.
+    pub fn cooccurrence(&self) -> BilevelMap<K, K, usize, H> {
+        BilevelMap::from_core(self.inner.cooccurrence())
+    }
+}
+
+impl<G, K> From<HashMap<G, HashSet<K>>> for BilevelSet<G, K>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+{
+    fn from(data: HashMap<G, HashSet<K>>) -> Self {
+        Self::from_core(data.into())
+    }
+}
+
+impl<G, K, H> std::fmt::Debug for BilevelSet<G, K, H>
+where
+    G: Hash + Eq + std::fmt::Debug,
+    K: Hash + Eq + std::fmt::Debug,
+    H: KeyHasher,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<G, K, H> Clone for BilevelSet<G, K, H>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone(), pivoted: Derived::new() }
+    }
+}
+
+impl<G, K, H> PartialEq for BilevelSet<G, K, H>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+    H: KeyHasher,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<G, K, H> Eq for BilevelSet<G, K, H>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+    H: KeyHasher,
+{
+}
+
+#[cfg(feature = "derive")]
+impl<G, K, H> BilevelSet<G, K, H>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+    /// Insert the key pair of a row into the collection.
+    /// 
+    /// Return false if the key was already present, otherwise true.
+    pub fn insert_row<R: crate::BilevelKey<Group = G, Agg = K>>(&mut self, row: &R) -> bool {
+        self.insert(row.group(), row.agg())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<G, K, H> serde::Serialize for BilevelSet<G, K, H>
+where
+    G: Hash + Eq + serde::Serialize,
+    K: Hash + Eq + serde::Serialize,
+    H: KeyHasher,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.inner.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, G, K, H> serde::Deserialize<'de> for BilevelSet<G, K, H>
+where
+    G: Hash + Eq + Clone + serde::Deserialize<'de>,
+    K: Hash + Eq + Clone + serde::Deserialize<'de>,
+    H: KeyHasher,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashMap::deserialize(deserializer).map(|data| Self::from_core(data.into()))
+    }
+}
+
+#[cfg(feature = "csv")]
+impl<G, K, H> BilevelSet<G, K, H>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+    /// Write the collection as delimited text, such as CSV or TSV, with a
+    /// row for each pair. The value column, if listed, is left empty.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn write_csv(&self, writer: impl std::io::Write, options: &crate::CsvOptions) -> std::io::Result<()>
+    where
+        G: std::fmt::Display,
+        K: std::fmt::Display,
+    {
+        self.inner.write_csv(writer, options)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<G, K, H> BilevelSet<G, K, H>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+    /// Write the collection as a JSON object, from each group key to an
+    /// array of its aggregation keys.
+    /// 
+    /// The groups, and the keys of each group, are written in order, so
+    /// that the same contents are always written the same way.
+    pub fn to_json_grouped(&self, writer: impl std::io::Write) -> Result<(), serde_json::Error>
+    where
+        G: Ord + serde::Serialize,
+        K: Ord + serde::Serialize,
+    {
+        self.inner.to_json_grouped(writer)
+    }
+}
+
+#[cfg(feature = "snapshot")]
+impl<G, K, H> BilevelSet<G, K, H>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+    /// Write a binary snapshot of the collection.
+    pub fn write_snapshot(&self, writer: impl std::io::Write) -> Result<(), crate::SnapshotError>
+    where
+        G: serde::Serialize,
+        K: serde::Serialize,
+    {
+        self.inner.write_snapshot(writer)
+    }
+
+    /// Read a collection from a binary snapshot written by write_snapshot.
+    pub fn read_snapshot(reader: impl std::io::Read) -> Result<Self, crate::SnapshotError>
+    where
+        G: serde::de::DeserializeOwned,
+        K: serde::de::DeserializeOwned,
+    {
+        core::BilevelSet::read_snapshot(reader).map(Self::from_core)
+    }
+
+    /// Read a collection from a binary snapshot written by write_snapshot,
+    /// keeping the groups read before any error in reading the groups.
+    /// 
+    /// Return the groups that could be read, with a report of those that
+    /// could not, if any. A snapshot whose header or other contents cannot
+    /// be read is still an error.
+    pub fn read_snapshot_partial(
+        reader: impl std::io::Read,
+    ) -> Result<(Self, Option<crate::MissingGroups>), crate::SnapshotError>
+    where
+        G: serde::de::DeserializeOwned,
+        K: serde::de::DeserializeOwned,
+    {
+        let (inner, missing) = core::BilevelSet::read_snapshot_partial(reader)?;
+        Ok((Self::from_core(inner), missing))
+    }
+}
+
+#[cfg(feature = "replay")]
+impl<G, K, H> crate::Replay<G, K, ()> for BilevelSet<G, K, H>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+    fn apply(&mut self, mutation: crate::Mutation<&G, &K, &()>) {
+        match mutation {
+            crate::Mutation::DeclareGroup(g) => self.declare_groups([g.clone()]),
+            crate::Mutation::Insert(g, k) | crate::Mutation::Update(g, k, _) => {
+                self.insert(g.clone(), k.clone());
+            }
+            crate::Mutation::Remove(..) | crate::Mutation::RemoveGroup(_) => {
+                self.invalidate();
+                crate::Replay::apply(&mut self.inner, mutation);
+            }
         }
-        pivoted
     }
-}
\ No newline at end of file
+}
+
+#[cfg(feature = "tenant")]
+impl<G, K, H> crate::TenantData for BilevelSet<G, K, H>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+    type Item<'a> = (G, K) where Self: 'a;
+
+    fn pairs(&self) -> impl Iterator<Item = (G, K)> {
+        self.iter()
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        crate::TenantData::memory_usage(&self.inner)
+    }
+}
+
+impl<'a, G, K, H> IntoIterator for &'a BilevelSet<G, K, H>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+    type Item = (G, K);
+    type IntoIter = SetIter<'a, G, K, H>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<G, K, H> IntoIterator for BilevelSet<G, K, H>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+    type Item = (G, K);
+    type IntoIter = SetIntoIter<G, K, H>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        BilevelSet::into_iter(self)
+    }
+}
+
+/// Lists the pairs of a BilevelSet, grouped by g, with owned keys.
+/// 
+/// This is returned by BilevelSet::iter.
+pub struct SetIter<'a, G, K, H = DefaultHashing>
+where
+    K: Hash + Eq,
+    H: KeyHasher,
+{
+    inner: core::SetIter<'a, G, K, Inline<H>>,
+}
+
+impl<G, K, H> Iterator for SetIter<'_, G, K, H>
+where
+    G: Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+    type Item = (G, K);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(g, k)| (g.clone(), k.clone()))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<G, K, H> ExactSizeIterator for SetIter<'_, G, K, H>
+where
+    G: Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+}
+
+impl<G, K, H> std::iter::FusedIterator for SetIter<'_, G, K, H>
+where
+    G: Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+}
+
+/// Lists the pairs of a BilevelSet, grouped by g, consuming the
+/// collection.
+/// 
+/// This is returned by BilevelSet::into_iter.
+pub struct SetIntoIter<G, K, H = DefaultHashing>
+where
+    K: Hash + Eq,
+    H: KeyHasher,
+{
+    inner: core::SetIntoIter<G, K, Inline<H>>,
+}
+
+impl<G, K, H> Iterator for SetIntoIter<G, K, H>
+where
+    G: Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+    type Item = (G, K);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<G, K, H> ExactSizeIterator for SetIntoIter<G, K, H>
+where
+    G: Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+}
+
+impl<G, K, H> std::iter::FusedIterator for SetIntoIter<G, K, H>
+where
+    G: Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+}
+
+/// Lists the aggregation keys of one group of a BilevelSet, as owned
+/// values.
+/// 
+/// This is given to the closure passed to BilevelSet::for_each_group.
+pub struct GroupKeys<'a, K, H = DefaultHashing>
+where
+    K: Hash + Eq,
+    H: KeyHasher,
+{
+    inner: core::GroupKeys<'a, K, Inline<H>>,
+}
+
+impl<K, H> Iterator for GroupKeys<'_, K, H>
+where
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+    type Item = K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().cloned()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K, H> ExactSizeIterator for GroupKeys<'_, K, H>
+where
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+}
+
+impl<K, H> std::iter::FusedIterator for GroupKeys<'_, K, H>
+where
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+}
+
+#[cfg(feature = "rayon")]
+impl<G, K, H> BilevelSet<G, K, H>
+where
+    G: Hash + Eq + Clone + Send + Sync,
+    K: Hash + Eq + Clone + Send + Sync,
+    H: KeyHasher,
+{
+    /// List the pairs currently in the collection in parallel, splitting
+    /// the work between threads by group.
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (G, K)> + '_ {
+        use rayon::prelude::*;
+        self.inner.par_iter().map(|(g, k)| (g.clone(), k.clone()))
+    }
+}
+
+impl<G, K, H> crate::BilevelSetOps for BilevelSet<G, K, H>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    H: KeyHasher,
+{
+    type Group = G;
+    type Key = K;
+
+    fn insert_pair(&mut self, g: G, k: K) -> bool {
+        self.insert(g, k)
+    }
+
+    fn iter_pairs(&self) -> impl Iterator<Item = (&G, &K)> {
+        self.inner.iter()
+    }
+
+    fn group_sizes(&self) -> impl Iterator<Item = (&G, usize)> {
+        self.inner.group_sizes()
+    }
+
+    fn group_len(&self, g: &G) -> usize {
+        self.inner.group_len(g)
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
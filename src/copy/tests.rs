@@ -188,4 +188,606 @@ pub fn test_map() {
             }
             assert!(!set.contains(&g));
         }
-}
\ No newline at end of file
+}
+#[test]
+pub fn test_regroup() {
+    let mut map: BilevelMap<i32, i32, u32> = BilevelMap::new();
+    for (g, k) in [(2, 1), (3, 1), (4, 2), (5, 1), (5, 2)] {
+        *map.add_or_get(g, k) += 1;
+    }
+    // Collapse the groups by parity, summing the counts.
+    let regrouped = map.regroup_by(|g| g % 2, |v, w| *v += w);
+    let mut result: Vec<_> = regrouped.into_iter().collect();
+    result.sort();
+    assert_eq!(result, [(0, 1, 1), (0, 2, 1), (1, 1, 2), (1, 2, 1)]);
+}
+
+#[test]
+pub fn test_dedup_groups() {
+    let mut set = BilevelSet::new();
+    let mut map: BilevelMap<i32, i32, u32> = BilevelMap::new();
+    for (g, k) in [(1, 1), (1, 2), (2, 2), (2, 1), (3, 1), (4, 1), (4, 2), (4, 2)] {
+        set.insert(g, k);
+        *map.add_or_get(g, k) += 1;
+    }
+    let mut classes = set.dedup_groups();
+    classes.iter_mut().for_each(|class| class.sort());
+    assert_eq!(classes, [vec![1, 2, 4]]);
+    let mut classes = map.dedup_groups();
+    classes.iter_mut().for_each(|class| class.sort());
+    assert_eq!(classes, [vec![1, 2, 4]]);
+    // Group 4 has a different count for key 2.
+    let mut classes = map.dedup_groups_with_values();
+    classes.iter_mut().for_each(|class| class.sort());
+    assert_eq!(classes, [vec![1, 2]]);
+}
+
+#[test]
+pub fn test_map_values() {
+    let mut map: BilevelMap<i32, i32, u32> = BilevelMap::new();
+    for (g, k) in [(1, 1), (1, 1), (1, 2), (2, 1)] {
+        *map.add_or_get(g, k) += 1;
+    }
+    let mapped = map.map_values(|&g, &k, v| format!("{}:{}={}", g, k, v));
+    let mut result: Vec<_> = mapped.into_iter().map(|(_, _, v)| v).collect();
+    result.sort();
+    assert_eq!(result, ["1:1=2", "1:2=1", "2:1=1"]);
+}
+
+#[test]
+pub fn test_flat() {
+    let flat = std::collections::HashMap::from([((1, 1), 2), ((1, 2), 1), ((2, 1), 1)]);
+    let map: BilevelMap<i32, i32, u32> = BilevelMap::from_flat(flat.clone());
+    let mut result: Vec<_> = map.iter().map(|(g, k, &v)| (g, k, v)).collect();
+    result.sort();
+    assert_eq!(result, [(1, 1, 2), (1, 2, 1), (2, 1, 1)]);
+    assert_eq!(map.into_flat(), flat);
+}
+
+#[test]
+pub fn test_expand() {
+    let mut map: BilevelMap<i32, i32, i32> = BilevelMap::new();
+    *map.add_or_get(2, 1) += 2;
+    *map.add_or_get(1, 2) += 1;
+    *map.add_or_get(1, 1) += 3;
+    // Negative counts are treated as zero.
+    *map.add_or_get(1, 3) -= 1;
+    assert_eq!(map.expand().count(), 6);
+    let result: Vec<_> = map.expand_sorted().collect();
+    assert_eq!(result, [(1, 1), (1, 1), (1, 1), (1, 2), (2, 1), (2, 1)]);
+}
+
+#[test]
+pub fn test_iter_with_empty() {
+    let mut set = BilevelSet::new();
+    let mut map: BilevelMap<i32, i32, u32> = BilevelMap::new();
+    set.declare_groups([1, 2, 3]);
+    map.declare_groups([1, 2, 3]);
+    set.insert(1, 1);
+    set.insert(4, 1);
+    *map.add_or_get(1, 1) += 1;
+    *map.add_or_get(4, 1) += 1;
+    let mut result: Vec<_> = set.iter_with_empty().collect();
+    result.sort();
+    assert_eq!(result, [(1, Some(1)), (2, None), (3, None), (4, Some(1))]);
+    let mut result: Vec<_> = map.iter_with_empty().collect();
+    result.sort();
+    assert_eq!(result, [(1, Some((1, &1))), (2, None), (3, None), (4, Some((1, &1)))]);
+    // Empty groups are not listed by iter.
+    assert_eq!(set.iter().count(), 2);
+    assert_eq!(map.iter().count(), 2);
+}
+
+#[test]
+pub fn test_nested() {
+    use std::collections::HashMap;
+    let grouped = HashMap::from([(1, HashSet::from([1, 2])), (2, HashSet::from([1]))]);
+    let set = BilevelSet::from(grouped.clone());
+    assert_eq!(set.iter().count(), 3);
+    assert_eq!(set.into_grouped(), grouped);
+    let nested = HashMap::from([(1, HashMap::from([(1, 2), (2, 1)])), (2, HashMap::from([(1, 1)]))]);
+    let map: BilevelMap<i32, i32, u32> = BilevelMap::from(nested.clone());
+    assert_eq!(map.iter().count(), 3);
+    assert_eq!(map.into_nested(), nested);
+}
+
+#[test]
+pub fn test_derived() {
+    let mut map: BilevelMap<i32, i32, u32> = BilevelMap::new();
+    *map.add_or_get(1, 2) += 1;
+    *map.add_or_get(2, 2) += 1;
+    assert_eq!(map.pivoted().iter().count(), 2);
+    let mut classes = map.dedup_groups();
+    classes.iter_mut().for_each(|class| class.sort());
+    assert_eq!(classes, [vec![1, 2]]);
+    // Modifying the map discards the derived values.
+    *map.add_or_get(1, 3) += 1;
+    assert_eq!(map.pivoted().iter().count(), 3);
+    assert_eq!(*map.pivoted().iter().find(|&(k, g, _)| (k, g) == (3, 1)).unwrap().2, 1);
+    assert!(map.dedup_groups().is_empty());
+}
+
+#[test]
+pub fn test_sorted() {
+    let mut set = BilevelSet::new();
+    let mut map: BilevelMap<i32, i32, u32> = BilevelMap::new();
+    for (g, k) in [(2, 1), (1, 3), (1, 1), (2, 0)] {
+        set.insert(g, k);
+        *map.add_or_get(g, k) += 1;
+    }
+    let expected = [(1, 1), (1, 3), (2, 0), (2, 1)];
+    assert!(set.iter_sorted().eq(expected));
+    assert!(map.iter_sorted().map(|(g, k, _)| (g, k)).eq(expected));
+    // Sort by descending aggregation key.
+    let result: Vec<_> = set.iter_sorted_by(|a, b| b.1.cmp(&a.1).then(a.0.cmp(&b.0))).collect();
+    assert_eq!(result, [(1, 3), (1, 1), (2, 1), (2, 0)]);
+    assert!(set.into_iter_sorted().eq(expected));
+    assert!(map.into_iter_sorted().map(|(g, k, _)| (g, k)).eq(expected));
+}
+
+#[test]
+pub fn test_send_sync() {
+    // Fails to compile if the collections cannot be shared with, or moved
+    // into, worker threads.
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<BilevelSet<u32, u32>>();
+    assert_send_sync::<BilevelMap<u32, u32, u64>>();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+pub fn test_serde() {
+    let mut map: BilevelMap<i32, i32, u32> = BilevelMap::new();
+    *map.add_or_get(1, 2) += 3;
+    let json = serde_json::to_string(&map).unwrap();
+    assert_eq!(json, r#"{"1":{"2":3}}"#);
+    let map: BilevelMap<i32, i32, u32> = serde_json::from_str(r#"{"1":{"2":3,"4":5},"6":{}}"#).unwrap();
+    let mut result: Vec<_> = map.iter_with_empty().map(|(g, p)| (g, p.map(|(k, &v)| (k, v)))).collect();
+    result.sort();
+    assert_eq!(result, [(1, Some((2, 3))), (1, Some((4, 5))), (6, None)]);
+    let set: BilevelSet<i32, i32> = serde_json::from_str(r#"{"1":[2,4]}"#).unwrap();
+    let json = serde_json::to_string(&set).unwrap();
+    let round_trip: BilevelSet<i32, i32> = serde_json::from_str(&json).unwrap();
+    assert!(round_trip.iter_sorted().eq([(1, 2), (1, 4)]));
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+pub fn test_snapshot() {
+    let mut map: BilevelMap<i32, i32, u32> = BilevelMap::new();
+    for (g, k) in [(1, 1), (1, 2), (2, 1), (1, 1)] {
+        *map.add_or_get(g, k) += 1;
+    }
+    let mut buf = Vec::new();
+    map.write_snapshot(&mut buf).unwrap();
+    let restored: BilevelMap<i32, i32, u32> = BilevelMap::read_snapshot(&buf[..]).unwrap();
+    assert!(restored.iter_sorted().eq(map.iter_sorted()));
+    // Data that is not a snapshot is rejected.
+    buf[0] = b'X';
+    assert!(matches!(
+        BilevelMap::<i32, i32, u32>::read_snapshot(&buf[..]),
+        Err(crate::SnapshotError::BadMagic)
+    ));
+}
+
+#[cfg(feature = "replay")]
+#[test]
+pub fn test_replay() {
+    use crate::{Logged, Mutation, Replay};
+    let mut logged = Logged::new(BilevelMap::<i32, i32, u32>::new(), Vec::new());
+    logged.declare_group(3);
+    logged.update(1, 1, 2);
+    logged.insert(1, 2);
+    logged.update(1, 1, 1);
+    logged.update(2, 1, 1);
+    logged.remove(1, 2);
+    logged.remove_group(2);
+    let (map, log) = logged.into_parts();
+    assert_eq!(log[1], Mutation::Update(1, 1, 2));
+    assert_eq!(log[6], Mutation::RemoveGroup(2));
+    assert!(map.iter_sorted().eq([(1, 1, &3)]));
+    #[cfg(feature = "serde")]
+    {
+        let read: Vec<Mutation<i32, i32, u32>> = serde_json::from_str(&serde_json::to_string(&log).unwrap()).unwrap();
+        assert_eq!(read, log);
+    }
+    let mut rebuilt = BilevelMap::new();
+    rebuilt.replay(log);
+    assert!(rebuilt.iter_sorted().eq(map.iter_sorted()));
+    assert!(rebuilt.iter_with_empty().any(|(g, pair)| g == 3 && pair.is_none()));
+    assert!(!rebuilt.iter_with_empty().any(|(g, _)| g == 2));
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+pub fn test_snapshot_partial() {
+    let mut set: BilevelSet<i32, i32> = BilevelSet::new();
+    for (g, k) in [(1, 1), (1, 2), (2, 1), (3, 3)] {
+        set.insert(g, k);
+    }
+    let mut buf = Vec::new();
+    set.write_snapshot(&mut buf).unwrap();
+    let (restored, missing) = BilevelSet::<i32, i32>::read_snapshot_partial(&buf[..]).unwrap();
+    assert!(restored.iter_sorted().eq(set.iter_sorted()));
+    assert!(missing.is_none());
+    // Losing the end of the snapshot loses only the last group.
+    buf.truncate(buf.len() - 2);
+    assert!(BilevelSet::<i32, i32>::read_snapshot(&buf[..]).is_err());
+    let (restored, missing) = BilevelSet::<i32, i32>::read_snapshot_partial(&buf[..]).unwrap();
+    let missing = missing.unwrap();
+    assert_eq!((missing.expected, missing.recovered, missing.missing()), (3, 2, 1));
+    assert!(restored.iter().all(|pair| set.iter().any(|p| p == pair)));
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+pub fn test_snapshot_partial_length() {
+    // The number of groups ends a snapshot with none, and a damaged one
+    // is reported as groups missing rather than allocated for.
+    let set: BilevelSet<i32, i32> = BilevelSet::new();
+    let mut buf = Vec::new();
+    set.write_snapshot(&mut buf).unwrap();
+    let at = buf.len() - 8;
+    for n in [u64::MAX, 1 << 40] {
+        buf[at..].copy_from_slice(&n.to_le_bytes());
+        let (restored, missing) = BilevelSet::<i32, i32>::read_snapshot_partial(&buf[..]).unwrap();
+        assert!(restored.is_empty());
+        assert_eq!(missing.unwrap().recovered, 0);
+    }
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+pub fn test_delta() {
+    let mut central: BilevelMap<i32, i32, u32> = BilevelMap::new();
+    let mut worker: BilevelMap<i32, i32, u32> = BilevelMap::new();
+    *worker.add_or_get(1, 1) += 2;
+    *worker.add_or_get(1, 2) += 1;
+    central.apply_delta(&worker.take_delta().unwrap(), |v, w| *v = w).unwrap();
+    *worker.add_or_get(1, 1) += 3;
+    let delta = worker.take_delta().unwrap();
+    // A delta holds the current payloads, so applying it twice is harmless.
+    central.apply_delta(&delta, |v, w| *v = w).unwrap();
+    central.apply_delta(&delta, |v, w| *v = w).unwrap();
+    assert!(central.iter_sorted().eq([(1, 1, &5), (1, 2, &1)]));
+    // Nothing has changed since the last delta.
+    central.apply_delta(&worker.take_delta().unwrap(), |v, w| *v = w).unwrap();
+    assert!(central.iter_sorted().eq([(1, 1, &5), (1, 2, &1)]));
+    // A snapshot is not a delta.
+    let mut buf = Vec::new();
+    worker.write_snapshot(&mut buf).unwrap();
+    assert!(matches!(
+        central.apply_delta(&buf, |v, w| *v = w),
+        Err(crate::SnapshotError::BadMagic)
+    ));
+}
+
+#[cfg(feature = "tenant")]
+#[test]
+pub fn test_tenanted() {
+    use crate::Tenanted;
+    let mut agg: Tenanted<u8, BilevelSet<i32, i32>> = Tenanted::new(BilevelSet::new);
+    agg.tenant_mut(1).insert(1, 1);
+    agg.tenant_mut(2).insert(1, 2);
+    agg.tenant_mut(2).insert(1, 3);
+    assert!(agg.iter_tenant(&1).eq([(1, 1)]));
+    assert!(agg.tenant(&3).is_none());
+    // The tenant with more data is estimated to use at least as much memory.
+    assert!(agg.memory_usage(&2) >= agg.memory_usage(&1));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+pub fn test_par_iter() {
+    use rayon::prelude::*;
+    let mut map: BilevelMap<i32, i32, u32> = BilevelMap::new();
+    for g in 0..100 {
+        for k in 0..10 {
+            *map.add_or_get(g, k) += 1;
+        }
+    }
+    map.par_iter_mut().for_each(|(g, _, v)| *v += g as u32);
+    assert_eq!(map.par_iter().map(|(_, _, v)| *v).sum::<u32>(), 1000 + 10 * 4950);
+    let mut pairs: Vec<_> = map.par_iter().map(|(g, k, &v)| (g, k, v)).collect();
+    pairs.sort();
+    assert!(pairs.into_iter().eq(map.iter_sorted().map(|(g, k, &v)| (g, k, v))));
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+pub fn test_par_extend() {
+    use rayon::prelude::*;
+    let mut map: BilevelMap<u32, u32, u64> = BilevelMap::new();
+    *map.add_or_get(0, 0) += 1;
+    map.par_extend((0..10_000u32).into_par_iter().map(|i| (i % 7, i % 3, 1)));
+    assert_eq!(map.iter().map(|(_, _, v)| v).sum::<u64>(), 10_001);
+    assert_eq!(map.iter().count(), 21);
+    // A collector can fold rows of any type.
+    let collector = crate::ShardedCollector::new(
+        BilevelMap::new,
+        |map: &mut BilevelMap<u32, u32, u64>, row: &str| *map.add_or_get(row.len() as u32, 0) += 1,
+        |a: &mut u64, b| *a += b,
+    );
+    let lengths = collector.collect(vec!["a", "bb", "cc", "d", "eee"]);
+    assert!(lengths.iter_sorted().eq([(1, 0, &2), (2, 0, &2), (3, 0, &1)]));
+}
+
+/// A payload with no default, counting values into buckets whose bounds
+/// are only known at run time.
+#[derive(Clone, Debug, PartialEq)]
+struct Histogram {
+    bounds: std::sync::Arc<[u32]>,
+    counts: Vec<u32>,
+}
+
+impl Histogram {
+    fn new(bounds: std::sync::Arc<[u32]>) -> Self {
+        Self { counts: vec![0; bounds.len() + 1], bounds }
+    }
+
+    fn record(&mut self, value: u32) {
+        let i = self.bounds.iter().take_while(|&&b| value >= b).count();
+        self.counts[i] += 1;
+    }
+}
+
+#[test]
+pub fn test_new_with() {
+    // The bounds are read from configuration.
+    let bounds: std::sync::Arc<[u32]> = "10,100,1000".split(',').map(|b| b.parse().unwrap()).collect();
+    let latency = move || Histogram::new(bounds.clone());
+    let mut a = BilevelMap::new_with(latency.clone());
+    let mut b = BilevelMap::with_capacity_and(4, 4, latency);
+    for (g, k, value) in [(1, 1, 5), (1, 1, 50), (1, 2, 5000), (2, 1, 500)] {
+        a.add_or_get(g, k).record(value);
+        b.add_or_get(g, k).record(value);
+    }
+    let counts: Vec<_> = a.iter_sorted().map(|(g, k, h)| (g, k, h.counts.clone())).collect();
+    assert_eq!(counts, [
+        (1, 1, vec![1, 1, 0, 0]),
+        (1, 2, vec![0, 0, 0, 1]),
+        (2, 1, vec![0, 0, 1, 0]),
+    ]);
+    // Derived collections keep the constructor.
+    let mut pivoted = a.pivot();
+    pivoted.add_or_get(3, 3).record(1);
+    assert_eq!(pivoted.add_or_get(3, 3).counts, [1, 0, 0, 0]);
+    let mut regrouped = b.regroup_by(|_| 0, |a, b| {
+        a.counts.iter_mut().zip(b.counts).for_each(|(a, b)| *a += b)
+    });
+    assert_eq!(regrouped.add_or_get(0, 1).counts, [1, 1, 1, 0]);
+    regrouped.add_or_get(0, 9).record(100);
+    assert_eq!(regrouped.add_or_get(0, 9).counts, [0, 0, 1, 0]);
+}
+
+#[test]
+pub fn test_add_or_get_with_key() {
+    let mut map = BilevelMap::new();
+    for (g, k) in [(2u32, 3u32), (2, 3), (4, 5)] {
+        let v = map.add_or_get_with_key(g, k, |&g, &k| vec![0; (g * k) as usize]);
+        v.push(1);
+    }
+    let lengths: Vec<_> = map.iter_sorted().map(|(g, k, v)| (g, k, v.len())).collect();
+    assert_eq!(lengths, [(2, 3, 8), (4, 5, 21)]);
+}
+
+#[test]
+pub fn test_extend_with() {
+    let mut map: BilevelMap<u8, u8, u32> = BilevelMap::new();
+    *map.add_or_get(1, 2) += 1;
+    map.extend_with([(1, 2, 5), (2, 3, 1), (2, 3, 1)], |a, b| *a = (*a).max(b));
+    assert!(map.iter_sorted().eq([(1, 2, &5), (2, 3, &1)]));
+}
+
+#[test]
+pub fn test_top_k() {
+    let mut map: BilevelMap<u8, u8, u32> = BilevelMap::new();
+    map.extend_with([(1, 1, 4), (1, 2, 8), (1, 3, 6), (2, 1, 1)], |a, b| *a += b);
+    assert_eq!(map.top_k(&1, 2, |v| *v), [(2, &8), (3, &6)]);
+    let mut all: Vec<_> = map.top_k_all(1, |v| *v).collect();
+    all.sort();
+    assert_eq!(all, [(1, 2, &8), (2, 1, &1)]);
+}
+
+#[test]
+pub fn test_group_sizes() {
+    let mut set: BilevelSet<u8, u8> = BilevelSet::new();
+    set.insert(1, 1);
+    set.insert(1, 2);
+    set.insert(2, 1);
+    let mut sizes: Vec<_> = set.group_sizes().collect();
+    sizes.sort();
+    assert_eq!(sizes, [(1, 2), (2, 1)]);
+    assert_eq!(set.size_histogram(&[1]), [1, 1]);
+}
+
+#[test]
+pub fn test_len() {
+    let mut map: BilevelMap<u8, u8, u32> = BilevelMap::new();
+    assert!(map.is_empty());
+    *map.add_or_get(1, 1) += 1;
+    *map.add_or_get(1, 2) += 1;
+    *map.add_or_get(1, 1) += 1;
+    assert_eq!((map.len(), map.group_len(&1), map.group_len(&2)), (2, 2, 0));
+}
+
+#[test]
+pub fn test_iter_sorted_by_value() {
+    let mut map: BilevelMap<u8, u8, u32> = BilevelMap::new();
+    map.extend_with([(1, 1, 5), (1, 2, 9), (1, 3, 1)], |a, b| *a += b);
+    assert!(map.iter_sorted_by_value(Ord::cmp).eq([(1, 3, &1), (1, 1, &5), (1, 2, &9)]));
+}
+
+#[test]
+pub fn test_similarity() {
+    let mut set: BilevelSet<u8, u8> = BilevelSet::new();
+    for (g, k) in [(1, 1), (1, 2), (2, 2), (3, 1), (3, 2)] {
+        set.insert(g, k);
+    }
+    assert_eq!(set.similarity(1, 2), Some(0.5));
+    assert_eq!(set.most_similar(1, 2), [(3, 1.0), (2, 0.5)]);
+}
+
+#[test]
+pub fn test_cooccurrence() {
+    let mut set: BilevelSet<u8, u8> = BilevelSet::new();
+    for (g, k) in [(1, 10), (1, 20), (2, 10), (2, 20), (2, 30)] {
+        set.insert(g, k);
+    }
+    let counts = set.cooccurrence();
+    assert!(counts.iter_sorted().filter(|&(a, _, _)| a == 10).eq([(10, 20, &2), (10, 30, &1)]));
+}
+
+#[cfg(feature = "json")]
+#[test]
+pub fn test_to_json_grouped() {
+    let mut map: BilevelMap<u8, u8, u32> = BilevelMap::new();
+    *map.add_or_get(2, 1) += 1;
+    *map.add_or_get(1, 3) += 2;
+    *map.add_or_get(1, 2) += 3;
+    let mut out = Vec::new();
+    map.to_json_grouped(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), r#"{"1":{"2":3,"3":2},"2":{"1":1}}"#);
+}
+
+#[cfg(feature = "roaring")]
+#[test]
+pub fn test_bitmap_set() {
+    let mut bitmaps: BilevelBitmapSet<u16> = BilevelBitmapSet::new();
+    assert!(bitmaps.insert(1, 7));
+    assert!(!bitmaps.insert(1, 7));
+    assert_eq!(bitmaps.extend_group(2, 0..100_000), 100_000);
+    assert_eq!(bitmaps.extend_group(1, [3, 7]), 1);
+    assert_eq!((bitmaps.group_count(), bitmaps.len()), (2, 100_002));
+    assert!(bitmaps.contains(2, 99_999) && !bitmaps.contains(1, 4));
+    assert!(bitmaps.iter_sorted().take(3).eq([(1, 3), (1, 7), (2, 0)]));
+    assert_eq!(bitmaps.similarity(1, 2), Some(2.0 / 100_000.0));
+    // A dense run of ids takes far less than a hash table of them would.
+    assert!(bitmaps.estimated_heap_bytes() < 100_000);
+    let set = bitmaps.to_set();
+    assert_eq!(set.len(), 100_002);
+    assert_eq!(BilevelBitmapSet::from(&set), bitmaps);
+    assert_eq!(bitmaps.remove_group(2).map(|bitmap| bitmap.len()), Some(100_000));
+    assert_eq!(bitmaps.len(), 2);
+}
+
+#[test]
+pub fn test_dense() {
+    let mut set: BilevelDenseSet<u8, u16> = BilevelDenseSet::with_domain(600);
+    for (g, k) in [(1, 200), (1, 404), (2, 200), (1, 200), (1, 599)] {
+        set.insert(g, k);
+    }
+    assert_eq!((set.group_count(), set.len(), set.group_len(1)), (2, 4, 3));
+    assert!(set.contains(2, 200) && !set.contains(2, 404) && !set.contains(2, 1_000));
+    assert!(set.iter_sorted().eq([(1, 200), (1, 404), (1, 599), (2, 200)]));
+
+    let mut map: BilevelDenseMap<u8, u8, u32> = BilevelDenseMap::with_domain(7);
+    *map.add_or_get(1, 3) += 2;
+    *map.add_or_get(1, 0) += 1;
+    *map.add_or_get(1, 3) += 5;
+    assert_eq!(map.get(1, 3), Some(&7));
+    assert_eq!(map.get(1, 4), None);
+    assert!(map.iter_sorted().eq([(1, 0, &1), (1, 3, &7)]));
+    let mut other = BilevelDenseMap::with_domain(7);
+    *other.add_or_get(1, 3) += 7;
+    *other.add_or_get(1, 0) += 1;
+    assert_eq!(map, other);
+}
+
+#[test]
+#[should_panic]
+pub fn test_dense_outside_domain() {
+    let mut set: BilevelDenseSet<u8, u8> = BilevelDenseSet::with_domain(4);
+    set.insert(1, 4);
+}
+
+#[test]
+pub fn test_identity_hashing() {
+    use std::hash::BuildHasher;
+    use crate::core::IdentityHashing;
+    assert_eq!(IdentityHashing::default().hash_one(1_234_u32), 1_234);
+
+    let mut map: BilevelMap<u32, u32, u64, IdentityHashing> = BilevelMap::default();
+    let mut hashed: BilevelMap<u32, u32, u64> = BilevelMap::new();
+    for i in 0..1_000 {
+        *map.add_or_get(i % 7, i % 50) += 1;
+        *hashed.add_or_get(i % 7, i % 50) += 1;
+    }
+    assert_eq!(map.len(), hashed.len());
+    assert!(map.iter_sorted().eq(hashed.iter_sorted()));
+    assert!(map.pivot().iter_sorted().eq(hashed.pivot().iter_sorted()));
+
+    let mut set: BilevelSet<(u8, u16), u64, IdentityHashing> = BilevelSet::with_capacity_and_hashing(2, 4);
+    assert!(set.insert((1, 2), 3) && !set.insert((1, 2), 3));
+    assert!(set.insert((2, 1), 3));
+    assert_eq!(set.group_sizes().count(), 2);
+}
+
+#[test]
+pub fn test_borrowed_group_keys() {
+    // Group keys can borrow from data that lives only as long as the
+    // collection is used.
+    let users: Vec<String> = (0..3).map(|i| format!("user{i}")).collect();
+    let mut map: BilevelMap<&str, u32, u32> = BilevelMap::new();
+    let mut set: BilevelSet<&str, u32> = BilevelSet::new();
+    for (i, user) in users.iter().enumerate() {
+        *map.add_or_get(user, i as u32 % 2) += 1;
+        set.insert(user, i as u32);
+    }
+    assert_eq!(map.len(), 3);
+    assert_eq!(set.group_len("user1"), 1);
+    assert_eq!(map.pivot().group_len(&0), 2);
+}
+
+#[test]
+pub fn test_clone_keys() {
+    use std::sync::Arc;
+    let (u1, u2): (Arc<str>, Arc<str>) = ("u1".into(), "u2".into());
+    let mut map: BilevelMap<Arc<str>, Arc<str>, u32> = BilevelMap::new();
+    *map.add_or_get(u1.clone(), "a".into()) += 1;
+    *map.add_or_get(u1.clone(), "a".into()) += 1;
+    *map.add_or_get(u2.clone(), "b".into()) += 1;
+    let pairs: Vec<_> = map.iter_sorted().map(|(g, k, v)| (g.to_string(), k.to_string(), *v)).collect();
+    assert_eq!(pairs, [("u1".into(), "a".into(), 2), ("u2".into(), "b".into(), 1)]);
+    // Groups are looked up by any form their keys borrow as.
+    assert_eq!(map.group_len("u1"), 1);
+    map.reserve_in_group(&u1, 4);
+    assert_eq!(map.len(), 2);
+    let mut set: BilevelSet<Arc<str>, u32> = BilevelSet::new();
+    assert!(set.insert(u2.clone(), 7) && !set.insert(u2.clone(), 7));
+    assert_eq!(set.pivot().group_len(&7), 1);
+}
+
+#[test]
+pub fn test_named_iterators() {
+    // The iterators can be held in a struct field, between calls.
+    struct Pending<'a> {
+        pairs: SetIter<'a, u32, u32>,
+    }
+    let mut set = BilevelSet::new();
+    for (g, k) in [(1, 2), (1, 3), (2, 2)] {
+        set.insert(g, k);
+    }
+    let mut pending = Pending { pairs: set.iter() };
+    assert_eq!(pending.pairs.len(), 3);
+    pending.pairs.next();
+    assert_eq!(pending.pairs.len(), 2);
+    let mut pairs: Vec<_> = (&set).into_iter().collect();
+    pairs.sort();
+    assert_eq!(pairs, [(1, 2), (1, 3), (2, 2)]);
+    let remaining: SetIntoIter<u32, u32> = set.into_iter();
+    assert_eq!(remaining.len(), 3);
+
+    let mut map: BilevelMap<u32, u32, u32> = BilevelMap::new();
+    *map.add_or_get(1, 2) += 1;
+    *map.add_or_get(2, 2) += 1;
+    assert_eq!(map.pivoted().group_len(&2), 2);
+    // Changing the payloads discards the pivoted view.
+    for (_, _, v) in &mut map {
+        *v *= 10;
+    }
+    let values: Vec<_> = map.pivoted().iter().map(|(_, _, v)| *v).collect();
+    assert_eq!(values, [10, 10]);
+    let remaining: MapIntoIter<u32, u32, u32> = map.into_iter();
+    assert_eq!(remaining.map(|(_, _, v)| v).sum::<u32>(), 20);
+}
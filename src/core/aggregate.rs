@@ -0,0 +1,40 @@
+use std::hash::Hash;
+
+use super::BilevelMap;
+
+/// Aggregate rows into a new BilevelMap, grouped by the group key of
+/// each row.
+///
+/// rows: The rows to aggregate, such as records read from a file.
+/// group_fn: Gets the group key of a row.
+/// key_fn: Gets the aggregation key of a row.
+/// init: A constructor for the payload of each new pair.
+/// fold: Adds a row to the payload of its pair.
+///
+/// # Examples
+/// ```
+/// use bilevel_aggregator::aggregate_by;
+///
+/// let rows = [("web1", 200, 512), ("web1", 500, 0), ("web1", 200, 128)];
+/// let map = aggregate_by(rows, |r| r.0, |r| r.1, || 0, |total, r| *total += r.2);
+/// assert!(map.iter_sorted().eq([(&"web1", &200, &640), (&"web1", &500, &0)]));
+/// ```
+pub fn aggregate_by<R, G, K, V>(
+    rows: impl IntoIterator<Item = R>,
+    mut group_fn: impl FnMut(&R) -> G,
+    mut key_fn: impl FnMut(&R) -> K,
+    init: fn() -> V,
+    mut fold: impl FnMut(&mut V, R),
+) -> BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+{
+    let mut map = BilevelMap::with_fn(init);
+    for row in rows {
+        let g = group_fn(&row);
+        let k = key_fn(&row);
+        fold(map.add_or_get(&g, &k), row);
+    }
+    map
+}
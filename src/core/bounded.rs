@@ -0,0 +1,173 @@
+use std::hash::Hash;
+
+use super::{BilevelMap, Inline, KeyStore, recency::Recency};
+
+/// How a BoundedMap chooses the group to evict when it is full.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Eviction {
+    /// Evict the group whose pairs were least recently added to or
+    /// updated.
+    LeastRecentlyUpdated,
+    /// Evict the group that was found first, however recently it was
+    /// updated.
+    FirstFound,
+}
+
+/// A BilevelMap that holds no more than a fixed number of groups, evicting
+/// a group to make room for each new group once full.
+///
+/// Each evicted group is passed to a callback, along with its pairs, such
+/// as to write it out, so that an unbounded space of group keys can be
+/// aggregated in bounded memory.
+///
+/// The map can be read through get, but can only be added to through
+/// this wrapper, so that the order of the groups is kept.
+///
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// V is the type of the payload.
+/// S is the strategy for storing the aggregation keys. By default each
+/// group holds its own keys, which are dropped with it when it is
+/// evicted; interned keys are kept for the life of the collection, so
+/// only suit a bounded space of aggregation keys.
+pub struct BoundedMap<G, K, V, S = Inline>
+where
+    S: KeyStore<K>,
+{
+    inner: BilevelMap<G, K, V, S>,
+    max_groups: usize,
+    policy: Eviction,
+    /// The order in which the groups were last touched.
+    recency: Recency<()>,
+    on_evict: OnEvict<G, K, V>,
+}
+
+/// A callback passed each evicted group, with its pairs.
+type OnEvict<G, K, V> = Box<dyn FnMut(G, Vec<(K, V)>) + Send + Sync>;
+
+impl<G, K, V, S> BoundedMap<G, K, V, S>
+where
+    G: Hash + Eq,
+    K: Clone,
+    V: Default,
+    S: KeyStore<K>,
+{
+    /// Create a new collection, with the default payload for each new pair,
+    /// that holds no more than the specified number of groups.
+    ///
+    /// Evicted groups are discarded until a callback is set by on_evict.
+    ///
+    /// max_groups: The number of groups to hold, which must not be 0.
+    /// policy: How to choose the group to evict.
+    pub fn with_max_groups(max_groups: usize, policy: Eviction) -> Self {
+        assert!(max_groups > 0, "a bounded map must hold at least one group");
+        Self {
+            inner: BilevelMap::new(),
+            max_groups,
+            policy,
+            recency: Recency::with_capacity(max_groups),
+            on_evict: Box::new(|_, _| ()),
+        }
+    }
+}
+
+impl<G, K, V, S> BoundedMap<G, K, V, S>
+where
+    G: Hash + Eq,
+    K: Clone,
+    S: KeyStore<K>,
+{
+    /// Set the callback to pass each evicted group to, along with the
+    /// aggregation key and payload of each of its pairs.
+    pub fn on_evict<F>(mut self, on_evict: F) -> Self
+    where
+        F: FnMut(G, Vec<(K, V)>) + Send + Sync + 'static,
+    {
+        self.on_evict = Box::new(on_evict);
+        self
+    }
+
+    /// Get a mutable reference to the payload for the specified key pair.
+    ///
+    /// If the key pair is currently not present, a new payload is inserted.
+    /// If the group is new and the collection is full, a group is evicted
+    /// first.
+    pub fn add_or_get<GRef, KRef>(&mut self, g: &GRef, k: &KRef) -> &mut V
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+        KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        self.touch(g);
+        self.inner.add_or_get(g, k)
+    }
+
+    /// Evict every group, oldest first by the policy, leaving the
+    /// collection empty, such as when the input ends.
+    pub fn flush(&mut self) {
+        while !self.recency.is_empty() {
+            self.evict();
+        }
+    }
+
+    /// Get the collection.
+    pub fn get(&self) -> &BilevelMap<G, K, V, S> {
+        &self.inner
+    }
+
+    /// Unwrap the collection, without evicting the groups it holds.
+    pub fn into_inner(self) -> BilevelMap<G, K, V, S> {
+        self.inner
+    }
+
+    /// Get the number of groups the collection holds at most.
+    pub fn max_groups(&self) -> usize {
+        self.max_groups
+    }
+
+    /// Get how the group to evict is chosen.
+    pub fn policy(&self) -> Eviction {
+        self.policy
+    }
+
+    /// Record that a group is about to be updated, evicting another group
+    /// first if the group is new and the collection is full.
+    fn touch<GRef>(&mut self, g: &GRef)
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+    {
+        match self.inner.group_id(g) {
+            Some(id) => {
+                if self.policy == Eviction::LeastRecentlyUpdated {
+                    self.recency.touch(id, ());
+                }
+            },
+            None => {
+                if self.recency.len() >= self.max_groups {
+                    self.evict();
+                }
+                let id = self.inner.declare_group(g);
+                self.recency.insert(id, ());
+            },
+        }
+    }
+
+    /// Evict the oldest group by the policy, passing it to the callback.
+    fn evict(&mut self) {
+        let Some(id) = self.recency.pop_oldest() else {
+            return;
+        };
+        if let Some((g, pairs)) = self.inner.remove_group_by_id(id) {
+            (self.on_evict)(g, pairs);
+        }
+    }
+}
+
+impl<G, K, V, S> std::fmt::Debug for BoundedMap<G, K, V, S>
+where
+    S: KeyStore<K>,
+    BilevelMap<G, K, V, S>: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.inner, f)
+    }
+}
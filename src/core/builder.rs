@@ -0,0 +1,136 @@
+use std::{hash::Hash, marker::PhantomData, time::{Duration, Instant}};
+
+use super::{BilevelMap, BilevelSet, Interned, KeyStore};
+
+/// Statistics of the rows fed to a Builder.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct BuildStats {
+    /// The number of rows fed.
+    pub rows: u64,
+    /// The number of rows whose key pair was new.
+    pub pairs: u64,
+    /// The number of rows whose key pair had already been found.
+    pub duplicates: u64,
+    /// The number of times the builder was fed.
+    pub batches: u64,
+    /// The time spent aggregating the rows fed, including the time taken
+    /// to produce them.
+    pub elapsed: Duration,
+}
+
+impl BuildStats {
+    /// The average number of rows aggregated per second, or 0 if no time
+    /// has been spent.
+    pub fn rows_per_second(&self) -> f64 {
+        let seconds = self.elapsed.as_secs_f64();
+        if seconds > 0.0 { self.rows as f64 / seconds } else { 0.0 }
+    }
+}
+
+/// Aggregates rows into a BilevelMap as they become available, such as
+/// batches read from a stream, keeping statistics along the way.
+///
+/// R is the type of the rows.
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// V is the type of the payload, or () to build a BilevelSet.
+/// S is the strategy for storing the aggregation keys.
+pub struct Builder<R, G, K, V, FG, FK, FF, S = Interned<K>>
+where
+    S: KeyStore<K>,
+{
+    map: BilevelMap<G, K, V, S>,
+    group_fn: FG,
+    key_fn: FK,
+    fold: FF,
+    init: fn() -> V,
+    stats: BuildStats,
+    row: PhantomData<fn(R)>,
+}
+
+impl<R, G, K, V, FG, FK, FF, S> Builder<R, G, K, V, FG, FK, FF, S>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    S: KeyStore<K>,
+    FG: FnMut(&R) -> G,
+    FK: FnMut(&R) -> K,
+    FF: FnMut(&mut V, R),
+{
+    /// Create a builder with an empty collection.
+    ///
+    /// group_fn: Gets the group key of a row.
+    /// key_fn: Gets the aggregation key of a row.
+    /// init: A constructor for the payload of each new pair.
+    /// fold: Adds a row to the payload of its pair.
+    pub fn new(group_fn: FG, key_fn: FK, init: fn() -> V, fold: FF) -> Self {
+        Self {
+            map: BilevelMap::with_fn(init),
+            group_fn,
+            key_fn,
+            fold,
+            init,
+            stats: BuildStats::default(),
+            row: PhantomData,
+        }
+    }
+
+    /// Aggregate a batch of rows.
+    pub fn feed(&mut self, rows: impl IntoIterator<Item = R>) {
+        let start = Instant::now();
+        let init = self.init;
+        for row in rows {
+            let g = (self.group_fn)(&row);
+            let k = (self.key_fn)(&row);
+            let mut new = false;
+            let v = self.map.add_or_get_with_key(&g, &k, |_, _| {
+                new = true;
+                init()
+            });
+            (self.fold)(v, row);
+            self.stats.rows += 1;
+            if new {
+                self.stats.pairs += 1;
+            } else {
+                self.stats.duplicates += 1;
+            }
+        }
+        self.stats.batches += 1;
+        self.stats.elapsed += start.elapsed();
+    }
+
+    /// Get the statistics of the rows fed so far.
+    pub fn stats(&self) -> BuildStats {
+        self.stats
+    }
+
+    /// Get the collection built so far.
+    pub fn map(&self) -> &BilevelMap<G, K, V, S> {
+        &self.map
+    }
+
+    /// Finish building, returning the collection.
+    pub fn into_map(self) -> BilevelMap<G, K, V, S> {
+        self.map
+    }
+}
+
+impl<R, G, K, FG, FK, FF, S> Builder<R, G, K, (), FG, FK, FF, S>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    S: KeyStore<K>,
+{
+    /// Finish building, returning the pairs found as a set.
+    pub fn into_set(self) -> BilevelSet<G, K, S> {
+        let mut set = BilevelSet::with_capacity(crate::Capacity {
+            groups: self.map.group_count(),
+            per_group: 4,
+            agg_keys: 0,
+        });
+        for (g, k, ()) in self.map.iter() {
+            set.insert(g, k);
+        }
+        set
+    }
+}
@@ -0,0 +1,33 @@
+/// The payloads of a map as a sparse matrix in compressed sparse row
+/// form, with a row for each group and a column for each aggregation key,
+/// as read by libraries such as sprs and scipy.
+///
+/// The columns are the ids of the aggregation keys, so the key of each
+/// column can be found by id_mapping.
+#[derive(Clone, Debug, PartialEq)]
+pub struct CsrMatrix<'a, G, V> {
+    /// The group key of each row.
+    pub groups: Vec<&'a G>,
+    /// The start of each row within indices and values, followed by the
+    /// number of values, so that row i is offsets[i]..offsets[i + 1].
+    pub offsets: Vec<usize>,
+    /// The column of each value, in ascending order within each row.
+    pub indices: Vec<usize>,
+    /// The values, row by row.
+    pub values: Vec<V>,
+    /// The number of columns, which is the number of distinct aggregation
+    /// keys, including any found only in groups since removed.
+    pub columns: usize,
+}
+
+impl<G, V> CsrMatrix<'_, G, V> {
+    /// Get the number of rows and columns of the matrix.
+    pub fn shape(&self) -> (usize, usize) {
+        (self.groups.len(), self.columns)
+    }
+
+    /// Count the values stored in the matrix.
+    pub fn nnz(&self) -> usize {
+        self.values.len()
+    }
+}
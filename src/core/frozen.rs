@@ -0,0 +1,324 @@
+use std::{borrow::Borrow, hash::Hash, mem::size_of_val};
+
+use crate::{HeapSize, debug::debug_map, hash};
+
+/// A read-only BilevelMap, laid out for reading once it is built.
+///
+/// The group keys are held in order in one slice, and the aggregation keys
+/// and payloads of all the groups in two more, in order within each group,
+/// so that a group or a pair is found by binary search, and the pairs are
+/// listed without hashing or chasing pointers. With no hash tables, the
+/// layout takes about half the memory of the map it was frozen from.
+///
+/// A FrozenBilevelMap is made by calling freeze on a map once it is built,
+/// such as to serve queries after a batch has been aggregated. Where the
+/// map serves as a lookup table, with_perfect_hash adds a minimal perfect
+/// hash of the pairs, so that get finds a pair in a single probe rather
+/// than by binary search.
+///
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// V is the type of the payload.
+#[derive(Clone)]
+pub struct FrozenBilevelMap<G, K, V> {
+    groups: Box<[G]>,
+    /// The start of the pairs of each group within keys and values,
+    /// followed by the number of pairs, so that the pairs of group i are
+    /// offsets[i]..offsets[i + 1].
+    offsets: Box<[usize]>,
+    keys: Box<[K]>,
+    values: Box<[V]>,
+    perfect: Option<PerfectHash>,
+}
+
+impl<G: Ord, K: Ord, V> FrozenBilevelMap<G, K, V> {
+    /// Lay out the groups listed, each with the aggregation key and payload
+    /// of each of its pairs, where no group or pair is listed twice.
+    pub(crate) fn from_groups(groups: impl Iterator<Item = (G, Vec<(K, V)>)>) -> Self {
+        let mut groups: Vec<_> = groups.collect();
+        groups.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+        let pairs = groups.iter().map(|(_, pairs)| pairs.len()).sum();
+        let mut offsets = Vec::with_capacity(groups.len() + 1);
+        let mut keys = Vec::with_capacity(pairs);
+        let mut values = Vec::with_capacity(pairs);
+        let groups: Vec<_> = groups.into_iter().map(|(g, mut pairs)| {
+            pairs.sort_unstable_by(|a, b| a.0.cmp(&b.0));
+            offsets.push(keys.len());
+            for (k, v) in pairs {
+                keys.push(k);
+                values.push(v);
+            }
+            g
+        }).collect();
+        offsets.push(keys.len());
+        Self {
+            groups: groups.into_boxed_slice(),
+            offsets: offsets.into_boxed_slice(),
+            keys: keys.into_boxed_slice(),
+            values: values.into_boxed_slice(),
+            perfect: None,
+        }
+    }
+
+    /// Build a minimal perfect hash of the pairs, so that get and contains
+    /// find a pair in a single probe rather than by binary search.
+    ///
+    /// Building takes time roughly in proportion to the number of pairs,
+    /// and the hash takes about two words of memory for each pair.
+    pub fn with_perfect_hash(mut self) -> Self
+    where
+        G: Hash,
+        K: Hash,
+    {
+        let pairs = self.iter_groups().enumerate()
+            .flat_map(|(i, (g, keys, _))| keys.iter().map(move |k| (g, k, i)));
+        self.perfect = Some(PerfectHash::build(pairs.enumerate().map(|(pair, (g, k, group))| (g, k, group, pair))));
+        self
+    }
+
+    /// Check whether the collection has a perfect hash of its pairs.
+    pub fn has_perfect_hash(&self) -> bool {
+        self.perfect.is_some()
+    }
+
+    /// Get the payload for the specified key pair, or None if the pair is
+    /// not present.
+    pub fn get<GRef, KRef>(&self, g: &GRef, k: &KRef) -> Option<&V>
+    where
+        G: Borrow<GRef>,
+        K: Borrow<KRef>,
+        GRef: Ord + Hash + ?Sized,
+        KRef: Ord + Hash + ?Sized,
+    {
+        if let Some(perfect) = &self.perfect {
+            let (group, pair) = perfect.find(g, k)?;
+            let found = self.groups[group].borrow() == g && self.keys[pair].borrow() == k;
+            return found.then(|| &self.values[pair]);
+        }
+        let range = self.range(self.find_group(g)?);
+        let i = self.keys[range.clone()].binary_search_by(|o| o.borrow().cmp(k)).ok()?;
+        Some(&self.values[range.start + i])
+    }
+
+    /// Check whether the collection holds the specified key pair.
+    pub fn contains<GRef, KRef>(&self, g: &GRef, k: &KRef) -> bool
+    where
+        G: Borrow<GRef>,
+        K: Borrow<KRef>,
+        GRef: Ord + Hash + ?Sized,
+        KRef: Ord + Hash + ?Sized,
+    {
+        self.get(g, k).is_some()
+    }
+
+    /// Get the aggregation keys of a group, in order, and the payload of
+    /// each, or None if the group is not present.
+    pub fn group<GRef>(&self, g: &GRef) -> Option<(&[K], &[V])>
+    where
+        G: Borrow<GRef>,
+        GRef: Ord + ?Sized,
+    {
+        let range = self.range(self.find_group(g)?);
+        Some((&self.keys[range.clone()], &self.values[range]))
+    }
+
+    /// Find the index of a group.
+    fn find_group<GRef>(&self, g: &GRef) -> Option<usize>
+    where
+        G: Borrow<GRef>,
+        GRef: Ord + ?Sized,
+    {
+        self.groups.binary_search_by(|o| o.borrow().cmp(g)).ok()
+    }
+}
+
+impl<G, K, V> FrozenBilevelMap<G, K, V> {
+    /// List the pairs in the collection, sorted by the group key and then
+    /// by the aggregation key, or from the last with rev.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&G, &K, &V)> {
+        self.iter_groups().flat_map(|(g, keys, values)| keys.iter().zip(values).map(move |(k, v)| (g, k, v)))
+    }
+
+    /// List the groups in order, with the aggregation keys of each, in
+    /// order, and the payload of each.
+    ///
+    /// The groups can be listed from the last with rev, such as to take
+    /// the last few without listing the others.
+    pub fn iter_groups(&self) -> impl DoubleEndedIterator<Item = (&G, &[K], &[V])> {
+        self.groups.iter().enumerate().map(|(i, g)| {
+            let range = self.range(i);
+            (g, &self.keys[range.clone()], &self.values[range])
+        })
+    }
+
+    /// List the group keys, in order.
+    pub fn group_keys(&self) -> &[G] {
+        &self.groups
+    }
+
+    /// Count the groups in the collection.
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Count the pairs in the collection.
+    pub fn pair_count(&self) -> usize {
+        self.keys.len()
+    }
+
+    /// Count the pairs in the collection.
+    pub fn len(&self) -> usize {
+        self.pair_count()
+    }
+
+    /// Check whether the collection holds no pairs.
+    pub fn is_empty(&self) -> bool {
+        self.keys.is_empty()
+    }
+
+    /// Estimate the memory held by the collection, in bytes, not counting
+    /// the collection itself.
+    pub fn estimated_heap_bytes(&self) -> usize
+    where
+        G: HeapSize,
+        K: HeapSize,
+        V: HeapSize,
+    {
+        size_of_val(&*self.groups) + size_of_val(&*self.offsets)
+            + size_of_val(&*self.keys) + size_of_val(&*self.values)
+            + self.groups.iter().map(G::heap_bytes).sum::<usize>()
+            + self.keys.iter().map(K::heap_bytes).sum::<usize>()
+            + self.values.iter().map(V::heap_bytes).sum::<usize>()
+            + self.perfect.as_ref().map_or(0, PerfectHash::heap_bytes)
+    }
+
+    /// Get the range of the pairs of the group with the specified index.
+    fn range(&self, i: usize) -> std::ops::Range<usize> {
+        self.offsets[i]..self.offsets[i + 1]
+    }
+}
+
+impl<G: PartialEq, K: PartialEq, V: PartialEq> PartialEq for FrozenBilevelMap<G, K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        // Whether either has a perfect hash does not change the contents.
+        self.groups == other.groups
+            && self.offsets == other.offsets
+            && self.keys == other.keys
+            && self.values == other.values
+    }
+}
+
+impl<G: Eq, K: Eq, V: Eq> Eq for FrozenBilevelMap<G, K, V> {}
+
+impl<G, K, V> std::fmt::Debug for FrozenBilevelMap<G, K, V>
+where
+    G: std::fmt::Debug,
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        debug_map(f, self.iter_groups().map(|(g, keys, values)| (g, keys.iter().zip(values))))
+    }
+}
+
+/// The average number of pairs hashed to each bucket of a PerfectHash.
+const BUCKET_SIZE: usize = 4;
+
+/// A minimal perfect hash of the pairs of a FrozenBilevelMap, built by
+/// hashing and displacement.
+///
+/// Each pair is hashed to a bucket, and the buckets, largest first, are
+/// each given the first displacement that places all of their pairs in
+/// free slots, so that every pair has a slot of its own and no slot is
+/// left empty. Finding a pair takes a hash, a displacement and a slot.
+#[derive(Clone)]
+struct PerfectHash {
+    /// Mixed into the hash of each pair, and changed if no displacement
+    /// can be found for a bucket.
+    seed: u64,
+    displacements: Box<[u32]>,
+    /// The index of the group, and of the pair, in each slot.
+    slots: Box<[(usize, usize)]>,
+}
+
+impl PerfectHash {
+    /// Build the hash of the pairs listed, each with the index of its
+    /// group and its own index, where no pair is listed twice.
+    fn build<G: Hash, K: Hash>(pairs: impl Iterator<Item = (G, K, usize, usize)>) -> Self {
+        let pairs: Vec<_> = pairs.collect();
+        let n = pairs.len();
+        let buckets = n.div_ceil(BUCKET_SIZE).max(1) as u64;
+        // The last bucket placed may find only one slot free, so allow
+        // enough tries to find it before changing the seed.
+        let tries = n.saturating_mul(16).max(1 << 16).min(u32::MAX as usize) as u32;
+        for seed in 0u64.. {
+            let mut hashed: Vec<_> = pairs.iter()
+                .map(|(g, k, group, pair)| (hash(&(seed, g, k)), (*group, *pair)))
+                .collect();
+            hashed.sort_unstable_by_key(|&(h, _)| h % buckets);
+            let mut order: Vec<_> = hashed.chunk_by(|a, b| a.0 % buckets == b.0 % buckets).collect();
+            order.sort_by_key(|bucket| std::cmp::Reverse(bucket.len()));
+            let mut displacements = vec![0; buckets as usize].into_boxed_slice();
+            let mut slots = vec![None; n];
+            let placed = order.into_iter().all(|bucket| {
+                let d = Self::place(bucket, tries, &mut slots);
+                d.map(|d| displacements[(bucket[0].0 % buckets) as usize] = d).is_some()
+            });
+            if placed {
+                let slots = slots.into_iter().map(Option::unwrap).collect();
+                return Self { seed, displacements, slots };
+            }
+        }
+        unreachable!("some seed places every pair")
+    }
+
+    /// Find a displacement that places every pair of a bucket in a free
+    /// slot, trying at most the specified number, and place them.
+    fn place(bucket: &[(u64, (usize, usize))], tries: u32, slots: &mut [Option<(usize, usize)>]) -> Option<u32> {
+        let mut placed = Vec::with_capacity(bucket.len());
+        for d in 0..tries {
+            placed.clear();
+            for &(h, _) in bucket {
+                let slot = slot(h, d, slots.len());
+                if slots[slot].is_some() || placed.contains(&slot) {
+                    break;
+                }
+                placed.push(slot);
+            }
+            if placed.len() == bucket.len() {
+                for (&slot, &(_, pair)) in placed.iter().zip(bucket) {
+                    slots[slot] = Some(pair);
+                }
+                return Some(d);
+            }
+        }
+        None
+    }
+
+    /// Find the slot of a pair, giving the index of the group and of the
+    /// pair placed in it, which are those of the pair if it is present.
+    fn find<G: Hash + ?Sized, K: Hash + ?Sized>(&self, g: &G, k: &K) -> Option<(usize, usize)> {
+        if self.slots.is_empty() {
+            return None;
+        }
+        let h = hash(&(self.seed, g, k));
+        let d = self.displacements[(h % self.displacements.len() as u64) as usize];
+        Some(self.slots[slot(h, d, self.slots.len())])
+    }
+
+    /// Estimate the memory held by the hash, in bytes.
+    fn heap_bytes(&self) -> usize {
+        size_of_val(&*self.displacements) + size_of_val(&*self.slots)
+    }
+}
+
+/// Find the slot in a table of n slots for a pair with the hash h,
+/// displaced by d.
+fn slot(h: u64, d: u32, n: usize) -> usize {
+    // Mix the displacement into the hash, with the finalizer of SplitMix64,
+    // so that each displacement scatters the pairs of a bucket anew.
+    let mut z = h ^ (d as u64).wrapping_mul(0x9e3779b97f4a7c15);
+    z = (z ^ (z >> 30)).wrapping_mul(0xbf58476d1ce4e5b9);
+    z = (z ^ (z >> 27)).wrapping_mul(0x94d049bb133111eb);
+    ((z ^ (z >> 31)) % n as u64) as usize
+}
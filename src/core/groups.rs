@@ -0,0 +1,281 @@
+use std::{iter::Flatten, mem::size_of, slice, vec};
+use hashbrown::{HashTable, hash_table::Entry};
+
+use crate::table_size;
+
+/// The stable id of a group of a collection, to refer to the group
+/// without holding a copy of its key.
+///
+/// A group keeps its id for as long as it is in the collection. The slot
+/// of a removed group may be given to a group added later, but under a
+/// new generation, so that the id of the removed group, such as one held
+/// by another system, refers to no group rather than to the new one.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct GroupId {
+    index: usize,
+    generation: u32,
+}
+
+impl GroupId {
+    /// Get the position of the slot of the group, such as to store it
+    /// compactly.
+    pub fn index(self) -> usize {
+        self.index
+    }
+
+    /// Get the number of times the slot of the group had been emptied
+    /// before the group was added, wrapping around.
+    pub fn generation(self) -> u32 {
+        self.generation
+    }
+
+    /// Make an id from its index and generation, such as ones read back
+    /// from storage.
+    pub fn from_parts(index: usize, generation: u32) -> Self {
+        Self { index, generation }
+    }
+}
+
+impl std::fmt::Display for GroupId {
+    /// Format the id as its index and generation, separated by a colon,
+    /// such as 3:0.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(f, "{}:{}", self.index, self.generation)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for GroupId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        (self.index as u64, self.generation).serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for GroupId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <(u64, u32)>::deserialize(deserializer).map(|(i, generation)| Self { index: i as usize, generation })
+    }
+}
+
+/// The groups of a collection, each in a slot whose position is its id.
+///
+/// The groups are held in a vector, with a hash table of their positions
+/// to find them by key. A removed group leaves its slot empty, to be
+/// filled by the next group added, so that no other group moves. The
+/// generation of the slot is advanced when it is emptied, so that the id
+/// of the removed group is not taken for the id of the next.
+///
+/// The hash of each group key is kept with its position, so that growing
+/// the table never hashes the keys again, and a probe compares a key only
+/// if its whole hash matches, which saves time where the keys are long
+/// strings.
+#[derive(Clone)]
+pub(crate) struct GroupTable<T> {
+    slots: Vec<Option<T>>,
+    /// The generation of each slot.
+    generations: Vec<u32>,
+    /// The positions of the empty slots.
+    free: Vec<usize>,
+    /// The hash of the key of each group, with its position.
+    index: HashTable<(u64, usize)>,
+}
+
+/// Get the group in a slot that the index refers to, and so is filled.
+fn filled<T>(slots: &[Option<T>], i: usize) -> &T {
+    slots[i].as_ref().expect("indexed slot is empty")
+}
+
+impl<T> GroupTable<T> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self {
+            slots: Vec::with_capacity(capacity),
+            generations: Vec::with_capacity(capacity),
+            free: Vec::new(),
+            index: HashTable::with_capacity(capacity),
+        }
+    }
+
+    /// Count the groups.
+    pub fn len(&self) -> usize {
+        self.index.len()
+    }
+
+    /// Measure the memory allocated for the slots and the index, in bytes.
+    pub fn allocated(&self) -> usize {
+        self.slots.capacity() * size_of::<Option<T>>()
+            + self.generations.capacity() * size_of::<u32>()
+            + self.free.capacity() * size_of::<usize>()
+            + table_size::<(u64, usize)>(self.index.capacity())
+    }
+
+    pub fn reserve(&mut self, additional: usize) {
+        self.slots.reserve(additional.saturating_sub(self.free.len()));
+        self.generations.reserve(additional.saturating_sub(self.free.len()));
+        self.index.reserve(additional, |&(hash, _)| hash);
+    }
+
+    pub fn find(&self, hash: u64, mut eq: impl FnMut(&T) -> bool) -> Option<&T> {
+        self.find_id(hash, &mut eq).map(|id| filled(&self.slots, id.index))
+    }
+
+    /// Find the id of a group.
+    pub fn find_id(&self, hash: u64, mut eq: impl FnMut(&T) -> bool) -> Option<GroupId> {
+        self.index.find(hash, |&(h, i)| h == hash && eq(filled(&self.slots, i))).map(|&(_, i)| self.id(i))
+    }
+
+    /// Get the id of the group in a slot.
+    fn id(&self, index: usize) -> GroupId {
+        GroupId { index, generation: self.generations[index] }
+    }
+
+    /// Check whether an id is of the group now in its slot, rather than
+    /// of a group removed since, or of no group of this table.
+    fn is_current(&self, id: GroupId) -> bool {
+        self.generations.get(id.index) == Some(&id.generation)
+    }
+
+    /// Get a group by its id, or None if no group has the id.
+    pub fn get(&self, id: GroupId) -> Option<&T> {
+        if !self.is_current(id) {
+            return None;
+        }
+        self.slots[id.index].as_ref()
+    }
+
+    pub fn get_mut(&mut self, id: GroupId) -> Option<&mut T> {
+        if !self.is_current(id) {
+            return None;
+        }
+        self.slots[id.index].as_mut()
+    }
+
+    /// Find a group, adding the one made by `f` if not found, and return
+    /// its id.
+    pub fn find_or_insert_with(
+        &mut self,
+        hash: u64,
+        mut eq: impl FnMut(&T) -> bool,
+        f: impl FnOnce() -> T,
+    ) -> GroupId {
+        let slots = &self.slots;
+        match self.index.entry(hash, |&(h, i)| h == hash && eq(filled(slots, i)), |&(h, _)| h) {
+            Entry::Occupied(e) => {
+                let i = e.get().1;
+                self.id(i)
+            }
+            Entry::Vacant(e) => {
+                let i = Self::fill(&mut self.slots, &mut self.generations, &mut self.free, f());
+                e.insert((hash, i));
+                self.id(i)
+            }
+        }
+    }
+
+    /// Find a group, adding the one made by `f` if not found.
+    pub fn get_or_insert_with(
+        &mut self,
+        hash: u64,
+        eq: impl FnMut(&T) -> bool,
+        f: impl FnOnce() -> T,
+    ) -> &mut T {
+        let id = self.find_or_insert_with(hash, eq, f);
+        self.slots[id.index].as_mut().expect("group just found")
+    }
+
+    /// Add a group known not to be present, and return its id.
+    pub fn insert_unique(&mut self, hash: u64, value: T) -> GroupId {
+        let i = Self::fill(&mut self.slots, &mut self.generations, &mut self.free, value);
+        self.index.insert_unique(hash, (hash, i), |&(h, _)| h);
+        self.id(i)
+    }
+
+    /// Put a group in the first empty slot, or in a new slot at the end,
+    /// and return its position.
+    fn fill(slots: &mut Vec<Option<T>>, generations: &mut Vec<u32>, free: &mut Vec<usize>, value: T) -> usize {
+        match free.pop() {
+            Some(i) => {
+                slots[i] = Some(value);
+                i
+            }
+            None => {
+                slots.push(Some(value));
+                generations.push(0);
+                slots.len() - 1
+            }
+        }
+    }
+
+    /// Remove a group, leaving its slot to be filled by a later group.
+    pub fn remove(&mut self, hash: u64, mut eq: impl FnMut(&T) -> bool) -> Option<T> {
+        let slots = &self.slots;
+        let ((_, i), _) = self.index.find_entry(hash, |&(h, i)| h == hash && eq(filled(slots, i))).ok()?.remove();
+        self.free.push(i);
+        self.generations[i] = self.generations[i].wrapping_add(1);
+        self.slots[i].take()
+    }
+
+    /// Remove the group with the specified id, or None if no group has the
+    /// id.
+    ///
+    /// hash: the hash of the key of the group.
+    pub fn remove_id(&mut self, hash: u64, id: GroupId) -> Option<T> {
+        if !self.is_current(id) {
+            return None;
+        }
+        self.index.find_entry(hash, |&(_, i)| i == id.index).ok()?.remove();
+        self.free.push(id.index);
+        self.generations[id.index] = self.generations[id.index].wrapping_add(1);
+        self.slots[id.index].take()
+    }
+
+    /// List the groups, in the order of their ids.
+    pub fn iter(&self) -> Iter<'_, T> {
+        self.slots.iter().flatten()
+    }
+
+    /// List the groups with their ids.
+    pub fn iter_with_ids(&self) -> impl Iterator<Item = (GroupId, &T)> {
+        self.slots.iter().enumerate().filter_map(|(i, slot)| Some((self.id(i), slot.as_ref()?)))
+    }
+
+    pub fn iter_mut(&mut self) -> IterMut<'_, T> {
+        self.slots.iter_mut().flatten()
+    }
+
+    #[cfg(feature = "rayon")]
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = &T>
+    where
+        T: Sync,
+    {
+        use rayon::prelude::*;
+        self.slots.par_iter().filter_map(Option::as_ref)
+    }
+
+    #[cfg(feature = "rayon")]
+    pub fn par_iter_mut(&mut self) -> impl rayon::iter::ParallelIterator<Item = &mut T>
+    where
+        T: Send,
+    {
+        use rayon::prelude::*;
+        self.slots.par_iter_mut().filter_map(Option::as_mut)
+    }
+}
+
+impl<T> IntoIterator for GroupTable<T> {
+    type Item = T;
+    type IntoIter = IntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.slots.into_iter().flatten()
+    }
+}
+
+/// Lists the groups of a GroupTable.
+pub(crate) type Iter<'a, T> = Flatten<slice::Iter<'a, Option<T>>>;
+
+/// Lists the groups of a GroupTable, for modification.
+pub(crate) type IterMut<'a, T> = Flatten<slice::IterMut<'a, Option<T>>>;
+
+/// Lists the groups of a GroupTable, consuming it.
+pub(crate) type IntoIter<T> = Flatten<vec::IntoIter<Option<T>>>;
@@ -0,0 +1,630 @@
+use std::{hash::{BuildHasher, BuildHasherDefault, DefaultHasher, Hash, Hasher}, marker::PhantomData, mem::size_of, sync::{Arc, Mutex}};
+use hashbrown::{Equivalent, HashTable};
+
+use crate::{HeapSize, hash, table_size};
+
+/// A strategy for storing the aggregation keys of a collection.
+///
+/// Each group refers to its aggregation keys by ids given out by the
+/// store, so a store can either keep each key in every group where it
+/// appears, or keep a single copy of each key and give out its index.
+///
+/// The store also chooses how the group keys and the ids of the
+/// aggregation keys are hashed.
+///
+/// K is the type of the aggregation key.
+pub trait KeyStore<K> {
+    /// The type of the id by which a group refers to a key.
+    type Id: Hash + Eq;
+
+    /// The hasher for the group keys and the ids of the aggregation keys,
+    /// which must hash each value the same way every time.
+    type Hasher: KeyHasher;
+
+    /// Create an empty store with space for the specified number of
+    /// distinct keys.
+    fn with_capacity(capacity: usize) -> Self;
+
+    /// Reserve space for at least the specified number of additional
+    /// distinct keys.
+    fn reserve(&mut self, additional: usize);
+
+    /// Find the id of a key, adding the key if it is new.
+    fn intern<Q>(&mut self, k: &Q) -> Self::Id
+    where
+        Q: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized;
+
+    /// Find the id of a key, adding the key given, rather than a copy of
+    /// it, if it is new. The key is dropped if it is already stored.
+    fn intern_owned(&mut self, k: K) -> Self::Id
+    where
+        K: Eq;
+
+    /// Find the id of a key, or None if the key is not stored, without
+    /// adding it or copying it.
+    ///
+    /// The id is given in a form that hashes and compares as the id does,
+    /// such as the key itself where the key is its own id, by which the
+    /// key can be looked up in a group.
+    fn find<'a, Q>(&'a self, k: &'a Q) -> Option<impl Hash + Equivalent<Self::Id> + 'a>
+    where
+        Q: PartialEq<K> + Hash + ?Sized;
+
+    /// Get the key with the specified id.
+    fn key<'a>(&'a self, id: &'a Self::Id) -> &'a K;
+
+    /// Get the key with the specified id as an owned value, consuming
+    /// the id.
+    fn to_key(&self, id: Self::Id) -> K
+    where
+        K: Clone;
+
+    /// Check that an id refers to a key of this store, such as an id
+    /// read from a snapshot.
+    fn contains(&self, id: &Self::Id) -> bool;
+
+    /// Get the id of a key as an AggKeyId, such as to export it, or None
+    /// if the store does not number its keys.
+    fn agg_key_id(_id: &Self::Id) -> Option<AggKeyId>
+    where
+        Self: Sized,
+    {
+        None
+    }
+
+    /// Estimate the memory allocated by the store, in bytes, not counting
+    /// any memory the keys own themselves.
+    fn memory_usage(&self) -> usize;
+
+    /// Estimate the memory allocated by the store, in bytes, including
+    /// the memory the keys own themselves.
+    fn heap_bytes(&self) -> usize
+    where
+        K: HeapSize;
+
+    /// Hash a group key or the id of an aggregation key.
+    fn hash<T: Hash + ?Sized>(t: &T) -> u64
+    where
+        Self: Sized,
+    {
+        Self::Hasher::default().hash_one(t)
+    }
+}
+
+/// A hasher for the keys of a collection, which can be created afresh to
+/// hash each value the same way every time, and shared between threads.
+pub trait KeyHasher: BuildHasher + Default + Clone + Send + Sync {}
+
+impl<H: BuildHasher + Default + Clone + Send + Sync> KeyHasher for H {}
+
+/// Hash keys with SipHash, as the standard hash tables do, but with fixed
+/// keys, so the same value always has the same hash.
+pub type DefaultHashing = BuildHasherDefault<DefaultHasher>;
+
+/// Hash integer keys to themselves, with no hashing at all.
+///
+/// Hashing small integer keys, such as u32 ids, can take most of the time
+/// to insert a pair, and ids that are already spread over their range need
+/// no hashing to find a slot. This hashes any other key too, by folding
+/// its bytes together, but keys whose hashes are not spread will collide.
+pub type IdentityHashing = BuildHasherDefault<IdentityHasher>;
+
+/// A hasher that takes an integer as its own hash.
+///
+/// A key written as several integers, such as a tuple, is hashed by
+/// combining them.
+#[derive(Clone, Copy, Debug, Default)]
+pub struct IdentityHasher(u64);
+
+impl IdentityHasher {
+    fn combine(&mut self, n: u64) {
+        self.0 = self.0.rotate_left(5) ^ n;
+    }
+}
+
+impl Hasher for IdentityHasher {
+    fn finish(&self) -> u64 {
+        self.0
+    }
+
+    fn write(&mut self, bytes: &[u8]) {
+        for chunk in bytes.chunks(8) {
+            let mut word = [0; 8];
+            word[..chunk.len()].copy_from_slice(chunk);
+            self.combine(u64::from_le_bytes(word));
+        }
+    }
+
+    fn write_u8(&mut self, n: u8) {
+        self.combine(n.into());
+    }
+
+    fn write_u16(&mut self, n: u16) {
+        self.combine(n.into());
+    }
+
+    fn write_u32(&mut self, n: u32) {
+        self.combine(n.into());
+    }
+
+    fn write_u64(&mut self, n: u64) {
+        self.combine(n);
+    }
+
+    fn write_usize(&mut self, n: usize) {
+        self.combine(n as u64);
+    }
+
+    fn write_i8(&mut self, n: i8) {
+        self.write_u8(n as u8);
+    }
+
+    fn write_i16(&mut self, n: i16) {
+        self.write_u16(n as u16);
+    }
+
+    fn write_i32(&mut self, n: i32) {
+        self.write_u32(n as u32);
+    }
+
+    fn write_i64(&mut self, n: i64) {
+        self.write_u64(n as u64);
+    }
+
+    fn write_isize(&mut self, n: isize) {
+        self.write_usize(n as usize);
+    }
+}
+
+/// Keep each aggregation key in every group where it appears.
+///
+/// This suits keys that are small and cheap to compare, such as Copy
+/// types, where looking up an index would cost more than the key saves.
+///
+/// H is the hasher for the keys, such as IdentityHashing for integer ids.
+pub struct Inline<H = DefaultHashing>(PhantomData<fn() -> H>);
+
+impl<H> Clone for Inline<H> {
+    fn clone(&self) -> Self {
+        *self
+    }
+}
+
+impl<H> Copy for Inline<H> {}
+
+impl<H> Default for Inline<H> {
+    fn default() -> Self {
+        Self(PhantomData)
+    }
+}
+
+impl<H> std::fmt::Debug for Inline<H> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.write_str("Inline")
+    }
+}
+
+/// A borrowed form of a key, which hashes as the key does and is
+/// equivalent to it, so that a key that is its own id can be looked up
+/// in a group without copying it.
+struct ByRef<'a, Q: ?Sized>(&'a Q);
+
+impl<Q: Hash + ?Sized> Hash for ByRef<'_, Q> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        self.0.hash(state);
+    }
+}
+
+impl<K, Q: PartialEq<K> + ?Sized> Equivalent<K> for ByRef<'_, Q> {
+    fn equivalent(&self, key: &K) -> bool {
+        self.0 == key
+    }
+}
+
+impl<K: Hash + Eq, H: KeyHasher> KeyStore<K> for Inline<H> {
+    type Id = K;
+    type Hasher = H;
+
+    fn with_capacity(_capacity: usize) -> Self {
+        Self::default()
+    }
+
+    fn reserve(&mut self, _additional: usize) {}
+
+    fn intern<Q>(&mut self, k: &Q) -> K
+    where
+        Q: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        k.to_owned()
+    }
+
+    fn intern_owned(&mut self, k: K) -> K {
+        k
+    }
+
+    fn find<'a, Q>(&'a self, k: &'a Q) -> Option<impl Hash + Equivalent<K> + 'a>
+    where
+        Q: PartialEq<K> + Hash + ?Sized,
+    {
+        Some(ByRef(k))
+    }
+
+    fn key<'a>(&'a self, id: &'a K) -> &'a K {
+        id
+    }
+
+    fn to_key(&self, id: K) -> K {
+        id
+    }
+
+    fn contains(&self, _id: &K) -> bool {
+        true
+    }
+
+    fn memory_usage(&self) -> usize {
+        0
+    }
+
+    fn heap_bytes(&self) -> usize {
+        0
+    }
+}
+
+/// Keep a single copy of each aggregation key, rather than one in each
+/// group where it appears, and give each key an index as its id.
+///
+/// Each distinct key is given an id when first found, which does not
+/// change for the life of the store.
+#[derive(Clone)]
+pub struct Interned<K> {
+    keys: Vec<K>,
+    table: HashTable<usize>,
+}
+
+impl<K> Interned<K> {
+    /// List the keys, indexed by their ids.
+    pub fn as_slice(&self) -> &[K] {
+        &self.keys
+    }
+
+    /// Find the id of a key, or None if the key is not stored.
+    pub fn id_of<Q>(&self, k: &Q) -> Option<usize>
+    where
+        K: Hash,
+        Q: PartialEq<K> + Hash + ?Sized,
+    {
+        self.table.find(hash(k), |&i| k.eq(&self.keys[i])).copied()
+    }
+}
+
+/// The id of an aggregation key of a collection that interns its keys,
+/// to refer to the key without holding a copy of it.
+///
+/// A key keeps its id for the life of the collection, even once no group
+/// holds it.
+#[derive(Clone, Copy, Debug, PartialEq, Eq, PartialOrd, Ord, Hash)]
+pub struct AggKeyId(pub(crate) usize);
+
+impl AggKeyId {
+    /// Get the id as an index into the id mapping of the collection.
+    pub fn index(self) -> usize {
+        self.0
+    }
+
+    /// Make an id from an index, such as one read back from storage.
+    pub fn from_index(index: usize) -> Self {
+        Self(index)
+    }
+}
+
+impl std::fmt::Display for AggKeyId {
+    /// Format the id as its index.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Display::fmt(&self.0, f)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl serde::Serialize for AggKeyId {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_u64(self.0 as u64)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de> serde::Deserialize<'de> for AggKeyId {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        u64::deserialize(deserializer).map(|i| Self(i as usize))
+    }
+}
+
+impl<K: Hash> KeyStore<K> for Interned<K> {
+    type Id = usize;
+    type Hasher = DefaultHashing;
+
+    fn with_capacity(capacity: usize) -> Self {
+        Self {
+            keys: Vec::with_capacity(capacity),
+            table: HashTable::with_capacity(capacity),
+        }
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.keys.reserve(additional);
+        self.table.reserve(additional, |&i| hash(&self.keys[i]));
+    }
+
+    fn intern<Q>(&mut self, k: &Q) -> usize
+    where
+        Q: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        *self.table.entry(
+            hash(k),
+            |&i| k.eq(&self.keys[i]),
+            |&i| hash(&self.keys[i])
+        ).or_insert_with(|| {
+            let i = self.keys.len();
+            self.keys.push(k.to_owned());
+            i
+        }).get()
+    }
+
+    fn intern_owned(&mut self, k: K) -> usize
+    where
+        K: Eq,
+    {
+        *self.table.entry(
+            hash(&k),
+            |&i| k == self.keys[i],
+            |&i| hash(&self.keys[i])
+        ).or_insert_with(|| {
+            let i = self.keys.len();
+            self.keys.push(k);
+            i
+        }).get()
+    }
+
+    fn find<'a, Q>(&'a self, k: &'a Q) -> Option<impl Hash + Equivalent<usize> + 'a>
+    where
+        Q: PartialEq<K> + Hash + ?Sized,
+    {
+        self.id_of(k)
+    }
+
+    fn key<'a>(&'a self, &id: &'a usize) -> &'a K {
+        &self.keys[id]
+    }
+
+    fn to_key(&self, id: usize) -> K
+    where
+        K: Clone,
+    {
+        self.keys[id].clone()
+    }
+
+    fn contains(&self, &id: &usize) -> bool {
+        id < self.keys.len()
+    }
+
+    fn agg_key_id(&id: &usize) -> Option<AggKeyId> {
+        Some(AggKeyId(id))
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.keys.capacity() * size_of::<K>() + table_size::<usize>(self.table.capacity())
+    }
+
+    fn heap_bytes(&self) -> usize
+    where
+        K: HeapSize,
+    {
+        self.memory_usage() + self.keys.iter().map(K::heap_bytes).sum::<usize>()
+    }
+}
+
+/// A single copy of each aggregation key, shared by every collection that
+/// holds an Arc of it as its store of keys, such as a set and several maps
+/// built over the same corpus of URLs.
+///
+/// Each group refers to a key by a SharedKey, a pointer to the one copy,
+/// so ids are comparable between the collections sharing the interner.
+/// Keys are kept until the interner and every collection using it are
+/// dropped.
+pub struct KeyInterner<K> {
+    keys: Mutex<HashTable<Arc<K>>>,
+}
+
+impl<K: Hash> KeyInterner<K> {
+    /// Create an empty interner, to share between collections.
+    pub fn new() -> Arc<Self> {
+        Self::with_capacity(0)
+    }
+
+    /// Create an empty interner with space for the specified number of
+    /// distinct keys.
+    pub fn with_capacity(capacity: usize) -> Arc<Self> {
+        Arc::new(Self { keys: Mutex::new(HashTable::with_capacity(capacity)) })
+    }
+
+    /// Find the shared copy of a key, adding the key if it is new.
+    pub fn get_or_insert<Q>(&self, k: &Q) -> SharedKey<K>
+    where
+        Q: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        let mut keys = self.keys.lock().unwrap();
+        let key = keys.entry(hash(k), |o| k.eq(o), |o| hash(&**o))
+            .or_insert_with(|| Arc::new(k.to_owned()));
+        SharedKey(key.get().clone())
+    }
+
+    /// Count the distinct keys.
+    pub fn len(&self) -> usize {
+        self.keys.lock().unwrap().len()
+    }
+
+    /// Check whether no keys have been interned.
+    pub fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Estimate the memory held by the interner, in bytes, including the
+    /// memory the keys own themselves.
+    pub fn estimated_heap_bytes(&self) -> usize
+    where
+        K: HeapSize,
+    {
+        let keys = self.keys.lock().unwrap();
+        // Each key is in an allocation of its own, with the two counts of
+        // its Arc.
+        table_size::<Arc<K>>(keys.capacity())
+            + keys.iter().map(|k| 2 * size_of::<usize>() + size_of::<K>() + k.heap_bytes()).sum::<usize>()
+    }
+}
+
+/// A key of a KeyInterner, shared by every group where it appears.
+///
+/// Since the interner holds one copy of each key, two SharedKeys are
+/// compared, and hashed, by the copy they point to, not by the key.
+pub struct SharedKey<K>(Arc<K>);
+
+impl<K> SharedKey<K> {
+    /// Get the key.
+    pub fn get(&self) -> &K {
+        &self.0
+    }
+}
+
+impl<K> Clone for SharedKey<K> {
+    fn clone(&self) -> Self {
+        Self(self.0.clone())
+    }
+}
+
+impl<K> PartialEq for SharedKey<K> {
+    fn eq(&self, other: &Self) -> bool {
+        Arc::ptr_eq(&self.0, &other.0)
+    }
+}
+
+impl<K> Eq for SharedKey<K> {}
+
+impl<K> Hash for SharedKey<K> {
+    fn hash<H: Hasher>(&self, state: &mut H) {
+        Arc::as_ptr(&self.0).hash(state)
+    }
+}
+
+impl<K: std::fmt::Debug> std::fmt::Debug for SharedKey<K> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&*self.0, f)
+    }
+}
+
+impl<K: Hash> KeyStore<K> for Arc<KeyInterner<K>> {
+    type Id = SharedKey<K>;
+    type Hasher = DefaultHashing;
+
+    fn with_capacity(capacity: usize) -> Self {
+        KeyInterner::with_capacity(capacity)
+    }
+
+    fn reserve(&mut self, additional: usize) {
+        self.keys.lock().unwrap().reserve(additional, |k| hash(&**k));
+    }
+
+    fn intern<Q>(&mut self, k: &Q) -> SharedKey<K>
+    where
+        Q: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        self.get_or_insert(k)
+    }
+
+    fn intern_owned(&mut self, k: K) -> SharedKey<K>
+    where
+        K: Eq,
+    {
+        let mut keys = self.keys.lock().unwrap();
+        let key = keys.entry(hash(&k), |o| k == **o, |o| hash(&**o))
+            .or_insert_with(|| Arc::new(k));
+        SharedKey(key.get().clone())
+    }
+
+    fn find<'a, Q>(&'a self, k: &'a Q) -> Option<impl Hash + Equivalent<SharedKey<K>> + 'a>
+    where
+        Q: PartialEq<K> + Hash + ?Sized,
+    {
+        self.keys.lock().unwrap().find(hash(k), |o| k.eq(o)).map(|key| SharedKey(key.clone()))
+    }
+
+    fn key<'a>(&'a self, id: &'a SharedKey<K>) -> &'a K {
+        &id.0
+    }
+
+    fn to_key(&self, id: SharedKey<K>) -> K
+    where
+        K: Clone,
+    {
+        Arc::unwrap_or_clone(id.0)
+    }
+
+    fn contains(&self, id: &SharedKey<K>) -> bool {
+        self.keys.lock().unwrap().find(hash(&*id.0), |k| Arc::ptr_eq(k, &id.0)).is_some()
+    }
+
+    /// The interner is not counted, as it is shared; count it once with
+    /// KeyInterner::estimated_heap_bytes.
+    fn memory_usage(&self) -> usize {
+        0
+    }
+
+    fn heap_bytes(&self) -> usize
+    where
+        K: HeapSize,
+    {
+        0
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<H> serde::Serialize for Inline<H> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.serialize_unit()
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, H> serde::Deserialize<'de> for Inline<H> {
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        <()>::deserialize(deserializer).map(|()| Self::default())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K: serde::Serialize> serde::Serialize for Interned<K> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.keys.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K> serde::Deserialize<'de> for Interned<K>
+where
+    K: Hash + Eq + serde::Deserialize<'de>,
+{
+    /// Read the keys in order of their ids, failing if they are not
+    /// distinct.
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        use hashbrown::hash_table::Entry;
+        let keys = Vec::<K>::deserialize(deserializer)?;
+        let mut table = HashTable::with_capacity(keys.len());
+        for (i, k) in keys.iter().enumerate() {
+            match table.entry(hash(k), |&j| keys[j] == *k, |&j| hash(&keys[j])) {
+                Entry::Occupied(_) => {
+                    return Err(serde::de::Error::custom("duplicate aggregation key"));
+                },
+                Entry::Vacant(e) => { e.insert(i); },
+            }
+        }
+        Ok(Self { keys, table })
+    }
+}
@@ -0,0 +1,1993 @@
+use std::{cmp::Ordering, collections::HashMap, hash::Hash, iter::repeat_n, marker::PhantomData, sync::Arc};
+
+use crate::{Capacity, Derived, HeapSize, equivalence_classes, fingerprint, size_histogram, small::{self, Entry, SmallMap}, top_n};
+use super::{AggKeyId, CsrMatrix, FrozenBilevelMap, GroupId, GroupMetrics, GroupTable, Interned, KeyStore, groups};
+
+/// A group of a BilevelMap, with the ids of its aggregation keys and their
+/// payloads.
+type Group<G, Id, V, H> = (G, SmallMap<Id, V, H>);
+
+/// The table of groups of a BilevelMap.
+type Groups<G, Id, V, H> = GroupTable<Group<G, Id, V, H>>;
+
+/// The pairs of each group as of the last delta taken, with the group key,
+/// against which the next delta is found.
+#[cfg(feature = "snapshot")]
+type DeltaBase<G, Id, V> = HashMap<GroupId, (G, HashMap<Id, V>)>;
+
+/// A change to a BilevelMap, as written in a delta.
+#[cfg(feature = "snapshot")]
+#[derive(serde::Serialize, serde::Deserialize)]
+enum Change<G, K, V> {
+    /// A pair added, or whose payload changed, with its current payload.
+    Pair(G, K, V),
+    /// A pair removed.
+    RemovePair(G, K),
+    /// A group removed, along with its pairs.
+    RemoveGroup(G),
+}
+
+/// The payloads of a group, found to be added to, with the count of pairs
+/// of the collection and the constructor for the payload of each new pair.
+type GroupEntry<'a, Id, V, H> = (&'a mut SmallMap<Id, V, H>, &'a mut usize, &'a Constructor<V>);
+
+/// A constructor for the payload of each new pair.
+pub(super) enum Constructor<V> {
+    /// A function, such as V::default.
+    Fn(fn() -> V),
+    /// A closure, shared with the collections made from the collection,
+    /// such as by pivot.
+    Closure(Arc<dyn Fn() -> V + Send + Sync>),
+}
+
+impl<V> Constructor<V> {
+    /// Construct a payload.
+    pub fn construct(&self) -> V {
+        match self {
+            Self::Fn(f) => f(),
+            Self::Closure(f) => f(),
+        }
+    }
+}
+
+impl<V> Clone for Constructor<V> {
+    fn clone(&self) -> Self {
+        match self {
+            Self::Fn(f) => Self::Fn(*f),
+            Self::Closure(f) => Self::Closure(Arc::clone(f)),
+        }
+    }
+}
+
+/// A collection of distinct pairs (g, k) grouped by g, with a payload
+/// associated with each pair.
+///
+/// As pairs are found, they are added if not already present.
+/// When the collection is iterated over, the pairs are listed by group.
+///
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// V is the type of the payload.
+/// S is the strategy for storing the aggregation keys.
+pub struct BilevelMap<G, K, V, S = Interned<K>>
+where
+    S: KeyStore<K>,
+{
+    per_group: usize,
+    /// Whether new groups are given capacity for the average group size.
+    adaptive: bool,
+    keys: S,
+    groups: Groups<G, S::Id, V, S::Hasher>,
+    /// The number of pairs, counted as they are added.
+    pairs: usize,
+    constructor: Constructor<V>,
+    /// The fingerprint of the keys of each group, in iteration order.
+    fingerprints: Derived<Vec<u64>>,
+    /// The pairs as of the last delta taken, if any.
+    #[cfg(feature = "snapshot")]
+    delta_base: Option<DeltaBase<G, S::Id, V>>,
+    key: PhantomData<K>,
+}
+
+impl<G: Hash, K, V: Default, S: KeyStore<K>> BilevelMap<G, K, V, S> {
+    /// Create a new collection, with the default payload for each new pair.
+    ///
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    pub fn new() -> Self {
+        Self::with_capacity(Capacity::default())
+    }
+
+    /// Create a new collection with the specified capacity, with the
+    /// default payload for each new pair.
+    pub fn with_capacity(capacity: Capacity) -> Self {
+        Self::with_keys(capacity, S::with_capacity(capacity.agg_keys))
+    }
+
+    /// Create a new collection with the specified capacity for groups,
+    /// storing the aggregation keys in the store given, such as a
+    /// KeyInterner shared with other collections, with the default
+    /// payload for each new pair.
+    pub fn with_keys(capacity: Capacity, keys: S) -> Self {
+        Self::with_constructor(capacity, keys, Constructor::Fn(V::default))
+    }
+}
+
+impl<G: Hash, K, V: Default, S: KeyStore<K>> Default for BilevelMap<G, K, V, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: Hash, K, V, S: KeyStore<K>> BilevelMap<G, K, V, S> {
+    /// Create a new collection.
+    ///
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    ///
+    /// constructor: A constructor for the payload of each new pair.
+    ///     It can be a closure, such as one capturing bounds read from
+    ///     configuration.
+    pub fn new_with(constructor: impl Fn() -> V + Send + Sync + 'static) -> Self {
+        Self::with_capacity_and(Capacity::default(), constructor)
+    }
+
+    /// Create a new collection with the specified capacity.
+    ///
+    /// constructor: A constructor for the payload of each new pair.
+    pub fn with_capacity_and(capacity: Capacity, constructor: impl Fn() -> V + Send + Sync + 'static) -> Self {
+        Self::with_keys_and(capacity, S::with_capacity(capacity.agg_keys), constructor)
+    }
+
+    /// Create a new collection with the specified capacity for groups,
+    /// storing the aggregation keys in the store given, such as a
+    /// KeyInterner shared with other collections.
+    ///
+    /// constructor: A constructor for the payload of each new pair.
+    pub fn with_keys_and(capacity: Capacity, keys: S, constructor: impl Fn() -> V + Send + Sync + 'static) -> Self {
+        Self::with_constructor(capacity, keys, Constructor::Closure(Arc::new(constructor)))
+    }
+
+    /// Create a new collection, with a function rather than a closure as
+    /// the constructor for the payload of each new pair.
+    pub(super) fn with_fn(constructor: fn() -> V) -> Self {
+        Self::with_constructor(Capacity::default(), S::with_capacity(0), Constructor::Fn(constructor))
+    }
+
+    /// Create a new collection with the specified capacity for groups,
+    /// storing the aggregation keys in the store given.
+    pub(super) fn with_constructor(capacity: Capacity, keys: S, constructor: Constructor<V>) -> Self {
+        let Capacity { groups, per_group, agg_keys: _ } = capacity;
+        Self::from_parts(per_group, keys, GroupTable::with_capacity(groups), constructor)
+    }
+
+    /// Create a collection from its contents.
+    fn from_parts(
+        per_group: usize,
+        keys: S,
+        groups: Groups<G, S::Id, V, S::Hasher>,
+        constructor: Constructor<V>,
+    ) -> Self {
+        let pairs = groups.iter().map(|(_, inner)| inner.len()).sum();
+        Self {
+            per_group,
+            adaptive: false,
+            keys,
+            groups,
+            pairs,
+            constructor,
+            fingerprints: Derived::new(),
+            #[cfg(feature = "snapshot")]
+            delta_base: None,
+            key: PhantomData,
+        }
+    }
+
+    /// Get a mutable reference to the payload for the specified key pair.
+    ///
+    /// If the key pair is currently not present, a new payload is inserted.
+    pub fn add_or_get<GRef, KRef>(&mut self, g: &GRef, k: &KRef) -> &mut V
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+        KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        // The payload may be modified through the reference returned.
+        self.invalidate();
+        let id = self.keys.intern(k);
+        let (inner, pairs, constructor) = self.group_entry(g);
+        inner.entry(id).or_insert_with(|| {
+            *pairs += 1;
+            constructor.construct()
+        })
+    }
+
+    /// Get a mutable reference to the payload for the specified key pair,
+    /// taking ownership of the keys, so that a new key is stored without
+    /// being copied and a key already present is dropped.
+    ///
+    /// If the key pair is currently not present, a new payload is inserted.
+    pub fn add_or_get_owned(&mut self, g: G, k: K) -> &mut V
+    where
+        G: Eq,
+        K: Eq,
+    {
+        // The payload may be modified through the reference returned.
+        self.invalidate();
+        let id = self.keys.intern_owned(k);
+        let (inner, pairs, constructor) = self.group_entry_owned(g);
+        inner.entry(id).or_insert_with(|| {
+            *pairs += 1;
+            constructor.construct()
+        })
+    }
+
+    /// Get a mutable reference to the payload for the specified key pair.
+    ///
+    /// If the key pair is currently not present, the payload computed from
+    /// the keys is inserted.
+    ///
+    /// init: computes the payload for a new key pair, such as from
+    ///     configuration looked up for the group.
+    pub fn add_or_get_with_key<GRef, KRef, F>(&mut self, g: &GRef, k: &KRef, init: F) -> &mut V
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+        KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+        F: FnOnce(&G, &K) -> V,
+    {
+        // The payload may be modified through the reference returned.
+        self.invalidate();
+        let id = self.keys.intern(k);
+        let per_group = self.group_capacity();
+        let pairs = &mut self.pairs;
+        // Find the group here rather than through group_entry, so that the
+        // stored group key can be passed to init.
+        let (g, inner) = self.groups.get_or_insert_with(
+            S::hash(g),
+            |(o, _)| g.eq(o),
+            || (g.to_owned(), SmallMap::with_capacity(per_group)),
+        );
+        match inner.entry(id) {
+            Entry::Occupied(v) => v,
+            Entry::Vacant(e) => {
+                *pairs += 1;
+                let v = init(g, self.keys.key(e.key()));
+                e.insert(v)
+            },
+        }
+    }
+
+    /// Find the payloads of a group, adding the group given if new, along
+    /// with the count of pairs and the constructor of new payloads.
+    fn group_entry_owned(&mut self, g: G) -> GroupEntry<'_, S::Id, V, S::Hasher>
+    where
+        G: Eq,
+    {
+        let hash = S::hash(&g);
+        let id = match self.groups.find_id(hash, |(o, _)| g == *o) {
+            Some(id) => id,
+            None => {
+                let per_group = self.group_capacity();
+                self.groups.insert_unique(hash, (g, SmallMap::with_capacity(per_group)))
+            },
+        };
+        (&mut self.groups.get_mut(id).expect("group just found").1, &mut self.pairs, &self.constructor)
+    }
+
+    /// Find the payloads of a group, adding the group if new, along with
+    /// the count of pairs, to be updated as pairs are added to the group,
+    /// and the constructor of new payloads.
+    fn group_entry<GRef>(&mut self, g: &GRef) -> GroupEntry<'_, S::Id, V, S::Hasher>
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+    {
+        let per_group = self.group_capacity();
+        let inner = &mut self.groups.get_or_insert_with(
+            S::hash(g),
+            |(o, _)| g.eq(o),
+            || (g.to_owned(), SmallMap::with_capacity(per_group)),
+        ).1;
+        (inner, &mut self.pairs, &self.constructor)
+    }
+
+    /// List the payloads for the pairs currently in the collection,
+    /// without consuming the collection or the payloads.
+    ///
+    /// Pairs are grouped by g.
+    pub fn iter(&self) -> MapIter<'_, G, K, V, S> {
+        MapIter { keys: &self.keys, groups: self.groups.iter(), current: None, remaining: self.pairs }
+    }
+
+    /// List the payloads for the pairs currently in the collection, for
+    /// modification.
+    ///
+    /// Pairs are grouped by g.
+    pub fn iter_mut(&mut self) -> MapIterMut<'_, G, K, V, S> {
+        self.invalidate();
+        MapIterMut { keys: &self.keys, groups: self.groups.iter_mut(), current: None, remaining: self.pairs }
+    }
+
+    /// Call f once for each group, with the group key and the aggregation
+    /// keys and payloads of the group.
+    ///
+    /// The pairs are listed as iter lists them, but without the state iter
+    /// keeps to move from one group to the next, which suits a report that
+    /// writes each group in turn.
+    pub fn for_each_group<F>(&self, mut f: F)
+    where
+        F: FnMut(&G, GroupIter<'_, K, V, S>),
+    {
+        for (g, inner) in self.groups.iter() {
+            f(g, GroupIter { keys: &self.keys, pairs: inner.iter() });
+        }
+    }
+
+    /// List each group with its aggregation keys and payloads.
+    pub(crate) fn iter_groups(&self) -> impl Iterator<Item = (&G, impl Iterator<Item = (&K, &V)>)> {
+        let keys = &self.keys;
+        self.groups.iter()
+            .map(move |(g, inner)| (g, inner.iter().map(move |(id, v)| (keys.key(id), v))))
+    }
+
+    /// List the groups in the collection, each with its own iterator
+    /// over its aggregation keys and payloads, consuming the collection.
+    ///
+    /// Each group is an owned unit, which can be handed on, such as sent
+    /// to another thread, apart from the others. The aggregation keys of
+    /// a group are taken out of the store as the group is listed, so an
+    /// interned key is cloned for every group where it appears.
+    pub fn into_groups(self) -> IntoGroups<G, K, V, S>
+    where
+        K: Clone,
+    {
+        IntoGroups { remaining: self.groups.len(), keys: self.keys, groups: self.groups.into_iter() }
+    }
+
+    /// List the pairs and their payloads in the collection, consuming
+    /// the collection.
+    ///
+    /// Pairs are grouped by g. Each group key is cloned for every pair
+    /// in its group.
+    pub fn into_iter(self) -> MapIntoIter<G, K, V, S>
+    where
+        G: Clone,
+        K: Clone,
+    {
+        MapIntoIter { keys: self.keys, groups: self.groups.into_iter(), current: None, remaining: self.pairs }
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&G, &K, &V)>
+    where
+        G: Ord,
+        K: Ord,
+    {
+        self.iter_sorted_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)))
+    }
+
+    /// List the pairs currently in the collection, sorted using the
+    /// specified comparison function.
+    pub fn iter_sorted_by<'a, F>(&'a self, compare: F) -> impl Iterator<Item = (&'a G, &'a K, &'a V)> + 'a
+    where
+        F: FnMut(&(&'a G, &'a K, &'a V), &(&'a G, &'a K, &'a V)) -> Ordering + 'a,
+    {
+        let mut pairs: Vec<_> = self.iter().collect();
+        pairs.sort_unstable_by(compare);
+        pairs.into_iter()
+    }
+
+    /// List the pairs currently in the collection, grouped by g, with the
+    /// pairs of each group sorted by their payloads using the specified
+    /// comparison function, such as |a, b| b.cmp(a) for the greatest
+    /// counts first.
+    ///
+    /// Each group is sorted only when it is reached.
+    pub fn iter_sorted_by_value<'a, F>(&'a self, mut compare: F) -> impl Iterator<Item = (&'a G, &'a K, &'a V)> + 'a
+    where
+        F: FnMut(&V, &V) -> Ordering + 'a,
+    {
+        let keys = &self.keys;
+        self.groups.iter().flat_map(move |(g, inner)| {
+            let mut pairs: Vec<_> = inner.iter().map(|(id, v)| (keys.key(id), v)).collect();
+            pairs.sort_by(|a, b| compare(a.1, b.1));
+            pairs.into_iter().map(move |(k, v)| (g, k, v))
+        })
+    }
+
+    /// List the pairs in the collection, sorted by the group key and then
+    /// by the aggregation key, consuming the collection.
+    ///
+    /// Each group key is cloned for every pair in its group.
+    pub fn into_iter_sorted(self) -> impl Iterator<Item = (G, K, V)>
+    where
+        G: Ord + Clone,
+        K: Ord + Clone,
+    {
+        let mut pairs: Vec<_> = self.into_iter().collect();
+        pairs.sort_unstable_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        pairs.into_iter()
+    }
+
+    /// Convert the collection into a read-only layout, with the groups in
+    /// order and the pairs of each group in order, that takes less memory
+    /// and is faster to read.
+    ///
+    /// Each aggregation key is cloned for every group where it appears.
+    pub fn freeze(self) -> FrozenBilevelMap<G, K, V>
+    where
+        G: Ord,
+        K: Ord + Clone,
+    {
+        let keys = self.keys;
+        FrozenBilevelMap::from_groups(self.groups.into_iter().map(|(g, inner)| {
+            (g, inner.into_iter().map(|(id, v)| (keys.to_key(id), v)).collect())
+        }))
+    }
+
+    /// List the payloads for the pairs currently in the collection, as iter
+    /// does, but also list each empty group once, with no pair.
+    pub fn iter_with_empty(&self) -> impl Iterator<Item = (&G, Option<(&K, &V)>)> {
+        let keys = &self.keys;
+        self.groups.iter().flat_map(move |(g, inner)| {
+            let empty = inner.is_empty().then_some((g, None));
+            inner.iter().map(move |(id, v)| (g, Some((keys.key(id), v)))).chain(empty)
+        })
+    }
+
+    /// Declare group keys that are expected to appear, so that they are
+    /// listed by iter_with_empty even if no pairs are found for them.
+    pub fn declare_groups<'a, GRef>(&mut self, groups: impl IntoIterator<Item = &'a GRef>)
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized + 'a,
+    {
+        self.invalidate();
+        for g in groups {
+            self.group_entry(g);
+        }
+    }
+
+    /// Declare a group, if new, and return its id.
+    pub(super) fn declare_group<GRef>(&mut self, g: &GRef) -> GroupId
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+    {
+        self.invalidate();
+        let per_group = self.group_capacity();
+        self.groups.find_or_insert_with(S::hash(g), |(o, _)| g.eq(o), || (g.to_owned(), SmallMap::with_capacity(per_group)))
+    }
+
+    /// Remove a pair, returning its payload, or None if the pair is not
+    /// present.
+    ///
+    /// The group stays, even if left empty, as a declared group does.
+    pub fn remove<GRef, KRef>(&mut self, g: &GRef, k: &KRef) -> Option<V>
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+        KRef: PartialEq<K> + Hash + ?Sized,
+    {
+        let id = self.groups.find_id(S::hash(g), |(o, _)| g.eq(o))?;
+        let v = self.keys.find(k).and_then(|k| self.groups.get_mut(id).expect("group just found").1.remove(&k))?;
+        self.invalidate();
+        self.pairs -= 1;
+        Some(v)
+    }
+
+    /// Remove a group, along with its pairs.
+    ///
+    /// Return the group key and the aggregation key and payload of each
+    /// pair, or None if the group is not present.
+    /// The aggregation keys stay stored, so that their ids are not reused.
+    pub fn remove_group<GRef>(&mut self, g: &GRef) -> Option<(G, Vec<(K, V)>)>
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+        K: Clone,
+    {
+        let group = self.groups.remove(S::hash(g), |(o, _)| g.eq(o))?;
+        Some(self.removed(group))
+    }
+
+    /// Remove the group with the specified id, along with its pairs, as
+    /// remove_group does.
+    pub(super) fn remove_group_by_id(&mut self, id: GroupId) -> Option<(G, Vec<(K, V)>)>
+    where
+        K: Clone,
+    {
+        let hash = S::hash(&self.groups.get(id)?.0);
+        let group = self.groups.remove_id(hash, id)?;
+        Some(self.removed(group))
+    }
+
+    /// Account for a group just removed, and list its pairs.
+    fn removed(&mut self, (g, inner): Group<G, S::Id, V, S::Hasher>) -> (G, Vec<(K, V)>)
+    where
+        K: Clone,
+    {
+        self.invalidate();
+        self.pairs -= inner.len();
+        let keys = &self.keys;
+        (g, inner.into_iter().map(|(id, v)| (keys.to_key(id), v)).collect())
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// groups.
+    pub fn reserve_groups(&mut self, additional: usize) {
+        self.groups.reserve(additional);
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// distinct aggregation keys.
+    pub fn reserve_agg_keys(&mut self, additional: usize) {
+        self.keys.reserve(additional);
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// pairs in a group, declaring the group if new.
+    pub fn reserve_in_group<GRef>(&mut self, g: &GRef, additional: usize)
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+    {
+        self.reserve_in_group_with(g, || g.to_owned(), additional);
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// pairs in a group, declaring the group with the key given by to_owned
+    /// if new, for wrappers that look groups up by forms of the key that
+    /// cannot be converted to it with ToOwned.
+    pub(crate) fn reserve_in_group_with<GRef>(&mut self, g: &GRef, to_owned: impl FnOnce() -> G, additional: usize)
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+    {
+        self.invalidate();
+        let per_group = self.group_capacity();
+        self.groups.get_or_insert_with(
+            S::hash(g),
+            |(o, _)| g.eq(o),
+            || (to_owned(), SmallMap::with_capacity(per_group)),
+        ).1.reserve(additional);
+    }
+
+    /// Choose whether each new group is given capacity for the average
+    /// size of the groups found so far, rather than the per_group
+    /// capacity alone.
+    ///
+    /// The per_group capacity is still the least given, so this helps
+    /// where groups tend to grow much larger. The choice is not kept in
+    /// snapshots.
+    pub fn adapt_per_group(&mut self, adaptive: bool) {
+        self.adaptive = adaptive;
+    }
+
+    /// Estimate the memory held by the collection, in bytes, including
+    /// the memory owned by the keys and payloads, such as the contents of
+    /// strings.
+    ///
+    /// This walks every group, so takes time in proportion to the number
+    /// of pairs.
+    pub fn estimated_heap_bytes(&self) -> usize
+    where
+        G: HeapSize,
+        K: HeapSize,
+        V: HeapSize,
+        S::Id: HeapSize,
+    {
+        self.keys.heap_bytes()
+            + self.groups.allocated()
+            + self.groups.iter().map(|(g, inner)| {
+                g.heap_bytes()
+                    + inner.allocated()
+                    + inner.iter().map(|(id, v)| id.heap_bytes() + v.heap_bytes()).sum::<usize>()
+            }).sum::<usize>()
+    }
+
+    /// The capacity to give a new group.
+    fn group_capacity(&self) -> usize {
+        match self.groups.len() {
+            n if self.adaptive && n > 0 => self.per_group.max(self.pairs.div_ceil(n)),
+            _ => self.per_group,
+        }
+    }
+
+    /// Collapse the groups into coarser groups, consuming the collection.
+    ///
+    /// f: maps each group key to the key of the group it is merged into.
+    /// merge: folds a payload into the payload already present when
+    ///     two merged groups share an aggregation key.
+    ///
+    /// The aggregation keys are carried over without being stored again.
+    pub fn regroup_by<G2, F, M>(self, mut f: F, mut merge: M) -> BilevelMap<G2, K, V, S>
+    where
+        G2: Hash + Eq,
+        F: FnMut(&G) -> G2,
+        M: FnMut(&mut V, V),
+    {
+        let per_group = self.per_group;
+        let mut groups: Groups<G2, S::Id, V, S::Hasher> =
+            GroupTable::with_capacity(self.groups.len());
+        for (g, inner) in self.groups {
+            let g2 = f(&g);
+            let hash = S::hash(&g2);
+            let id = match groups.find_id(hash, |(o, _)| g2.eq(o)) {
+                Some(id) => id,
+                None => groups.insert_unique(hash, (g2, SmallMap::with_capacity(per_group))),
+            };
+            let target = &mut groups.get_mut(id).expect("group just found").1;
+            for (id, v) in inner {
+                match target.entry(id) {
+                    Entry::Occupied(target) => merge(target, v),
+                    Entry::Vacant(e) => { e.insert(v); },
+                }
+            }
+        }
+        let mut map = BilevelMap::from_parts(per_group, self.keys, groups, self.constructor);
+        map.adaptive = self.adaptive;
+        map
+    }
+
+    /// Convert the payloads, consuming the collection.
+    ///
+    /// f: computes the new payload from the key pair and the old payload.
+    ///
+    /// The aggregation keys are carried over without being stored again.
+    /// The new collection inserts the default payload for each new pair.
+    pub fn map_values<W, F>(self, f: F) -> BilevelMap<G, K, W, S>
+    where
+        W: Default,
+        F: FnMut(&G, &K, V) -> W,
+    {
+        self.map_values_into(f, Constructor::Fn(W::default))
+    }
+
+    /// Convert the payloads, consuming the collection, with a constructor
+    /// for the payload of each new pair of the new collection.
+    ///
+    /// f: computes the new payload from the key pair and the old payload.
+    /// constructor: A constructor for the payload of each new pair.
+    pub fn map_values_with<W, F>(self, f: F, constructor: impl Fn() -> W + Send + Sync + 'static) -> BilevelMap<G, K, W, S>
+    where
+        F: FnMut(&G, &K, V) -> W,
+    {
+        self.map_values_into(f, Constructor::Closure(Arc::new(constructor)))
+    }
+
+    /// Convert the payloads, consuming the collection, with the specified
+    /// constructor for the new collection.
+    fn map_values_into<W, F>(self, mut f: F, constructor: Constructor<W>) -> BilevelMap<G, K, W, S>
+    where
+        F: FnMut(&G, &K, V) -> W,
+    {
+        let Self { per_group, adaptive, keys, groups: old_groups, .. } = self;
+        let mut groups = GroupTable::with_capacity(old_groups.len());
+        for (g, inner) in old_groups {
+            let inner = inner.into_iter()
+                .map(|(id, v)| {
+                    let w = f(&g, keys.key(&id), v);
+                    (id, w)
+                })
+                .collect();
+            groups.insert_unique(S::hash(&g), (g, inner));
+        }
+        let mut map = BilevelMap::from_parts(per_group, keys, groups, constructor);
+        map.adaptive = adaptive;
+        map
+    }
+
+    /// Find groups with identical sets of aggregation keys, ignoring
+    /// the payloads.
+    ///
+    /// Return each class of two or more equivalent groups.
+    pub fn dedup_groups(&self) -> Vec<Vec<&G>> {
+        // Each distinct key has a single id, so the ids can be compared
+        // in place of the keys.
+        let fingerprints = self.fingerprints.get_or_init(|| {
+            self.groups.iter().map(|(_, inner)| fingerprint(inner.keys())).collect()
+        });
+        equivalence_classes(
+            self.groups.iter().zip(fingerprints).map(|((g, inner), &f)| (g, (f, inner))),
+            |&(f, _)| f,
+            |(_, a), (_, b)| a.len() == b.len() && a.keys().all(|id| b.contains_key(id)),
+        )
+    }
+
+    /// List the n aggregation keys of the specified group with the greatest
+    /// payloads, with their payloads, greatest first.
+    ///
+    /// by: computes the value by which each payload is ranked, such as a
+    ///     count.
+    ///
+    /// Only n pairs are kept as the group is scanned, so the group is not
+    /// sorted. Pairs with equal values are listed in no particular order.
+    pub fn top_k<GRef, B, F>(&self, g: &GRef, n: usize, mut by: F) -> Vec<(&K, &V)>
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+        B: Ord,
+        F: FnMut(&V) -> B,
+    {
+        let keys = &self.keys;
+        let pairs = self.groups.find(S::hash(g), |(o, _)| g.eq(o)).into_iter()
+            .flat_map(move |(_, inner)| inner.iter().map(move |(id, v)| (keys.key(id), v)));
+        top_n(pairs, n, |(_, v)| by(v))
+    }
+
+    /// List the n aggregation keys of each group with the greatest payloads,
+    /// as top_k does for a single group.
+    ///
+    /// Pairs are grouped by g, and listed greatest first within each group.
+    pub fn top_k_all<B, F>(&self, n: usize, mut by: F) -> impl Iterator<Item = (&G, &K, &V)>
+    where
+        B: Ord,
+        F: FnMut(&V) -> B,
+    {
+        let keys = &self.keys;
+        self.groups.iter().flat_map(move |(g, inner)| {
+            let pairs = inner.iter().map(|(id, v)| (keys.key(id), v));
+            top_n(pairs, n, |(_, v)| by(v)).into_iter().map(move |(k, v)| (g, k, v))
+        })
+    }
+
+    /// List the n pairs with the greatest payloads in the whole collection,
+    /// greatest first, whatever their groups.
+    ///
+    /// Only n pairs are kept as the collection is scanned, so the pairs are
+    /// not sorted. Pairs with equal payloads are listed in no particular
+    /// order.
+    pub fn heavy_hitters(&self, n: usize) -> Vec<(&G, &K, &V)>
+    where
+        V: Ord,
+    {
+        top_n(self.iter(), n, |&(_, _, v)| v)
+    }
+
+    /// List the group keys currently in the collection, with the number
+    /// of aggregation keys in each group.
+    pub fn group_sizes(&self) -> impl Iterator<Item = (&G, usize)> {
+        self.groups.iter().map(|(g, inner)| (g, inner.len()))
+    }
+
+    /// Count the groups by their number of aggregation keys, such as to
+    /// choose Capacity::per_group.
+    ///
+    /// buckets: the greatest size counted in each bucket, in ascending
+    ///     order. A final bucket counts the groups larger than the last of
+    ///     these.
+    ///
+    /// Return the number of groups in each bucket.
+    pub fn size_histogram(&self, buckets: &[usize]) -> Vec<usize> {
+        size_histogram(self.group_sizes().map(|(_, n)| n), buckets)
+    }
+
+    /// Render the collection as a text table, with a heading for each
+    /// group followed by its aggregation keys and payloads in aligned columns,
+    /// such as for debugging or the output of a command.
+    ///
+    /// The groups, and the keys of each group, are listed in the order of
+    /// their text, so that the same contents are always rendered the same
+    /// way.
+    pub fn render_table(&self, options: &crate::TableOptions) -> String
+    where
+        G: std::fmt::Display,
+        K: std::fmt::Display,
+        V: std::fmt::Display,
+    {
+        let keys = &self.keys;
+        crate::table::render(options, self.groups.iter().map(|(g, inner)| {
+            (g, inner.iter().map(|(id, v)| (keys.key(id), Some(v))))
+        }))
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Count the pairs currently in the collection.
+    ///
+    /// The count is kept as pairs are added, so this takes constant time.
+    pub fn pair_count(&self) -> usize {
+        self.pairs
+    }
+
+    /// Count the pairs currently in the collection, as pair_count does.
+    pub fn len(&self) -> usize {
+        self.pairs
+    }
+
+    /// Check whether the collection holds no pairs, although it may hold
+    /// empty groups.
+    pub fn is_empty(&self) -> bool {
+        self.pairs == 0
+    }
+
+    /// Count the pairs of the specified group, which is 0 if the group is
+    /// not present.
+    pub fn group_len<GRef>(&self, g: &GRef) -> usize
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+    {
+        self.groups.find(S::hash(g), |(o, _)| g.eq(o)).map_or(0, |(_, inner)| inner.len())
+    }
+
+    /// Find the id of a group, or None if the group is not present.
+    ///
+    /// A group keeps its id for as long as it is in the collection.
+    pub fn group_id<GRef>(&self, g: &GRef) -> Option<GroupId>
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+    {
+        self.groups.find_id(S::hash(g), |(o, _)| g.eq(o))
+    }
+
+    /// Get the key of the group with the specified id, or None if no
+    /// group has the id.
+    pub fn resolve_group(&self, id: GroupId) -> Option<&G> {
+        self.groups.get(id).map(|(g, _)| g)
+    }
+
+    /// List the group keys currently in the collection, with their ids.
+    pub fn group_ids(&self) -> impl Iterator<Item = (GroupId, &G)> {
+        self.groups.iter_with_ids().map(|(id, (g, _))| (id, g))
+    }
+
+    /// Discard the values derived from the current contents.
+    fn invalidate(&mut self) {
+        self.fingerprints.invalidate();
+    }
+}
+
+impl<G: Hash, K: Hash, V> BilevelMap<G, K, V, Interned<K>> {
+    /// List the payloads for the pairs currently in the collection, as
+    /// iter does, along with the ids of the group and aggregation key of
+    /// each.
+    pub fn iter_with_ids(&self) -> impl Iterator<Item = (GroupId, &G, AggKeyId, &K, &V)> {
+        let keys = self.keys.as_slice();
+        self.groups.iter_with_ids().flat_map(move |(id, (g, inner))| {
+            inner.iter().map(move |(&i, v)| (id, g, AggKeyId(i), &keys[i], v))
+        })
+    }
+
+    /// List the aggregation keys with their ids, in the order of the ids.
+    ///
+    /// Each distinct aggregation key is given an id when first found,
+    /// which does not change for the life of the collection.
+    pub fn id_mapping(&self) -> impl ExactSizeIterator<Item = (AggKeyId, &K)> {
+        self.keys.as_slice().iter().enumerate().map(|(i, k)| (AggKeyId(i), k))
+    }
+
+    /// Count the distinct aggregation keys found in any group.
+    ///
+    /// Each distinct key is stored once, so this takes constant time.
+    pub fn distinct_agg_keys(&self) -> usize {
+        self.keys.as_slice().len()
+    }
+
+    /// Find the id of an aggregation key, or None if the key has not been
+    /// found in any group.
+    pub fn agg_key_id<KRef>(&self, k: &KRef) -> Option<AggKeyId>
+    where
+        KRef: PartialEq<K> + Hash + ?Sized,
+    {
+        self.keys.id_of(k).map(AggKeyId)
+    }
+
+    /// Get the aggregation key with the specified id, or None if no key
+    /// has the id.
+    pub fn resolve_agg_key(&self, id: AggKeyId) -> Option<&K> {
+        self.keys.as_slice().get(id.0)
+    }
+
+    /// Get a mutable reference to the payload for a pair given by the ids
+    /// of its group and aggregation key, such as ids passed from another
+    /// system, without copying either key.
+    ///
+    /// If the pair is currently not present, a new payload is inserted.
+    ///
+    /// Return None if either id is not of this collection, such as the id
+    /// of a group removed since.
+    pub fn add_or_get_by_id(&mut self, g: GroupId, k: AggKeyId) -> Option<&mut V> {
+        if k.0 >= self.keys.as_slice().len() || self.groups.get(g).is_none() {
+            return None;
+        }
+        // The payload may be modified through the reference returned.
+        self.invalidate();
+        let constructor = &self.constructor;
+        let pairs = &mut self.pairs;
+        let (_, inner) = self.groups.get_mut(g).expect("group just found");
+        Some(inner.entry(k.0).or_insert_with(|| {
+            *pairs += 1;
+            constructor.construct()
+        }))
+    }
+
+    /// Copy the payloads into a sparse matrix in compressed sparse row
+    /// form, with a row for each group, including any empty group, and a
+    /// column for each aggregation key, numbered by id.
+    ///
+    /// The group of each row is listed in the groups of the matrix.
+    pub fn to_csr(&self) -> CsrMatrix<'_, G, V>
+    where
+        V: Clone,
+    {
+        let mut csr = CsrMatrix {
+            groups: Vec::with_capacity(self.groups.len()),
+            offsets: Vec::with_capacity(self.groups.len() + 1),
+            indices: Vec::with_capacity(self.pairs),
+            values: Vec::with_capacity(self.pairs),
+            columns: self.keys.as_slice().len(),
+        };
+        csr.offsets.push(0);
+        for (g, inner) in self.groups.iter() {
+            let mut row: Vec<_> = inner.iter().collect();
+            row.sort_unstable_by_key(|&(&i, _)| i);
+            csr.groups.push(g);
+            csr.indices.extend(row.iter().map(|&(&i, _)| i));
+            csr.values.extend(row.into_iter().map(|(_, v)| v.clone()));
+            csr.offsets.push(csr.indices.len());
+        }
+        csr
+    }
+}
+
+impl<G, K, V, S> BilevelMap<G, K, V, S>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    S: KeyStore<K>,
+{
+    /// Convert into a single-level map keyed by the key pair,
+    /// consuming the collection.
+    pub fn into_flat(self) -> HashMap<(G, K), V> {
+        self.into_iter().map(|(g, k, v)| ((g, k), v)).collect()
+    }
+
+    /// Create a new collection from a single-level map keyed by the key pair.
+    pub fn from_flat(flat: HashMap<(G, K), V>) -> Self
+    where
+        V: Default,
+    {
+        let mut map = Self::new();
+        for ((g, k), v) in flat {
+            *map.add_or_get(&g, &k) = v;
+        }
+        map
+    }
+
+    /// Merge another collection into this one, consuming it.
+    ///
+    /// combine: folds a payload from the other collection into the payload
+    ///     already present when both collections hold the same pair.
+    pub fn merge<F>(&mut self, other: Self, mut combine: F)
+    where
+        F: FnMut(&mut V, V),
+    {
+        self.invalidate();
+        for (g, inner) in other.groups {
+            let inner: Vec<_> = inner.into_iter()
+                .map(|(id, v)| (self.keys.intern(other.keys.key(&id)), v))
+                .collect();
+            let (target, pairs, _) = self.group_entry(&g);
+            for (id, v) in inner {
+                match target.entry(id) {
+                    Entry::Occupied(target) => combine(target, v),
+                    Entry::Vacant(e) => {
+                        *pairs += 1;
+                        e.insert(v);
+                    },
+                }
+            }
+        }
+    }
+
+    /// Add rows of key pairs and payloads to the collection.
+    ///
+    /// combine: folds the payload of a row into the payload already present
+    ///     when the collection already holds its pair, including a pair
+    ///     found earlier in the same rows.
+    pub fn extend_with<F>(&mut self, rows: impl IntoIterator<Item = (G, K, V)>, mut combine: F)
+    where
+        F: FnMut(&mut V, V),
+    {
+        self.invalidate();
+        for (g, k, v) in rows {
+            let id = self.keys.intern(&k);
+            let (inner, pairs, _) = self.group_entry(&g);
+            match inner.entry(id) {
+                Entry::Occupied(target) => combine(target, v),
+                Entry::Vacant(e) => {
+                    *pairs += 1;
+                    e.insert(v);
+                },
+            }
+        }
+    }
+}
+
+impl<G, K, V, S> BilevelMap<G, K, V, S>
+where
+    G: Hash + Eq,
+    K: Hash + Eq + Clone,
+    S: KeyStore<K>,
+{
+    /// Convert into a nested map of the groups, consuming the collection.
+    pub fn into_nested(self) -> HashMap<G, HashMap<K, V>> {
+        let keys = self.keys;
+        self.groups.into_iter()
+            .map(|(g, inner)| {
+                (g, inner.into_iter().map(|(id, v)| (keys.to_key(id), v)).collect())
+            })
+            .collect()
+    }
+}
+
+impl<G, K, V, S> From<HashMap<G, HashMap<K, V>>> for BilevelMap<G, K, V, S>
+where
+    G: Hash + Eq,
+    K: Hash + Eq + Clone,
+    V: Default,
+    S: KeyStore<K>,
+{
+    fn from(nested: HashMap<G, HashMap<K, V>>) -> Self {
+        let mut map = Self::new();
+        for (g, inner) in nested {
+            let inner: SmallMap<_, _, S::Hasher> = inner.into_iter().map(|(k, v)| (map.keys.intern(&k), v)).collect();
+            map.pairs += inner.len();
+            map.groups.insert_unique(S::hash(&g), (g, inner));
+        }
+        map
+    }
+}
+
+impl<G, K, V, S> BilevelMap<G, K, V, S>
+where
+    G: Hash,
+    V: Copy,
+    S: KeyStore<K>,
+    usize: TryFrom<V>,
+{
+    /// Expand a map of counters back into a stream of key pairs, with
+    /// each pair repeated as many times as its count.
+    ///
+    /// Counts that cannot be represented as a usize, such as negative
+    /// counts, are treated as zero.
+    pub fn expand(&self) -> impl Iterator<Item = (&G, &K)> {
+        self.iter()
+            .flat_map(|(g, k, &v)| repeat_n((g, k), usize::try_from(v).unwrap_or(0)))
+    }
+
+    /// Expand a map of counters back into a stream of key pairs, as
+    /// expand does, but with the pairs listed in sorted order.
+    pub fn expand_sorted(&self) -> impl Iterator<Item = (&G, &K)>
+    where
+        G: Ord,
+        K: Ord,
+    {
+        self.iter_sorted()
+            .flat_map(|(g, k, &v)| repeat_n((g, k), usize::try_from(v).unwrap_or(0)))
+    }
+
+    /// Measure how the counts of each group are spread over its aggregation
+    /// keys, by their entropy, Gini coefficient and the share of the key
+    /// with the greatest count.
+    ///
+    /// Counts that cannot be represented as a usize, such as negative
+    /// counts, are treated as zero.
+    pub fn group_metrics(&self) -> impl Iterator<Item = (&G, GroupMetrics)> {
+        self.groups.iter().map(|(g, inner)| {
+            let counts = inner.values().map(|&v| usize::try_from(v).unwrap_or(0));
+            (g, GroupMetrics::from_counts(counts))
+        })
+    }
+}
+
+impl<G: Hash, K, V: Hash + Eq, S: KeyStore<K>> BilevelMap<G, K, V, S> {
+    /// Find groups with identical sets of aggregation keys and identical
+    /// payloads for each key.
+    ///
+    /// Return each class of two or more equivalent groups.
+    pub fn dedup_groups_with_values(&self) -> Vec<Vec<&G>> {
+        equivalence_classes(
+            self.groups.iter().map(|(g, inner)| (g, inner)),
+            |inner| fingerprint(inner.iter()),
+            |a, b| a == b,
+        )
+    }
+}
+
+impl<G, K, V, S> BilevelMap<G, K, V, S>
+where
+    G: Clone + PartialEq + Hash,
+    K: Clone + PartialEq + Hash,
+    V: Clone,
+    S: KeyStore<K>,
+{
+    /// Copy the data into a new collection that groups by the aggregation
+    /// key, storing the group keys, which become the aggregation keys,
+    /// as the strategy S2 does.
+    pub fn pivot<S2: KeyStore<G>>(&self) -> BilevelMap<K, G, V, S2> {
+        // Pre-allocate capacity assuming approximate symmetry.
+        let capacity = Capacity {
+            groups: self.groups.len(),
+            agg_keys: self.groups.len(),
+            per_group: self.per_group,
+        };
+        let mut pivoted = BilevelMap::with_constructor(capacity, S2::with_capacity(capacity.agg_keys), self.constructor.clone());
+        for (g, k, v) in self.iter() {
+            pivoted.add_or_get(k, g).clone_from(v);
+        }
+        pivoted
+    }
+}
+
+impl<G, K, V, S> std::fmt::Debug for BilevelMap<G, K, V, S>
+where
+    G: Hash + std::fmt::Debug,
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+    S: KeyStore<K>,
+{
+    /// Format the collection as a map from each group key to a map of its
+    /// aggregation keys and payloads, listing only the first few groups,
+    /// and keys of each group, of a large collection.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::debug::debug_map(f, self.iter_groups())
+    }
+}
+
+impl<G, K, V, S> Clone for BilevelMap<G, K, V, S>
+where
+    G: Clone,
+    V: Clone,
+    S: KeyStore<K> + Clone,
+    S::Id: Clone,
+{
+    /// Copy the collection, including its store of aggregation keys.
+    ///
+    /// Values derived from the contents are not copied, but derived again
+    /// when next needed.
+    fn clone(&self) -> Self {
+        Self {
+            per_group: self.per_group,
+            adaptive: self.adaptive,
+            keys: self.keys.clone(),
+            groups: self.groups.clone(),
+            pairs: self.pairs,
+            constructor: self.constructor.clone(),
+            fingerprints: Derived::new(),
+            #[cfg(feature = "snapshot")]
+            delta_base: self.delta_base.clone(),
+            key: PhantomData,
+        }
+    }
+}
+
+impl<G, K, V, S> PartialEq for BilevelMap<G, K, V, S>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+    V: PartialEq,
+    S: KeyStore<K>,
+{
+    /// Check whether two collections hold the same groups, with the same
+    /// pairs and payloads, whatever the order of the groups and the ids
+    /// given to the aggregation keys.
+    fn eq(&self, other: &Self) -> bool {
+        self.pairs == other.pairs && self.groups.len() == other.groups.len()
+            && self.groups.iter().all(|(g, inner)| {
+                let Some((_, theirs)) = other.groups.find(S::hash(g), |(o, _)| g.eq(o)) else {
+                    return false;
+                };
+                let theirs: HashMap<&K, &V> = theirs.iter().map(|(id, v)| (other.keys.key(id), v)).collect();
+                inner.len() == theirs.len()
+                    && inner.iter().all(|(id, v)| theirs.get(self.keys.key(id)) == Some(&v))
+            })
+    }
+}
+
+impl<G: Hash + Eq, K: Hash + Eq, V: Eq, S: KeyStore<K>> Eq for BilevelMap<G, K, V, S> {}
+
+#[cfg(feature = "derive")]
+impl<G, K, V, S> BilevelMap<G, K, V, S>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    S: KeyStore<K>,
+{
+    /// Get a mutable reference to the payload for the key pair of a row.
+    ///
+    /// If the key pair is currently not present, a new payload is inserted.
+    pub fn add_or_get_row<R: crate::BilevelKey<Group = G, Agg = K>>(&mut self, row: &R) -> &mut V {
+        self.add_or_get(&row.group(), &row.agg())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<G, K, V, S> serde::Serialize for BilevelMap<G, K, V, S>
+where
+    G: serde::Serialize,
+    K: serde::Serialize,
+    V: serde::Serialize,
+    S: KeyStore<K>,
+{
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        use crate::serde_support::MapOf;
+        let keys = &self.keys;
+        serializer.collect_map(self.groups.iter().map(|(g, inner)| {
+            (g, MapOf(move || inner.iter().map(move |(id, v)| (keys.key(id), v))))
+        }))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, G, K, V, S> serde::Deserialize<'de> for BilevelMap<G, K, V, S>
+where
+    G: Hash + Eq + serde::Deserialize<'de>,
+    K: Hash + Eq + Clone + serde::Deserialize<'de>,
+    V: Default + serde::Deserialize<'de>,
+    S: KeyStore<K>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashMap::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<G: Hash, K, V, S: KeyStore<K>> BilevelMap<G, K, V, S> {
+    /// Write the collection as a JSON object, from each group key to an
+    /// object from each aggregation key to its payload.
+    ///
+    /// The groups, and the keys of each group, are written in order, so
+    /// that the same contents are always written the same way.
+    pub fn to_json_grouped(&self, writer: impl std::io::Write) -> Result<(), serde_json::Error>
+    where
+        G: Ord + serde::Serialize,
+        K: Ord + serde::Serialize,
+        V: serde::Serialize,
+    {
+        let keys = &self.keys;
+        crate::serde_support::write_json_map(writer, self.groups.iter().map(|(g, inner)| {
+            (g, inner.iter().map(|(id, v)| (keys.key(id), v)).collect())
+        }))
+    }
+
+    /// Add the rows of JSON Lines text, such as a log, to the collection,
+    /// adding the payload of each row to the payload for its key pair.
+    ///
+    /// group_fields: the fields of the group key, read into a tuple if
+    ///     there are several. A name starting with / is a JSON pointer to
+    ///     a nested field, such as /request/host.
+    /// key_fields: the fields of the aggregation key, read likewise.
+    /// value_field: the field of the payload to add.
+    ///
+    /// Blank lines are skipped. A line that is not a JSON object, or lacks
+    /// a field, is an error, but the rows before it are kept.
+    pub fn extend_from_jsonl(
+        &mut self,
+        reader: impl std::io::BufRead,
+        group_fields: &[&str],
+        key_fields: &[&str],
+        value_field: &str,
+    ) -> Result<(), serde_json::Error>
+    where
+        G: Eq + Clone + serde::de::DeserializeOwned,
+        K: Hash + Eq + Clone + serde::de::DeserializeOwned,
+        V: std::ops::AddAssign + serde::de::DeserializeOwned,
+    {
+        crate::serde_support::read_jsonl(reader, group_fields, key_fields, value_field, |g: G, k: K, v| {
+            *self.add_or_get(&g, &k) += v;
+        })
+    }
+}
+
+#[cfg(feature = "json")]
+impl<G: Hash, K: Hash, V> BilevelMap<G, K, V, Interned<K>> {
+    /// Write the collection as a JSON array, with an object for each pair
+    /// holding the ids of its group and aggregation key along with the
+    /// keys and payload, such as
+    /// {"group_id":[0,0],"group":"web1","key_id":0,"key":"200","value":5}.
+    ///
+    /// The pairs are listed as iter_with_ids lists them.
+    pub fn to_json_with_ids(&self, writer: impl std::io::Write) -> Result<(), serde_json::Error>
+    where
+        G: serde::Serialize,
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        serde_json::to_writer(writer, &crate::serde_support::SeqOf(|| {
+            self.iter_with_ids().map(|(group_id, group, key_id, key, v)| {
+                crate::serde_support::IdRow { group_id, group, key_id, key, value: Some(v) }
+            })
+        }))
+    }
+}
+
+#[cfg(feature = "csv")]
+impl<G: Hash, K, V, S: KeyStore<K>> BilevelMap<G, K, V, S> {
+    /// Write the collection as delimited text, such as CSV or TSV, with a
+    /// row for each pair and its payload, and optionally the ids of the
+    /// group and aggregation key.
+    ///
+    /// Pairs are grouped by g.
+    pub fn write_csv(&self, writer: impl std::io::Write, options: &crate::CsvOptions) -> std::io::Result<()>
+    where
+        G: std::fmt::Display,
+        K: std::fmt::Display,
+        V: std::fmt::Display,
+    {
+        let keys = &self.keys;
+        let rows = self.groups.iter_with_ids().flat_map(|(group_id, (g, inner))| {
+            inner.iter().map(move |(id, v)| (group_id, S::agg_key_id(id), [g as _, keys.key(id) as _, v as _]))
+        });
+        crate::csv::write_rows(writer, options, rows)
+    }
+}
+
+#[cfg(feature = "frozen")]
+impl<G: Hash, K, V, S: KeyStore<K>> BilevelMap<G, K, V, S> {
+    /// Write the collection in a frozen format, to be read by FrozenMap
+    /// without being deserialized, such as from a file mapped into memory.
+    ///
+    /// The groups are written in order of the bytes of their keys, and the
+    /// pairs of each group in order of the bytes of their aggregation keys.
+    pub fn write_frozen(&self, writer: impl std::io::Write) -> std::io::Result<()>
+    where
+        G: AsRef<[u8]>,
+        K: AsRef<[u8]>,
+        V: crate::FrozenValue,
+    {
+        crate::frozen::write(writer, self.iter_groups().map(|(g, pairs)| {
+            (g.as_ref(), pairs.map(|(k, v)| (k.as_ref(), v)).collect())
+        }))
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl<G: Hash, K, V, S: KeyStore<K>> BilevelMap<G, K, V, S> {
+    /// Convert the collection into an Arrow record batch, with a row for
+    /// each pair, in group, key and value columns.
+    ///
+    /// Pairs are grouped by g.
+    pub fn to_record_batch(&self) -> Result<arrow_array::RecordBatch, arrow_schema::ArrowError>
+    where
+        G: crate::ArrowValue,
+        K: crate::ArrowValue,
+        V: crate::ArrowValue,
+    {
+        crate::arrow::record_batch(self.iter())
+    }
+
+    /// Write the collection to a Parquet file, with a row for each pair,
+    /// in group, key and value columns.
+    ///
+    /// Pairs are grouped by g.
+    #[cfg(feature = "parquet")]
+    pub fn write_parquet(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        options: &crate::ParquetOptions,
+    ) -> Result<(), parquet::errors::ParquetError>
+    where
+        G: crate::ArrowValue,
+        K: crate::ArrowValue,
+        V: crate::ArrowValue,
+    {
+        let keys = &self.keys;
+        crate::parquet::write_groups(path.as_ref(), options, self.groups.iter().map(|(g, inner)| {
+            inner.iter().map(move |(id, v)| (g, keys.key(id), v))
+        }))
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl<G: Hash, K: Hash, V> BilevelMap<G, K, V, Interned<K>> {
+    /// Convert the collection into an Arrow record batch, as
+    /// to_record_batch does, with the ids of the group and aggregation key
+    /// of each pair in group_id, group_generation and key_id columns before
+    /// the others.
+    ///
+    /// The pairs are listed as iter_with_ids lists them.
+    pub fn to_record_batch_with_ids(&self) -> Result<arrow_array::RecordBatch, arrow_schema::ArrowError>
+    where
+        G: crate::ArrowValue,
+        K: crate::ArrowValue,
+        V: crate::ArrowValue,
+    {
+        crate::arrow::record_batch_with_ids(self.iter_with_ids())
+    }
+}
+
+#[cfg(feature = "polars")]
+impl<G: Hash, K, V, S: KeyStore<K>> BilevelMap<G, K, V, S> {
+    /// Build a collection from the rows of a Polars DataFrame, adding the
+    /// values of rows with the same key pair together.
+    ///
+    /// group_cols: the columns of the group key, one for each field of G
+    ///     if it is a tuple.
+    /// key_cols: the columns of the aggregation key, one for each field of
+    ///     K if it is a tuple.
+    /// value_col: the column of the values to add.
+    pub fn from_dataframe(
+        df: &polars::prelude::DataFrame,
+        group_cols: &[&str],
+        key_cols: &[&str],
+        value_col: &str,
+    ) -> polars::prelude::PolarsResult<Self>
+    where
+        G: Eq + Clone + crate::PolarsValue,
+        K: Hash + Eq + Clone + crate::PolarsValue,
+        V: Default + std::ops::AddAssign + crate::PolarsValue,
+    {
+        let mut map = Self::new();
+        for (g, k, v) in crate::polars::read_rows::<G, K, V>(df, group_cols, key_cols, value_col)? {
+            *map.add_or_get(&g, &k) += v;
+        }
+        Ok(map)
+    }
+
+    /// Convert the collection into a Polars DataFrame, with a row for each
+    /// pair, in group, key and value columns.
+    ///
+    /// A group or key of several columns is written to columns numbered
+    /// from 0, such as group_0 and group_1. Pairs are grouped by g.
+    pub fn to_dataframe(&self) -> polars::prelude::PolarsResult<polars::prelude::DataFrame>
+    where
+        G: crate::PolarsValue,
+        K: crate::PolarsValue,
+        V: crate::PolarsValue,
+    {
+        crate::polars::data_frame(self.iter())
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl<G: Hash, K, V, S: KeyStore<K>> BilevelMap<G, K, V, S> {
+    /// Insert a row for each pair into an SQLite table with group, key and
+    /// value columns, creating the table if it does not exist.
+    ///
+    /// The rows are inserted in a single transaction.
+    pub fn to_sqlite(&self, conn: &rusqlite::Connection, table: &str) -> rusqlite::Result<()>
+    where
+        G: rusqlite::ToSql,
+        K: rusqlite::ToSql,
+        V: rusqlite::ToSql,
+    {
+        crate::sqlite::write_rows(conn, table, self.iter().map(|(g, k, v)| [g as _, k as _, v as _]))
+    }
+
+    /// Build a collection from the results of an SQLite query, adding the
+    /// payloads of rows with the same key pair together.
+    ///
+    /// mapping: reads the group key, aggregation key and payload of a row,
+    ///     such as sqlite_row, which reads the first three columns.
+    pub fn from_sqlite<F>(conn: &rusqlite::Connection, query: &str, mut mapping: F) -> rusqlite::Result<Self>
+    where
+        G: Eq + Clone,
+        K: Hash + Eq + Clone,
+        V: Default + std::ops::AddAssign,
+        F: FnMut(&rusqlite::Row) -> rusqlite::Result<(G, K, V)>,
+    {
+        let mut map = Self::new();
+        crate::sqlite::read_rows(conn, query, |row| {
+            let (g, k, v) = mapping(row)?;
+            *map.add_or_get(&g, &k) += v;
+            Ok(())
+        })?;
+        Ok(map)
+    }
+}
+
+#[cfg(feature = "snapshot")]
+impl<G, K, V, S> BilevelMap<G, K, V, S>
+where
+    G: Hash + Eq,
+    V: Default,
+    S: KeyStore<K>,
+{
+    /// Write a binary snapshot of the collection, including the store of
+    /// aggregation keys.
+    ///
+    /// The constructor of new payloads and the GroupIds are not written,
+    /// as read_snapshot describes.
+    pub fn write_snapshot(&self, writer: impl std::io::Write) -> Result<(), crate::SnapshotError>
+    where
+        G: serde::Serialize,
+        V: serde::Serialize,
+        S: serde::Serialize,
+        S::Id: serde::Serialize,
+    {
+        let groups: Vec<_> = self.groups.iter().collect();
+        crate::snapshot::write(writer, &(self.per_group, &self.keys, groups))
+    }
+
+    /// Read a collection from a binary snapshot written by write_snapshot.
+    ///
+    /// The snapshot keeps neither the constructor of new payloads nor the
+    /// GroupIds: the collection inserts the default payload for each new
+    /// pair, whatever the constructor of the collection written, and its
+    /// groups are given new ids, so ids taken before the snapshot do not
+    /// carry over.
+    pub fn read_snapshot(reader: impl std::io::Read) -> Result<Self, crate::SnapshotError>
+    where
+        G: serde::de::DeserializeOwned,
+        V: serde::de::DeserializeOwned,
+        S: serde::de::DeserializeOwned,
+        S::Id: serde::de::DeserializeOwned,
+    {
+        let (per_group, keys, group_list) =
+            crate::snapshot::read::<(usize, S, Vec<Group<G, S::Id, V, S::Hasher>>)>(reader)?;
+        Self::from_snapshot(per_group, keys, group_list)
+    }
+
+    /// Read a collection from a binary snapshot written by write_snapshot,
+    /// keeping the groups read before any error in reading the groups.
+    ///
+    /// Return the groups that could be read, with a report of those that
+    /// could not, if any. A snapshot whose header or other contents cannot
+    /// be read is still an error.
+    pub fn read_snapshot_partial(
+        reader: impl std::io::Read,
+    ) -> Result<(Self, Option<crate::MissingGroups>), crate::SnapshotError>
+    where
+        G: serde::de::DeserializeOwned,
+        V: serde::de::DeserializeOwned,
+        S: serde::de::DeserializeOwned,
+        S::Id: serde::de::DeserializeOwned,
+    {
+        let (per_group, keys, group_list) =
+            crate::snapshot::read::<(usize, S, crate::snapshot::Lenient<Group<G, S::Id, V, S::Hasher>>)>(reader)?;
+        let (group_list, missing) = group_list.finish();
+        Ok((Self::from_snapshot(per_group, keys, group_list)?, missing))
+    }
+
+    /// Take the changes to the collection since the last delta was taken,
+    /// or since it was created, as a delta that can be merged into another
+    /// collection by apply_delta.
+    ///
+    /// The delta holds each pair added, or whose payload changed, with its
+    /// current payload, and each pair and group removed. The payloads are
+    /// left as they are. To find the changes, the collection keeps a copy
+    /// of its pairs as of the last delta, so taking deltas doubles the
+    /// memory the pairs take.
+    pub fn take_delta(&mut self) -> Result<Vec<u8>, crate::SnapshotError>
+    where
+        G: Clone + serde::Serialize,
+        K: serde::Serialize,
+        V: Clone + PartialEq + serde::Serialize,
+        S::Id: Clone,
+    {
+        let base = self.delta_base.take().unwrap_or_default();
+        let keys = &self.keys;
+        let mut changes: Vec<Change<&G, &K, &V>> = base.iter()
+            .filter(|(&id, _)| self.groups.get(id).is_none())
+            .map(|(_, (g, _))| Change::RemoveGroup(g))
+            .collect();
+        for (id, (g, inner)) in self.groups.iter_with_ids() {
+            let last = base.get(&id).map(|(_, pairs)| pairs);
+            if let Some(last) = last {
+                changes.extend(last.keys()
+                    .filter(|k| !inner.contains_key(*k))
+                    .map(|k| Change::RemovePair(g, keys.key(k))));
+            }
+            changes.extend(inner.iter()
+                .filter(|(k, v)| last.and_then(|last| last.get(*k)) != Some(*v))
+                .map(|(k, v)| Change::Pair(g, keys.key(k), v)));
+        }
+        let delta = crate::snapshot::write_delta(&changes)?;
+        self.delta_base = Some(self.groups.iter_with_ids()
+            .map(|(id, (g, inner))| (id, (g.clone(), inner.iter().map(|(k, v)| (k.clone(), v.clone())).collect())))
+            .collect());
+        Ok(delta)
+    }
+
+    /// Merge a delta taken from another collection by take_delta, adding
+    /// the pairs it holds and removing the pairs and groups it removes.
+    ///
+    /// combine: folds the current payload of a pair from the delta into
+    ///     the payload here, which is new if the pair was not already
+    ///     present, such as by replacing it, to keep a copy of the other
+    ///     collection.
+    pub fn apply_delta<F>(&mut self, delta: &[u8], mut combine: F) -> Result<(), crate::SnapshotError>
+    where
+        G: Clone + serde::de::DeserializeOwned,
+        K: Clone + PartialEq + Hash + serde::de::DeserializeOwned,
+        V: serde::de::DeserializeOwned,
+        F: FnMut(&mut V, V),
+    {
+        for change in crate::snapshot::read_delta::<Change<G, K, V>>(delta)? {
+            match change {
+                Change::Pair(g, k, v) => combine(self.add_or_get(&g, &k), v),
+                Change::RemovePair(g, k) => {
+                    self.remove(&g, &k);
+                },
+                Change::RemoveGroup(g) => {
+                    self.remove_group(&g);
+                },
+            }
+        }
+        Ok(())
+    }
+
+    /// Check the contents read from a snapshot, and rebuild the collection.
+    fn from_snapshot(
+        per_group: usize,
+        keys: S,
+        group_list: Vec<Group<G, S::Id, V, S::Hasher>>,
+    ) -> Result<Self, crate::SnapshotError> {
+        let groups = crate::snapshot::group_table(per_group, group_list, S::hash::<G>, |inner| {
+            inner.keys().all(|id| keys.contains(id))
+        })?;
+        Ok(Self::from_parts(per_group, keys, groups, Constructor::Fn(V::default)))
+    }
+}
+
+#[cfg(feature = "replay")]
+impl<G, K, V, S> crate::Replay<G, K, V> for BilevelMap<G, K, V, S>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    V: Clone + std::ops::AddAssign,
+    S: KeyStore<K>,
+{
+    fn apply(&mut self, mutation: crate::Mutation<&G, &K, &V>) {
+        match mutation {
+            crate::Mutation::DeclareGroup(g) => self.declare_groups([g]),
+            crate::Mutation::Insert(g, k) => { self.add_or_get(g, k); }
+            crate::Mutation::Update(g, k, v) => *self.add_or_get(g, k) += v.clone(),
+            crate::Mutation::Remove(g, k) => { self.remove(g, k); }
+            crate::Mutation::RemoveGroup(g) => { self.remove_group(g); }
+        }
+    }
+}
+
+#[cfg(feature = "tenant")]
+impl<G: Hash, K, V, S: KeyStore<K>> crate::TenantData for BilevelMap<G, K, V, S> {
+    type Item<'a> = (&'a G, &'a K, &'a V) where Self: 'a;
+
+    fn pairs(&self) -> impl Iterator<Item = (&G, &K, &V)> {
+        self.iter()
+    }
+
+    fn group_count(&self) -> usize {
+        self.group_count()
+    }
+
+    fn pair_count(&self) -> usize {
+        self.pair_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.keys.memory_usage()
+            + self.groups.allocated()
+            + self.groups.iter().map(|(_, inner)| inner.allocated()).sum::<usize>()
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<G, K, V, S> BilevelMap<G, K, V, S>
+where
+    G: Hash + Send + Sync,
+    K: Sync,
+    S: KeyStore<K> + Sync,
+    S::Id: Send + Sync,
+{
+    /// List the pairs currently in the collection in parallel, splitting
+    /// the work between threads by group.
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (&G, &K, &V)>
+    where
+        V: Sync,
+    {
+        use rayon::prelude::*;
+        let keys = &self.keys;
+        self.groups.par_iter()
+            .flat_map_iter(move |(g, inner)| inner.iter().map(move |(id, v)| (g, keys.key(id), v)))
+    }
+
+    /// List the payloads for the pairs currently in the collection in
+    /// parallel, for modification, splitting the work between threads
+    /// by group.
+    pub fn par_iter_mut(&mut self) -> impl rayon::iter::ParallelIterator<Item = (&G, &K, &mut V)>
+    where
+        V: Send,
+    {
+        use rayon::prelude::*;
+        self.invalidate();
+        let keys = &self.keys;
+        self.groups.par_iter_mut()
+            .flat_map_iter(move |(g, inner)| {
+                let g = &*g;
+                inner.iter_mut().map(move |(id, v)| (g, keys.key(id), v))
+            })
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<G, K, V, S> crate::Shard for BilevelMap<G, K, V, S>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    S: KeyStore<K>,
+{
+    type Payload = V;
+
+    fn merge_shard<F>(&mut self, other: Self, combine: F)
+    where
+        F: FnMut(&mut V, V),
+    {
+        self.merge(other, combine)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<G, K, V, S> rayon::iter::ParallelExtend<(G, K, V)> for BilevelMap<G, K, V, S>
+where
+    G: Hash + Eq + Clone + Send,
+    K: Hash + Eq + Clone + Send,
+    V: Default + std::ops::AddAssign + Send,
+    S: KeyStore<K> + Send,
+    S::Id: Send,
+{
+    /// Add the payloads of the rows to the payloads for their key pairs,
+    /// with each worker thread summing its rows into a map of its own.
+    fn par_extend<I>(&mut self, rows: I)
+    where
+        I: rayon::iter::IntoParallelIterator<Item = (G, K, V)>,
+    {
+        let collector = crate::ShardedCollector::new(
+            Self::new,
+            |map: &mut Self, (g, k, v)| *map.add_or_get(&g, &k) += v,
+            |a: &mut V, b| *a += b,
+        );
+        self.merge(collector.collect(rows), |a, b| *a += b);
+    }
+}
+
+impl<'a, G: Hash, K, V, S: KeyStore<K>> IntoIterator for &'a BilevelMap<G, K, V, S> {
+    type Item = (&'a G, &'a K, &'a V);
+    type IntoIter = MapIter<'a, G, K, V, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, G: Hash, K, V, S: KeyStore<K>> IntoIterator for &'a mut BilevelMap<G, K, V, S> {
+    type Item = (&'a G, &'a K, &'a mut V);
+    type IntoIter = MapIterMut<'a, G, K, V, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<G: Hash + Clone, K: Clone, V, S: KeyStore<K>> IntoIterator for BilevelMap<G, K, V, S> {
+    type Item = (G, K, V);
+    type IntoIter = MapIntoIter<G, K, V, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        BilevelMap::into_iter(self)
+    }
+}
+
+/// Lists the payloads for the pairs of a BilevelMap, grouped by g.
+///
+/// This is returned by BilevelMap::iter.
+pub struct MapIter<'a, G, K, V, S: KeyStore<K>> {
+    keys: &'a S,
+    groups: groups::Iter<'a, Group<G, S::Id, V, S::Hasher>>,
+    /// The group being listed, with its remaining key ids and payloads.
+    current: Option<(&'a G, small::MapIter<'a, S::Id, V>)>,
+    /// The number of pairs not yet listed.
+    remaining: usize,
+}
+
+impl<'a, G, K: 'a, V, S: KeyStore<K>> Iterator for MapIter<'a, G, K, V, S> {
+    type Item = (&'a G, &'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((g, inner)) = &mut self.current {
+                if let Some((id, v)) = inner.next() {
+                    self.remaining -= 1;
+                    return Some((*g, self.keys.key(id), v));
+                }
+            }
+            let (g, inner) = self.groups.next()?;
+            self.current = Some((g, inner.iter()));
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, G, K: 'a, V, S: KeyStore<K>> ExactSizeIterator for MapIter<'a, G, K, V, S> {}
+
+impl<'a, G, K: 'a, V, S: KeyStore<K>> std::iter::FusedIterator for MapIter<'a, G, K, V, S> {}
+
+/// Lists the payloads for the pairs of a BilevelMap, grouped by g, for
+/// modification.
+///
+/// This is returned by BilevelMap::iter_mut.
+pub struct MapIterMut<'a, G, K, V, S: KeyStore<K>> {
+    keys: &'a S,
+    groups: groups::IterMut<'a, Group<G, S::Id, V, S::Hasher>>,
+    /// The group being listed, with its remaining key ids and payloads.
+    current: Option<(&'a G, small::MapIterMut<'a, S::Id, V>)>,
+    /// The number of pairs not yet listed.
+    remaining: usize,
+}
+
+impl<'a, G, K: 'a, V, S: KeyStore<K>> Iterator for MapIterMut<'a, G, K, V, S> {
+    type Item = (&'a G, &'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((g, inner)) = &mut self.current {
+                if let Some((id, v)) = inner.next() {
+                    self.remaining -= 1;
+                    return Some((*g, self.keys.key(id), v));
+                }
+            }
+            let (g, inner) = self.groups.next()?;
+            self.current = Some((&*g, inner.iter_mut()));
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, G, K: 'a, V, S: KeyStore<K>> ExactSizeIterator for MapIterMut<'a, G, K, V, S> {}
+
+impl<'a, G, K: 'a, V, S: KeyStore<K>> std::iter::FusedIterator for MapIterMut<'a, G, K, V, S> {}
+
+/// Lists the payloads for the pairs of a BilevelMap, grouped by g,
+/// consuming the collection.
+///
+/// This is returned by BilevelMap::into_iter.
+pub struct MapIntoIter<G, K, V, S: KeyStore<K>> {
+    keys: S,
+    groups: groups::IntoIter<Group<G, S::Id, V, S::Hasher>>,
+    /// The group being listed, with its remaining key ids and payloads.
+    current: Option<(G, small::MapIntoIter<S::Id, V>)>,
+    /// The number of pairs not yet listed.
+    remaining: usize,
+}
+
+impl<G: Clone, K: Clone, V, S: KeyStore<K>> Iterator for MapIntoIter<G, K, V, S> {
+    type Item = (G, K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((g, inner)) = &mut self.current {
+                if let Some((id, v)) = inner.next() {
+                    self.remaining -= 1;
+                    return Some((g.clone(), self.keys.to_key(id), v));
+                }
+            }
+            let (g, inner) = self.groups.next()?;
+            self.current = Some((g, inner.into_iter()));
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<G: Clone, K: Clone, V, S: KeyStore<K>> ExactSizeIterator for MapIntoIter<G, K, V, S> {}
+
+impl<G: Clone, K: Clone, V, S: KeyStore<K>> std::iter::FusedIterator for MapIntoIter<G, K, V, S> {}
+
+/// Lists the groups of a BilevelMap, each with the pairs of the group,
+/// consuming the collection.
+///
+/// This is returned by BilevelMap::into_groups.
+pub struct IntoGroups<G, K, V, S: KeyStore<K>> {
+    keys: S,
+    groups: groups::IntoIter<Group<G, S::Id, V, S::Hasher>>,
+    /// The number of groups not yet listed.
+    remaining: usize,
+}
+
+impl<G, K: Clone, V, S: KeyStore<K>> Iterator for IntoGroups<G, K, V, S> {
+    type Item = (G, GroupIntoIter<K, V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (g, inner) = self.groups.next()?;
+        self.remaining -= 1;
+        let pairs: Vec<_> = inner.into_iter().map(|(id, v)| (self.keys.to_key(id), v)).collect();
+        Some((g, GroupIntoIter { pairs: pairs.into_iter() }))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<G, K: Clone, V, S: KeyStore<K>> ExactSizeIterator for IntoGroups<G, K, V, S> {}
+
+impl<G, K: Clone, V, S: KeyStore<K>> std::iter::FusedIterator for IntoGroups<G, K, V, S> {}
+
+/// Lists the aggregation keys and payloads of one group of a BilevelMap.
+///
+/// This is given to the closure passed to BilevelMap::for_each_group.
+pub struct GroupIter<'a, K, V, S: KeyStore<K>> {
+    keys: &'a S,
+    pairs: small::MapIter<'a, S::Id, V>,
+}
+
+impl<'a, K: 'a, V, S: KeyStore<K>> Iterator for GroupIter<'a, K, V, S> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        let (id, v) = self.pairs.next()?;
+        Some((self.keys.key(id), v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.pairs.size_hint()
+    }
+}
+
+impl<'a, K: 'a, V, S: KeyStore<K>> ExactSizeIterator for GroupIter<'a, K, V, S> {}
+
+impl<'a, K: 'a, V, S: KeyStore<K>> std::iter::FusedIterator for GroupIter<'a, K, V, S> {}
+
+/// Lists the aggregation keys and payloads of one group of a BilevelMap,
+/// owning them apart from the collection.
+///
+/// This is listed by BilevelMap::into_groups.
+pub struct GroupIntoIter<K, V> {
+    pairs: std::vec::IntoIter<(K, V)>,
+}
+
+impl<K, V> Iterator for GroupIntoIter<K, V> {
+    type Item = (K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.pairs.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.pairs.size_hint()
+    }
+}
+
+impl<K, V> ExactSizeIterator for GroupIntoIter<K, V> {}
+
+impl<K, V> std::iter::FusedIterator for GroupIntoIter<K, V> {}
+
+impl<G, K, V, S> crate::BilevelMapOps for BilevelMap<G, K, V, S>
+where
+    G: Hash + Eq,
+    K: Eq,
+    S: KeyStore<K>,
+{
+    type Group = G;
+    type Key = K;
+    type Value = V;
+
+    fn add_or_get_pair(&mut self, g: G, k: K) -> &mut V {
+        self.add_or_get_owned(g, k)
+    }
+
+    fn iter_pairs(&self) -> impl Iterator<Item = (&G, &K, &V)> {
+        self.iter()
+    }
+
+    fn group_sizes(&self) -> impl Iterator<Item = (&G, usize)> {
+        self.group_sizes()
+    }
+
+    fn group_len(&self, g: &G) -> usize {
+        self.group_len(g)
+    }
+
+    fn group_count(&self) -> usize {
+        self.group_count()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+}
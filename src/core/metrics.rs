@@ -0,0 +1,45 @@
+/// Measures of how the counts of a group are spread over its aggregation
+/// keys, such as to detect skewed traffic.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct GroupMetrics {
+    /// The sum of the counts.
+    pub total: usize,
+    /// The Shannon entropy of the counts, in bits: 0 when a single key has
+    /// all the count, and log2 of the number of keys when the count is
+    /// spread evenly.
+    pub entropy: f64,
+    /// The Gini coefficient of the counts: 0 when the count is spread
+    /// evenly, approaching 1 as it is concentrated in a single key.
+    pub gini: f64,
+    /// The share of the total held by the key with the greatest count.
+    pub top_share: f64,
+}
+
+impl GroupMetrics {
+    /// Compute the metrics of the counts of the keys of a group.
+    /// 
+    /// A group whose total count is zero has all its metrics zero.
+    pub fn from_counts(counts: impl IntoIterator<Item = usize>) -> Self {
+        let mut counts: Vec<usize> = counts.into_iter().collect();
+        let total: usize = counts.iter().sum();
+        if total == 0 {
+            return Self::default();
+        }
+        let sum = total as f64;
+        let entropy = counts.iter()
+            .filter(|&&c| c > 0)
+            .map(|&c| {
+                let p = c as f64 / sum;
+                -p * p.log2()
+            })
+            .sum::<f64>();
+        counts.sort_unstable();
+        // With the counts in ascending order, the Gini coefficient is
+        // 2 Σ i·x_i / (n Σ x_i) - (n + 1) / n, counting i from 1.
+        let n = counts.len() as f64;
+        let weighted: f64 = counts.iter().enumerate().map(|(i, &c)| (i + 1) as f64 * c as f64).sum();
+        let gini = (2.0 * weighted / (n * sum) - (n + 1.0) / n).max(0.0);
+        let top_share = counts[counts.len() - 1] as f64 / sum;
+        Self { total, entropy: entropy.max(0.0), gini, top_share }
+    }
+}
@@ -0,0 +1,182 @@
+use std::{cmp::Ordering, hash::Hash};
+
+use crate::Capacity;
+use super::{BilevelMap, Interned, KeyStore};
+
+/// A collection of pairs (g, k) grouped by g, counting how many times
+/// each distinct pair was inserted.
+///
+/// This lies between a BilevelSet, which only records that a pair was
+/// found, and a BilevelMap, which keeps any payload for each pair.
+///
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// S is the strategy for storing the aggregation keys.
+pub struct BilevelMultiset<G, K, S = Interned<K>>
+where
+    S: KeyStore<K>,
+{
+    inner: BilevelMap<G, K, usize, S>,
+}
+
+impl<G: Hash, K, S: KeyStore<K>> BilevelMultiset<G, K, S> {
+    /// Create a new collection.
+    ///
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    pub fn new() -> Self {
+        Self { inner: BilevelMap::new() }
+    }
+
+    /// Create a new collection with the specified capacity.
+    pub fn with_capacity(capacity: Capacity) -> Self {
+        Self { inner: BilevelMap::with_capacity(capacity) }
+    }
+
+    /// Insert a key pair found into the collection.
+    ///
+    /// g: the group key.
+    /// k: the remaining key.
+    ///
+    /// Return the number of times the pair has now been inserted, which
+    /// is 1 if it is new.
+    pub fn insert<GRef, KRef>(&mut self, g: &GRef, k: &KRef) -> usize
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+        KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        self.insert_n(g, k, 1)
+    }
+
+    /// Insert a key pair found into the collection the specified number
+    /// of times.
+    ///
+    /// Return the number of times the pair has now been inserted.
+    pub fn insert_n<GRef, KRef>(&mut self, g: &GRef, k: &KRef, n: usize) -> usize
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+        KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        let count = self.inner.add_or_get(g, k);
+        *count += n;
+        *count
+    }
+
+    /// List the distinct pairs currently in the collection, with the
+    /// number of times each was inserted.
+    ///
+    /// Pairs are grouped by g.
+    pub fn iter(&self) -> impl Iterator<Item = (&G, &K, usize)> {
+        self.inner.iter().map(|(g, k, &n)| (g, k, n))
+    }
+
+    /// List the distinct pairs currently in the collection, sorted by the
+    /// group key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&G, &K, usize)>
+    where
+        G: Ord,
+        K: Ord,
+    {
+        self.iter_sorted_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)))
+    }
+
+    /// List the distinct pairs currently in the collection, sorted using
+    /// the specified comparison function, such as by descending count.
+    pub fn iter_sorted_by<'a, F>(&'a self, compare: F) -> impl Iterator<Item = (&'a G, &'a K, usize)> + 'a
+    where
+        F: FnMut(&(&'a G, &'a K, usize), &(&'a G, &'a K, usize)) -> Ordering + 'a,
+    {
+        let mut pairs: Vec<_> = self.iter().collect();
+        pairs.sort_unstable_by(compare);
+        pairs.into_iter()
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    /// Count the distinct pairs currently in the collection.
+    pub fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    /// Count the insertions of all the pairs currently in the collection.
+    pub fn total_count(&self) -> usize {
+        self.iter().map(|(_, _, n)| n).sum()
+    }
+
+    /// Convert into a map from each distinct pair to its count, consuming
+    /// the collection.
+    pub fn into_map(self) -> BilevelMap<G, K, usize, S> {
+        self.inner
+    }
+}
+
+impl<G: Hash, K, S: KeyStore<K>> Default for BilevelMultiset<G, K, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G, K, S> std::fmt::Debug for BilevelMultiset<G, K, S>
+where
+    G: Hash + std::fmt::Debug,
+    K: std::fmt::Debug,
+    S: KeyStore<K>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<G, K, S> Clone for BilevelMultiset<G, K, S>
+where
+    G: Clone,
+    S: KeyStore<K> + Clone,
+    S::Id: Clone,
+{
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone() }
+    }
+}
+
+impl<G, K, S> PartialEq for BilevelMultiset<G, K, S>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+    S: KeyStore<K>,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<G, K, S> Eq for BilevelMultiset<G, K, S>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+    S: KeyStore<K>,
+{
+}
+
+#[cfg(feature = "tenant")]
+impl<G: Hash, K, S: KeyStore<K>> crate::TenantData for BilevelMultiset<G, K, S> {
+    type Item<'a> = (&'a G, &'a K, usize) where Self: 'a;
+
+    fn pairs(&self) -> impl Iterator<Item = (&G, &K, usize)> {
+        self.iter()
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        crate::TenantData::memory_usage(&self.inner)
+    }
+}
@@ -0,0 +1,79 @@
+use std::collections::{BTreeMap, HashMap};
+
+use super::GroupId;
+
+/// The order in which the groups of a collection were last touched, such
+/// as by adding to them, so that the oldest can be found to evict.
+///
+/// Each group is stamped with a time, and with a sequence number that
+/// orders it among the groups touched at the same time. A collection that
+/// has no times stamps its groups with (), so that they are ordered by
+/// the sequence alone.
+pub(crate) struct Recency<T> {
+    /// The stamp of each group.
+    stamps: HashMap<GroupId, (T, u64)>,
+    /// The groups by their stamps, oldest first.
+    order: BTreeMap<(T, u64), GroupId>,
+    seq: u64,
+}
+
+impl<T: Ord + Copy> Recency<T> {
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { stamps: HashMap::with_capacity(capacity), order: BTreeMap::new(), seq: 0 }
+    }
+
+    /// Count the groups.
+    pub fn len(&self) -> usize {
+        self.order.len()
+    }
+
+    pub fn is_empty(&self) -> bool {
+        self.order.is_empty()
+    }
+
+    /// Record a group new to the collection, touched at the specified time.
+    pub fn insert(&mut self, id: GroupId, at: T) {
+        let stamp = self.stamp(at);
+        self.stamps.insert(id, stamp);
+        self.order.insert(stamp, id);
+    }
+
+    /// Record that a group was touched again at the specified time, moving
+    /// it after the groups touched before, unless the time is earlier than
+    /// the group was last touched.
+    pub fn touch(&mut self, id: GroupId, at: T) {
+        let stamp = self.stamp(at);
+        if let Some(last) = self.stamps.get_mut(&id) {
+            if at >= last.0 {
+                self.order.remove(&*last);
+                self.order.insert(stamp, id);
+                *last = stamp;
+            }
+        }
+    }
+
+    /// Get the time at which a group was last touched, or None if the
+    /// group is not recorded.
+    pub fn last_touched(&self, id: GroupId) -> Option<T> {
+        self.stamps.get(&id).map(|&(at, _)| at)
+    }
+
+    /// Get the time at which the oldest group was last touched.
+    pub fn oldest(&self) -> Option<T> {
+        self.order.first_key_value().map(|(&(at, _), _)| at)
+    }
+
+    /// Stop recording the oldest group, and return its id.
+    pub fn pop_oldest(&mut self) -> Option<GroupId> {
+        let (_, id) = self.order.pop_first()?;
+        self.stamps.remove(&id);
+        Some(id)
+    }
+
+    /// Make the stamp of a group touched at the specified time, after that
+    /// of any group touched before.
+    fn stamp(&mut self, at: T) -> (T, u64) {
+        self.seq += 1;
+        (at, self.seq)
+    }
+}
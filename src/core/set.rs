@@ -1,29 +1,1110 @@
-use std::{hash::Hash, marker::PhantomData};
-use super::Capacity;
-pub struct BilevelSet<G: Hash, K: Hash> {
-    g: PhantomData<G>,
-    k: PhantomData<K>,
-}
+use std::{cmp::Ordering, collections::{HashMap, HashSet}, hash::{BuildHasher, Hash}, marker::PhantomData};
+
+use crate::{Capacity, Derived, HeapSize, equivalence_classes, fingerprint, size_histogram, small::{self, SmallSet}, top_n};
+use super::{AggKeyId, BilevelMap, GroupId, GroupTable, Interned, KeyStore, groups};
 
-impl<G: Hash, K: Hash> BilevelSet<G, K>
+/// A group of a BilevelSet, with the ids of its aggregation keys.
+type Group<G, Id, H> = (G, SmallSet<Id, H>);
+
+/// A collection of distinct pairs (g, k) grouped by g.
+///
+/// As pairs are found, they are added if not already present.
+/// When the collection is iterated over, the pairs are listed by group.
+///
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// S is the strategy for storing the aggregation keys.
+pub struct BilevelSet<G, K, S = Interned<K>>
+where
+    S: KeyStore<K>,
 {
+    per_group: usize,
+    /// Whether new groups are given capacity for the average group size.
+    adaptive: bool,
+    keys: S,
+    groups: GroupTable<Group<G, S::Id, S::Hasher>>,
+    /// The number of pairs, counted as they are added.
+    pairs: usize,
+    /// The fingerprint of each group, in iteration order.
+    fingerprints: Derived<Vec<u64>>,
+    key: PhantomData<K>,
+}
+
+impl<G: Hash, K, S: KeyStore<K>> BilevelSet<G, K, S> {
+    /// Create a new collection.
+    ///
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
     pub fn new() -> Self {
-        todo!()
+        Self::with_capacity(Capacity::default())
     }
 
+    /// Create a new collection with the specified capacity.
     pub fn with_capacity(capacity: Capacity) -> Self {
-        todo!()
+        Self::with_keys(capacity, S::with_capacity(capacity.agg_keys))
+    }
+
+    /// Create a new collection with the specified capacity for groups,
+    /// storing the aggregation keys in the store given, such as a
+    /// KeyInterner shared with other collections.
+    pub fn with_keys(capacity: Capacity, keys: S) -> Self {
+        let Capacity { groups, per_group, agg_keys: _ } = capacity;
+        Self {
+            per_group,
+            adaptive: false,
+            keys,
+            groups: GroupTable::with_capacity(groups),
+            pairs: 0,
+            fingerprints: Derived::new(),
+            key: PhantomData,
+        }
+    }
+
+    /// Insert a key pair found into the collection.
+    ///
+    /// g: the group key.
+    /// k: the remaining key.
+    ///
+    /// Return false if the key was already present, otherwise true.
+    pub fn insert<GRef, KRef>(&mut self, g: &GRef, k: &KRef) -> bool
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+        KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        self.invalidate();
+        let id = self.keys.intern(k);
+        let (inner, pairs) = self.group_entry(g);
+        let added = inner.insert(id);
+        if added {
+            *pairs += 1;
+        }
+        added
+    }
+
+    /// Insert a key pair found into the collection, taking ownership of
+    /// the keys, so that a new key is stored without being copied and a
+    /// key already present is dropped.
+    ///
+    /// Return false if the key was already present, otherwise true.
+    pub fn insert_owned(&mut self, g: G, k: K) -> bool
+    where
+        G: Eq,
+        K: Eq,
+    {
+        self.invalidate();
+        let id = self.keys.intern_owned(k);
+        let (inner, pairs) = self.group_entry_owned(g);
+        let added = inner.insert(id);
+        if added {
+            *pairs += 1;
+        }
+        added
+    }
+
+    /// Find the set of key ids for a group, adding the group given if new,
+    /// along with the count of pairs.
+    fn group_entry_owned(&mut self, g: G) -> (&mut SmallSet<S::Id, S::Hasher>, &mut usize)
+    where
+        G: Eq,
+    {
+        let hash = S::hash(&g);
+        let id = match self.groups.find_id(hash, |(o, _)| g == *o) {
+            Some(id) => id,
+            None => {
+                let per_group = self.group_capacity();
+                self.groups.insert_unique(hash, (g, SmallSet::with_capacity(per_group)))
+            },
+        };
+        (&mut self.groups.get_mut(id).expect("group just found").1, &mut self.pairs)
+    }
+
+    /// Find the set of key ids for a group, adding the group if new, along
+    /// with the count of pairs, to be updated as pairs are added to the group.
+    fn group_entry<GRef>(&mut self, g: &GRef) -> (&mut SmallSet<S::Id, S::Hasher>, &mut usize)
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+    {
+        let per_group = self.group_capacity();
+        let inner = &mut self.groups.get_or_insert_with(
+            S::hash(g),
+            |(o, _)| g.eq(o),
+            || (g.to_owned(), SmallSet::with_capacity(per_group)),
+        ).1;
+        (inner, &mut self.pairs)
+    }
+
+    /// List the pairs currently in the collection without consuming
+    /// the collection.
+    ///
+    /// Pairs are grouped by g.
+    pub fn iter(&self) -> SetIter<'_, G, K, S> {
+        SetIter { keys: &self.keys, groups: self.groups.iter(), current: None, remaining: self.pairs }
+    }
+
+    /// Call f once for each group, with the group key and the aggregation
+    /// keys of the group.
+    ///
+    /// The pairs are listed as iter lists them, but without the state iter
+    /// keeps to move from one group to the next, which suits a report that
+    /// writes each group in turn.
+    pub fn for_each_group<F>(&self, mut f: F)
+    where
+        F: FnMut(&G, GroupKeys<'_, K, S>),
+    {
+        for (g, inner) in self.groups.iter() {
+            f(g, GroupKeys { keys: &self.keys, ids: inner.iter() });
+        }
+    }
+
+    /// List each group with its aggregation keys.
+    pub(crate) fn iter_groups(&self) -> impl Iterator<Item = (&G, impl Iterator<Item = &K>)> {
+        let keys = &self.keys;
+        self.groups.iter().map(move |(g, inner)| (g, inner.iter().map(move |id| keys.key(id))))
+    }
+
+    /// List the pairs in the collection and consume the collection.
+    ///
+    /// Pairs are grouped by g. Each group key is cloned for every pair
+    /// in its group.
+    pub fn into_iter(self) -> SetIntoIter<G, K, S>
+    where
+        G: Clone,
+        K: Clone,
+    {
+        SetIntoIter { keys: self.keys, groups: self.groups.into_iter(), current: None, remaining: self.pairs }
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&G, &K)>
+    where
+        G: Ord,
+        K: Ord,
+    {
+        self.iter_sorted_by(Ord::cmp)
+    }
+
+    /// List the pairs currently in the collection, sorted using the
+    /// specified comparison function.
+    pub fn iter_sorted_by<'a, F>(&'a self, compare: F) -> impl Iterator<Item = (&'a G, &'a K)> + 'a
+    where
+        F: FnMut(&(&'a G, &'a K), &(&'a G, &'a K)) -> Ordering + 'a,
+    {
+        let mut pairs: Vec<_> = self.iter().collect();
+        pairs.sort_unstable_by(compare);
+        pairs.into_iter()
+    }
+
+    /// List the pairs in the collection, sorted by the group key and then
+    /// by the aggregation key, consuming the collection.
+    ///
+    /// Each group key is cloned for every pair in its group.
+    pub fn into_iter_sorted(self) -> impl Iterator<Item = (G, K)>
+    where
+        G: Ord + Clone,
+        K: Ord + Clone,
+    {
+        let mut pairs: Vec<_> = self.into_iter().collect();
+        pairs.sort_unstable();
+        pairs.into_iter()
     }
 
-    pub fn insert(
-        &mut self,
-        g: impl ToOwned<Owned = G> + PartialEq<G>,
-        k: impl ToOwned<Owned = K> + PartialEq<K>,
-    ) -> bool {
-        todo!()
+    /// List the pairs currently in the collection, as iter does, but also
+    /// list each empty group once, with no aggregation key.
+    pub fn iter_with_empty(&self) -> impl Iterator<Item = (&G, Option<&K>)> {
+        let keys = &self.keys;
+        self.groups.iter().flat_map(move |(g, inner)| {
+            let empty = inner.is_empty().then_some((g, None));
+            inner.iter().map(move |id| (g, Some(keys.key(id)))).chain(empty)
+        })
     }
 
-    pub fn iter(&self) -> impl Iterator<Item = (&G, &K)> {
-        todo!()
+    /// Declare group keys that are expected to appear, so that they are
+    /// listed by iter_with_empty even if no pairs are found for them.
+    pub fn declare_groups<'a, GRef>(&mut self, groups: impl IntoIterator<Item = &'a GRef>)
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized + 'a,
+    {
+        self.invalidate();
+        for g in groups {
+            self.group_entry(g);
+        }
+    }
+
+    /// Remove a pair, returning whether it was present.
+    ///
+    /// The group stays, even if left empty, as a declared group does.
+    pub fn remove<GRef, KRef>(&mut self, g: &GRef, k: &KRef) -> bool
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+        KRef: PartialEq<K> + Hash + ?Sized,
+    {
+        let Some(id) = self.groups.find_id(S::hash(g), |(o, _)| g.eq(o)) else {
+            return false;
+        };
+        let removed = match self.keys.find(k) {
+            Some(k) => self.groups.get_mut(id).expect("group just found").1.remove(&k),
+            None => false,
+        };
+        if removed {
+            self.invalidate();
+            self.pairs -= 1;
+        }
+        removed
+    }
+
+    /// Remove a group, along with its pairs.
+    ///
+    /// Return the group key and the aggregation key of each pair, or None
+    /// if the group is not present.
+    /// The aggregation keys stay stored, so that their ids are not reused.
+    pub fn remove_group<GRef>(&mut self, g: &GRef) -> Option<(G, Vec<K>)>
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+        K: Clone,
+    {
+        let (g, inner) = self.groups.remove(S::hash(g), |(o, _)| g.eq(o))?;
+        self.invalidate();
+        self.pairs -= inner.len();
+        Some((g, inner.into_iter().map(|id| self.keys.to_key(id)).collect()))
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// groups.
+    pub fn reserve_groups(&mut self, additional: usize) {
+        self.groups.reserve(additional);
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// distinct aggregation keys.
+    pub fn reserve_agg_keys(&mut self, additional: usize) {
+        self.keys.reserve(additional);
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// pairs in a group, declaring the group if new.
+    pub fn reserve_in_group<GRef>(&mut self, g: &GRef, additional: usize)
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+    {
+        self.reserve_in_group_with(g, || g.to_owned(), additional);
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// pairs in a group, declaring the group with the key given by to_owned
+    /// if new, for wrappers that look groups up by forms of the key that
+    /// cannot be converted to it with ToOwned.
+    pub(crate) fn reserve_in_group_with<GRef>(&mut self, g: &GRef, to_owned: impl FnOnce() -> G, additional: usize)
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+    {
+        self.invalidate();
+        let per_group = self.group_capacity();
+        self.groups.get_or_insert_with(
+            S::hash(g),
+            |(o, _)| g.eq(o),
+            || (to_owned(), SmallSet::with_capacity(per_group)),
+        ).1.reserve(additional);
+    }
+
+    /// Choose whether each new group is given capacity for the average
+    /// size of the groups found so far, rather than the per_group
+    /// capacity alone.
+    ///
+    /// The per_group capacity is still the least given, so this helps
+    /// where groups tend to grow much larger. The choice is not kept in
+    /// snapshots.
+    pub fn adapt_per_group(&mut self, adaptive: bool) {
+        self.adaptive = adaptive;
+    }
+
+    /// Estimate the memory held by the collection, in bytes, including
+    /// the memory owned by the keys, such as the contents of strings.
+    ///
+    /// This walks every group, so takes time in proportion to the number
+    /// of pairs.
+    pub fn estimated_heap_bytes(&self) -> usize
+    where
+        G: HeapSize,
+        K: HeapSize,
+        S::Id: HeapSize,
+    {
+        self.keys.heap_bytes()
+            + self.groups.allocated()
+            + self.groups.iter().map(|(g, inner)| {
+                g.heap_bytes()
+                    + inner.allocated()
+                    + inner.iter().map(S::Id::heap_bytes).sum::<usize>()
+            }).sum::<usize>()
+    }
+
+    /// The capacity to give a new group.
+    fn group_capacity(&self) -> usize {
+        match self.groups.len() {
+            n if self.adaptive && n > 0 => self.per_group.max(self.pairs.div_ceil(n)),
+            _ => self.per_group,
+        }
+    }
+
+    /// Find groups with identical sets of aggregation keys.
+    ///
+    /// Return each class of two or more equivalent groups.
+    pub fn dedup_groups(&self) -> Vec<Vec<&G>> {
+        // Each distinct key has a single id, so the ids can be compared
+        // in place of the keys.
+        let fingerprints = self.fingerprints.get_or_init(|| {
+            self.groups.iter().map(|(_, inner)| fingerprint(inner.iter())).collect()
+        });
+        equivalence_classes(
+            self.groups.iter().zip(fingerprints).map(|((g, inner), &f)| (g, (f, inner))),
+            |&(f, _)| f,
+            |(_, a), (_, b)| a == b,
+        )
+    }
+
+    /// List the group keys currently in the collection, with the number
+    /// of aggregation keys in each group.
+    pub fn group_sizes(&self) -> impl Iterator<Item = (&G, usize)> {
+        self.groups.iter().map(|(g, inner)| (g, inner.len()))
+    }
+
+    /// Count the groups by their number of aggregation keys, such as to
+    /// choose Capacity::per_group.
+    ///
+    /// buckets: the greatest size counted in each bucket, in ascending
+    ///     order. A final bucket counts the groups larger than the last of
+    ///     these.
+    ///
+    /// Return the number of groups in each bucket.
+    pub fn size_histogram(&self, buckets: &[usize]) -> Vec<usize> {
+        size_histogram(self.group_sizes().map(|(_, n)| n), buckets)
+    }
+
+    /// Render the collection as a text table, with a heading for each
+    /// group followed by its aggregation keys in aligned columns,
+    /// such as for debugging or the output of a command.
+    ///
+    /// The groups, and the keys of each group, are listed in the order of
+    /// their text, so that the same contents are always rendered the same
+    /// way.
+    pub fn render_table(&self, options: &crate::TableOptions) -> String
+    where
+        G: std::fmt::Display,
+        K: std::fmt::Display,
+    {
+        let keys = &self.keys;
+        crate::table::render::<_, _, str, _>(options, self.groups.iter().map(|(g, inner)| {
+            (g, inner.iter().map(|id| (keys.key(id), None)))
+        }))
+    }
+
+    /// Measure the overlap of the aggregation keys of two groups, as their
+    /// Jaccard similarity: the number of keys they share divided by the
+    /// number of keys in either.
+    ///
+    /// Return None if either group is not present. Two empty groups are
+    /// taken to be identical, with a similarity of 1.
+    pub fn similarity<GRef>(&self, g1: &GRef, g2: &GRef) -> Option<f64>
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+    {
+        let a = &self.groups.find(S::hash(g1), |(o, _)| g1.eq(o))?.1;
+        let b = &self.groups.find(S::hash(g2), |(o, _)| g2.eq(o))?.1;
+        Some(jaccard(a, b))
+    }
+
+    /// List the n groups whose aggregation keys overlap most with those of
+    /// the specified group, with their Jaccard similarity, greatest first.
+    ///
+    /// Groups that share no keys with the group are not listed, nor is the
+    /// group itself.
+    pub fn most_similar<GRef>(&self, g: &GRef, n: usize) -> Vec<(&G, f64)>
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+    {
+        let Some((_, target)) = self.groups.find(S::hash(g), |(o, _)| g.eq(o)) else {
+            return Vec::new();
+        };
+        let others = self.groups.iter()
+            .filter(|(_, inner)| !std::ptr::eq(inner, target))
+            .map(|(o, inner)| (o, jaccard(target, inner)))
+            .filter(|&(_, s)| s > 0.0);
+        // The bits of a non-negative float are ordered as the float is.
+        top_n(others, n, |&(_, s)| s.to_bits())
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Count the pairs currently in the collection.
+    ///
+    /// The count is kept as pairs are added, so this takes constant time.
+    pub fn pair_count(&self) -> usize {
+        self.pairs
+    }
+
+    /// Count the pairs currently in the collection, as pair_count does.
+    pub fn len(&self) -> usize {
+        self.pairs
+    }
+
+    /// Check whether the collection holds no pairs, although it may hold
+    /// empty groups.
+    pub fn is_empty(&self) -> bool {
+        self.pairs == 0
+    }
+
+    /// Count the pairs of the specified group, which is 0 if the group is
+    /// not present.
+    pub fn group_len<GRef>(&self, g: &GRef) -> usize
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+    {
+        self.groups.find(S::hash(g), |(o, _)| g.eq(o)).map_or(0, |(_, inner)| inner.len())
+    }
+
+    /// Find the id of a group, or None if the group is not present.
+    ///
+    /// A group keeps its id for as long as it is in the collection.
+    pub fn group_id<GRef>(&self, g: &GRef) -> Option<GroupId>
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+    {
+        self.groups.find_id(S::hash(g), |(o, _)| g.eq(o))
+    }
+
+    /// Get the key of the group with the specified id, or None if no
+    /// group has the id.
+    pub fn resolve_group(&self, id: GroupId) -> Option<&G> {
+        self.groups.get(id).map(|(g, _)| g)
+    }
+
+    /// List the group keys currently in the collection, with their ids.
+    pub fn group_ids(&self) -> impl Iterator<Item = (GroupId, &G)> {
+        self.groups.iter_with_ids().map(|(id, (g, _))| (id, g))
+    }
+
+    /// Discard the values derived from the current contents.
+    fn invalidate(&mut self) {
+        self.fingerprints.invalidate();
+    }
+}
+
+impl<G: Hash, K, S: KeyStore<K>> Default for BilevelSet<G, K, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: Hash, K: Hash> BilevelSet<G, K, Interned<K>> {
+    /// List the pairs currently in the collection, as iter does, along
+    /// with the ids of the group and aggregation key of each.
+    pub fn iter_with_ids(&self) -> impl Iterator<Item = (GroupId, &G, AggKeyId, &K)> {
+        let keys = self.keys.as_slice();
+        self.groups.iter_with_ids().flat_map(move |(id, (g, inner))| {
+            inner.iter().map(move |&i| (id, g, AggKeyId(i), &keys[i]))
+        })
+    }
+
+    /// List the aggregation keys with their ids, in the order of the ids.
+    ///
+    /// Each distinct aggregation key is given an id when first found,
+    /// which does not change for the life of the collection.
+    pub fn id_mapping(&self) -> impl ExactSizeIterator<Item = (AggKeyId, &K)> {
+        self.keys.as_slice().iter().enumerate().map(|(i, k)| (AggKeyId(i), k))
+    }
+
+    /// Count the distinct aggregation keys found in any group.
+    ///
+    /// Each distinct key is stored once, so this takes constant time.
+    pub fn distinct_agg_keys(&self) -> usize {
+        self.keys.as_slice().len()
+    }
+
+    /// Find the id of an aggregation key, or None if the key has not been
+    /// found in any group.
+    pub fn agg_key_id<KRef>(&self, k: &KRef) -> Option<AggKeyId>
+    where
+        KRef: PartialEq<K> + Hash + ?Sized,
+    {
+        self.keys.id_of(k).map(AggKeyId)
+    }
+
+    /// Get the aggregation key with the specified id, or None if no key
+    /// has the id.
+    pub fn resolve_agg_key(&self, id: AggKeyId) -> Option<&K> {
+        self.keys.as_slice().get(id.0)
+    }
+
+    /// Insert a pair given by the ids of its group and aggregation key,
+    /// such as ids passed from another system, without copying either key.
+    ///
+    /// Return false if the pair was already present, otherwise true, or
+    /// None if either id is not of this collection, such as the id of a
+    /// group removed since.
+    pub fn insert_by_id(&mut self, g: GroupId, k: AggKeyId) -> Option<bool> {
+        if k.0 >= self.keys.as_slice().len() || self.groups.get(g).is_none() {
+            return None;
+        }
+        self.invalidate();
+        let (_, inner) = self.groups.get_mut(g).expect("group just found");
+        let added = inner.insert(k.0);
+        self.pairs += added as usize;
+        Some(added)
+    }
+}
+
+impl<G, K, S> BilevelSet<G, K, S>
+where
+    G: Clone + PartialEq + Hash,
+    K: Clone + PartialEq + Hash,
+    S: KeyStore<K>,
+{
+    /// Copy the data into a new collection that groups by the aggregation
+    /// key, storing the group keys, which become the aggregation keys,
+    /// as the strategy S2 does.
+    pub fn pivot<S2: KeyStore<G>>(&self) -> BilevelSet<K, G, S2> {
+        // Pre-allocate capacity assuming approximate symmetry.
+        let capacity = Capacity {
+            groups: self.groups.len(),
+            agg_keys: self.groups.len(),
+            per_group: self.per_group,
+        };
+        let mut pivoted = BilevelSet::with_capacity(capacity);
+        for (g, k) in self.iter() {
+            pivoted.insert(k, g);
+        }
+        pivoted
+    }
+
+    /// Count how often each two aggregation keys appear in the same group,
+    /// as in a basket analysis, storing the keys as the strategy S2 does.
+    ///
+    /// Each two distinct keys found together are listed both ways, so that
+    /// the keys found with a key a are those of the group a. A key is not
+    /// counted with itself.
+    pub fn cooccurrence<S2: KeyStore<K>>(&self) -> BilevelMap<K, K, usize, S2> {
+        let mut counts = BilevelMap::new();
+        for (_, inner) in self.groups.iter() {
+            for a in inner {
+                for b in inner.iter().filter(|&b| b != a) {
+                    *counts.add_or_get(self.keys.key(a), self.keys.key(b)) += 1;
+                }
+            }
+        }
+        counts
+    }
+}
+
+impl<G, K, S> BilevelSet<G, K, S>
+where
+    G: Hash + Eq,
+    K: Hash + Eq + Clone,
+    S: KeyStore<K>,
+{
+    /// Convert into a map of the groups, consuming the collection.
+    pub fn into_grouped(self) -> HashMap<G, HashSet<K>> {
+        let keys = self.keys;
+        self.groups.into_iter()
+            .map(|(g, inner)| (g, inner.into_iter().map(|id| keys.to_key(id)).collect()))
+            .collect()
+    }
+}
+
+impl<G, K, S> From<HashMap<G, HashSet<K>>> for BilevelSet<G, K, S>
+where
+    G: Hash + Eq,
+    K: Hash + Eq + Clone,
+    S: KeyStore<K>,
+{
+    fn from(grouped: HashMap<G, HashSet<K>>) -> Self {
+        let mut set = Self::new();
+        for (g, inner) in grouped {
+            let inner: SmallSet<_, S::Hasher> = inner.iter().map(|k| set.keys.intern(k)).collect();
+            set.pairs += inner.len();
+            set.groups.insert_unique(S::hash(&g), (g, inner));
+        }
+        set
+    }
+}
+
+impl<G, K, S> std::fmt::Debug for BilevelSet<G, K, S>
+where
+    G: Hash + std::fmt::Debug,
+    K: std::fmt::Debug,
+    S: KeyStore<K>,
+{
+    /// Format the collection as a map from each group key to the set of
+    /// its aggregation keys, listing only the first few groups, and keys
+    /// of each group, of a large collection.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::debug::debug_set(f, self.iter_groups())
+    }
+}
+
+impl<G, K, S> Clone for BilevelSet<G, K, S>
+where
+    G: Clone,
+    S: KeyStore<K> + Clone,
+    S::Id: Clone,
+{
+    /// Copy the collection, including its store of aggregation keys.
+    ///
+    /// Values derived from the contents are not copied, but derived again
+    /// when next needed.
+    fn clone(&self) -> Self {
+        Self {
+            per_group: self.per_group,
+            adaptive: self.adaptive,
+            keys: self.keys.clone(),
+            groups: self.groups.clone(),
+            pairs: self.pairs,
+            fingerprints: Derived::new(),
+            key: PhantomData,
+        }
     }
-}
\ No newline at end of file
+}
+
+impl<G, K, S> PartialEq for BilevelSet<G, K, S>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+    S: KeyStore<K>,
+{
+    /// Check whether two collections hold the same groups, with the same
+    /// aggregation keys, whatever the order of the groups and the ids
+    /// given to the keys.
+    fn eq(&self, other: &Self) -> bool {
+        self.pairs == other.pairs && self.groups.len() == other.groups.len()
+            && self.groups.iter().all(|(g, inner)| {
+                let Some((_, theirs)) = other.groups.find(S::hash(g), |(o, _)| g.eq(o)) else {
+                    return false;
+                };
+                let theirs: HashSet<&K> = theirs.iter().map(|id| other.keys.key(id)).collect();
+                inner.len() == theirs.len() && inner.iter().all(|id| theirs.contains(self.keys.key(id)))
+            })
+    }
+}
+
+impl<G: Hash + Eq, K: Hash + Eq, S: KeyStore<K>> Eq for BilevelSet<G, K, S> {}
+
+#[cfg(feature = "derive")]
+impl<G, K, S> BilevelSet<G, K, S>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    S: KeyStore<K>,
+{
+    /// Insert the key pair of a row into the collection.
+    ///
+    /// Return false if the key was already present, otherwise true.
+    pub fn insert_row<R: crate::BilevelKey<Group = G, Agg = K>>(&mut self, row: &R) -> bool {
+        self.insert(&row.group(), &row.agg())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<G, K, S> serde::Serialize for BilevelSet<G, K, S>
+where
+    G: serde::Serialize,
+    K: serde::Serialize,
+    S: KeyStore<K>,
+{
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        use crate::serde_support::SeqOf;
+        let keys = &self.keys;
+        serializer.collect_map(self.groups.iter().map(|(g, inner)| {
+            (g, SeqOf(move || inner.iter().map(move |id| keys.key(id))))
+        }))
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, G, K, S> serde::Deserialize<'de> for BilevelSet<G, K, S>
+where
+    G: Hash + Eq + serde::Deserialize<'de>,
+    K: Hash + Eq + Clone + serde::Deserialize<'de>,
+    S: KeyStore<K>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashMap::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<G: Hash, K, S: KeyStore<K>> BilevelSet<G, K, S> {
+    /// Write the collection as a JSON object, from each group key to an
+    /// array of its aggregation keys.
+    ///
+    /// The groups, and the keys of each group, are written in order, so
+    /// that the same contents are always written the same way.
+    pub fn to_json_grouped(&self, writer: impl std::io::Write) -> Result<(), serde_json::Error>
+    where
+        G: Ord + serde::Serialize,
+        K: Ord + serde::Serialize,
+    {
+        let keys = &self.keys;
+        crate::serde_support::write_json_set(writer, self.groups.iter().map(|(g, inner)| {
+            (g, inner.iter().map(|id| keys.key(id)).collect())
+        }))
+    }
+}
+
+#[cfg(feature = "json")]
+impl<G: Hash, K: Hash> BilevelSet<G, K, Interned<K>> {
+    /// Write the collection as a JSON array, with an object for each pair
+    /// holding the ids of its group and aggregation key along with the
+    /// keys, such as {"group_id":[0,0],"group":"web1","key_id":0,"key":"200"}.
+    ///
+    /// The pairs are listed as iter_with_ids lists them.
+    pub fn to_json_with_ids(&self, writer: impl std::io::Write) -> Result<(), serde_json::Error>
+    where
+        G: serde::Serialize,
+        K: serde::Serialize,
+    {
+        serde_json::to_writer(writer, &crate::serde_support::SeqOf(|| {
+            self.iter_with_ids().map(|(group_id, group, key_id, key)| {
+                crate::serde_support::IdRow::<_, _, ()> { group_id, group, key_id, key, value: None }
+            })
+        }))
+    }
+}
+
+#[cfg(feature = "csv")]
+impl<G: Hash, K, S: KeyStore<K>> BilevelSet<G, K, S> {
+    /// Write the collection as delimited text, such as CSV or TSV, with a
+    /// row for each pair, and optionally the ids of the group and
+    /// aggregation key. The value column, if listed, is left empty.
+    ///
+    /// Pairs are grouped by g.
+    pub fn write_csv(&self, writer: impl std::io::Write, options: &crate::CsvOptions) -> std::io::Result<()>
+    where
+        G: std::fmt::Display,
+        K: std::fmt::Display,
+    {
+        let keys = &self.keys;
+        let rows = self.groups.iter_with_ids().flat_map(|(group_id, (g, inner))| {
+            inner.iter().map(move |id| (group_id, S::agg_key_id(id), [g as _, keys.key(id) as _, &"" as _]))
+        });
+        crate::csv::write_rows(writer, options, rows)
+    }
+}
+
+#[cfg(feature = "frozen")]
+impl<G: Hash, K, S: KeyStore<K>> BilevelSet<G, K, S> {
+    /// Write the collection in a frozen format, to be read by FrozenMap
+    /// with payloads of () without being deserialized, such as from a file
+    /// mapped into memory.
+    ///
+    /// The groups are written in order of the bytes of their keys, and the
+    /// pairs of each group in order of the bytes of their aggregation keys.
+    pub fn write_frozen(&self, writer: impl std::io::Write) -> std::io::Result<()>
+    where
+        G: AsRef<[u8]>,
+        K: AsRef<[u8]>,
+    {
+        crate::frozen::write(writer, self.iter_groups().map(|(g, keys)| {
+            (g.as_ref(), keys.map(|k| (k.as_ref(), &())).collect())
+        }))
+    }
+}
+
+#[cfg(feature = "snapshot")]
+impl<G, K, S> BilevelSet<G, K, S>
+where
+    G: Hash + Eq,
+    S: KeyStore<K>,
+{
+    /// Write a binary snapshot of the collection, including the store of
+    /// aggregation keys.
+    pub fn write_snapshot(&self, writer: impl std::io::Write) -> Result<(), crate::SnapshotError>
+    where
+        G: serde::Serialize,
+        S: serde::Serialize,
+        S::Id: serde::Serialize,
+    {
+        let groups: Vec<_> = self.groups.iter().collect();
+        crate::snapshot::write(writer, &(self.per_group, &self.keys, groups))
+    }
+
+    /// Read a collection from a binary snapshot written by write_snapshot.
+    ///
+    /// The snapshot does not keep the GroupIds: the groups are given new
+    /// ids, so ids taken before the snapshot do not carry over.
+    pub fn read_snapshot(reader: impl std::io::Read) -> Result<Self, crate::SnapshotError>
+    where
+        G: serde::de::DeserializeOwned,
+        S: serde::de::DeserializeOwned,
+        S::Id: serde::de::DeserializeOwned,
+    {
+        let (per_group, keys, group_list) =
+            crate::snapshot::read::<(usize, S, Vec<Group<G, S::Id, S::Hasher>>)>(reader)?;
+        Self::from_snapshot(per_group, keys, group_list)
+    }
+
+    /// Read a collection from a binary snapshot written by write_snapshot,
+    /// keeping the groups read before any error in reading the groups.
+    ///
+    /// Return the groups that could be read, with a report of those that
+    /// could not, if any. A snapshot whose header or other contents cannot
+    /// be read is still an error.
+    pub fn read_snapshot_partial(
+        reader: impl std::io::Read,
+    ) -> Result<(Self, Option<crate::MissingGroups>), crate::SnapshotError>
+    where
+        G: serde::de::DeserializeOwned,
+        S: serde::de::DeserializeOwned,
+        S::Id: serde::de::DeserializeOwned,
+    {
+        let (per_group, keys, group_list) =
+            crate::snapshot::read::<(usize, S, crate::snapshot::Lenient<Group<G, S::Id, S::Hasher>>)>(reader)?;
+        let (group_list, missing) = group_list.finish();
+        Ok((Self::from_snapshot(per_group, keys, group_list)?, missing))
+    }
+
+    /// Check the contents read from a snapshot, and rebuild the collection.
+    fn from_snapshot(
+        per_group: usize,
+        keys: S,
+        group_list: Vec<Group<G, S::Id, S::Hasher>>,
+    ) -> Result<Self, crate::SnapshotError> {
+        let groups = crate::snapshot::group_table(per_group, group_list, S::hash::<G>, |inner| {
+            inner.iter().all(|id| keys.contains(id))
+        })?;
+        let pairs = groups.iter().map(|(_, inner)| inner.len()).sum();
+        Ok(Self {
+            per_group,
+            adaptive: false,
+            keys,
+            groups,
+            pairs,
+            fingerprints: Derived::new(),
+            key: PhantomData,
+        })
+    }
+}
+
+#[cfg(feature = "replay")]
+impl<G, K, S> crate::Replay<G, K, ()> for BilevelSet<G, K, S>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    S: KeyStore<K>,
+{
+    fn apply(&mut self, mutation: crate::Mutation<&G, &K, &()>) {
+        match mutation {
+            crate::Mutation::DeclareGroup(g) => self.declare_groups([g]),
+            crate::Mutation::Insert(g, k) | crate::Mutation::Update(g, k, _) => {
+                self.insert(g, k);
+            }
+            crate::Mutation::Remove(g, k) => { self.remove(g, k); }
+            crate::Mutation::RemoveGroup(g) => { self.remove_group(g); }
+        }
+    }
+}
+
+#[cfg(feature = "tenant")]
+impl<G: Hash, K, S: KeyStore<K>> crate::TenantData for BilevelSet<G, K, S> {
+    type Item<'a> = (&'a G, &'a K) where Self: 'a;
+
+    fn pairs(&self) -> impl Iterator<Item = (&G, &K)> {
+        self.iter()
+    }
+
+    fn group_count(&self) -> usize {
+        self.group_count()
+    }
+
+    fn pair_count(&self) -> usize {
+        self.pair_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.keys.memory_usage()
+            + self.groups.allocated()
+            + self.groups.iter().map(|(_, inner)| inner.allocated()).sum::<usize>()
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<G, K, S> BilevelSet<G, K, S>
+where
+    G: Hash + Send + Sync,
+    K: Sync,
+    S: KeyStore<K> + Sync,
+    S::Id: Send + Sync,
+{
+    /// List the pairs currently in the collection in parallel, splitting
+    /// the work between threads by group.
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (&G, &K)> {
+        use rayon::prelude::*;
+        let keys = &self.keys;
+        self.groups.par_iter()
+            .flat_map_iter(move |(g, inner)| inner.iter().map(move |id| (g, keys.key(id))))
+    }
+}
+
+impl<'a, G: Hash, K, S: KeyStore<K>> IntoIterator for &'a BilevelSet<G, K, S> {
+    type Item = (&'a G, &'a K);
+    type IntoIter = SetIter<'a, G, K, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<G: Hash + Clone, K: Clone, S: KeyStore<K>> IntoIterator for BilevelSet<G, K, S> {
+    type Item = (G, K);
+    type IntoIter = SetIntoIter<G, K, S>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        BilevelSet::into_iter(self)
+    }
+}
+
+/// Lists the pairs of a BilevelSet, grouped by g.
+///
+/// This is returned by BilevelSet::iter.
+pub struct SetIter<'a, G, K, S: KeyStore<K>> {
+    keys: &'a S,
+    groups: groups::Iter<'a, Group<G, S::Id, S::Hasher>>,
+    /// The group being listed, with its remaining key ids.
+    current: Option<(&'a G, small::SetIter<'a, S::Id>)>,
+    /// The number of pairs not yet listed.
+    remaining: usize,
+}
+
+impl<'a, G, K: 'a, S: KeyStore<K>> Iterator for SetIter<'a, G, K, S> {
+    type Item = (&'a G, &'a K);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((g, inner)) = &mut self.current {
+                if let Some(id) = inner.next() {
+                    self.remaining -= 1;
+                    return Some((*g, self.keys.key(id)));
+                }
+            }
+            let (g, inner) = self.groups.next()?;
+            self.current = Some((g, inner.iter()));
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<'a, G, K: 'a, S: KeyStore<K>> ExactSizeIterator for SetIter<'a, G, K, S> {}
+
+impl<'a, G, K: 'a, S: KeyStore<K>> std::iter::FusedIterator for SetIter<'a, G, K, S> {}
+
+/// Lists the pairs of a BilevelSet, grouped by g, consuming the
+/// collection.
+///
+/// This is returned by BilevelSet::into_iter.
+pub struct SetIntoIter<G, K, S: KeyStore<K>> {
+    keys: S,
+    groups: groups::IntoIter<Group<G, S::Id, S::Hasher>>,
+    /// The group being listed, with its remaining key ids.
+    current: Option<(G, small::SetIntoIter<S::Id>)>,
+    /// The number of pairs not yet listed.
+    remaining: usize,
+}
+
+impl<G: Clone, K: Clone, S: KeyStore<K>> Iterator for SetIntoIter<G, K, S> {
+    type Item = (G, K);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        loop {
+            if let Some((g, inner)) = &mut self.current {
+                if let Some(id) = inner.next() {
+                    self.remaining -= 1;
+                    return Some((g.clone(), self.keys.to_key(id)));
+                }
+            }
+            let (g, inner) = self.groups.next()?;
+            self.current = Some((g, inner.into_iter()));
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        (self.remaining, Some(self.remaining))
+    }
+}
+
+impl<G: Clone, K: Clone, S: KeyStore<K>> ExactSizeIterator for SetIntoIter<G, K, S> {}
+
+impl<G: Clone, K: Clone, S: KeyStore<K>> std::iter::FusedIterator for SetIntoIter<G, K, S> {}
+
+/// Lists the aggregation keys of one group of a BilevelSet.
+///
+/// This is given to the closure passed to BilevelSet::for_each_group.
+pub struct GroupKeys<'a, K, S: KeyStore<K>> {
+    keys: &'a S,
+    ids: small::SetIter<'a, S::Id>,
+}
+
+impl<'a, K: 'a, S: KeyStore<K>> Iterator for GroupKeys<'a, K, S> {
+    type Item = &'a K;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.ids.next().map(|id| self.keys.key(id))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.ids.size_hint()
+    }
+}
+
+impl<'a, K: 'a, S: KeyStore<K>> ExactSizeIterator for GroupKeys<'a, K, S> {}
+
+impl<'a, K: 'a, S: KeyStore<K>> std::iter::FusedIterator for GroupKeys<'a, K, S> {}
+
+/// Compute the Jaccard similarity of two sets of key ids.
+fn jaccard<Id: Hash + Eq, H: BuildHasher + Default>(a: &SmallSet<Id, H>, b: &SmallSet<Id, H>) -> f64 {
+    let (small, large) = if a.len() <= b.len() { (a, b) } else { (b, a) };
+    let shared = small.iter().filter(|id| large.contains(id)).count();
+    let union = a.len() + b.len() - shared;
+    if union == 0 {
+        1.0
+    } else {
+        shared as f64 / union as f64
+    }
+}
+
+impl<G, K, S> crate::BilevelSetOps for BilevelSet<G, K, S>
+where
+    G: Hash + Eq,
+    K: Eq,
+    S: KeyStore<K>,
+{
+    type Group = G;
+    type Key = K;
+
+    fn insert_pair(&mut self, g: G, k: K) -> bool {
+        self.insert_owned(g, k)
+    }
+
+    fn iter_pairs(&self) -> impl Iterator<Item = (&G, &K)> {
+        self.iter()
+    }
+
+    fn group_sizes(&self) -> impl Iterator<Item = (&G, usize)> {
+        self.group_sizes()
+    }
+
+    fn group_len(&self, g: &G) -> usize {
+        self.group_len(g)
+    }
+
+    fn group_count(&self) -> usize {
+        self.group_count()
+    }
+
+    fn len(&self) -> usize {
+        self.len()
+    }
+}
@@ -0,0 +1,138 @@
+use std::hash::Hash;
+use hashbrown::HashTable;
+
+use crate::hash;
+
+/// An aggregator for input that arrives sorted, or at least clustered, by
+/// group key, which holds only the group being read.
+///
+/// Each pair is added to the current group while its group key stays the
+/// same. When a pair of another group arrives, the current group is passed
+/// to a callback, along with its pairs, such as to write it out, and the
+/// new group is started. So a pipeline can aggregate input far larger
+/// than memory, so long as each group fits.
+///
+/// The input is not checked to be sorted: a group key that arrives again
+/// after its group was passed on starts a new group, which is passed on
+/// separately.
+///
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// V is the type of the payload.
+pub struct StreamingAggregator<G, K, V> {
+    /// The key of the group being read, if any.
+    group: Option<G>,
+    /// The pairs of the group being read.
+    pairs: HashTable<(K, V)>,
+    on_group: OnGroup<G, K, V>,
+}
+
+/// A callback passed each completed group, with its pairs.
+type OnGroup<G, K, V> = Box<dyn FnMut(G, Vec<(K, V)>) + Send + Sync>;
+
+impl<G, K, V> StreamingAggregator<G, K, V>
+where
+    V: Default,
+{
+    /// Create a new aggregator, with the default payload for each new pair.
+    ///
+    /// Completed groups are discarded until a callback is set by on_group.
+    pub fn new() -> Self {
+        Self {
+            group: None,
+            pairs: HashTable::new(),
+            on_group: Box::new(|_, _| ()),
+        }
+    }
+}
+
+impl<G, K, V> Default for StreamingAggregator<G, K, V>
+where
+    V: Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G, K, V> StreamingAggregator<G, K, V> {
+    /// Set the callback to pass each completed group to, along with the
+    /// aggregation key and payload of each of its pairs.
+    pub fn on_group<F>(mut self, on_group: F) -> Self
+    where
+        F: FnMut(G, Vec<(K, V)>) + Send + Sync + 'static,
+    {
+        self.on_group = Box::new(on_group);
+        self
+    }
+
+    /// Get the key of the group being read, or None if no pair has been
+    /// added since the last flush.
+    pub fn group(&self) -> Option<&G> {
+        self.group.as_ref()
+    }
+
+    /// Count the pairs of the group being read.
+    pub fn len(&self) -> usize {
+        self.pairs.len()
+    }
+
+    /// Check whether the group being read holds no pairs.
+    pub fn is_empty(&self) -> bool {
+        self.pairs.is_empty()
+    }
+
+    /// Pass the group being read to the callback, if any, leaving the
+    /// aggregator empty, such as when the input ends.
+    ///
+    /// The last group is only passed on by a flush, so it must be called
+    /// once the input ends.
+    pub fn flush(&mut self) {
+        if let Some(g) = self.group.take() {
+            let pairs = std::mem::take(&mut self.pairs).into_iter().collect();
+            (self.on_group)(g, pairs);
+        }
+    }
+}
+
+impl<G, K, V> StreamingAggregator<G, K, V>
+where
+    K: Hash,
+    V: Default,
+{
+    /// Get a mutable reference to the payload for the specified key pair.
+    ///
+    /// If the group differs from the group being read, that group is
+    /// passed on first, and the specified group is started. If the key
+    /// pair is currently not present, a new payload is inserted.
+    pub fn add_or_get<GRef, KRef>(&mut self, g: &GRef, k: &KRef) -> &mut V
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + ?Sized,
+        KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        if !self.group.as_ref().is_some_and(|o| g.eq(o)) {
+            self.flush();
+            self.group = Some(g.to_owned());
+        }
+        let (_, v) = self
+            .pairs
+            .entry(hash(k), |(o, _)| k.eq(o), |(o, _)| hash(o))
+            .or_insert_with(|| (k.to_owned(), V::default()))
+            .into_mut();
+        v
+    }
+}
+
+impl<G, K, V> std::fmt::Debug for StreamingAggregator<G, K, V>
+where
+    G: std::fmt::Debug,
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("StreamingAggregator")
+            .field("group", &self.group)
+            .field("pairs", &self.pairs.iter().collect::<Vec<_>>())
+            .finish()
+    }
+}
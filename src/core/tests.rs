@@ -0,0 +1,1229 @@
+use super::*;
+use crate::Capacity;
+
+#[test]
+pub fn test_set() {
+    let test_data = [(2, "a"), (2, "b"), (3, "a"), (2, "a"), (4, "c")];
+    let mut inline: BilevelSet<u8, String, Inline> = BilevelSet::new();
+    let mut interned: BilevelSet<u8, String> = BilevelSet::with_capacity(Capacity {
+        groups: 4,
+        per_group: 4,
+        agg_keys: 4,
+    });
+    for (i, (g, k)) in test_data.iter().enumerate() {
+        // Insertion returns false only for the duplicate pair.
+        assert_eq!(inline.insert(g, *k), i != 3);
+        assert_eq!(interned.insert(g, *k), i != 3);
+    }
+    let expected = [(2, "a"), (2, "b"), (3, "a"), (4, "c")];
+    assert!(inline.iter_sorted().map(|(g, k)| (*g, k.as_str())).eq(expected));
+    assert!(interned.iter_sorted().map(|(g, k)| (*g, k.as_str())).eq(expected));
+    assert_eq!(inline.pair_count(), 4);
+    assert_eq!(interned.group_count(), 3);
+    // Each distinct key is stored once when interned.
+    assert_eq!(interned.id_mapping().len(), 3);
+    let result: Vec<_> = interned.into_iter_sorted().collect();
+    assert!(result.iter().map(|(g, k)| (*g, k.as_str())).eq(expected));
+}
+
+#[test]
+pub fn test_map() {
+    let mut inline: BilevelMap<String, u16, u32, Inline> = BilevelMap::new();
+    let mut interned: BilevelMap<String, u16, u32> = BilevelMap::new();
+    for (g, k) in [("web1", 200), ("web1", 500), ("web2", 200), ("web1", 200)] {
+        *inline.add_or_get(g, &k) += 1;
+        *interned.add_or_get(g, &k) += 1;
+    }
+    let expected = [("web1", 200, 2), ("web1", 500, 1), ("web2", 200, 1)];
+    assert!(inline.iter_sorted().map(|(g, k, v)| (g.as_str(), *k, *v)).eq(expected));
+    assert!(interned.iter_sorted().map(|(g, k, v)| (g.as_str(), *k, *v)).eq(expected));
+    assert!(interned.id_mapping().map(|(_, k)| *k).eq([200, 500]));
+    let mut result: Vec<_> = inline.into_iter().collect();
+    result.sort_unstable();
+    assert!(result.iter().map(|(g, k, v)| (g.as_str(), *k, *v)).eq(expected));
+}
+
+#[test]
+pub fn test_pivot() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    for (g, k) in [("x", "a"), ("x", "b"), ("y", "a")] {
+        *map.add_or_get(g, k) += 1;
+    }
+    // The group keys may be stored either way once they become the
+    // aggregation keys.
+    let inline: BilevelMap<String, String, u32, Inline> = map.pivot();
+    let interned: BilevelMap<String, String, u32> = map.pivot();
+    let expected = [("a", "x"), ("a", "y"), ("b", "x")];
+    assert!(inline.iter_sorted().map(|(g, k, _)| (g.as_str(), k.as_str())).eq(expected));
+    assert!(interned.iter_sorted().map(|(g, k, _)| (g.as_str(), k.as_str())).eq(expected));
+    assert_eq!(interned.id_mapping().len(), 2);
+}
+
+#[test]
+pub fn test_merge() {
+    let mut a: BilevelMap<u8, String, u32> = BilevelMap::new();
+    let mut b: BilevelMap<u8, String, u32> = BilevelMap::new();
+    *a.add_or_get(&1, "p") += 1;
+    *b.add_or_get(&2, "q") += 2;
+    *b.add_or_get(&1, "p") += 3;
+    a.merge(b, |v, w| *v += w);
+    let result: Vec<_> = a.iter_sorted().map(|(g, k, v)| (*g, k.as_str(), *v)).collect();
+    assert_eq!(result, [(1, "p", 4), (2, "q", 2)]);
+    // The keys of the other collection are interned again here.
+    assert!(a.id_mapping().map(|(_, k)| k).eq(["p", "q"]));
+}
+
+#[test]
+pub fn test_dedup_groups() {
+    let mut set: BilevelSet<u8, String> = BilevelSet::new();
+    for (g, k) in [(1, "a"), (1, "b"), (2, "b"), (2, "a"), (3, "a")] {
+        set.insert(&g, k);
+    }
+    let mut classes = set.dedup_groups();
+    classes[0].sort_unstable();
+    assert_eq!(classes, [[&1, &2]]);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+pub fn test_serde() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    *map.add_or_get("x", "a") += 3;
+    assert_eq!(serde_json::to_string(&map).unwrap(), r#"{"x":{"a":3}}"#);
+    let set: BilevelSet<u8, u8, Inline> = serde_json::from_str(r#"{"1":[2,3]}"#).unwrap();
+    assert!(set.iter_sorted().map(|(g, k)| (*g, *k)).eq([(1, 2), (1, 3)]));
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+pub fn test_snapshot() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    for (g, k) in [("x", "a"), ("x", "b"), ("y", "a"), ("x", "a")] {
+        *map.add_or_get(g, k) += 1;
+    }
+    let mut buf = Vec::new();
+    map.write_snapshot(&mut buf).unwrap();
+    let mut restored: BilevelMap<String, String, u32> = BilevelMap::read_snapshot(&buf[..]).unwrap();
+    assert!(restored.iter_sorted().eq(map.iter_sorted()));
+    assert_eq!(*restored.add_or_get("x", "a"), 2);
+    let mut set: BilevelSet<u8, u8, Inline> = BilevelSet::new();
+    set.insert(&1, &2);
+    let mut buf = Vec::new();
+    set.write_snapshot(&mut buf).unwrap();
+    let restored: BilevelSet<u8, u8, Inline> = BilevelSet::read_snapshot(&buf[..]).unwrap();
+    assert!(restored.iter().eq(set.iter()));
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+pub fn test_snapshot_per_group() {
+    // The capacity for each new group follows the header, and a damaged
+    // one is rejected rather than failing the next insert.
+    let mut map: BilevelMap<u8, u8, u32> = BilevelMap::new();
+    *map.add_or_get(&1, &2) += 1;
+    let mut buf = Vec::new();
+    map.write_snapshot(&mut buf).unwrap();
+    buf[5..13].copy_from_slice(&u64::MAX.to_le_bytes());
+    assert!(matches!(BilevelMap::<u8, u8, u32>::read_snapshot(&buf[..]), Err(crate::SnapshotError::Corrupt)));
+    assert!(matches!(
+        BilevelMap::<u8, u8, u32>::read_snapshot_partial(&buf[..]),
+        Err(crate::SnapshotError::Corrupt)
+    ));
+    let mut set: BilevelSet<u8, u8> = BilevelSet::new();
+    set.insert(&1, &2);
+    let mut buf = Vec::new();
+    set.write_snapshot(&mut buf).unwrap();
+    buf[5..13].copy_from_slice(&(1u64 << 40).to_le_bytes());
+    assert!(matches!(BilevelSet::<u8, u8>::read_snapshot(&buf[..]), Err(crate::SnapshotError::Corrupt)));
+}
+
+#[cfg(all(feature = "snapshot", feature = "hybrid"))]
+#[test]
+pub fn test_snapshot_compatible() {
+    // A snapshot written by the hybrid map is read by the core map
+    // with interned keys, since the hybrid map is built on it.
+    let mut map: crate::hybrid::BilevelMap<u8, String, u32> = crate::hybrid::BilevelMap::new();
+    *map.add_or_get(1, "a") += 5;
+    let mut buf = Vec::new();
+    map.write_snapshot(&mut buf).unwrap();
+    let restored: BilevelMap<u8, String, u32> = BilevelMap::read_snapshot(&buf[..]).unwrap();
+    assert!(restored.iter().map(|(g, k, v)| (*g, k.as_str(), *v)).eq([(1, "a", 5)]));
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+pub fn test_delta_remove() {
+    // A pair removed between deltas is removed from the coordinator, and
+    // a payload changed back to the default is still sent.
+    let mut worker: BilevelMap<u8, String, u32> = BilevelMap::new();
+    let mut coordinator: BilevelMap<u8, String, u32> = BilevelMap::new();
+    for (g, k) in [(1, "a"), (1, "b"), (1, "a"), (2, "c")] {
+        *worker.add_or_get(&g, k) += 1;
+    }
+    coordinator.apply_delta(&worker.take_delta().unwrap(), |v, w| *v = w).unwrap();
+    assert_eq!(*worker.add_or_get(&1, "a"), 2);
+    worker.remove(&1, "b");
+    *worker.add_or_get(&1, "a") = 0;
+    worker.remove_group(&2);
+    coordinator.apply_delta(&worker.take_delta().unwrap(), |v, w| *v = w).unwrap();
+    assert!(coordinator.iter_sorted().eq(worker.iter_sorted()));
+    assert_eq!(coordinator.group_count(), 1);
+    // Nothing has changed since the last delta.
+    let mut unchanged: BilevelMap<u8, String, u32> = BilevelMap::new();
+    unchanged.apply_delta(&worker.take_delta().unwrap(), |v, w| *v = w).unwrap();
+    assert_eq!(unchanged.pair_count(), 0);
+}
+
+#[test]
+pub fn test_trilevel() {
+    let mut map: TrilevelMap<String, String, u16, u32> = TrilevelMap::new();
+    let test_data = [
+        ("eu", "web1", 500),
+        ("eu", "web1", 503),
+        ("eu", "web2", 500),
+        ("us", "web3", 500),
+        ("eu", "web1", 500),
+    ];
+    for (region, host, code) in test_data {
+        *map.add_or_get(region, host, &code) += 1;
+    }
+    let expected = [
+        ("eu", "web1", 500, 2),
+        ("eu", "web1", 503, 1),
+        ("eu", "web2", 500, 1),
+        ("us", "web3", 500, 1),
+    ];
+    assert!(map.iter_sorted().map(|(r, h, c, v)| (r.as_str(), h.as_str(), *c, *v)).eq(expected));
+    assert_eq!((map.group_count(), map.subgroup_count(), map.triple_count()), (2, 3, 4));
+    // Each outer group is a BilevelMap of its own.
+    let eu = map.group("eu").unwrap();
+    assert!(eu.iter_sorted().map(|(h, c, v)| (h.as_str(), *c, *v))
+        .eq([("web1", 500, 2), ("web1", 503, 1), ("web2", 500, 1)]));
+    assert!(map.group("asia").is_none());
+    let mut regions: Vec<_> = map.iter_groups().map(|(r, hosts)| (r.as_str(), hosts.group_count())).collect();
+    regions.sort_unstable();
+    assert_eq!(regions, [("eu", 2), ("us", 1)]);
+}
+
+#[cfg(feature = "derive")]
+#[test]
+pub fn test_derive() {
+    use crate::BilevelKey;
+
+    #[derive(BilevelKey)]
+    struct Request {
+        #[group]
+        region: String,
+        #[agg]
+        status: u16,
+        #[group]
+        host: String,
+        bytes: u64,
+    }
+
+    #[derive(BilevelKey)]
+    struct Pair<'a>(#[agg] &'a str, #[group] u8);
+
+    let rows = [
+        Request { region: "eu".into(), host: "web1".into(), status: 200, bytes: 10 },
+        Request { region: "eu".into(), host: "web1".into(), status: 200, bytes: 20 },
+        Request { region: "us".into(), host: "web2".into(), status: 500, bytes: 5 },
+    ];
+    let mut map: BilevelMap<(String, String), u16, u64> = BilevelMap::new();
+    for row in &rows {
+        *map.add_or_get_row(row) += row.bytes;
+    }
+    // The fields of the group key are listed in the order declared.
+    let result: Vec<_> = map.iter_sorted().map(|((r, h), s, v)| (r.as_str(), h.as_str(), *s, *v)).collect();
+    assert_eq!(result, [("eu", "web1", 200, 30), ("us", "web2", 500, 5)]);
+
+    let mut set: BilevelSet<u8, &str, Inline> = BilevelSet::new();
+    assert!(set.insert_row(&Pair("a", 1)));
+    assert!(!set.insert_row(&Pair("a", 1)));
+    assert_eq!(Pair("b", 2).group(), 2);
+}
+
+#[test]
+pub fn test_aggregate_by() {
+    struct Row {
+        host: String,
+        status: u16,
+        bytes: u64,
+    }
+    let rows = [("web1", 200, 10), ("web2", 200, 5), ("web1", 200, 20), ("web1", 404, 1)]
+        .map(|(h, s, b)| Row { host: h.to_owned(), status: s, bytes: b });
+    let map = crate::aggregate_by(rows, |r| r.host.clone(), |r| r.status, Vec::new, |v, r| v.push(r.bytes));
+    let result: Vec<_> = map.iter_sorted().map(|(g, k, v)| (g.as_str(), *k, v.clone())).collect();
+    assert_eq!(result, [("web1", 200, vec![10, 20]), ("web1", 404, vec![1]), ("web2", 200, vec![5])]);
+}
+
+#[test]
+pub fn test_builder() {
+    let mut builder = Builder::new(|r: &(&str, u16)| r.0.to_owned(), |r| r.1, || 0, |n: &mut u32, _| *n += 1);
+    builder.feed([("web1", 200), ("web1", 500)]);
+    builder.feed([("web1", 200), ("web2", 200), ("web1", 200)]);
+    let stats = builder.stats();
+    assert_eq!((stats.rows, stats.pairs, stats.duplicates, stats.batches), (5, 3, 2, 2));
+    assert_eq!(builder.map().group_count(), 2);
+    let map: BilevelMap<String, u16, u32> = builder.into_map();
+    let result: Vec<_> = map.iter_sorted().map(|(g, k, v)| (g.as_str(), *k, *v)).collect();
+    assert_eq!(result, [("web1", 200, 3), ("web1", 500, 1), ("web2", 200, 1)]);
+
+    let mut builder = Builder::new(|r: &(u8, u8)| r.0, |r| r.1, || (), |_, _| ());
+    builder.feed([(1, 2), (1, 2), (2, 3)]);
+    let set: BilevelSet<u8, u8, Inline> = builder.into_set();
+    assert!(set.iter_sorted().map(|(g, k)| (*g, *k)).eq([(1, 2), (2, 3)]));
+}
+
+#[test]
+pub fn test_extend_with() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    *map.add_or_get("x", "a") += 1;
+    let rows = [("x", "a", 2), ("x", "b", 3), ("x", "b", 4)]
+        .map(|(g, k, v)| (g.to_owned(), k.to_owned(), v));
+    map.extend_with(rows, |a, b| *a += b);
+    let result: Vec<_> = map.iter_sorted().map(|(g, k, v)| (g.as_str(), k.as_str(), *v)).collect();
+    // The payloads of repeated pairs are combined, not replaced.
+    assert_eq!(result, [("x", "a", 3), ("x", "b", 7)]);
+}
+
+#[test]
+pub fn test_top_k() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    for (g, k, v) in [("x", "a", 3), ("x", "b", 9), ("x", "c", 1), ("x", "d", 5), ("y", "a", 2)] {
+        *map.add_or_get(g, k) = v;
+    }
+    let top: Vec<_> = map.top_k("x", 2, |v| *v).into_iter().map(|(k, v)| (k.as_str(), *v)).collect();
+    assert_eq!(top, [("b", 9), ("d", 5)]);
+    // The least payloads can be found by reversing the ranking.
+    let bottom: Vec<_> = map.top_k("x", 1, |v| std::cmp::Reverse(*v)).into_iter().map(|(k, _)| k.as_str()).collect();
+    assert_eq!(bottom, ["c"]);
+    assert!(map.top_k("z", 2, |v| *v).is_empty());
+    assert!(map.top_k("x", 0, |v| *v).is_empty());
+    let mut all: Vec<_> = map.top_k_all(2, |v| *v).map(|(g, k, v)| (g.as_str(), k.as_str(), *v)).collect();
+    all.sort_by_key(|r| r.0);
+    assert_eq!(all, [("x", "b", 9), ("x", "d", 5), ("y", "a", 2)]);
+}
+
+#[test]
+pub fn test_multiset() {
+    let mut set: BilevelMultiset<String, String> = BilevelMultiset::new();
+    assert_eq!(set.insert("x", "a"), 1);
+    assert_eq!(set.insert("x", "b"), 1);
+    assert_eq!(set.insert("x", "a"), 2);
+    assert_eq!(set.insert_n("y", "a", 5), 5);
+    let result: Vec<_> = set.iter_sorted().map(|(g, k, n)| (g.as_str(), k.as_str(), n)).collect();
+    assert_eq!(result, [("x", "a", 2), ("x", "b", 1), ("y", "a", 5)]);
+    assert_eq!((set.pair_count(), set.total_count()), (3, 8));
+    let top = set.iter_sorted_by(|a, b| b.2.cmp(&a.2)).next().unwrap();
+    assert_eq!((top.0.as_str(), top.2), ("y", 5));
+}
+
+#[test]
+pub fn test_vec_map() {
+    let mut map: BilevelVecMap<String, u16, u32> = BilevelVecMap::new();
+    for (g, k, v) in [("web1", 200, 12), ("web1", 200, 7), ("web2", 500, 30), ("web1", 200, 9)] {
+        map.push(g, &k, v);
+    }
+    let result: Vec<_> = map.iter_sorted().map(|(g, k, v)| (g.as_str(), *k, v.to_vec())).collect();
+    // The values are kept in the order pushed.
+    assert_eq!(result, [("web1", 200, vec![12, 7, 9]), ("web2", 500, vec![30])]);
+    assert_eq!((map.pair_count(), map.value_count()), (2, 4));
+    assert_eq!(map.iter_values().filter(|(g, _, _)| g.as_str() == "web1").count(), 3);
+}
+
+#[test]
+pub fn test_group_sizes() {
+    let mut set: BilevelSet<u32, u32> = BilevelSet::new();
+    for (g, n) in [(1, 1), (2, 3), (3, 4), (4, 10)] {
+        for k in 0..n {
+            set.insert(&g, &k);
+        }
+    }
+    set.declare_groups([&5]);
+    let mut sizes: Vec<_> = set.group_sizes().map(|(g, n)| (*g, n)).collect();
+    sizes.sort();
+    assert_eq!(sizes, [(1, 1), (2, 3), (3, 4), (4, 10), (5, 0)]);
+    // Buckets of up to 0, 1 to 2, 3 to 4, and more than 4.
+    assert_eq!(set.size_histogram(&[0, 2, 4]), [1, 1, 2, 1]);
+    assert_eq!(set.size_histogram(&[]), [5]);
+
+    let mut map: BilevelMap<u32, u32, ()> = BilevelMap::new();
+    map.add_or_get(&1, &1);
+    map.add_or_get(&1, &2);
+    assert!(map.group_sizes().eq([(&1, 2)]));
+    assert_eq!(map.size_histogram(&[1]), [0, 1]);
+}
+
+#[test]
+pub fn test_len() {
+    let mut set: BilevelSet<String, String> = BilevelSet::new();
+    assert!(set.is_empty());
+    set.insert("x", "a");
+    set.insert("x", "a");
+    set.insert("x", "b");
+    set.insert("y", "a");
+    set.declare_groups(["z"]);
+    assert_eq!((set.len(), set.group_len("x"), set.group_len("z"), set.group_len("w")), (3, 2, 0, 0));
+    assert_eq!(set.distinct_agg_keys(), 2);
+
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    *map.add_or_get("x", "a") += 1;
+    map.add_or_get_with_key("x", "b", |_, _| 1);
+    map.extend_with([("x".to_owned(), "a".to_owned(), 1), ("y".to_owned(), "c".to_owned(), 1)], |a, b| *a += b);
+    assert_eq!((map.len(), map.group_len("x"), map.distinct_agg_keys()), (3, 2, 3));
+    let mut other: BilevelMap<String, String, u32> = BilevelMap::new();
+    *other.add_or_get("x", "a") += 1;
+    *other.add_or_get("z", "a") += 1;
+    map.merge(other, |a, b| *a += b);
+    assert_eq!(map.len(), 4);
+    // Pairs that fall together when regrouped are counted once.
+    let regrouped = map.regroup_by(|_| "all".to_owned(), |a, b| *a += b);
+    assert_eq!((regrouped.len(), regrouped.group_len("all")), (3, 3));
+    let nested = regrouped.into_nested();
+    let rebuilt: BilevelMap<String, String, u32, Inline> = nested.into();
+    assert_eq!(rebuilt.len(), 3);
+}
+
+#[test]
+pub fn test_iter_sorted_by_value() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    for (g, k, v) in [("x", "a", 2), ("x", "b", 7), ("x", "c", 4), ("y", "a", 1), ("y", "b", 3)] {
+        *map.add_or_get(g, k) = v;
+    }
+    let mut groups: Vec<Vec<(&str, &str, u32)>> = Vec::new();
+    for (g, k, v) in map.iter_sorted_by_value(|a, b| b.cmp(a)) {
+        match groups.last_mut() {
+            Some(group) if group[0].0 == g.as_str() => group.push((g.as_str(), k.as_str(), *v)),
+            _ => groups.push(vec![(g.as_str(), k.as_str(), *v)]),
+        }
+    }
+    // Each group is listed once, with its greatest payloads first.
+    groups.sort();
+    assert_eq!(groups, [
+        vec![("x", "b", 7), ("x", "c", 4), ("x", "a", 2)],
+        vec![("y", "b", 3), ("y", "a", 1)],
+    ]);
+}
+
+#[test]
+pub fn test_heavy_hitters() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    for (g, k, v) in [("x", "a", 2), ("x", "b", 7), ("y", "a", 9), ("y", "b", 3), ("z", "a", 5)] {
+        *map.add_or_get(g, k) = v;
+    }
+    let top: Vec<_> = map.heavy_hitters(3).into_iter().map(|(g, k, v)| (g.as_str(), k.as_str(), *v)).collect();
+    assert_eq!(top, [("y", "a", 9), ("x", "b", 7), ("z", "a", 5)]);
+    assert_eq!(map.heavy_hitters(10).len(), 5);
+}
+
+#[test]
+pub fn test_group_metrics() {
+    let even = GroupMetrics::from_counts([5, 5, 5, 5]);
+    assert_eq!((even.total, even.entropy, even.gini, even.top_share), (20, 2.0, 0.0, 0.25));
+    let single = GroupMetrics::from_counts([7]);
+    assert_eq!((single.entropy, single.gini, single.top_share), (0.0, 0.0, 1.0));
+    assert_eq!(GroupMetrics::from_counts([]), GroupMetrics::default());
+
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    *map.add_or_get("skewed", "a") = 97;
+    *map.add_or_get("skewed", "b") = 1;
+    *map.add_or_get("skewed", "c") = 1;
+    *map.add_or_get("skewed", "d") = 1;
+    let (_, skewed) = map.group_metrics().next().unwrap();
+    assert_eq!((skewed.total, skewed.top_share), (100, 0.97));
+    assert!((skewed.gini - 0.72).abs() < 1e-9);
+    assert!(skewed.entropy < 0.25);
+}
+
+#[test]
+pub fn test_similarity() {
+    let mut set: BilevelSet<String, String> = BilevelSet::new();
+    for (g, keys) in [("ann", "abcd"), ("bob", "abce"), ("cat", "ab"), ("dan", "xyz")] {
+        for k in keys.chars() {
+            set.insert(g, &k.to_string());
+        }
+    }
+    set.declare_groups(["eve", "fay"]);
+    assert_eq!(set.similarity("ann", "bob"), Some(0.6));
+    assert_eq!(set.similarity("ann", "cat"), Some(0.5));
+    assert_eq!(set.similarity("ann", "dan"), Some(0.0));
+    assert_eq!(set.similarity("eve", "fay"), Some(1.0));
+    assert_eq!(set.similarity("ann", "gus"), None);
+    let similar: Vec<_> = set.most_similar("ann", 5).into_iter().map(|(g, s)| (g.as_str(), s)).collect();
+    assert_eq!(similar, [("bob", 0.6), ("cat", 0.5)]);
+    assert_eq!(set.most_similar("ann", 1).len(), 1);
+    assert!(set.most_similar("gus", 1).is_empty());
+}
+
+#[test]
+pub fn test_cooccurrence() {
+    let mut set: BilevelSet<u32, String> = BilevelSet::new();
+    for (g, basket) in [(1, ["bread", "milk"].as_slice()), (2, &["bread", "milk", "eggs"]), (3, &["eggs"])] {
+        for k in basket {
+            set.insert(&g, *k);
+        }
+    }
+    let counts: BilevelMap<String, String, usize> = set.cooccurrence();
+    let result: Vec<_> = counts.iter_sorted().map(|(a, b, n)| (a.as_str(), b.as_str(), *n)).collect();
+    assert_eq!(result, [
+        ("bread", "eggs", 1), ("bread", "milk", 2),
+        ("eggs", "bread", 1), ("eggs", "milk", 1),
+        ("milk", "bread", 2), ("milk", "eggs", 1),
+    ]);
+}
+
+#[cfg(feature = "json")]
+#[test]
+pub fn test_to_json_grouped() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    for (g, k, v) in [("web2", "500", 1), ("web1", "404", 2), ("web1", "200", 5), ("web2", "200", 3)] {
+        *map.add_or_get(g, k) = v;
+    }
+    let mut out = Vec::new();
+    map.to_json_grouped(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), r#"{"web1":{"200":5,"404":2},"web2":{"200":3,"500":1}}"#);
+
+    let mut set: BilevelSet<u32, String> = BilevelSet::new();
+    set.insert(&2, "b");
+    set.insert(&2, "a");
+    set.insert(&1, "c");
+    let mut out = Vec::new();
+    set.to_json_grouped(&mut out).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), r#"{"1":["c"],"2":["a","b"]}"#);
+
+    let mut out = Vec::new();
+    set.remove_group(&2);
+    set.insert(&3, "a");
+    set.to_json_with_ids(&mut out).unwrap();
+    // The group added since takes the slot of the removed group, under a
+    // new generation.
+    assert_eq!(String::from_utf8(out).unwrap(), concat!(
+        r#"[{"group_id":[0,1],"group":3,"key_id":1,"key":"a"},"#,
+        r#"{"group_id":[1,0],"group":1,"key_id":2,"key":"c"}]"#,
+    ));
+    let mut out = Vec::new();
+    map.remove_group("web2");
+    map.to_json_with_ids(&mut out).unwrap();
+    assert!(String::from_utf8(out).unwrap().contains(r#"{"group_id":[1,0],"group":"web1","key_id":2,"key":"200","value":5}"#));
+}
+
+#[cfg(feature = "csv")]
+#[test]
+pub fn test_write_csv() {
+    use crate::{Column, CsvOptions};
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    *map.add_or_get("web1", "GET /a,b") = 3;
+    let mut out = Vec::new();
+    map.write_csv(&mut out, &CsvOptions::default()).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "group,key,value\nweb1,\"GET /a,b\",3\n");
+
+    let options = CsvOptions {
+        header: false,
+        columns: vec![Column::Value, Column::Group],
+        ..CsvOptions::tsv()
+    };
+    let mut out = Vec::new();
+    map.write_csv(&mut out, &options).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "3\tweb1\n");
+
+    let mut set: BilevelSet<u32, String> = BilevelSet::new();
+    set.insert(&1, "say \"hi\"");
+    let options = CsvOptions { names: ["host", "path", "n"].map(String::from), ..CsvOptions::default() };
+    let mut out = Vec::new();
+    set.write_csv(&mut out, &options).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "host,path,n\n1,\"say \"\"hi\"\"\",\n");
+
+    // The key ids are written only where the keys are interned.
+    let options = CsvOptions {
+        columns: vec![Column::GroupId, Column::KeyId, Column::Key],
+        ..CsvOptions::default()
+    };
+    let mut out = Vec::new();
+    set.write_csv(&mut out, &options).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "group_id,key_id,key\n0:0,0,\"say \"\"hi\"\"\"\n");
+    let mut inline: BilevelSet<u32, String, Inline> = BilevelSet::new();
+    inline.insert(&1, "a");
+    let mut out = Vec::new();
+    inline.write_csv(&mut out, &options).unwrap();
+    assert_eq!(String::from_utf8(out).unwrap(), "group_id,key_id,key\n0:0,,a\n");
+}
+
+#[cfg(feature = "arrow")]
+#[test]
+pub fn test_to_record_batch() {
+    use arrow_array::{Array, StringArray, UInt64Array};
+    let mut map: BilevelMap<String, String, u64> = BilevelMap::new();
+    *map.add_or_get("web1", "200") += 5;
+    *map.add_or_get("web1", "404") += 1;
+    let batch = map.to_record_batch().unwrap();
+    assert_eq!(batch.num_rows(), 2);
+    let names: Vec<_> = batch.schema().fields().iter().map(|f| f.name().clone()).collect();
+    assert_eq!(names, ["group", "key", "value"]);
+    let keys = batch.column(1).as_any().downcast_ref::<StringArray>().unwrap();
+    let values = batch.column(2).as_any().downcast_ref::<UInt64Array>().unwrap();
+    let mut rows: Vec<_> = (0..2).map(|i| (keys.value(i), values.value(i))).collect();
+    rows.sort();
+    assert_eq!(rows, [("200", 5), ("404", 1)]);
+    assert_eq!(batch.column(0).null_count(), 0);
+
+    let batch = map.to_record_batch_with_ids().unwrap();
+    let names: Vec<_> = batch.schema().fields().iter().map(|f| f.name().clone()).collect();
+    assert_eq!(names, ["group_id", "group_generation", "key_id", "group", "key", "value"]);
+    let key_ids = batch.column(2).as_any().downcast_ref::<UInt64Array>().unwrap();
+    let keys = batch.column(4).as_any().downcast_ref::<StringArray>().unwrap();
+    for i in 0..2 {
+        let id = AggKeyId::from_index(key_ids.value(i) as usize);
+        assert_eq!(map.resolve_agg_key(id).map(String::as_str), Some(keys.value(i)));
+    }
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+pub fn test_write_parquet() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    let mut map: BilevelMap<String, String, u64> = BilevelMap::new();
+    *map.add_or_get("web1", "200") += 5;
+    *map.add_or_get("web1", "404") += 1;
+    *map.add_or_get("web2", "200") += 3;
+    let path = std::env::temp_dir().join(format!("bilevel-{}.parquet", std::process::id()));
+    let read = |options: &crate::ParquetOptions| {
+        map.write_parquet(&path, options).unwrap();
+        let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+        let metadata = reader.metadata();
+        (metadata.num_row_groups(), metadata.file_metadata().num_rows())
+    };
+    assert_eq!(read(&crate::ParquetOptions::default()), (1, 3));
+    let options = crate::ParquetOptions { row_group_per_group: true, ..Default::default() };
+    assert_eq!(read(&options), (2, 3));
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[cfg(feature = "polars")]
+#[test]
+pub fn test_dataframe() {
+    use polars::prelude::*;
+    let df = df! {
+        "host" => ["web1", "web1", "web1", "web2"],
+        "port" => [80u32, 80, 80, 443],
+        "status" => ["200", "200", "404", "200"],
+        "hits" => [5u64, 2, 1, 3],
+    }.unwrap();
+    let map: BilevelMap<(String, u32), String, u64> =
+        BilevelMap::from_dataframe(&df, &["host", "port"], &["status"], "hits").unwrap();
+    assert_eq!(map.pair_count(), 3);
+    let mut rows: Vec<_> = map.iter().map(|((h, p), k, &v)| (h.as_str(), *p, k.as_str(), v)).collect();
+    rows.sort();
+    assert_eq!(rows, [("web1", 80, "200", 7), ("web1", 80, "404", 1), ("web2", 443, "200", 3)]);
+    let out = map.to_dataframe().unwrap();
+    let names: Vec<_> = out.get_column_names().into_iter().map(|n| n.as_str()).collect();
+    assert_eq!(names, ["group_0", "group_1", "key", "value"]);
+    assert_eq!(out.column("value").unwrap().u64().unwrap().sum(), Some(11));
+    let wrong = BilevelMap::<String, String, u64>::from_dataframe(&df, &["host", "port"], &["status"], "hits");
+    assert!(wrong.is_err());
+}
+
+#[test]
+pub fn test_to_csr() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    *map.add_or_get("web1", "404") += 1;
+    *map.add_or_get("web1", "200") += 5;
+    *map.add_or_get("web2", "200") += 3;
+    let csr = map.to_csr();
+    assert_eq!(csr.shape(), (2, 2));
+    assert_eq!(csr.nnz(), 3);
+    assert_eq!(csr.offsets.len(), 3);
+    for (row, g) in csr.groups.iter().enumerate() {
+        let range = csr.offsets[row]..csr.offsets[row + 1];
+        let cells: Vec<_> = csr.indices[range.clone()].iter()
+            .map(|&i| map.resolve_agg_key(AggKeyId::from_index(i)).unwrap().as_str())
+            .zip(csr.values[range].iter().copied())
+            .collect();
+        match g.as_str() {
+            "web1" => assert_eq!(cells, [("404", 1), ("200", 5)]),
+            _ => assert_eq!(cells, [("200", 3)]),
+        }
+    }
+}
+
+#[test]
+pub fn test_freeze() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    *map.add_or_get("web2", "200") += 3;
+    *map.add_or_get("web1", "404") += 1;
+    *map.add_or_get("web1", "200") += 5;
+    let expected: Vec<_> = map.iter_sorted().map(|(g, k, v)| (g.clone(), k.clone(), *v)).collect();
+    let frozen = map.freeze();
+    assert_eq!((frozen.group_count(), frozen.pair_count()), (2, 3));
+    assert!(frozen.iter().map(|(g, k, v)| (g.clone(), k.clone(), *v)).eq(expected.iter().cloned()));
+    assert_eq!(frozen.get("web1", "200"), Some(&5));
+    assert!(frozen.contains("web2", "200") && !frozen.contains("web2", "404"));
+    assert_eq!(frozen.group("web1"), Some((&["200".to_string(), "404".to_string()][..], &[5, 1][..])));
+    assert!(frozen.group("web3").is_none());
+    assert_eq!(frozen.group_keys(), ["web1", "web2"]);
+    // The last group is found without listing the others.
+    assert_eq!(frozen.iter_groups().next_back().map(|(g, _, _)| g.as_str()), Some("web2"));
+    assert!(frozen.iter().rev().map(|(g, k, v)| (g.clone(), k.clone(), *v)).eq(expected.into_iter().rev()));
+}
+
+#[test]
+pub fn test_compact_str() {
+    use crate::{CompactStr, HeapSize};
+    assert_eq!(std::mem::size_of::<CompactStr>(), std::mem::size_of::<String>());
+    let long = "a key longer than twenty-two bytes";
+    assert!(CompactStr::new("web").is_inline() && !CompactStr::new(long).is_inline());
+    assert_eq!(CompactStr::from(long.to_string()), long);
+
+    let mut map: BilevelMap<CompactStr, CompactStr, u32> = BilevelMap::new();
+    for (g, k) in [("web", "200"), ("web", long), ("db", "200"), ("web", "200")] {
+        *map.add_or_get(&CompactStr::new(g), &CompactStr::new(k)) += 1;
+    }
+    // The keys are searched by str, as they hash and compare as str.
+    assert_eq!(map.group_len("web"), 2);
+    assert_eq!(map.len(), 3);
+    assert_eq!(CompactStr::new(long).heap_bytes(), long.len());
+    assert!(map.iter_sorted().map(|(g, k, v)| (g.as_str(), k.as_str(), *v)).eq([("db", "200", 1), ("web", "200", 2), ("web", long, 1)]));
+    // Pairs are removed by str too, whether the group holds its keys in a
+    // list or, once grown, in a hash table.
+    assert_eq!(map.remove("web", long), Some(1));
+    for i in 0..16 {
+        *map.add_or_get(&CompactStr::new("db"), &CompactStr::from(format!("{long} {i}"))) += 1;
+    }
+    assert_eq!(map.remove("db", format!("{long} 9").as_str()), Some(1));
+    assert_eq!(map.remove("db", "404"), None);
+    assert_eq!((map.group_len("web"), map.group_len("db")), (1, 16));
+}
+
+#[test]
+pub fn test_key_interner() {
+    use std::sync::Arc;
+    let urls = KeyInterner::<String>::new();
+    let mut visited: BilevelSet<u32, String, Arc<KeyInterner<String>>> =
+        BilevelSet::with_keys(Capacity::default(), urls.clone());
+    let mut hits: BilevelMap<u32, String, u32, Arc<KeyInterner<String>>> =
+        BilevelMap::with_keys(Capacity::default(), urls.clone());
+    let mut bytes: BilevelMap<String, String, u64, Arc<KeyInterner<String>>> =
+        BilevelMap::with_keys(Capacity::default(), urls.clone());
+    for (user, url, size) in [(1, "/a", 10), (2, "/a", 10), (1, "/b", 20), (1, "/a", 10)] {
+        visited.insert(&user, url);
+        *hits.add_or_get(&user, url) += 1;
+        *bytes.add_or_get("all", url) += size;
+    }
+    // Each URL is kept once, however many collections and groups hold it.
+    assert_eq!(urls.len(), 2);
+    assert_eq!((visited.len(), hits.len(), bytes.len()), (3, 3, 2));
+    assert!(hits.iter_sorted().map(|(g, k, v)| (*g, k.as_str(), *v)).eq([(1, "/a", 2), (1, "/b", 1), (2, "/a", 1)]));
+    assert!(bytes.iter_sorted().map(|(_, k, v)| (k.as_str(), *v)).eq([("/a", 30), ("/b", 20)]));
+    drop((visited, hits, bytes));
+    assert_eq!(urls.get_or_insert("/b").get(), "/b");
+    assert_eq!(urls.len(), 2);
+}
+
+#[test]
+pub fn test_ids() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    *map.add_or_get("u1", "https://example.com/a") += 1;
+    *map.add_or_get("u2", "https://example.com/b") += 1;
+    map.declare_groups(["u3"]);
+    let u3 = map.group_id("u3").unwrap();
+    let a = map.agg_key_id("https://example.com/a").unwrap();
+    assert_eq!(map.resolve_group(u3).map(String::as_str), Some("u3"));
+    assert_eq!(map.resolve_agg_key(a).map(String::as_str), Some("https://example.com/a"));
+    assert_eq!(map.agg_key_id("https://example.com/c"), None);
+    // Pairs can be added by id, without copying the keys.
+    *map.add_or_get_by_id(u3, a).unwrap() += 5;
+    *map.add_or_get_by_id(u3, a).unwrap() += 1;
+    assert_eq!(map.len(), 3);
+    assert_eq!(map.iter().find(|(g, _, _)| *g == "u3").map(|(_, _, v)| *v), Some(6));
+    // Removing a group leaves the ids of the others unchanged, and its
+    // slot is reused for the next group added, under a new generation, so
+    // that the old id refers to no group.
+    let u2 = map.group_id("u2").unwrap();
+    map.remove_group("u2");
+    assert_eq!(map.group_id("u3"), Some(u3));
+    assert_eq!(map.resolve_group(u2), None);
+    map.declare_groups(["u4"]);
+    let u4 = map.group_id("u4").unwrap();
+    assert_eq!((u4.index(), u4.generation()), (u2.index(), u2.generation() + 1));
+    assert_eq!(map.resolve_group(u2), None);
+    assert!(map.add_or_get_by_id(u2, a).is_none());
+    assert_eq!(map.group_len("u4"), 0);
+    assert_eq!(map.group_ids().count(), 3);
+    // Ids of no group or no key of this collection are refused.
+    assert!(map.add_or_get_by_id(GroupId::from_parts(100, 0), a).is_none());
+    assert!(map.add_or_get_by_id(u4, AggKeyId::from_index(100)).is_none());
+
+    let mut set: BilevelSet<u8, String> = BilevelSet::new();
+    set.insert(&1, "a");
+    set.insert(&2, "b");
+    let (g, k) = (set.group_id(&2).unwrap(), set.agg_key_id("a").unwrap());
+    assert_eq!(set.insert_by_id(g, k), Some(true));
+    assert_eq!(set.insert_by_id(g, k), Some(false));
+    assert_eq!(set.group_len(&2), 2);
+    assert_eq!(GroupId::from_parts(g.index(), g.generation()), g);
+    assert_eq!(set.insert_by_id(GroupId::from_parts(g.index(), g.generation() + 1), k), None);
+}
+
+#[test]
+pub fn test_small_groups() {
+    // Groups hold their keys inline at first, then in a hash table once
+    // they grow, and must behave the same either way.
+    let mut map: BilevelMap<u32, u32, u32> = BilevelMap::new();
+    for i in 0..100 {
+        *map.add_or_get(&(i % 2), &(i % 50)) += 1;
+    }
+    *map.add_or_get(&2, &7) += 1;
+    assert_eq!((map.group_len(&0), map.group_len(&1), map.group_len(&2)), (25, 25, 1));
+    assert_eq!(map.len(), 51);
+    let nested = map.into_nested();
+    assert_eq!(nested[&0][&48], 2);
+    assert!(!nested[&1].contains_key(&48) && !nested[&2].contains_key(&8));
+    let mut set: BilevelSet<u32, u32> = BilevelSet::new();
+    let mut other: BilevelSet<u32, u32> = BilevelSet::new();
+    for k in 0..20 {
+        assert!(set.insert(&0, &k) && !set.insert(&0, &k));
+        other.insert(&0, &(19 - k));
+    }
+    assert_eq!(set, other);
+    assert!(set.iter_sorted().map(|(_, k)| *k).eq(0..20));
+}
+
+#[test]
+pub fn test_freeze_with_perfect_hash() {
+    let mut map: BilevelMap<u32, String, u32> = BilevelMap::new();
+    for i in 0..1_000 {
+        *map.add_or_get(&(i % 13), &format!("k{}", i % 97)) += i;
+    }
+    let sorted = map.clone().freeze();
+    let hashed = map.clone().freeze().with_perfect_hash();
+    assert!(hashed.has_perfect_hash() && !sorted.has_perfect_hash());
+    assert_eq!(hashed, sorted);
+    for (g, k, v) in map.iter() {
+        assert_eq!(hashed.get(g, k.as_str()), Some(v));
+    }
+    assert!(!hashed.contains(&13, "k0") && !hashed.contains(&0, "k97"));
+    let empty: BilevelMap<u32, String, u32> = BilevelMap::new();
+    assert!(!empty.freeze().with_perfect_hash().contains(&0, "k0"));
+}
+
+#[cfg(feature = "sqlite")]
+#[test]
+pub fn test_sqlite() {
+    let conn = rusqlite::Connection::open_in_memory().unwrap();
+    let mut map: BilevelMap<String, String, u64> = BilevelMap::new();
+    *map.add_or_get("web1", "200") += 5;
+    *map.add_or_get("web1", "404") += 1;
+    *map.add_or_get("web2", "200") += 3;
+    map.to_sqlite(&conn, "hits").unwrap();
+    map.to_sqlite(&conn, "hits").unwrap();
+    let total: u64 = conn.query_row("SELECT SUM(value) FROM hits WHERE \"group\" = 'web1'", [], |r| r.get(0)).unwrap();
+    assert_eq!(total, 12);
+    let read: BilevelMap<String, String, u64> =
+        BilevelMap::from_sqlite(&conn, "SELECT * FROM hits", crate::sqlite_row).unwrap();
+    let mut rows: Vec<_> = read.iter().map(|(g, k, &v)| (g.as_str(), k.as_str(), v)).collect();
+    rows.sort();
+    assert_eq!(rows, [("web1", "200", 10), ("web1", "404", 2), ("web2", "200", 6)]);
+}
+
+#[cfg(feature = "json")]
+#[test]
+pub fn test_extend_from_jsonl() {
+    let log = r#"{"host": "web1", "request": {"port": 80}, "status": 200, "bytes": 512}
+{"host": "web1", "request": {"port": 80}, "status": 200, "bytes": 128}
+
+{"host": "web2", "request": {"port": 443}, "status": 404, "bytes": 64}
+"#;
+    let mut map: BilevelMap<(String, u16), u16, u64> = BilevelMap::new();
+    map.extend_from_jsonl(log.as_bytes(), &["host", "/request/port"], &["status"], "bytes").unwrap();
+    let mut rows: Vec<_> = map.iter().map(|((h, p), &k, &v)| (h.as_str(), *p, k, v)).collect();
+    rows.sort();
+    assert_eq!(rows, [("web1", 80, 200, 640), ("web2", 443, 404, 64)]);
+    let bad = "{\"host\": \"web1\", \"status\": 200}\n";
+    let e = map.extend_from_jsonl(bad.as_bytes(), &["host", "/request/port"], &["status"], "bytes").unwrap_err();
+    assert!(e.to_string().starts_with("line 1: missing field /request/port"), "{e}");
+}
+
+#[test]
+pub fn test_render_table() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    *map.add_or_get("web2", "200") += 3;
+    *map.add_or_get("web1", "404") += 1;
+    *map.add_or_get("web1", "200") += 512;
+    *map.add_or_get("web1", "500") += 2;
+    let table = map.render_table(&crate::TableOptions::default());
+    assert_eq!(table, "web1\n  200  512\n  404    1\n  500    2\n\nweb2\n  200    3\n");
+    let options = crate::TableOptions { max_rows: Some(1), spaced: false, ..Default::default() };
+    assert_eq!(map.render_table(&options), "web1\n  200  512\n  ... 2 more\nweb2\n  200    3\n");
+    let mut set: BilevelSet<u8, String> = BilevelSet::new();
+    set.insert(&1, "b");
+    set.insert(&1, "a");
+    assert_eq!(set.render_table(&crate::TableOptions::default()), "1\n  a\n  b\n");
+}
+
+#[test]
+pub fn test_debug() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    *map.add_or_get("web1", "200") += 5;
+    assert_eq!(format!("{:?}", map), r#"{"web1": {"200": 5}}"#);
+    let mut set: BilevelSet<u32, u32> = BilevelSet::new();
+    for k in 0..100 {
+        set.insert(&1, &k);
+    }
+    let debug = format!("{:?}", set);
+    assert!(debug.starts_with("{1: {") && debug.ends_with(", ..}}"), "{debug}");
+    assert_eq!(debug.matches(", ").count(), 32);
+}
+
+#[test]
+pub fn test_clone_eq() {
+    let mut a: BilevelMap<String, String, u32> = BilevelMap::new();
+    *a.add_or_get("web1", "200") += 5;
+    *a.add_or_get("web1", "404") += 1;
+    *a.add_or_get("web2", "200") += 2;
+    let mut b: BilevelMap<String, String, u32> = BilevelMap::new();
+    *b.add_or_get("web2", "200") += 2;
+    *b.add_or_get("web1", "404") += 1;
+    *b.add_or_get("web1", "200") += 5;
+    assert_eq!(a, b);
+    let mut c = a.clone();
+    assert_eq!(a, c);
+    *c.add_or_get("web1", "500") += 1;
+    assert_ne!(a, c);
+    assert_eq!(a.pair_count(), 3);
+    *b.add_or_get("web2", "200") += 1;
+    assert_ne!(a, b);
+
+    let mut x: BilevelSet<u32, u32> = BilevelSet::new();
+    let mut y: BilevelSet<u32, u32> = BilevelSet::new();
+    for k in 0..10 {
+        x.insert(&(k % 3), &k);
+        y.insert(&((9 - k) % 3), &(9 - k));
+    }
+    assert_eq!(x, y);
+    assert_eq!(x.clone(), x);
+    y.insert(&0, &1);
+    assert_ne!(x, y);
+}
+
+#[test]
+pub fn test_default_capacity() {
+    let capacity = Capacity::builder().groups(1_000).agg_keys(50_000).build();
+    assert_eq!(capacity, Capacity { groups: 1_000, per_group: 4, agg_keys: 50_000 });
+    assert_eq!(Capacity::builder().per_group(16).build(), Capacity { per_group: 16, ..Capacity::default() });
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::with_capacity(capacity);
+    *map.add_or_get("web1", "200") += 1;
+    assert_ne!(map, BilevelMap::default());
+    let set: BilevelSet<u8, String> = Default::default();
+    assert!(set.is_empty());
+}
+
+#[test]
+pub fn test_reserve() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    *map.add_or_get("web1", "200") += 1;
+    map.reserve_groups(100);
+    map.reserve_agg_keys(1_000);
+    map.reserve_in_group("web1", 50);
+    assert_eq!((map.group_count(), map.pair_count()), (1, 1));
+    assert_eq!(*map.add_or_get("web1", "200"), 1);
+    // A new group is declared, and so listed with no pairs.
+    map.reserve_in_group("web2", 50);
+    assert_eq!(map.group_count(), 2);
+    assert_eq!(map.pair_count(), 1);
+    let mut set: BilevelSet<u8, String> = BilevelSet::new();
+    set.reserve_agg_keys(10);
+    set.reserve_in_group(&1, 10);
+    assert!(set.iter_with_empty().eq([(&1, None)]));
+}
+
+#[test]
+#[cfg(feature = "tenant")]
+pub fn test_adapt_per_group() {
+    use crate::TenantData;
+    let mut fixed: BilevelSet<u32, u32> = BilevelSet::new();
+    let mut adaptive: BilevelSet<u32, u32> = BilevelSet::new();
+    adaptive.adapt_per_group(true);
+    for k in 0..200 {
+        fixed.insert(&0, &k);
+        adaptive.insert(&0, &k);
+    }
+    let (before_fixed, before_adaptive) = (fixed.memory_usage(), adaptive.memory_usage());
+    fixed.insert(&1, &0);
+    adaptive.insert(&1, &0);
+    // The new group is given capacity for the 200 pairs of the first.
+    assert!(adaptive.memory_usage() - before_adaptive > fixed.memory_usage() - before_fixed);
+    assert_eq!(adaptive, fixed);
+}
+
+#[test]
+pub fn test_estimated_heap_bytes() {
+    let mut map: BilevelMap<String, String, Vec<u64>> = BilevelMap::new();
+    let empty = map.estimated_heap_bytes();
+    map.add_or_get("web1", "200").extend([1, 2, 3]);
+    let small = map.estimated_heap_bytes();
+    assert!(small > empty);
+    // The contents of the keys, and the vectors of the payloads, are counted.
+    let key = "x".repeat(1_000);
+    map.add_or_get(&key, &key).reserve(1_000);
+    assert!(map.estimated_heap_bytes() >= small + 2_000 + 8_000);
+    let mut set: BilevelSet<u8, String, Inline> = BilevelSet::new();
+    set.insert(&1, &key);
+    assert!(set.estimated_heap_bytes() >= 1_000);
+}
+
+#[test]
+pub fn test_bounded_map() {
+    use std::sync::{Arc, Mutex};
+    let evicted = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&evicted);
+    let mut map: BoundedMap<String, String, u32> = BoundedMap::with_max_groups(2, Eviction::LeastRecentlyUpdated)
+        .on_evict(move |g, pairs| sink.lock().unwrap().push((g, pairs)));
+    *map.add_or_get("web1", "200") += 1;
+    *map.add_or_get("web2", "200") += 1;
+    *map.add_or_get("web1", "404") += 1;
+    // web2 is the least recently updated, so makes room for web3.
+    *map.add_or_get("web3", "200") += 1;
+    assert_eq!(*evicted.lock().unwrap(), [("web2".to_owned(), vec![("200".to_owned(), 1)])]);
+    assert_eq!(map.get().group_count(), 2);
+    assert_eq!(map.get().pair_count(), 3);
+    map.flush();
+    let evicted: Vec<_> = evicted.lock().unwrap().iter().map(|(g, _)| g.clone()).collect();
+    assert_eq!(evicted, ["web2", "web1", "web3"]);
+    assert!(map.get().is_empty());
+
+    let mut first: BoundedMap<u8, u8, u32> = BoundedMap::with_max_groups(2, Eviction::FirstFound);
+    *first.add_or_get(&1, &1) += 1;
+    *first.add_or_get(&2, &1) += 1;
+    *first.add_or_get(&1, &2) += 1;
+    *first.add_or_get(&3, &1) += 1;
+    assert!(first.into_inner().iter_sorted().map(|(g, _, _)| *g).eq([2, 3]));
+
+    // The aggregation keys of an evicted group are dropped with it, so an
+    // unbounded space of keys is held in bounded memory.
+    let mut keys: BoundedMap<u32, String, u32> = BoundedMap::with_max_groups(2, Eviction::FirstFound);
+    for i in 0..1_000 {
+        *keys.add_or_get(&i, &format!("{i:0>100}")) += 1;
+    }
+    assert_eq!(keys.get().group_count(), 2);
+    assert!(keys.get().estimated_heap_bytes() < 10_000);
+}
+
+#[test]
+pub fn test_windowed_map() {
+    let mut map: WindowedMap<String, String, u32, u64> = WindowedMap::new();
+    *map.add_or_get_at("web1", "200", 10) += 1;
+    *map.add_or_get_at("web2", "200", 20) += 1;
+    *map.add_or_get_at("web3", "200", 30) += 1;
+    *map.add_or_get_at("web1", "404", 40) += 1;
+    // A late event does not make its group older.
+    *map.add_or_get_at("web3", "500", 5) += 1;
+    assert_eq!(map.last_updated("web3"), Some(30));
+    let evicted = map.evict_older_than(35);
+    let groups: Vec<_> = evicted.iter().map(|(g, _)| g.as_str()).collect();
+    assert_eq!(groups, ["web2", "web3"]);
+    assert_eq!(evicted[1].1.len(), 2);
+    assert_eq!(map.get().group_count(), 1);
+    assert_eq!(map.last_updated("web2"), None);
+    assert!(map.evict_older_than(40).is_empty());
+    assert_eq!(map.evict_older_than(41).len(), 1);
+
+    // The aggregation keys of an evicted group are dropped with it.
+    let mut keys: WindowedMap<u32, String, u32, u32> = WindowedMap::new();
+    for i in 0..1_000 {
+        *keys.add_or_get_at(&i, &format!("{i:0>100}"), i) += 1;
+        keys.evict_older_than(i.saturating_sub(1));
+    }
+    assert_eq!(keys.get().group_count(), 2);
+    assert!(keys.get().estimated_heap_bytes() < 10_000);
+}
+
+#[test]
+pub fn test_streaming_aggregator() {
+    use std::sync::{Arc, Mutex};
+    let groups = Arc::new(Mutex::new(Vec::new()));
+    let sink = Arc::clone(&groups);
+    let mut aggregator: StreamingAggregator<String, String, u32> = StreamingAggregator::new()
+        .on_group(move |g, mut pairs| {
+            pairs.sort();
+            sink.lock().unwrap().push((g, pairs))
+        });
+    for (g, k) in [("web1", "200"), ("web1", "404"), ("web1", "200"), ("web2", "200")] {
+        *aggregator.add_or_get(g, k) += 1;
+    }
+    // web1 was passed on as soon as web2 began.
+    assert_eq!(groups.lock().unwrap().len(), 1);
+    assert_eq!(aggregator.group().map(String::as_str), Some("web2"));
+    assert_eq!(aggregator.len(), 1);
+    // A group seen again after it was passed on is passed on again.
+    *aggregator.add_or_get("web1", "500") += 1;
+    aggregator.flush();
+    aggregator.flush();
+    assert!(aggregator.group().is_none() && aggregator.is_empty());
+    let pairs = |p: &[(&str, u32)]| p.iter().map(|&(k, v)| (k.to_owned(), v)).collect::<Vec<_>>();
+    assert_eq!(*groups.lock().unwrap(), [
+        ("web1".to_owned(), pairs(&[("200", 2), ("404", 1)])),
+        ("web2".to_owned(), pairs(&[("200", 1)])),
+        ("web1".to_owned(), pairs(&[("500", 1)])),
+    ]);
+}
+
+#[test]
+#[cfg(feature = "spill")]
+pub fn test_spilling_map() {
+    use crate::{SpillOptions, SpillingMap};
+    let options = SpillOptions { memory_budget: 1_000, ..SpillOptions::default() };
+    let mut map: SpillingMap<String, u32, u64, _> = SpillingMap::new(options, |total, v| *total += v);
+    for i in 0..1_000u32 {
+        *map.add_or_get(&format!("web{}", i % 7), &(i % 5)).unwrap() += 1;
+    }
+    assert!(map.spilled_runs() > 1);
+    let groups: Vec<_> = map.finish().collect::<Result<_, _>>().unwrap();
+    assert!(groups.iter().map(|(g, _)| g.as_str()).eq(["web0", "web1", "web2", "web3", "web4", "web5", "web6"]));
+    // Every pair is listed once, however many runs it was spilled to.
+    assert!(groups.iter().all(|(_, pairs)| pairs.iter().map(|(k, _)| *k).eq(0..5)));
+    assert_eq!(groups.iter().flat_map(|(_, pairs)| pairs).map(|(_, v)| v).sum::<u64>(), 1_000);
+}
+
+#[test]
+#[cfg(feature = "spill")]
+pub fn test_spilling_map_lost_run() {
+    use crate::{HeapSize, SpillOptions, SpillingMap};
+    use serde::{Deserialize, Deserializer, Serialize, Serializer, de::Error};
+
+    /// A count that cannot be read back if it is 13, to damage a run.
+    #[derive(Debug, Default, PartialEq)]
+    struct Count(u64);
+    impl HeapSize for Count {}
+    impl Serialize for Count {
+        fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+            serializer.serialize_u64(self.0)
+        }
+    }
+    impl<'de> Deserialize<'de> for Count {
+        fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+            match u64::deserialize(deserializer)? {
+                13 => Err(D::Error::custom("damaged")),
+                n => Ok(Count(n)),
+            }
+        }
+    }
+
+    let options = SpillOptions { memory_budget: usize::MAX, ..SpillOptions::default() };
+    let mut map: SpillingMap<char, u8, Count, _> = SpillingMap::new(options, |total: &mut Count, v: Count| total.0 += v.0);
+    for (g, n) in [('a', 1), ('b', 13), ('c', 1)] {
+        map.add_or_get(&g, &1).unwrap().0 += n;
+    }
+    map.spill().unwrap();
+    for g in ['a', 'b', 'c'] {
+        map.add_or_get(&g, &1).unwrap().0 += 2;
+    }
+    map.spill().unwrap();
+    map.add_or_get(&'a', &2).unwrap().0 += 5;
+    let mut groups = map.finish();
+    assert_eq!(groups.next().unwrap().unwrap(), ('a', vec![(1, Count(3)), (2, Count(5))]));
+    // The first run is lost from its damaged pair on, and the rest merged.
+    let lost = groups.next().unwrap().unwrap_err();
+    assert_eq!((lost.run, lost.expected, lost.recovered, lost.missing()), (0, Some(3), 1, Some(2)));
+    assert_eq!(groups.next().unwrap().unwrap(), ('b', vec![(1, Count(2))]));
+    assert_eq!(groups.next().unwrap().unwrap(), ('c', vec![(1, Count(2))]));
+    assert!(groups.next().is_none());
+}
+
+#[test]
+#[cfg(feature = "frozen")]
+pub fn test_frozen_map() {
+    use crate::{FrozenError, FrozenMap};
+    let mut map: BilevelMap<String, String, u64> = BilevelMap::new();
+    for (g, k, v) in [("web", "b", 2), ("db", "a", 1), ("web", "a", 3), ("db", "c", 4), ("web", "b", 5)] {
+        *map.add_or_get(g, k) += v;
+    }
+    let mut bytes = Vec::new();
+    map.write_frozen(&mut bytes).unwrap();
+    let frozen: FrozenMap<_, u64> = FrozenMap::new(bytes.as_slice()).unwrap();
+    frozen.validate().unwrap();
+    assert_eq!((frozen.group_count(), frozen.pair_count()), (2, 4));
+    assert_eq!(frozen.get("web", "b"), Some(7));
+    assert!(frozen.contains("db", "c"));
+    assert!(!frozen.contains("db", "b") && frozen.get("dns", "a").is_none());
+    assert!(frozen.group("web").unwrap().eq([(&b"a"[..], 3), (&b"b"[..], 7)]));
+    let pairs: Vec<_> = frozen.iter().collect();
+    assert_eq!(pairs, [(&b"db"[..], &b"a"[..], 1), (&b"db"[..], &b"c"[..], 4), (&b"web"[..], &b"a"[..], 3), (&b"web"[..], &b"b"[..], 7)]);
+    let (last, pairs) = frozen.iter_groups().next_back().unwrap();
+    assert_eq!(last, b"web");
+    assert!(pairs.rev().eq([(&b"b"[..], 7), (&b"a"[..], 3)]));
+    // The payloads must be read as the type they were written as.
+    assert!(matches!(FrozenMap::<_, u32>::new(bytes.as_slice()), Err(FrozenError::WrongValueWidth { .. })));
+    assert!(matches!(FrozenMap::<_, u64>::new(&bytes[..bytes.len() - 1]), Err(FrozenError::Corrupt)));
+
+    // A set is read back with payloads of (), and a file is mapped.
+    let mut set: BilevelSet<String, String> = BilevelSet::new();
+    set.insert("web", "a");
+    set.insert("web", "b");
+    let path = std::env::temp_dir().join(format!("bilevel-frozen-{}", std::process::id()));
+    set.write_frozen(std::fs::File::create(&path).unwrap()).unwrap();
+    // SAFETY: the file is not written to again until the map is dropped.
+    let frozen: FrozenMap<_, ()> = unsafe { FrozenMap::open(&path) }.unwrap();
+    assert!(frozen.contains("web", "b") && !frozen.contains("web", "c"));
+    drop(frozen);
+    std::fs::remove_file(&path).unwrap();
+}
+
+#[test]
+pub fn test_ops_traits() {
+    use crate::{BilevelMapOps, BilevelSetOps};
+
+    /// Count the pairs into any map, written once for every module.
+    fn tally<M: BilevelMapOps<Group = String, Key = String, Value = u32>>(map: &mut M) {
+        for (g, k) in [("u1", "a"), ("u1", "a"), ("u2", "b")] {
+            *map.add_or_get_pair(g.to_string(), k.to_string()) += 1;
+        }
+    }
+
+    /// Copy the pairs of any set into any map, grouping by the key.
+    fn pivot_into<S, M>(set: &S, map: &mut M)
+    where
+        S: BilevelSetOps<Group = u8, Key = String>,
+        M: BilevelMapOps<Group = String, Key = u8, Value = u32>,
+    {
+        for (g, k) in set.iter_pairs() {
+            *map.add_or_get_pair(k.clone(), *g) += 1;
+        }
+    }
+
+    let mut interned: BilevelMap<String, String, u32> = BilevelMap::new();
+    let mut inline: BilevelMap<String, String, u32, Inline> = BilevelMap::new();
+    tally(&mut interned);
+    tally(&mut inline);
+    assert_eq!((BilevelMapOps::len(&interned), BilevelMapOps::group_count(&inline)), (2, 2));
+    assert_eq!(BilevelMapOps::group_len(&inline, &"u1".to_string()), 1);
+    assert!(interned.iter_pairs().eq(inline.iter_pairs()));
+
+    let mut set: BilevelSet<u8, String> = BilevelSet::new();
+    assert!(set.insert_pair(1, "a".to_string()) && !set.insert_pair(1, "a".to_string()));
+    set.insert_pair(2, "a".to_string());
+    let mut pivoted: BilevelMap<String, u8, u32> = BilevelMap::new();
+    pivot_into(&set, &mut pivoted);
+    assert_eq!(BilevelMapOps::group_len(&pivoted, &"a".to_string()), 2);
+    assert!(!BilevelSetOps::is_empty(&set));
+}
+
+#[test]
+pub fn test_into_groups() {
+    let mut map: BilevelMap<String, String, u32> = BilevelMap::new();
+    for (g, k, v) in [("web", "a", 1), ("db", "a", 2), ("web", "b", 3)] {
+        *map.add_or_get(g, k) += v;
+    }
+    // Each group is sent on its own, to be summed by another thread.
+    let (sender, receiver) = std::sync::mpsc::channel();
+    let groups = map.into_groups();
+    assert_eq!(groups.len(), 2);
+    let workers: Vec<_> = groups.map(|(g, pairs)| {
+        let sender = sender.clone();
+        std::thread::spawn(move || sender.send((g, pairs.len(), pairs.map(|(_, v)| v).sum::<u32>())).unwrap())
+    }).collect();
+    drop(sender);
+    workers.into_iter().for_each(|w| w.join().unwrap());
+    let mut totals: Vec<_> = receiver.iter().collect();
+    totals.sort();
+    assert_eq!(totals, [("db".to_string(), 1, 2), ("web".to_string(), 2, 4)]);
+}
@@ -0,0 +1,228 @@
+use std::{cmp::Ordering, hash::Hash, sync::Arc};
+use hashbrown::HashTable;
+
+use crate::{Capacity, hash};
+use super::{BilevelMap, Interned, KeyStore, map::Constructor};
+
+/// A collection of distinct triples (g1, g2, k) grouped by g1 and then
+/// by g2, with a payload associated with each triple.
+///
+/// As triples are found, they are added if not already present.
+/// When the collection is iterated over, the triples are listed by g1,
+/// and within each g1 by g2, such as for rollups by region, then by
+/// host, then by error code.
+///
+/// Each outer group is kept as a BilevelMap of its own, so the pairs of
+/// one outer group can be used as any other BilevelMap.
+///
+/// G1 is the type of the outer group key.
+/// G2 is the type of the inner group key.
+/// K is the type of the remaining key.
+/// V is the type of the payload.
+/// S is the strategy for storing the aggregation keys of each outer group.
+pub struct TrilevelMap<G1, G2, K, V, S = Interned<K>>
+where
+    S: KeyStore<K>,
+{
+    per_group: usize,
+    groups: HashTable<(G1, BilevelMap<G2, K, V, S>)>,
+    constructor: Constructor<V>,
+}
+
+impl<G1: Hash, G2: Hash, K, V: Default, S: KeyStore<K>> TrilevelMap<G1, G2, K, V, S> {
+    /// Create a new collection, with the default payload for each new
+    /// triple.
+    ///
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new inner group key found.
+    pub fn new() -> Self {
+        Self::with_capacity(Capacity::default())
+    }
+
+    /// Create a new collection with the specified capacity, with the
+    /// default payload for each new triple.
+    ///
+    /// The capacity for groups is taken as the capacity for outer groups,
+    /// and the capacity per group as that for each inner group.
+    pub fn with_capacity(capacity: Capacity) -> Self {
+        Self::with_constructor(capacity, Constructor::Fn(V::default))
+    }
+}
+
+impl<G1: Hash, G2: Hash, K, V: Default, S: KeyStore<K>> Default for TrilevelMap<G1, G2, K, V, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G1: Hash, G2: Hash, K, V, S: KeyStore<K>> TrilevelMap<G1, G2, K, V, S> {
+    /// Create a new collection.
+    ///
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new inner group key found.
+    ///
+    /// constructor: A constructor for the payload of each new triple.
+    pub fn new_with(constructor: impl Fn() -> V + Send + Sync + 'static) -> Self {
+        Self::with_capacity_and(Capacity::default(), constructor)
+    }
+
+    /// Create a new collection with the specified capacity.
+    ///
+    /// The capacity for groups is taken as the capacity for outer groups,
+    /// and the capacity per group as that for each inner group.
+    ///
+    /// constructor: A constructor for the payload of each new triple.
+    pub fn with_capacity_and(capacity: Capacity, constructor: impl Fn() -> V + Send + Sync + 'static) -> Self {
+        Self::with_constructor(capacity, Constructor::Closure(Arc::new(constructor)))
+    }
+
+    /// Create a new collection with the specified capacity, and the
+    /// constructor given for the payload of each new triple.
+    fn with_constructor(capacity: Capacity, constructor: Constructor<V>) -> Self {
+        Self {
+            per_group: capacity.per_group,
+            groups: HashTable::with_capacity(capacity.groups),
+            constructor,
+        }
+    }
+
+    /// Get a mutable reference to the payload for the specified keys.
+    ///
+    /// If the triple is currently not present, a new payload is inserted.
+    pub fn add_or_get<G1Ref, G2Ref, KRef>(&mut self, g1: &G1Ref, g2: &G2Ref, k: &KRef) -> &mut V
+    where
+        G1Ref: ToOwned<Owned = G1> + PartialEq<G1> + Hash + ?Sized,
+        G2Ref: ToOwned<Owned = G2> + PartialEq<G2> + Hash + ?Sized,
+        KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        let capacity = Capacity { groups: 0, per_group: self.per_group, agg_keys: 0 };
+        let constructor = &self.constructor;
+        self.groups.entry(
+            hash(g1),
+            |(o, _)| g1.eq(o),
+            |(o, _)| hash(o)
+        ).or_insert_with(|| (g1.to_owned(), BilevelMap::with_constructor(capacity, S::with_capacity(0), constructor.clone())))
+            .into_mut().1
+            .add_or_get(g2, k)
+    }
+
+    /// Get the pairs of the specified outer group, if it is present.
+    pub fn group<G1Ref>(&self, g1: &G1Ref) -> Option<&BilevelMap<G2, K, V, S>>
+    where
+        G1Ref: PartialEq<G1> + Hash + ?Sized,
+    {
+        self.groups.find(hash(g1), |(o, _)| g1.eq(o)).map(|(_, inner)| inner)
+    }
+
+    /// List the outer groups currently in the collection, each with its
+    /// pairs grouped by the inner group key.
+    pub fn iter_groups(&self) -> impl Iterator<Item = (&G1, &BilevelMap<G2, K, V, S>)> {
+        self.groups.iter().map(|(g1, inner)| (g1, inner))
+    }
+
+    /// List the payloads for the triples currently in the collection,
+    /// without consuming the collection or the payloads.
+    ///
+    /// Triples are grouped by g1, and then by g2.
+    pub fn iter(&self) -> impl Iterator<Item = (&G1, &G2, &K, &V)> {
+        self.groups.iter()
+            .flat_map(|(g1, inner)| inner.iter().map(move |(g2, k, v)| (g1, g2, k, v)))
+    }
+
+    /// List the triples currently in the collection, sorted by the outer
+    /// group key, then by the inner group key, then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&G1, &G2, &K, &V)>
+    where
+        G1: Ord,
+        G2: Ord,
+        K: Ord,
+    {
+        self.iter_sorted_by(|a, b| (a.0, a.1, a.2).cmp(&(b.0, b.1, b.2)))
+    }
+
+    /// List the triples currently in the collection, sorted using the
+    /// specified comparison function.
+    pub fn iter_sorted_by<'a, F>(&'a self, compare: F) -> impl Iterator<Item = (&'a G1, &'a G2, &'a K, &'a V)> + 'a
+    where
+        F: FnMut(&(&'a G1, &'a G2, &'a K, &'a V), &(&'a G1, &'a G2, &'a K, &'a V)) -> Ordering + 'a,
+    {
+        let mut triples: Vec<_> = self.iter().collect();
+        triples.sort_unstable_by(compare);
+        triples.into_iter()
+    }
+
+    /// Count the outer groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Count the inner groups currently in the collection, over all the
+    /// outer groups.
+    pub fn subgroup_count(&self) -> usize {
+        self.groups.iter().map(|(_, inner)| inner.group_count()).sum()
+    }
+
+    /// Count the triples currently in the collection.
+    pub fn triple_count(&self) -> usize {
+        self.groups.iter().map(|(_, inner)| inner.pair_count()).sum()
+    }
+}
+
+impl<G1, G2, K, V, S> std::fmt::Debug for TrilevelMap<G1, G2, K, V, S>
+where
+    G1: std::fmt::Debug,
+    G2: Hash + std::fmt::Debug,
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+    S: KeyStore<K>,
+{
+    /// Format the collection as a map from each outer group key to the
+    /// map of its inner groups, listing only the first few groups of a
+    /// large collection.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::debug::debug_entries(f, self.groups.iter().map(|(g1, map)| (g1, map)))
+    }
+}
+
+impl<G1, G2, K, V, S> Clone for TrilevelMap<G1, G2, K, V, S>
+where
+    G1: Clone,
+    G2: Clone,
+    V: Clone,
+    S: KeyStore<K> + Clone,
+    S::Id: Clone,
+{
+    fn clone(&self) -> Self {
+        Self {
+            per_group: self.per_group,
+            groups: self.groups.clone(),
+            constructor: self.constructor.clone(),
+        }
+    }
+}
+
+impl<G1, G2, K, V, S> PartialEq for TrilevelMap<G1, G2, K, V, S>
+where
+    G1: Hash + Eq,
+    G2: Hash + Eq,
+    K: Hash + Eq,
+    V: PartialEq,
+    S: KeyStore<K>,
+{
+    /// Check whether two collections hold the same outer groups, each
+    /// with the same pairs and payloads, whatever their order.
+    fn eq(&self, other: &Self) -> bool {
+        self.groups.len() == other.groups.len()
+            && self.groups.iter().all(|(g1, inner)| other.group(g1) == Some(inner))
+    }
+}
+
+impl<G1, G2, K, V, S> Eq for TrilevelMap<G1, G2, K, V, S>
+where
+    G1: Hash + Eq,
+    G2: Hash + Eq,
+    K: Hash + Eq,
+    V: Eq,
+    S: KeyStore<K>,
+{
+}
@@ -0,0 +1,179 @@
+use std::{cmp::Ordering, hash::Hash};
+
+use crate::Capacity;
+use super::{BilevelMap, Interned, KeyStore};
+
+/// A collection of distinct pairs (g, k) grouped by g, keeping every
+/// value pushed for each pair, in the order pushed.
+///
+/// This suits observations that must all be retained, such as latencies
+/// or request ids, rather than folded into a single payload.
+///
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// V is the type of the values.
+/// S is the strategy for storing the aggregation keys.
+pub struct BilevelVecMap<G, K, V, S = Interned<K>>
+where
+    S: KeyStore<K>,
+{
+    inner: BilevelMap<G, K, Vec<V>, S>,
+}
+
+impl<G: Hash, K, V, S: KeyStore<K>> BilevelVecMap<G, K, V, S> {
+    /// Create a new collection.
+    ///
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    pub fn new() -> Self {
+        Self { inner: BilevelMap::new() }
+    }
+
+    /// Create a new collection with the specified capacity.
+    pub fn with_capacity(capacity: Capacity) -> Self {
+        Self { inner: BilevelMap::with_capacity(capacity) }
+    }
+
+    /// Add a value to those kept for the specified key pair.
+    pub fn push<GRef, KRef>(&mut self, g: &GRef, k: &KRef, v: V)
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+        KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        self.inner.add_or_get(g, k).push(v);
+    }
+
+    /// List the pairs currently in the collection, with the values kept
+    /// for each.
+    ///
+    /// Pairs are grouped by g.
+    pub fn iter(&self) -> impl Iterator<Item = (&G, &K, &[V])> {
+        self.inner.iter().map(|(g, k, v)| (g, k, v.as_slice()))
+    }
+
+    /// List every value currently in the collection, with its key pair.
+    ///
+    /// Values are grouped by g, and then by k, in the order pushed.
+    pub fn iter_values(&self) -> impl Iterator<Item = (&G, &K, &V)> {
+        self.inner.iter().flat_map(|(g, k, v)| v.iter().map(move |v| (g, k, v)))
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&G, &K, &[V])>
+    where
+        G: Ord,
+        K: Ord,
+    {
+        self.iter_sorted_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)))
+    }
+
+    /// List the pairs currently in the collection, sorted using the
+    /// specified comparison function.
+    pub fn iter_sorted_by<'a, F>(&'a self, compare: F) -> impl Iterator<Item = (&'a G, &'a K, &'a [V])> + 'a
+    where
+        F: FnMut(&(&'a G, &'a K, &'a [V]), &(&'a G, &'a K, &'a [V])) -> Ordering + 'a,
+    {
+        let mut pairs: Vec<_> = self.iter().collect();
+        pairs.sort_unstable_by(compare);
+        pairs.into_iter()
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    /// Count the pairs currently in the collection.
+    pub fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    /// Count the values currently in the collection.
+    pub fn value_count(&self) -> usize {
+        self.inner.iter().map(|(_, _, v)| v.len()).sum()
+    }
+
+    /// Convert into a map from each pair to its values, consuming the
+    /// collection.
+    pub fn into_map(self) -> BilevelMap<G, K, Vec<V>, S> {
+        self.inner
+    }
+}
+
+impl<G: Hash, K, V, S: KeyStore<K>> Default for BilevelVecMap<G, K, V, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G, K, V, S> std::fmt::Debug for BilevelVecMap<G, K, V, S>
+where
+    G: Hash + std::fmt::Debug,
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+    S: KeyStore<K>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<G, K, V, S> Clone for BilevelVecMap<G, K, V, S>
+where
+    G: Clone,
+    V: Clone,
+    S: KeyStore<K> + Clone,
+    S::Id: Clone,
+{
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone() }
+    }
+}
+
+impl<G, K, V, S> PartialEq for BilevelVecMap<G, K, V, S>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+    V: PartialEq,
+    S: KeyStore<K>,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<G, K, V, S> Eq for BilevelVecMap<G, K, V, S>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+    V: Eq,
+    S: KeyStore<K>,
+{
+}
+
+#[cfg(feature = "tenant")]
+impl<G: Hash, K, V, S: KeyStore<K>> crate::TenantData for BilevelVecMap<G, K, V, S> {
+    type Item<'a> = (&'a G, &'a K, &'a [V]) where Self: 'a;
+
+    fn pairs(&self) -> impl Iterator<Item = (&G, &K, &[V])> {
+        self.iter()
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    /// Count the space allocated for the values as well, since they are
+    /// the bulk of the collection.
+    fn memory_usage(&self) -> usize {
+        let values = self.inner.iter()
+            .map(|(_, _, v)| v.capacity() * std::mem::size_of::<V>())
+            .sum::<usize>();
+        crate::TenantData::memory_usage(&self.inner) + values
+    }
+}
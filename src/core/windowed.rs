@@ -0,0 +1,141 @@
+use std::hash::Hash;
+
+use super::{BilevelMap, Inline, KeyStore, recency::Recency};
+
+/// A BilevelMap that records when each group was last updated, so that
+/// the groups not updated within a window of time can be swept out.
+///
+/// Each pair is added with a timestamp, such as the time of the event
+/// it was found in, and evict_older_than removes every group last updated
+/// before a cutoff, so a streaming aggregator can keep only the groups of
+/// the last few minutes without being rebuilt.
+///
+/// The map can be read through get, but can only be added to through
+/// this wrapper, so that the times of the groups are kept.
+///
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// V is the type of the payload.
+/// T is the type of the timestamps, such as SystemTime or seconds since
+/// the epoch.
+/// S is the strategy for storing the aggregation keys. By default each
+/// group holds its own keys, which are dropped with it when it is
+/// evicted; interned keys are kept for the life of the collection, so
+/// only suit a bounded space of aggregation keys.
+pub struct WindowedMap<G, K, V, T, S = Inline>
+where
+    S: KeyStore<K>,
+{
+    inner: BilevelMap<G, K, V, S>,
+    /// The order in which the groups were last updated, by time.
+    recency: Recency<T>,
+}
+
+impl<G, K, V, T, S> WindowedMap<G, K, V, T, S>
+where
+    G: Hash + Eq,
+    K: Clone,
+    V: Default,
+    T: Ord + Copy,
+    S: KeyStore<K>,
+{
+    /// Create a new collection, with the default payload for each new pair.
+    pub fn new() -> Self {
+        Self {
+            inner: BilevelMap::new(),
+            recency: Recency::with_capacity(0),
+        }
+    }
+}
+
+impl<G, K, V, T, S> Default for WindowedMap<G, K, V, T, S>
+where
+    G: Hash + Eq,
+    K: Clone,
+    V: Default,
+    T: Ord + Copy,
+    S: KeyStore<K>,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G, K, V, T, S> WindowedMap<G, K, V, T, S>
+where
+    G: Hash + Eq,
+    K: Clone,
+    T: Ord + Copy,
+    S: KeyStore<K>,
+{
+    /// Get a mutable reference to the payload for the specified key pair,
+    /// recording that its group was updated at the specified time.
+    ///
+    /// If the key pair is currently not present, a new payload is inserted.
+    /// A time earlier than the group was last updated, such as that of an
+    /// event that arrived late, leaves the time of the group as it was.
+    pub fn add_or_get_at<GRef, KRef>(&mut self, g: &GRef, k: &KRef, at: T) -> &mut V
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+        KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        self.touch(g, at);
+        self.inner.add_or_get(g, k)
+    }
+
+    /// Remove every group last updated before the cutoff.
+    ///
+    /// Return each group removed, oldest first, along with the aggregation
+    /// key and payload of each of its pairs.
+    pub fn evict_older_than(&mut self, cutoff: T) -> Vec<(G, Vec<(K, V)>)> {
+        let mut evicted = Vec::new();
+        while self.recency.oldest().is_some_and(|at| at < cutoff) {
+            let id = self.recency.pop_oldest().expect("oldest group just found");
+            evicted.extend(self.inner.remove_group_by_id(id));
+        }
+        evicted
+    }
+
+    /// Get the time at which a group was last updated, or None if the
+    /// group is not present.
+    pub fn last_updated<GRef>(&self, g: &GRef) -> Option<T>
+    where
+        GRef: PartialEq<G> + Hash + ?Sized,
+    {
+        self.recency.last_touched(self.inner.group_id(g)?)
+    }
+
+    /// Get the collection.
+    pub fn get(&self) -> &BilevelMap<G, K, V, S> {
+        &self.inner
+    }
+
+    /// Unwrap the collection.
+    pub fn into_inner(self) -> BilevelMap<G, K, V, S> {
+        self.inner
+    }
+
+    /// Record that a group is about to be updated at the specified time.
+    fn touch<GRef>(&mut self, g: &GRef, at: T)
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+    {
+        match self.inner.group_id(g) {
+            Some(id) => self.recency.touch(id, at),
+            None => {
+                let id = self.inner.declare_group(g);
+                self.recency.insert(id, at);
+            },
+        }
+    }
+}
+
+impl<G, K, V, T, S> std::fmt::Debug for WindowedMap<G, K, V, T, S>
+where
+    S: KeyStore<K>,
+    BilevelMap<G, K, V, S>: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.inner, f)
+    }
+}
@@ -0,0 +1,97 @@
+use std::{fmt::Display, io::{self, Write}};
+use crate::core::{AggKeyId, GroupId};
+
+/// The ids of the group and aggregation key of a row, if the collection
+/// numbers its aggregation keys, and its group key, aggregation key and
+/// payload.
+pub(crate) type Row<'a> = (GroupId, Option<AggKeyId>, [&'a dyn Display; 3]);
+
+/// A column of the rows written by write_csv.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum Column {
+    /// The group key.
+    Group,
+    /// The aggregation key.
+    Key,
+    /// The payload, which is left empty for a set.
+    Value,
+    /// The id of the group, as its index and generation, such as 3:0.
+    GroupId,
+    /// The id of the aggregation key, which is left empty unless the
+    /// collection interns its aggregation keys.
+    KeyId,
+}
+
+/// How write_csv lays out its rows.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct CsvOptions {
+    /// The delimiter between fields, such as ',' for CSV or '\t' for TSV.
+    pub delimiter: char,
+    /// Whether to write a header row naming the columns.
+    pub header: bool,
+    /// The columns to write, in order. Columns may be left out, such as
+    /// the value of a set.
+    pub columns: Vec<Column>,
+    /// The names of the group, key and value columns in the header.
+    pub names: [String; 3],
+    /// The names of the group id and key id columns in the header.
+    pub id_names: [String; 2],
+}
+
+impl Default for CsvOptions {
+    /// Write comma-separated group, key and value columns, with a header.
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            header: true,
+            columns: vec![Column::Group, Column::Key, Column::Value],
+            names: ["group", "key", "value"].map(String::from),
+            id_names: ["group_id", "key_id"].map(String::from),
+        }
+    }
+}
+
+impl CsvOptions {
+    /// Write tab-separated group, key and value columns, with a header.
+    pub fn tsv() -> Self {
+        Self { delimiter: '\t', ..Self::default() }
+    }
+}
+
+/// Write one row for each group key, aggregation key and payload listed.
+pub(crate) fn write_rows<'a>(
+    mut writer: impl Write,
+    options: &CsvOptions,
+    rows: impl Iterator<Item = Row<'a>>,
+) -> io::Result<()> {
+    if options.header {
+        let [g, k, v] = &options.names;
+        let [group_id, key_id] = &options.id_names;
+        write_row(&mut writer, options, [g, k, v, group_id, key_id])?;
+    }
+    for (group_id, key_id, [g, k, v]) in rows {
+        let key_id: &dyn Display = match &key_id {
+            Some(id) => id,
+            None => &"",
+        };
+        write_row(&mut writer, options, [g, k, v, &group_id, key_id])?;
+    }
+    writer.flush()
+}
+
+/// Write the fields of a row in the order of the columns.
+fn write_row(writer: &mut impl Write, options: &CsvOptions, row: [&dyn Display; 5]) -> io::Result<()> {
+    for (i, column) in options.columns.iter().enumerate() {
+        if i > 0 {
+            write!(writer, "{}", options.delimiter)?;
+        }
+        let field = row[*column as usize].to_string();
+        // Quote a field that contains the delimiter, a quote or a line break.
+        if field.contains([options.delimiter, '"', '\n', '\r']) {
+            write!(writer, "\"{}\"", field.replace('"', "\"\""))?;
+        } else {
+            write!(writer, "{}", field)?;
+        }
+    }
+    writeln!(writer)
+}
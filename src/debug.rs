@@ -0,0 +1,84 @@
+use std::{cell::Cell, fmt::{self, Debug, Formatter}};
+
+/// The greatest number of groups, and of keys of each group, listed when
+/// a collection is formatted with Debug, so that formatting a huge
+/// collection does not flood the output.
+const DEBUG_LIMIT: usize = 32;
+
+/// Format the entries listed as a map, listing at most DEBUG_LIMIT
+/// entries, followed by .. if there are more.
+pub(crate) fn debug_entries<K: Debug, V: Debug>(
+    f: &mut Formatter<'_>,
+    entries: impl IntoIterator<Item = (K, V)>,
+) -> fmt::Result {
+    let mut map = f.debug_map();
+    for (i, (k, v)) in entries.into_iter().enumerate() {
+        if i == DEBUG_LIMIT {
+            return map.finish_non_exhaustive();
+        }
+        map.entry(&k, &v);
+    }
+    map.finish()
+}
+
+/// Format the groups listed as a map from each group key to a map of its
+/// aggregation keys and payloads.
+pub(crate) fn debug_map<G, K, V, I>(f: &mut Formatter<'_>, groups: impl Iterator<Item = (G, I)>) -> fmt::Result
+where
+    G: Debug,
+    K: Debug,
+    V: Debug,
+    I: IntoIterator<Item = (K, V)>,
+{
+    debug_entries(f, groups.map(|(g, pairs)| (g, Entries(Cell::new(Some(pairs))))))
+}
+
+/// Format the groups listed as a map from each group key to a set of its
+/// aggregation keys.
+#[cfg(any(feature = "core", feature = "ordered", feature = "indexed"))]
+pub(crate) fn debug_set<G, K, I>(f: &mut Formatter<'_>, groups: impl Iterator<Item = (G, I)>) -> fmt::Result
+where
+    G: Debug,
+    K: Debug,
+    I: IntoIterator<Item = K>,
+{
+    debug_entries(f, groups.map(|(g, keys)| (g, Keys(Cell::new(Some(keys))))))
+}
+
+/// The pairs of a group, formatted as a map the first time they are
+/// formatted.
+struct Entries<I>(Cell<Option<I>>);
+
+impl<I, K, V> Debug for Entries<I>
+where
+    I: IntoIterator<Item = (K, V)>,
+    K: Debug,
+    V: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        debug_entries(f, self.0.take().into_iter().flatten())
+    }
+}
+
+/// The aggregation keys of a group, formatted as a set the first time
+/// they are formatted.
+#[cfg(any(feature = "core", feature = "ordered", feature = "indexed"))]
+struct Keys<I>(Cell<Option<I>>);
+
+#[cfg(any(feature = "core", feature = "ordered", feature = "indexed"))]
+impl<I> Debug for Keys<I>
+where
+    I: IntoIterator,
+    I::Item: Debug,
+{
+    fn fmt(&self, f: &mut Formatter<'_>) -> fmt::Result {
+        let mut set = f.debug_set();
+        for (i, k) in self.0.take().into_iter().flatten().enumerate() {
+            if i == DEBUG_LIMIT {
+                return set.finish_non_exhaustive();
+            }
+            set.entry(&k);
+        }
+        set.finish()
+    }
+}
@@ -0,0 +1,41 @@
+use std::sync::OnceLock;
+
+/// A value derived from the contents of a collection.
+///
+/// The value is computed on first use, and discarded whenever the
+/// collection is modified, so it costs nothing unless it is used, and
+/// it is never stale.
+///
+/// The value is held in a thread-safe cell, so that a collection holding
+/// one is still Sync.
+pub struct Derived<T> {
+    value: OnceLock<T>,
+}
+
+impl<T> Derived<T> {
+    /// Create a new derived value that has not yet been computed.
+    pub const fn new() -> Self {
+        Self { value: OnceLock::new() }
+    }
+
+    /// Get the value, computing it if it is not currently held.
+    pub fn get_or_init(&self, f: impl FnOnce() -> T) -> &T {
+        self.value.get_or_init(f)
+    }
+
+    /// Get the value if it is currently held.
+    pub fn get(&self) -> Option<&T> {
+        self.value.get()
+    }
+
+    /// Discard the value, so that it is computed again on next use.
+    pub fn invalidate(&mut self) {
+        self.value.take();
+    }
+}
+
+impl<T> Default for Derived<T> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
@@ -0,0 +1,425 @@
+use std::{cmp::Ordering, collections::HashMap, error::Error, fmt, fs::File, io::{self, Write}, marker::PhantomData, path::Path};
+use memmap2::Mmap;
+
+/// Identifies a frozen collection written by this crate.
+const MAGIC: &[u8; 4] = b"BLVF";
+
+/// The version of the frozen format, to be incremented whenever the
+/// format changes.
+const VERSION: u8 = 1;
+
+/// The length of the header: the magic and version, the width of the
+/// payloads, and the numbers of groups, pairs and key bytes.
+const HEADER: usize = 40;
+
+/// The length of the entry of a group: the offset and length of its key,
+/// and the range of its pairs.
+const GROUP_ENTRY: usize = 32;
+
+/// The length of the entry of a pair: the offset and length of its key.
+const PAIR_ENTRY: usize = 16;
+
+/// An error opening a frozen collection.
+#[derive(Debug)]
+pub enum FrozenError {
+    /// The file could not be opened or mapped.
+    Io(io::Error),
+    /// The data is not a frozen collection.
+    BadMagic,
+    /// The data was written in a format version this build cannot read.
+    UnsupportedVersion(u8),
+    /// The payloads written are of a different width than the payload
+    /// type read.
+    WrongValueWidth {
+        /// The width of the payload type read.
+        expected: usize,
+        /// The width of the payloads written.
+        found: usize,
+    },
+    /// The data is shorter or longer than its header describes, or refers
+    /// to keys or pairs outside it.
+    Corrupt,
+}
+
+impl fmt::Display for FrozenError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "frozen collection I/O failed: {}", e),
+            Self::BadMagic => write!(f, "not a frozen collection"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported frozen collection version {}", v),
+            Self::WrongValueWidth { expected, found } => {
+                write!(f, "expected payloads of {} bytes, found {}", expected, found)
+            },
+            Self::Corrupt => write!(f, "frozen collection is corrupt"),
+        }
+    }
+}
+
+impl Error for FrozenError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for FrozenError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+/// A payload of a fixed width, that can be read from a frozen collection
+/// where it lies, without being deserialized.
+pub trait FrozenValue: Sized {
+    /// The number of bytes the payload takes.
+    const WIDTH: usize;
+
+    /// Append the bytes of the payload.
+    fn write_bytes(&self, out: &mut Vec<u8>);
+
+    /// Read the payload from exactly WIDTH bytes.
+    fn read_bytes(bytes: &[u8]) -> Self;
+}
+
+impl FrozenValue for () {
+    const WIDTH: usize = 0;
+
+    fn write_bytes(&self, _out: &mut Vec<u8>) {}
+
+    fn read_bytes(_bytes: &[u8]) -> Self {}
+}
+
+macro_rules! number_frozen_value {
+    ($($t:ty),*) => {
+        $(
+            impl FrozenValue for $t {
+                const WIDTH: usize = std::mem::size_of::<$t>();
+
+                fn write_bytes(&self, out: &mut Vec<u8>) {
+                    out.extend_from_slice(&self.to_le_bytes());
+                }
+
+                fn read_bytes(bytes: &[u8]) -> Self {
+                    let mut array = [0; std::mem::size_of::<$t>()];
+                    array.copy_from_slice(bytes);
+                    Self::from_le_bytes(array)
+                }
+            }
+        )*
+    };
+}
+
+number_frozen_value!(u8, u16, u32, u64, i8, i16, i32, i64, f32, f64);
+
+impl FrozenValue for usize {
+    const WIDTH: usize = 8;
+
+    fn write_bytes(&self, out: &mut Vec<u8>) {
+        (*self as u64).write_bytes(out);
+    }
+
+    fn read_bytes(bytes: &[u8]) -> Self {
+        u64::read_bytes(bytes) as usize
+    }
+}
+
+/// Write the groups of a collection in the frozen format.
+///
+/// The groups are sorted by the bytes of their keys, and the pairs of
+/// each group by the bytes of their aggregation keys, so that both can be
+/// found by binary search. Each distinct key is written once.
+pub(crate) fn write<'a, V: FrozenValue + 'a>(
+    mut writer: impl Write,
+    groups: impl Iterator<Item = (&'a [u8], Vec<(&'a [u8], &'a V)>)>,
+) -> io::Result<()> {
+    let mut groups: Vec<_> = groups.collect();
+    groups.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    let pair_count: usize = groups.iter().map(|(_, pairs)| pairs.len()).sum();
+    let mut heap = Vec::new();
+    let mut offsets: HashMap<&[u8], u64> = HashMap::new();
+    let mut intern = |key: &'a [u8]| -> [u64; 2] {
+        let offset = *offsets.entry(key).or_insert_with(|| {
+            heap.extend_from_slice(key);
+            (heap.len() - key.len()) as u64
+        });
+        [offset, key.len() as u64]
+    };
+    let mut group_table = Vec::with_capacity(groups.len() * GROUP_ENTRY);
+    let mut pair_table = Vec::with_capacity(pair_count * PAIR_ENTRY);
+    let mut values = Vec::with_capacity(pair_count * V::WIDTH);
+    let mut first = 0;
+    for (g, mut pairs) in groups.iter_mut().map(|(g, pairs)| (*g, std::mem::take(pairs))) {
+        pairs.sort_unstable_by(|a, b| a.0.cmp(b.0));
+        let end = first + pairs.len() as u64;
+        for n in intern(g).into_iter().chain([first, end]) {
+            group_table.extend_from_slice(&n.to_le_bytes());
+        }
+        for (k, v) in pairs {
+            for n in intern(k) {
+                pair_table.extend_from_slice(&n.to_le_bytes());
+            }
+            v.write_bytes(&mut values);
+        }
+        first = end;
+    }
+    writer.write_all(MAGIC)?;
+    writer.write_all(&[VERSION, 0, 0, 0])?;
+    writer.write_all(&(V::WIDTH as u32).to_le_bytes())?;
+    for n in [groups.len(), pair_count, heap.len()] {
+        writer.write_all(&(n as u64).to_le_bytes())?;
+    }
+    writer.write_all(&[0; HEADER - 36])?;
+    writer.write_all(&group_table)?;
+    writer.write_all(&pair_table)?;
+    writer.write_all(&values)?;
+    writer.write_all(&heap)
+}
+
+/// A read-only collection of pairs, with a fixed-width payload for each,
+/// queried where it lies in a buffer, such as a file mapped into memory,
+/// without being deserialized.
+///
+/// The collection is written by write_frozen, sorted so that a group or a
+/// pair can be found by binary search, so a huge aggregate can be shared
+/// by many processes mapping the same file. The keys are read back as
+/// bytes; a set is read back as a FrozenMap with payloads of ().
+///
+/// B is the type of the buffer, such as Mmap or Vec<u8>.
+/// V is the type of the payload.
+pub struct FrozenMap<B, V> {
+    bytes: B,
+    groups: usize,
+    pairs: usize,
+    value: PhantomData<fn() -> V>,
+}
+
+impl<V: FrozenValue> FrozenMap<Mmap, V> {
+    /// Open a file written by write_frozen, mapping it into memory.
+    ///
+    /// To read the file without mapping it, read it into a Vec<u8> and
+    /// pass that to new.
+    ///
+    /// # Safety
+    ///
+    /// The file must not be truncated or modified, by this process or any
+    /// other, for as long as the collection is alive. The mapped bytes are
+    /// read as an immutable slice, so a change to the file while mapped is
+    /// undefined behavior, and reading a part of the map cut off by
+    /// truncation raises SIGBUS, which kills the process on most
+    /// platforms. Only open a file that nothing will write to, such as one
+    /// written once and then only read.
+    pub unsafe fn open(path: impl AsRef<Path>) -> Result<Self, FrozenError> {
+        let file = File::open(path)?;
+        // SAFETY: the caller guarantees that the file is neither truncated
+        // nor modified while the map, which the collection owns, is alive.
+        let map = unsafe { Mmap::map(&file)? };
+        Self::new(map)
+    }
+}
+
+impl<B: AsRef<[u8]>, V: FrozenValue> FrozenMap<B, V> {
+    /// Read a collection from a buffer holding the data written by
+    /// write_frozen.
+    ///
+    /// Only the header is checked, so this takes constant time. A corrupt
+    /// buffer cannot cause reads outside it, but may give wrong results;
+    /// validate checks the whole buffer.
+    pub fn new(bytes: B) -> Result<Self, FrozenError> {
+        let data = bytes.as_ref();
+        if data.len() < HEADER || &data[..4] != MAGIC {
+            return Err(FrozenError::BadMagic);
+        }
+        if data[4] != VERSION {
+            return Err(FrozenError::UnsupportedVersion(data[4]));
+        }
+        let width = u32::from_le_bytes([data[8], data[9], data[10], data[11]]) as usize;
+        if width != V::WIDTH {
+            return Err(FrozenError::WrongValueWidth { expected: V::WIDTH, found: width });
+        }
+        let [groups, pairs, heap] = [12, 20, 28].map(|i| read_u64(data, i) as usize);
+        let len = groups.checked_mul(GROUP_ENTRY)
+            .zip(pairs.checked_mul(PAIR_ENTRY + width))
+            .and_then(|(g, p)| g.checked_add(p)?.checked_add(heap)?.checked_add(HEADER));
+        if len != Some(data.len()) {
+            return Err(FrozenError::Corrupt);
+        }
+        Ok(Self { bytes, groups, pairs, value: PhantomData })
+    }
+
+    /// Check that every group and pair refers to keys and pairs within
+    /// the buffer.
+    pub fn validate(&self) -> Result<(), FrozenError> {
+        let heap = self.heap().len() as u64;
+        let in_heap = |offset: u64, len: u64| offset.checked_add(len).is_some_and(|end| end <= heap);
+        let mut first = 0;
+        for i in 0..self.groups {
+            let [offset, len, start, end] = self.group_entry(i);
+            if !in_heap(offset, len) || start != first || end < start {
+                return Err(FrozenError::Corrupt);
+            }
+            first = end;
+        }
+        if first != self.pairs as u64 {
+            return Err(FrozenError::Corrupt);
+        }
+        for i in 0..self.pairs {
+            let data = self.bytes.as_ref();
+            let entry = HEADER + self.groups * GROUP_ENTRY + i * PAIR_ENTRY;
+            if !in_heap(read_u64(data, entry), read_u64(data, entry + 8)) {
+                return Err(FrozenError::Corrupt);
+            }
+        }
+        Ok(())
+    }
+
+    /// Count the groups in the collection.
+    pub fn group_count(&self) -> usize {
+        self.groups
+    }
+
+    /// Count the pairs in the collection.
+    pub fn pair_count(&self) -> usize {
+        self.pairs
+    }
+
+    /// Check whether the collection holds a pair.
+    pub fn contains(&self, g: impl AsRef<[u8]>, k: impl AsRef<[u8]>) -> bool {
+        self.find(g.as_ref(), k.as_ref()).is_some()
+    }
+
+    /// Get the payload of a pair, or None if the pair is not present.
+    pub fn get(&self, g: impl AsRef<[u8]>, k: impl AsRef<[u8]>) -> Option<V> {
+        self.find(g.as_ref(), k.as_ref()).map(|i| self.value(i))
+    }
+
+    /// List the aggregation keys and payloads of a group, in order of the
+    /// aggregation keys, or None if the group is not present.
+    pub fn group(&self, g: impl AsRef<[u8]>) -> Option<impl DoubleEndedIterator<Item = (&[u8], V)>> {
+        let i = self.find_group(g.as_ref())?;
+        Some(self.pairs_of(i))
+    }
+
+    /// List the groups in order of their keys, with the aggregation keys
+    /// and payloads of each group, in order of the aggregation keys.
+    ///
+    /// Both can be listed from the last with rev, such as to take the
+    /// last few groups without reading the others.
+    pub fn iter_groups(&self) -> impl DoubleEndedIterator<Item = (&[u8], impl DoubleEndedIterator<Item = (&[u8], V)>)> {
+        (0..self.groups).map(|i| (self.group_key(i), self.pairs_of(i)))
+    }
+
+    /// List the pairs, in order of the group keys and then of the
+    /// aggregation keys, or from the last with rev.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&[u8], &[u8], V)> {
+        self.iter_groups().flat_map(|(g, pairs)| pairs.map(move |(k, v)| (g, k, v)))
+    }
+
+    /// Find the index of the pair (g, k).
+    fn find(&self, g: &[u8], k: &[u8]) -> Option<usize> {
+        let [start, end] = self.pair_range(self.find_group(g)?);
+        search(start, end, |i| self.pair_key(i).cmp(k))
+    }
+
+    /// Find the index of a group.
+    fn find_group(&self, g: &[u8]) -> Option<usize> {
+        search(0, self.groups, |i| self.group_key(i).cmp(g))
+    }
+
+    /// List the aggregation keys and payloads of the group with the
+    /// specified index.
+    fn pairs_of(&self, i: usize) -> impl DoubleEndedIterator<Item = (&[u8], V)> {
+        let [start, end] = self.pair_range(i);
+        (start..end).map(|i| (self.pair_key(i), self.value(i)))
+    }
+
+    /// Read the entry of the group with the specified index.
+    fn group_entry(&self, i: usize) -> [u64; 4] {
+        let entry = HEADER + i * GROUP_ENTRY;
+        [0, 8, 16, 24].map(|field| read_u64(self.bytes.as_ref(), entry + field))
+    }
+
+    /// Get the range of the pairs of the group with the specified index,
+    /// empty if it lies outside the pairs.
+    fn pair_range(&self, i: usize) -> [usize; 2] {
+        let [_, _, start, end] = self.group_entry(i);
+        match start <= end && end <= self.pairs as u64 {
+            true => [start as usize, end as usize],
+            false => [0, 0],
+        }
+    }
+
+    /// Get the key of the group with the specified index.
+    fn group_key(&self, i: usize) -> &[u8] {
+        let [offset, len, _, _] = self.group_entry(i);
+        self.key(offset, len)
+    }
+
+    /// Get the aggregation key of the pair with the specified index.
+    fn pair_key(&self, i: usize) -> &[u8] {
+        let data = self.bytes.as_ref();
+        let entry = HEADER + self.groups * GROUP_ENTRY + i * PAIR_ENTRY;
+        self.key(read_u64(data, entry), read_u64(data, entry + 8))
+    }
+
+    /// Get the payload of the pair with the specified index.
+    fn value(&self, i: usize) -> V {
+        let start = HEADER + self.groups * GROUP_ENTRY + self.pairs * PAIR_ENTRY + i * V::WIDTH;
+        V::read_bytes(&self.bytes.as_ref()[start..start + V::WIDTH])
+    }
+
+    /// Get the key bytes at the specified offset, empty if they lie
+    /// outside the keys.
+    fn key(&self, offset: u64, len: u64) -> &[u8] {
+        let range = usize::try_from(offset).ok()
+            .zip(usize::try_from(len).ok())
+            .and_then(|(offset, len)| Some(offset..offset.checked_add(len)?));
+        range.and_then(|range| self.heap().get(range)).unwrap_or_default()
+    }
+
+    /// Get the bytes of all the keys.
+    fn heap(&self) -> &[u8] {
+        let data = self.bytes.as_ref();
+        &data[HEADER + self.groups * GROUP_ENTRY + self.pairs * (PAIR_ENTRY + V::WIDTH)..]
+    }
+}
+
+impl<B: AsRef<[u8]>, V: FrozenValue + fmt::Debug> fmt::Debug for FrozenMap<B, V> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        crate::debug::debug_map(f, self.iter_groups().map(|(g, pairs)| (Bytes(g), pairs.map(|(k, v)| (Bytes(k), v)))))
+    }
+}
+
+/// Formats a key as a string where it is UTF-8, or as bytes otherwise.
+struct Bytes<'a>(&'a [u8]);
+
+impl fmt::Debug for Bytes<'_> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match std::str::from_utf8(self.0) {
+            Ok(s) => fmt::Debug::fmt(s, f),
+            Err(_) => fmt::Debug::fmt(self.0, f),
+        }
+    }
+}
+
+/// Read a little-endian u64 at the specified offset.
+fn read_u64(data: &[u8], offset: usize) -> u64 {
+    let mut bytes = [0; 8];
+    bytes.copy_from_slice(&data[offset..offset + 8]);
+    u64::from_le_bytes(bytes)
+}
+
+/// Find the index in start..end at which compare gives Equal, where the
+/// items are in ascending order.
+fn search(mut start: usize, mut end: usize, compare: impl Fn(usize) -> Ordering) -> Option<usize> {
+    while start < end {
+        let mid = start + (end - start) / 2;
+        match compare(mid) {
+            Ordering::Less => start = mid + 1,
+            Ordering::Greater => end = mid,
+            Ordering::Equal => return Some(mid),
+        }
+    }
+    None
+}
@@ -0,0 +1,91 @@
+use std::{mem::size_of, path::PathBuf, ffi::OsString};
+
+/// A type whose values can estimate the memory they own on the heap, such
+/// as the contents of a string.
+///
+/// A value that owns no memory, such as a number, takes the default of
+/// zero. A reference counts as owning nothing, as the memory it refers to
+/// is owned elsewhere.
+pub trait HeapSize {
+    /// Estimate the memory owned by the value, in bytes, not counting the
+    /// value itself.
+    fn heap_bytes(&self) -> usize {
+        0
+    }
+}
+
+macro_rules! no_heap {
+    ($($t:ty),*) => {
+        $(impl HeapSize for $t {})*
+    };
+}
+
+no_heap!(u8, u16, u32, u64, u128, usize, i8, i16, i32, i64, i128, isize, f32, f64, bool, char, ());
+
+impl<T: ?Sized> HeapSize for &T {}
+
+impl HeapSize for String {
+    fn heap_bytes(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl HeapSize for Box<str> {
+    fn heap_bytes(&self) -> usize {
+        self.len()
+    }
+}
+
+impl HeapSize for OsString {
+    fn heap_bytes(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl HeapSize for PathBuf {
+    fn heap_bytes(&self) -> usize {
+        self.capacity()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Box<T> {
+    fn heap_bytes(&self) -> usize {
+        size_of::<T>() + (**self).heap_bytes()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Vec<T> {
+    fn heap_bytes(&self) -> usize {
+        self.capacity() * size_of::<T>() + self.iter().map(T::heap_bytes).sum::<usize>()
+    }
+}
+
+impl<T: HeapSize> HeapSize for Option<T> {
+    fn heap_bytes(&self) -> usize {
+        self.as_ref().map_or(0, T::heap_bytes)
+    }
+}
+
+impl<T: HeapSize, const N: usize> HeapSize for [T; N] {
+    fn heap_bytes(&self) -> usize {
+        self.iter().map(T::heap_bytes).sum()
+    }
+}
+
+macro_rules! tuple_heap_size {
+    ($(($($t:ident $i:tt),*);)*) => {
+        $(
+            impl<$($t: HeapSize),*> HeapSize for ($($t,)*) {
+                fn heap_bytes(&self) -> usize {
+                    0 $(+ self.$i.heap_bytes())*
+                }
+            }
+        )*
+    };
+}
+
+tuple_heap_size! {
+    (A 0, B 1);
+    (A 0, B 1, C 2);
+    (A 0, B 1, C 2, D 3);
+}
@@ -0,0 +1,162 @@
+use std::{collections::HashMap, hash::Hash};
+use hashbrown::HashTable;
+use crate::{Capacity, hash};
+
+/// A collection of distinct pairs (g, k) grouped by g, with a counter
+/// associated with each pair.
+///
+/// This serves the same purpose as a BilevelMap with an integer payload,
+/// but rather than keeping a hash map per group, the counters are kept
+/// inline next to 32-bit key indices in a vector per group, sorted by
+/// index. This roughly halves the memory used for pure counting
+/// workloads, at the cost of slower insertion of new pairs into very
+/// large groups.
+///
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// C is the type of the counter, typically u32 or u64.
+#[derive(Clone)]
+pub struct CounterMap<G, K, C = u64>
+where
+    G: Hash + Eq
+{
+    per_group: usize,
+    /// Keep a single copy of each key here, rather than one in each group
+    /// where it appears.
+    keys: Vec<K>,
+    groups: HashMap<G, Vec<(u32, C)>>,
+    key_table: HashTable<u32>,
+}
+
+impl<G, K, C> CounterMap<G, K, C>
+where
+    G: Hash + Eq + Copy,
+    K: Hash,
+    C: Default,
+{
+    /// Create a new collection.
+    ///
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    pub fn new() -> Self {
+        Self {
+            per_group: 4,
+            keys: Vec::new(),
+            groups: HashMap::new(),
+            key_table: HashTable::new(),
+        }
+    }
+
+    /// Create a new collection with the specified capacity.
+    pub fn with_capacity(capacity: Capacity) -> Self {
+        let Capacity { groups, per_group, agg_keys } = capacity;
+        Self {
+            per_group,
+            keys: Vec::with_capacity(agg_keys),
+            groups: HashMap::with_capacity(groups),
+            key_table: HashTable::with_capacity(agg_keys),
+        }
+    }
+
+    /// Get a mutable reference to the counter for the specified key pair.
+    ///
+    /// If the key pair is currently not present, a zero counter is inserted.
+    ///
+    /// Panics if more than u32::MAX distinct aggregation keys are added.
+    pub fn add_or_get<KRef>(&mut self, g: G, k: &KRef) -> &mut C
+    where
+        KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized
+    {
+        let i = self.key_index(k);
+        let inner = self.groups.entry(g)
+            .or_insert_with(|| Vec::with_capacity(self.per_group));
+        let pos = match inner.binary_search_by_key(&i, |&(j, _)| j) {
+            Ok(pos) => pos,
+            Err(pos) => {
+                inner.insert(pos, (i, C::default()));
+                pos
+            }
+        };
+        &mut inner[pos].1
+    }
+
+    /// Find the index of k in the key list, adding it if it is new.
+    fn key_index<KRef>(&mut self, k: &KRef) -> u32
+    where
+        KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        *self.key_table.entry(
+            hash(&k),
+            |&i| k.eq(&self.keys[i as usize]),
+            |&i| hash(&self.keys[i as usize])
+        ).or_insert_with(||{
+            let i = u32::try_from(self.keys.len())
+                .expect("too many distinct aggregation keys");
+            self.keys.push(k.to_owned());
+            i
+        }).get()
+    }
+
+    /// List the counters for the pairs currently in the collection,
+    /// without consuming the collection.
+    ///
+    /// Pairs are grouped by g.
+    ///
+    /// Since G is a Copy type, owned values are returned for g.
+    pub fn iter(&self) -> impl Iterator<Item = (G, &K, &C)> {
+        self.groups.iter()
+            .flat_map(|(g, inner)| {
+                inner.iter().map(|(i, c)| (*g, &self.keys[*i as usize], c))
+            })
+    }
+}
+
+impl<G, K, C> Default for CounterMap<G, K, C>
+where
+    G: Hash + Eq + Copy,
+    K: Hash,
+    C: Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G, K, C> std::fmt::Debug for CounterMap<G, K, C>
+where
+    G: Hash + Eq + std::fmt::Debug,
+    K: std::fmt::Debug,
+    C: std::fmt::Debug,
+{
+    /// Format the collection as a map from each group key to a map of its
+    /// aggregation keys and counts, listing only the first few groups, and
+    /// keys of each group, of a large collection.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let keys = &self.keys;
+        crate::debug::debug_map(f, self.groups.iter().map(|(g, inner)| {
+            (g, inner.iter().map(|(i, c)| (&keys[*i as usize], c)))
+        }))
+    }
+}
+
+impl<G, K, C> PartialEq for CounterMap<G, K, C>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+    C: PartialEq,
+{
+    /// Check whether two collections hold the same groups, with the same
+    /// counts for the same keys, whatever the order in which they were
+    /// found.
+    fn eq(&self, other: &Self) -> bool {
+        self.groups.len() == other.groups.len() && self.groups.iter().all(|(g, inner)| {
+            other.groups.get(g).is_some_and(|theirs| {
+                let theirs: HashMap<&K, &C> = theirs.iter().map(|(i, c)| (&other.keys[*i as usize], c)).collect();
+                inner.len() == theirs.len()
+                    && inner.iter().all(|(i, c)| theirs.get(&self.keys[*i as usize]) == Some(&c))
+            })
+        })
+    }
+}
+
+impl<G: Hash + Eq, K: Hash + Eq, C: Eq> Eq for CounterMap<G, K, C> {}
@@ -1,6 +1,5 @@
-use std::{collections::HashMap, hash::Hash};
-use hashbrown::HashTable;
-use crate::{Capacity, hash};
+use std::{cmp::Ordering, collections::HashMap, hash::Hash};
+use crate::{Capacity, HeapSize, core::{self, AggKeyId, GroupId, Interned}};
 
 /// A collection of distinct pairs (g, k) grouped by g, with a payload
 /// associated with each pair.
@@ -13,14 +12,12 @@ use crate::{Capacity, hash};
 /// V is the type of the payload.
 pub struct BilevelMap<G, K, V>
 where
-    G: Hash + Eq
+    G: Hash + Eq,
+    K: Hash,
 {
-    per_group: usize,
-    /// Keep a single copy of each key here, rather than one in each group
+    /// Keep a single copy of each key, rather than one in each group
     /// where it appears.
-    keys: Vec<K>,
-    groups: HashMap<G, HashMap<usize, V>>,
-    key_table: HashTable<usize>,
+    inner: core::BilevelMap<G, K, V, Interned<K>>,
 }
 
 impl<G, K, V> BilevelMap<G, K, V>
@@ -29,52 +26,89 @@ where
     K: Hash,
     V: Default,
 {
-    /// Create a new collection.
+    /// Create a new collection, with the default payload for each new pair.
     /// 
     /// No initial capacity is allocated, and capacity for a few items
     /// is allocated for each new group key found.
     pub fn new() -> Self {
-        Self {
-            per_group: 4,
-            keys: Vec::new(),
-            groups: HashMap::new(),
-            key_table: HashTable::new(),
-        }
+        Self { inner: core::BilevelMap::new() }
     }
 
-    // Create a new collection with the specified capacity.
+    // Create a new collection with the specified capacity, with the default
+    // payload for each new pair.
     pub fn with_capacity(capacity: Capacity) -> Self {
-        let Capacity { groups, per_group, agg_keys } = capacity;
-        Self {
-            per_group,
-            keys: Vec::with_capacity(agg_keys),
-            groups: HashMap::with_capacity(groups),
-            key_table: HashTable::with_capacity(agg_keys),
-        }
+        Self { inner: core::BilevelMap::with_capacity(capacity) }
+    }
+}
+
+impl<G, K, V> Default for BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash,
+    V: Default,
+{
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G, K, V> BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash,
+{
+    /// Create a new collection.
+    /// 
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    /// 
+    /// constructor: A constructor for the payload of each new pair.
+    pub fn new_with(constructor: impl Fn() -> V + Send + Sync + 'static) -> Self {
+        Self { inner: core::BilevelMap::new_with(constructor) }
+    }
+
+    /// Create a new collection with the specified capacity.
+    /// 
+    /// constructor: A constructor for the payload of each new pair.
+    pub fn with_capacity_and(capacity: Capacity, constructor: impl Fn() -> V + Send + Sync + 'static) -> Self {
+        Self { inner: core::BilevelMap::with_capacity_and(capacity, constructor) }
     }
 
     /// Get a mutable reference to the payload for the specified key pair.
     /// 
-    /// If the key pair is currently not present, the default payload is inserted.
+    /// If the key pair is currently not present, a new payload is inserted.
     pub fn add_or_get<KRef>(&mut self, g: G, k: &KRef) -> &mut V
     where
         KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized
     {
-        // Find the index of k in the key list, 
-        // adding it if it is new.
-        let &i = self.key_table.entry(
-            hash(&k),
-            |&i| k.eq(&self.keys[i]),
-            |&i| hash(&self.keys[i])
-        ).or_insert_with(||{
-            let i = self.keys.len();
-            self.keys.push(k.to_owned());
-            i
-        }).get();
-        self.groups.entry(g)
-            .or_insert(HashMap::with_capacity(self.per_group))
-            .entry(i)
-            .or_insert_with(V::default)
+        self.inner.add_or_get(&g, k)
+    }
+
+    /// Get a mutable reference to the payload for the specified key pair,
+    /// taking ownership of the aggregation key, so that a new key is stored
+    /// without being copied and a key already present is dropped.
+    /// 
+    /// If the key pair is currently not present, a new payload is inserted.
+    pub fn add_or_get_owned(&mut self, g: G, k: K) -> &mut V
+    where
+        K: Eq,
+    {
+        self.inner.add_or_get_owned(g, k)
+    }
+
+    /// Get a mutable reference to the payload for the specified key pair.
+    /// 
+    /// If the key pair is currently not present, the payload computed from
+    /// the keys is inserted.
+    /// 
+    /// init: computes the payload for a new key pair, such as from
+    ///     configuration looked up for the group.
+    pub fn add_or_get_with_key<KRef, F>(&mut self, g: G, k: &KRef, init: F) -> &mut V
+    where
+        KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+        F: FnOnce(&G, &K) -> V,
+    {
+        self.inner.add_or_get_with_key(&g, k, init)
     }
 
     /// List the payloads for the pairs currently in the collection,
@@ -83,8 +117,1175 @@ where
     /// Pairs are grouped by g.
     /// 
     /// Since G is a Copy type, owned values are returned for g.
-    pub fn iter(&self) -> impl Iterator<Item = (G, &K, &V)> {
-        self.groups.iter()
-            .flat_map(|(g, inner)| inner.iter().map(|(&i, v)| (*g, &self.keys[i], v)))
+    pub fn iter(&self) -> MapIter<'_, G, K, V> {
+        MapIter { inner: self.inner.iter() }
+    }
+
+    /// Call f once for each group, with the group key and the aggregation
+    /// keys and payloads of the group, as iter lists them.
+    pub fn for_each_group<F>(&self, mut f: F)
+    where
+        F: FnMut(G, GroupIter<'_, K, V>),
+    {
+        self.inner.for_each_group(|g, pairs| f(*g, GroupIter { inner: pairs }));
+    }
+
+    /// List the payloads for the pairs currently in the collection, for
+    /// modification.
+    /// 
+    /// Pairs are grouped by g, and owned values are returned for g, as
+    /// iter does.
+    pub fn iter_mut(&mut self) -> MapIterMut<'_, G, K, V> {
+        MapIterMut { inner: self.inner.iter_mut() }
+    }
+
+    /// List the payloads for the pairs currently in the collection, as
+    /// iter does, along with the ids of the group and aggregation key of
+    /// each.
+    pub fn iter_with_ids(&self) -> impl Iterator<Item = (GroupId, G, AggKeyId, &K, &V)> {
+        self.inner.iter_with_ids().map(|(id, g, i, k, v)| (id, *g, i, k, v))
+    }
+
+    /// List the aggregation keys with their ids, in the order of the ids.
+    /// 
+    /// Each distinct aggregation key is given an id when first found,
+    /// which does not change for the life of the collection.
+    pub fn id_mapping(&self) -> impl ExactSizeIterator<Item = (AggKeyId, &K)> {
+        self.inner.id_mapping()
+    }
+
+    /// Find the id of a group, or None if the group is not present.
+    /// 
+    /// A group keeps its id for as long as it is in the collection.
+    pub fn group_id(&self, g: G) -> Option<GroupId> {
+        self.inner.group_id(&g)
+    }
+
+    /// Get the key of the group with the specified id, or None if no
+    /// group has the id.
+    pub fn resolve_group(&self, id: GroupId) -> Option<G> {
+        self.inner.resolve_group(id).copied()
+    }
+
+    /// Find the id of an aggregation key, or None if the key has not been
+    /// found in any group.
+    pub fn agg_key_id<KRef>(&self, k: &KRef) -> Option<AggKeyId>
+    where
+        KRef: PartialEq<K> + Hash + ?Sized,
+    {
+        self.inner.agg_key_id(k)
+    }
+
+    /// Get the aggregation key with the specified id, or None if no key
+    /// has the id.
+    pub fn resolve_agg_key(&self, id: AggKeyId) -> Option<&K> {
+        self.inner.resolve_agg_key(id)
+    }
+
+    /// Get a mutable reference to the payload for a pair given by the ids
+    /// of its group and aggregation key, such as ids passed from another
+    /// system, without copying either key.
+    /// 
+    /// If the pair is currently not present, a new payload is inserted.
+    /// 
+    /// Return None if either id is not of this collection, such as the id
+    /// of a group removed since.
+    pub fn add_or_get_by_id(&mut self, g: GroupId, k: AggKeyId) -> Option<&mut V> {
+        self.inner.add_or_get_by_id(g, k)
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (G, &K, &V)>
+    where
+        G: Ord,
+        K: Ord,
+    {
+        self.iter_sorted_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)))
+    }
+
+    /// List the pairs currently in the collection, sorted using the
+    /// specified comparison function.
+    pub fn iter_sorted_by<'a, F>(&'a self, compare: F) -> impl Iterator<Item = (G, &'a K, &'a V)> + 'a
+    where
+        F: FnMut(&(G, &'a K, &'a V), &(G, &'a K, &'a V)) -> Ordering + 'a,
+    {
+        let mut pairs: Vec<_> = self.iter().collect();
+        pairs.sort_unstable_by(compare);
+        pairs.into_iter()
+    }
+
+    /// List the groups in the collection, each with its own iterator
+    /// over its aggregation keys and payloads, consuming the collection.
+    /// 
+    /// Each group is an owned unit, which can be handed on, such as sent
+    /// to another thread, apart from the others.
+    /// Each aggregation key is cloned for every group where it appears.
+    pub fn into_groups(self) -> IntoGroups<G, K, V>
+    where
+        K: Clone,
+    {
+        IntoGroups { inner: self.inner.into_groups() }
+    }
+
+    /// List and consume the payloads for the pairs in the collection,
+    /// consuming the collection.
+    /// 
+    /// Pairs are grouped by g. Each key is cloned for every group where
+    /// it appears.
+    pub fn into_iter(self) -> MapIntoIter<G, K, V>
+    where
+        K: Clone,
+    {
+        MapIntoIter { inner: self.inner.into_iter() }
+    }
+
+    /// List the pairs in the collection, sorted by the group key and then
+    /// by the aggregation key, consuming the collection.
+    /// 
+    /// Each key is cloned for every group where it appears.
+    pub fn into_iter_sorted(self) -> impl Iterator<Item = (G, K, V)>
+    where
+        G: Ord,
+        K: Ord + Clone,
+    {
+        self.inner.into_iter_sorted()
+    }
+
+    /// Convert the collection into a read-only layout, with the groups in
+    /// order and the pairs of each group in order, that takes less memory
+    /// and is faster to read.
+    /// 
+    /// Each key is cloned for every group where it appears.
+    pub fn freeze(self) -> core::FrozenBilevelMap<G, K, V>
+    where
+        G: Ord,
+        K: Ord + Clone,
+    {
+        self.inner.freeze()
+    }
+
+    /// List the payloads for the pairs currently in the collection, as iter
+    /// does, but also list each empty group once, with no pair.
+    pub fn iter_with_empty(&self) -> impl Iterator<Item = (G, Option<(&K, &V)>)> {
+        self.inner.iter_with_empty().map(|(g, pair)| (*g, pair))
+    }
+
+    /// Declare group keys that are expected to appear, so that they are
+    /// listed by iter_with_empty even if no pairs are found for them.
+    pub fn declare_groups(&mut self, groups: impl IntoIterator<Item = G>) {
+        for g in groups {
+            self.inner.declare_groups([&g]);
+        }
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// groups.
+    pub fn reserve_groups(&mut self, additional: usize) {
+        self.inner.reserve_groups(additional);
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// distinct aggregation keys.
+    pub fn reserve_agg_keys(&mut self, additional: usize) {
+        self.inner.reserve_agg_keys(additional);
+    }
+
+    /// Reserve space for at least the specified number of additional
+    /// pairs in a group, declaring the group if new.
+    pub fn reserve_in_group(&mut self, g: G, additional: usize) {
+        self.inner.reserve_in_group(&g, additional);
+    }
+
+    /// Choose whether each new group is given capacity for the average
+    /// size of the groups found so far, rather than the per_group
+    /// capacity alone.
+    /// 
+    /// The per_group capacity is still the least given. The choice is
+    /// not kept in snapshots.
+    pub fn adapt_per_group(&mut self, adaptive: bool) {
+        self.inner.adapt_per_group(adaptive);
+    }
+
+    /// Estimate the memory held by the collection, in bytes, including
+    /// the memory owned by the keys and payloads, such as the contents of strings.
+    /// 
+    /// This walks every group, so takes time in proportion to the number
+    /// of pairs.
+    pub fn estimated_heap_bytes(&self) -> usize
+    where
+        G: HeapSize,
+        K: HeapSize,
+        V: HeapSize,
+    {
+        self.inner.estimated_heap_bytes()
+    }
+
+    /// Collapse the groups into coarser groups, consuming the collection.
+    /// 
+    /// f: maps each group key to the key of the group it is merged into.
+    /// merge: folds a payload into the payload already present when
+    ///     two merged groups share an aggregation key.
+    /// 
+    /// The aggregation keys are carried over without being re-interned.
+    pub fn regroup_by<G2, F, M>(self, f: F, merge: M) -> BilevelMap<G2, K, V>
+    where
+        G2: Hash + Eq + Copy,
+        F: FnMut(&G) -> G2,
+        M: FnMut(&mut V, V),
+    {
+        BilevelMap { inner: self.inner.regroup_by(f, merge) }
+    }
+
+    /// Convert the payloads, consuming the collection.
+    /// 
+    /// f: computes the new payload from the key pair and the old payload.
+    /// 
+    /// The aggregation keys are carried over without being re-interned.
+    /// The new collection inserts the default payload for each new pair.
+    pub fn map_values<W, F>(self, f: F) -> BilevelMap<G, K, W>
+    where
+        W: Default,
+        F: FnMut(&G, &K, V) -> W,
+    {
+        BilevelMap { inner: self.inner.map_values(f) }
+    }
+
+    /// Convert the payloads, consuming the collection, with a constructor
+    /// for the payload of each new pair of the new collection.
+    /// 
+    /// f: computes the new payload from the key pair and the old payload.
+    /// constructor: A constructor for the payload of each new pair.
+    pub fn map_values_with<W, F>(self, f: F, constructor: impl Fn() -> W + Send + Sync + 'static) -> BilevelMap<G, K, W>
+    where
+        F: FnMut(&G, &K, V) -> W,
+    {
+        BilevelMap { inner: self.inner.map_values_with(f, constructor) }
+    }
+
+    /// Count the pairs currently in the collection.
+    /// 
+    /// The count is kept as pairs are added, so this takes constant time.
+    pub fn len(&self) -> usize {
+        self.inner.len()
+    }
+
+    /// Check whether the collection holds no pairs, although it may hold
+    /// empty groups.
+    pub fn is_empty(&self) -> bool {
+        self.inner.is_empty()
+    }
+
+    /// Count the pairs of the specified group, which is 0 if the group is
+    /// not present.
+    pub fn group_len(&self, g: G) -> usize {
+        self.inner.group_len(&g)
+    }
+
+    /// Count the distinct aggregation keys found in any group.
+    /// 
+    /// Each distinct key is stored once, so this takes constant time.
+    pub fn distinct_agg_keys(&self) -> usize {
+        self.inner.distinct_agg_keys()
+    }
+
+    /// Copy the payloads into a sparse matrix in compressed sparse row
+    /// form, with a row for each group, including any empty group, and a
+    /// column for each aggregation key, numbered by id.
+    /// 
+    /// The group of each row is listed in the groups of the matrix.
+    pub fn to_csr(&self) -> core::CsrMatrix<'_, G, V>
+    where
+        V: Clone,
+    {
+        self.inner.to_csr()
+    }
+
+    /// List the n pairs with the greatest payloads in the whole collection,
+    /// greatest first, whatever their groups.
+    /// 
+    /// Only n pairs are kept as the collection is scanned, so the pairs are
+    /// not sorted.
+    pub fn heavy_hitters(&self, n: usize) -> Vec<(G, &K, &V)>
+    where
+        V: Ord,
+    {
+        self.inner.heavy_hitters(n).into_iter().map(|(g, k, v)| (*g, k, v)).collect()
+    }
+
+    /// List the group keys currently in the collection, with the number
+    /// of aggregation keys in each group.
+    pub fn group_sizes(&self) -> impl Iterator<Item = (G, usize)> + '_ {
+        self.inner.group_sizes().map(|(g, n)| (*g, n))
+    }
+
+    /// Count the groups by their number of aggregation keys, such as to
+    /// choose the capacity per group.
+    /// 
+    /// buckets: the greatest size counted in each bucket, in ascending
+    ///     order. A final bucket counts the groups larger than the last of
+    ///     these.
+    /// 
+    /// Return the number of groups in each bucket.
+    pub fn size_histogram(&self, buckets: &[usize]) -> Vec<usize> {
+        self.inner.size_histogram(buckets)
+    }
+
+    /// Render the collection as a text table, with a heading for each
+    /// group followed by its aggregation keys and payloads in aligned columns,
+    /// such as for debugging or the output of a command.
+    /// 
+    /// The groups, and the keys of each group, are listed in the order of
+    /// their text, so that the same contents are always rendered the same
+    /// way.
+    pub fn render_table(&self, options: &crate::TableOptions) -> String
+    where
+        G: std::fmt::Display,
+        K: std::fmt::Display,
+        V: std::fmt::Display,
+    {
+        self.inner.render_table(options)
+    }
+
+    /// Find groups with identical sets of aggregation keys, ignoring
+    /// the payloads.
+    /// 
+    /// Return each class of two or more equivalent groups.
+    pub fn dedup_groups(&self) -> Vec<Vec<G>> {
+        copied_classes(self.inner.dedup_groups())
+    }
+}
+
+/// Copy the group keys out of each class of equivalent groups.
+fn copied_classes<G: Copy>(classes: Vec<Vec<&G>>) -> Vec<Vec<G>> {
+    classes.into_iter()
+        .map(|class| class.into_iter().copied().collect())
+        .collect()
+}
+
+impl<G, K, V> BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash + Eq + Clone,
+{
+    /// Convert into a single-level map keyed by the key pair,
+    /// consuming the collection.
+    pub fn into_flat(self) -> HashMap<(G, K), V> {
+        self.inner.into_flat()
+    }
+
+    /// Create a new collection from a single-level map keyed by the key pair.
+    pub fn from_flat(flat: HashMap<(G, K), V>) -> Self
+    where
+        V: Default,
+    {
+        Self { inner: core::BilevelMap::from_flat(flat) }
+    }
+
+    /// Merge another collection into this one, consuming it.
+    /// 
+    /// combine: folds a payload from the other collection into the payload
+    ///     already present when both collections hold the same pair.
+    pub fn merge<F>(&mut self, other: Self, combine: F)
+    where
+        F: FnMut(&mut V, V),
+    {
+        self.inner.merge(other.inner, combine)
+    }
+
+    /// Add rows of key pairs and payloads to the collection.
+    /// 
+    /// combine: folds the payload of a row into the payload already present
+    ///     when the collection already holds its pair, including a pair
+    ///     found earlier in the same rows.
+    pub fn extend_with<F>(&mut self, rows: impl IntoIterator<Item = (G, K, V)>, combine: F)
+    where
+        F: FnMut(&mut V, V),
+    {
+        self.inner.extend_with(rows, combine)
+    }
+
+    /// List the pairs currently in the collection, grouped by g, with the
+    /// pairs of each group sorted by their payloads using the specified
+    /// comparison function, such as |a, b| b.cmp(a) for the greatest
+    /// counts first.
+    /// 
+    /// Each group is sorted only when it is reached.
+    pub fn iter_sorted_by_value<'a, F>(&'a self, compare: F) -> impl Iterator<Item = (G, &'a K, &'a V)> + 'a
+    where
+        F: FnMut(&V, &V) -> Ordering + 'a,
+    {
+        self.inner.iter_sorted_by_value(compare).map(|(g, k, v)| (*g, k, v))
+    }
+
+    /// List the n aggregation keys of the specified group with the greatest
+    /// payloads, with their payloads, greatest first.
+    /// 
+    /// by: computes the value by which each payload is ranked, such as a
+    ///     count.
+    /// 
+    /// Only n pairs are kept as the group is scanned, so the group is not
+    /// sorted.
+    pub fn top_k<B, F>(&self, g: G, n: usize, by: F) -> Vec<(&K, &V)>
+    where
+        B: Ord,
+        F: FnMut(&V) -> B,
+    {
+        self.inner.top_k(&g, n, by)
+    }
+
+    /// List the n aggregation keys of each group with the greatest payloads,
+    /// as top_k does for a single group.
+    /// 
+    /// Pairs are grouped by g, and listed greatest first within each group.
+    pub fn top_k_all<B, F>(&self, n: usize, by: F) -> impl Iterator<Item = (G, &K, &V)>
+    where
+        B: Ord,
+        F: FnMut(&V) -> B,
+    {
+        self.inner.top_k_all(n, by).map(|(g, k, v)| (*g, k, v))
+    }
+
+    /// Convert into a nested map of the groups, consuming the collection.
+    pub fn into_nested(self) -> HashMap<G, HashMap<K, V>> {
+        self.inner.into_nested()
+    }
+}
+
+impl<G, K, V> From<HashMap<G, HashMap<K, V>>> for BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash + Eq + Clone,
+    V: Default,
+{
+    fn from(nested: HashMap<G, HashMap<K, V>>) -> Self {
+        Self { inner: nested.into() }
+    }
+}
+
+impl<G, K, V> BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash,
+    V: Copy,
+    usize: TryFrom<V>,
+{
+    /// Expand a map of counters back into a stream of key pairs, with
+    /// each pair repeated as many times as its count.
+    /// 
+    /// Counts that cannot be represented as a usize, such as negative
+    /// counts, are treated as zero.
+    pub fn expand(&self) -> impl Iterator<Item = (G, &K)> {
+        self.inner.expand().map(|(g, k)| (*g, k))
+    }
+
+    /// Expand a map of counters back into a stream of key pairs, as
+    /// expand does, but with the pairs listed in sorted order.
+    pub fn expand_sorted(&self) -> impl Iterator<Item = (G, &K)>
+    where
+        G: Ord,
+        K: Ord,
+    {
+        self.inner.expand_sorted().map(|(g, k)| (*g, k))
+    }
+
+    /// Measure how the counts of each group are spread over its aggregation
+    /// keys, by their entropy, Gini coefficient and the share of the key
+    /// with the greatest count.
+    /// 
+    /// Counts that cannot be represented as a usize, such as negative
+    /// counts, are treated as zero.
+    pub fn group_metrics(&self) -> impl Iterator<Item = (G, core::GroupMetrics)> + '_ {
+        self.inner.group_metrics().map(|(g, m)| (*g, m))
+    }
+}
+
+impl<G, K, V> BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash,
+    V: Hash + Eq,
+{
+    /// Find groups with identical sets of aggregation keys and identical
+    /// payloads for each key.
+    /// 
+    /// Return each class of two or more equivalent groups.
+    pub fn dedup_groups_with_values(&self) -> Vec<Vec<G>> {
+        copied_classes(self.inner.dedup_groups_with_values())
+    }
+}
+
+impl<G, K, V> std::fmt::Debug for BilevelMap<G, K, V>
+where
+    G: Hash + Eq + std::fmt::Debug,
+    K: Hash + std::fmt::Debug,
+    V: std::fmt::Debug,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+impl<G, K, V> Clone for BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Clone,
+    V: Clone,
+{
+    fn clone(&self) -> Self {
+        Self { inner: self.inner.clone() }
     }
-}
\ No newline at end of file
+}
+
+impl<G, K, V> PartialEq for BilevelMap<G, K, V>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+    V: PartialEq,
+{
+    fn eq(&self, other: &Self) -> bool {
+        self.inner == other.inner
+    }
+}
+
+impl<G, K, V> Eq for BilevelMap<G, K, V>
+where
+    G: Hash + Eq,
+    K: Hash + Eq,
+    V: Eq,
+{
+}
+
+#[cfg(feature = "derive")]
+impl<G, K, V> BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash + Eq + Clone,
+{
+    /// Get a mutable reference to the payload for the key pair of a row.
+    /// 
+    /// If the key pair is currently not present, a new payload is inserted.
+    pub fn add_or_get_row<R: crate::BilevelKey<Group = G, Agg = K>>(&mut self, row: &R) -> &mut V {
+        self.add_or_get(row.group(), &row.agg())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<G, K, V> serde::Serialize for BilevelMap<G, K, V>
+where
+    G: Hash + Eq + serde::Serialize,
+    K: Hash + serde::Serialize,
+    V: serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.inner.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, G, K, V> serde::Deserialize<'de> for BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy + serde::Deserialize<'de>,
+    K: Hash + Eq + Clone + serde::Deserialize<'de>,
+    V: Default + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        HashMap::deserialize(deserializer).map(Self::from)
+    }
+}
+
+#[cfg(feature = "arrow")]
+impl<G, K, V> BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash + Eq,
+{
+    /// Convert the collection into an Arrow record batch, with a row for
+    /// each pair, in group, key and value columns.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn to_record_batch(&self) -> Result<arrow_array::RecordBatch, arrow_schema::ArrowError>
+    where
+        G: crate::ArrowValue,
+        K: crate::ArrowValue,
+        V: crate::ArrowValue,
+    {
+        self.inner.to_record_batch()
+    }
+
+    /// Convert the collection into an Arrow record batch, as
+    /// to_record_batch does, with the ids of the group and aggregation key
+    /// of each pair in group_id, group_generation and key_id columns before
+    /// the others.
+    /// 
+    /// The pairs are listed as iter_with_ids lists them.
+    pub fn to_record_batch_with_ids(&self) -> Result<arrow_array::RecordBatch, arrow_schema::ArrowError>
+    where
+        G: crate::ArrowValue,
+        K: crate::ArrowValue,
+        V: crate::ArrowValue,
+    {
+        self.inner.to_record_batch_with_ids()
+    }
+
+    /// Write the collection to a Parquet file, with a row for each pair,
+    /// in group, key and value columns.
+    /// 
+    /// Pairs are grouped by g.
+    #[cfg(feature = "parquet")]
+    pub fn write_parquet(
+        &self,
+        path: impl AsRef<std::path::Path>,
+        options: &crate::ParquetOptions,
+    ) -> Result<(), parquet::errors::ParquetError>
+    where
+        G: crate::ArrowValue,
+        K: crate::ArrowValue,
+        V: crate::ArrowValue,
+    {
+        self.inner.write_parquet(path, options)
+    }
+}
+
+#[cfg(feature = "csv")]
+impl<G, K, V> BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash + Eq,
+{
+    /// Write the collection as delimited text, such as CSV or TSV, with a
+    /// row for each pair and its payload.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn write_csv(&self, writer: impl std::io::Write, options: &crate::CsvOptions) -> std::io::Result<()>
+    where
+        G: std::fmt::Display,
+        K: std::fmt::Display,
+        V: std::fmt::Display,
+    {
+        self.inner.write_csv(writer, options)
+    }
+}
+
+#[cfg(feature = "json")]
+impl<G, K, V> BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash + Eq,
+{
+    /// Write the collection as a JSON object, from each group key to an
+    /// object from each aggregation key to its payload.
+    /// 
+    /// The groups, and the keys of each group, are written in order, so
+    /// that the same contents are always written the same way.
+    pub fn to_json_grouped(&self, writer: impl std::io::Write) -> Result<(), serde_json::Error>
+    where
+        G: Ord + serde::Serialize,
+        K: Ord + serde::Serialize,
+        V: serde::Serialize,
+    {
+        self.inner.to_json_grouped(writer)
+    }
+
+    /// Write the collection as a JSON array, with an object for each pair
+    /// holding the ids of its group and aggregation key along with the
+    /// keys and payload, such as
+    /// {"group_id":[0,0],"group":"web1","key_id":0,"key":"200","value":5}.
+    /// 
+    /// The pairs are listed as iter_with_ids lists them.
+    pub fn to_json_with_ids(&self, writer: impl std::io::Write) -> Result<(), serde_json::Error>
+    where
+        G: serde::Serialize,
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        self.inner.to_json_with_ids(writer)
+    }
+
+    /// Add the rows of JSON Lines text, such as a log, to the collection,
+    /// adding the payload of each row to the payload for its key pair.
+    /// 
+    /// group_fields: the fields of the group key, read into a tuple if
+    ///     there are several. A name starting with / is a JSON pointer to
+    ///     a nested field, such as /request/host.
+    /// key_fields: the fields of the aggregation key, read likewise.
+    /// value_field: the field of the payload to add.
+    /// 
+    /// Blank lines are skipped. A line that is not a JSON object, or lacks
+    /// a field, is an error, but the rows before it are kept.
+    pub fn extend_from_jsonl(
+        &mut self,
+        reader: impl std::io::BufRead,
+        group_fields: &[&str],
+        key_fields: &[&str],
+        value_field: &str,
+    ) -> Result<(), serde_json::Error>
+    where
+        G: serde::de::DeserializeOwned,
+        K: Hash + Eq + Clone + serde::de::DeserializeOwned,
+        V: std::ops::AddAssign + serde::de::DeserializeOwned,
+    {
+        self.inner.extend_from_jsonl(reader, group_fields, key_fields, value_field)
+    }
+}
+
+#[cfg(feature = "polars")]
+impl<G, K, V> BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash + Eq + Clone,
+    V: Default,
+{
+    /// Build a collection from the rows of a Polars DataFrame, adding the
+    /// values of rows with the same key pair together.
+    /// 
+    /// group_cols: the columns of the group key, one for each field of G
+    ///     if it is a tuple.
+    /// key_cols: the columns of the aggregation key, one for each field of
+    ///     K if it is a tuple.
+    /// value_col: the column of the values to add.
+    pub fn from_dataframe(
+        df: &polars::prelude::DataFrame,
+        group_cols: &[&str],
+        key_cols: &[&str],
+        value_col: &str,
+    ) -> polars::prelude::PolarsResult<Self>
+    where
+        G: crate::PolarsValue,
+        K: crate::PolarsValue,
+        V: std::ops::AddAssign + crate::PolarsValue,
+    {
+        Ok(Self { inner: core::BilevelMap::from_dataframe(df, group_cols, key_cols, value_col)? })
+    }
+
+    /// Convert the collection into a Polars DataFrame, with a row for each
+    /// pair, in group, key and value columns.
+    /// 
+    /// A group or key of several columns is written to columns numbered
+    /// from 0, such as group_0 and group_1. Pairs are grouped by g.
+    pub fn to_dataframe(&self) -> polars::prelude::PolarsResult<polars::prelude::DataFrame>
+    where
+        G: crate::PolarsValue,
+        K: crate::PolarsValue,
+        V: crate::PolarsValue,
+    {
+        self.inner.to_dataframe()
+    }
+}
+
+#[cfg(feature = "sqlite")]
+impl<G, K, V> BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash + Eq + Clone,
+    V: Default,
+{
+    /// Insert a row for each pair into an SQLite table with group, key and
+    /// value columns, creating the table if it does not exist.
+    /// 
+    /// The rows are inserted in a single transaction.
+    pub fn to_sqlite(&self, conn: &rusqlite::Connection, table: &str) -> rusqlite::Result<()>
+    where
+        G: rusqlite::ToSql,
+        K: rusqlite::ToSql,
+        V: rusqlite::ToSql,
+    {
+        self.inner.to_sqlite(conn, table)
+    }
+
+    /// Build a collection from the results of an SQLite query, adding the
+    /// payloads of rows with the same key pair together.
+    /// 
+    /// mapping: reads the group key, aggregation key and payload of a row,
+    ///     such as sqlite_row, which reads the first three columns.
+    pub fn from_sqlite<F>(conn: &rusqlite::Connection, query: &str, mapping: F) -> rusqlite::Result<Self>
+    where
+        V: std::ops::AddAssign,
+        F: FnMut(&rusqlite::Row) -> rusqlite::Result<(G, K, V)>,
+    {
+        Ok(Self { inner: core::BilevelMap::from_sqlite(conn, query, mapping)? })
+    }
+}
+
+#[cfg(feature = "snapshot")]
+impl<G, K, V> BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash + Eq,
+    V: Default,
+{
+    /// Write a binary snapshot of the collection, including the table of
+    /// aggregation keys.
+    pub fn write_snapshot(&self, writer: impl std::io::Write) -> Result<(), crate::SnapshotError>
+    where
+        G: serde::Serialize,
+        K: serde::Serialize,
+        V: serde::Serialize,
+    {
+        self.inner.write_snapshot(writer)
+    }
+
+    /// Read a collection from a binary snapshot written by write_snapshot.
+    /// 
+    /// The snapshot keeps neither the constructor of new payloads nor the
+    /// GroupIds: the collection inserts the default payload for each new
+    /// pair, whatever the constructor of the collection written, and its
+    /// groups are given new ids, so ids taken before the snapshot do not
+    /// carry over.
+    pub fn read_snapshot(reader: impl std::io::Read) -> Result<Self, crate::SnapshotError>
+    where
+        G: serde::de::DeserializeOwned,
+        K: serde::de::DeserializeOwned,
+        V: serde::de::DeserializeOwned,
+    {
+        Ok(Self { inner: core::BilevelMap::read_snapshot(reader)? })
+    }
+
+    /// Read a collection from a binary snapshot written by write_snapshot,
+    /// keeping the groups read before any error in reading the groups.
+    /// 
+    /// Return the groups that could be read, with a report of those that
+    /// could not, if any. A snapshot whose header or other contents cannot
+    /// be read is still an error.
+    pub fn read_snapshot_partial(
+        reader: impl std::io::Read,
+    ) -> Result<(Self, Option<crate::MissingGroups>), crate::SnapshotError>
+    where
+        G: serde::de::DeserializeOwned,
+        K: serde::de::DeserializeOwned,
+        V: serde::de::DeserializeOwned,
+    {
+        let (inner, missing) = core::BilevelMap::read_snapshot_partial(reader)?;
+        Ok((Self { inner }, missing))
+    }
+
+    /// Take the changes to the collection since the last delta was taken,
+    /// or since it was created, as a delta that can be merged into another
+    /// collection by apply_delta.
+    /// 
+    /// The delta holds each pair added, or whose payload changed, with its
+    /// current payload, and each pair and group removed. The payloads are
+    /// left as they are. To find the changes, the collection keeps a copy
+    /// of its pairs as of the last delta, so taking deltas doubles the
+    /// memory the pairs take.
+    pub fn take_delta(&mut self) -> Result<Vec<u8>, crate::SnapshotError>
+    where
+        G: Clone + serde::Serialize,
+        K: serde::Serialize,
+        V: Clone + PartialEq + serde::Serialize,
+    {
+        self.inner.take_delta()
+    }
+
+    /// Merge a delta taken from another collection by take_delta, adding
+    /// the pairs it holds and removing the pairs and groups it removes.
+    /// 
+    /// combine: folds the current payload of a pair from the delta into
+    ///     the payload here, which is new if the pair was not already
+    ///     present, such as by replacing it, to keep a copy of the other
+    ///     collection.
+    pub fn apply_delta<F>(&mut self, delta: &[u8], combine: F) -> Result<(), crate::SnapshotError>
+    where
+        G: serde::de::DeserializeOwned,
+        K: Clone + serde::de::DeserializeOwned,
+        V: serde::de::DeserializeOwned,
+        F: FnMut(&mut V, V),
+    {
+        self.inner.apply_delta(delta, combine)
+    }
+}
+
+#[cfg(feature = "replay")]
+impl<G, K, V> crate::Replay<G, K, V> for BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash + Eq + Clone,
+    V: Clone + std::ops::AddAssign,
+{
+    fn apply(&mut self, mutation: crate::Mutation<&G, &K, &V>) {
+        crate::Replay::apply(&mut self.inner, mutation)
+    }
+}
+
+#[cfg(feature = "tenant")]
+impl<G, K, V> crate::TenantData for BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash,
+{
+    type Item<'a> = (G, &'a K, &'a V) where Self: 'a;
+
+    fn pairs(&self) -> impl Iterator<Item = (G, &K, &V)> {
+        self.iter()
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        crate::TenantData::memory_usage(&self.inner)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<G, K, V> BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy + Send + Sync,
+    K: Hash + Send + Sync,
+{
+    /// List the pairs currently in the collection in parallel, splitting
+    /// the work between threads by group.
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (G, &K, &V)>
+    where
+        V: Sync,
+    {
+        use rayon::prelude::*;
+        self.inner.par_iter().map(|(g, k, v)| (*g, k, v))
+    }
+
+    /// List the payloads for the pairs currently in the collection in
+    /// parallel, for modification, splitting the work between threads
+    /// by group.
+    pub fn par_iter_mut(&mut self) -> impl rayon::iter::ParallelIterator<Item = (G, &K, &mut V)>
+    where
+        V: Send,
+    {
+        use rayon::prelude::*;
+        self.inner.par_iter_mut().map(|(g, k, v)| (*g, k, v))
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<G, K, V> crate::Shard for BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash + Eq + Clone,
+{
+    type Payload = V;
+
+    fn merge_shard<F>(&mut self, other: Self, combine: F)
+    where
+        F: FnMut(&mut V, V),
+    {
+        self.merge(other, combine)
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<G, K, V> rayon::iter::ParallelExtend<(G, K, V)> for BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy + Send,
+    K: Hash + Eq + Clone + Send,
+    V: Default + std::ops::AddAssign + Send,
+{
+    /// Add the payloads of the rows to the payloads for their key pairs,
+    /// with each worker thread summing its rows into a map of its own.
+    fn par_extend<I>(&mut self, rows: I)
+    where
+        I: rayon::iter::IntoParallelIterator<Item = (G, K, V)>,
+    {
+        let collector = crate::ShardedCollector::new(
+            Self::new,
+            |map: &mut Self, (g, k, v)| *map.add_or_get(g, &k) += v,
+            |a: &mut V, b| *a += b,
+        );
+        self.merge(collector.collect(rows), |a, b| *a += b);
+    }
+}
+
+impl<'a, G, K, V> IntoIterator for &'a BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash,
+{
+    type Item = (G, &'a K, &'a V);
+    type IntoIter = MapIter<'a, G, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+impl<'a, G, K, V> IntoIterator for &'a mut BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash,
+{
+    type Item = (G, &'a K, &'a mut V);
+    type IntoIter = MapIterMut<'a, G, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter_mut()
+    }
+}
+
+impl<G, K, V> IntoIterator for BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash + Clone,
+{
+    type Item = (G, K, V);
+    type IntoIter = MapIntoIter<G, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        BilevelMap::into_iter(self)
+    }
+}
+
+/// Lists the payloads for the pairs of a BilevelMap, grouped by g, with
+/// owned group keys.
+/// 
+/// This is returned by BilevelMap::iter.
+pub struct MapIter<'a, G, K: Hash, V> {
+    inner: core::MapIter<'a, G, K, V, Interned<K>>,
+}
+
+impl<'a, G: Copy, K: Hash, V> Iterator for MapIter<'a, G, K, V> {
+    type Item = (G, &'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(g, k, v)| (*g, k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<G: Copy, K: Hash, V> ExactSizeIterator for MapIter<'_, G, K, V> {}
+
+impl<G: Copy, K: Hash, V> std::iter::FusedIterator for MapIter<'_, G, K, V> {}
+
+/// Lists the payloads for the pairs of a BilevelMap, grouped by g, with
+/// owned group keys, for modification.
+/// 
+/// This is returned by BilevelMap::iter_mut.
+pub struct MapIterMut<'a, G, K: Hash, V> {
+    inner: core::MapIterMut<'a, G, K, V, Interned<K>>,
+}
+
+impl<'a, G: Copy, K: Hash, V> Iterator for MapIterMut<'a, G, K, V> {
+    type Item = (G, &'a K, &'a mut V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next().map(|(g, k, v)| (*g, k, v))
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<G: Copy, K: Hash, V> ExactSizeIterator for MapIterMut<'_, G, K, V> {}
+
+impl<G: Copy, K: Hash, V> std::iter::FusedIterator for MapIterMut<'_, G, K, V> {}
+
+/// Lists the payloads for the pairs of a BilevelMap, grouped by g,
+/// consuming the collection.
+/// 
+/// This is returned by BilevelMap::into_iter.
+pub struct MapIntoIter<G, K: Hash, V> {
+    inner: core::MapIntoIter<G, K, V, Interned<K>>,
+}
+
+impl<G: Copy, K: Hash + Clone, V> Iterator for MapIntoIter<G, K, V> {
+    type Item = (G, K, V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<G: Copy, K: Hash + Clone, V> ExactSizeIterator for MapIntoIter<G, K, V> {}
+
+impl<G: Copy, K: Hash + Clone, V> std::iter::FusedIterator for MapIntoIter<G, K, V> {}
+
+/// Lists the aggregation keys and payloads of one group of a BilevelMap.
+/// 
+/// This is given to the closure passed to BilevelMap::for_each_group.
+pub struct GroupIter<'a, K: Hash, V> {
+    inner: core::GroupIter<'a, K, V, Interned<K>>,
+}
+
+impl<'a, K: Hash, V> Iterator for GroupIter<'a, K, V> {
+    type Item = (&'a K, &'a V);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<K: Hash, V> ExactSizeIterator for GroupIter<'_, K, V> {}
+
+impl<K: Hash, V> std::iter::FusedIterator for GroupIter<'_, K, V> {}
+
+/// Lists the groups of a BilevelMap, each with the pairs of the group,
+/// consuming the collection.
+/// 
+/// This is returned by BilevelMap::into_groups.
+pub struct IntoGroups<G, K: Hash, V> {
+    inner: core::IntoGroups<G, K, V, Interned<K>>,
+}
+
+impl<G, K: Hash + Clone, V> Iterator for IntoGroups<G, K, V> {
+    type Item = (G, core::GroupIntoIter<K, V>);
+
+    fn next(&mut self) -> Option<Self::Item> {
+        self.inner.next()
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        self.inner.size_hint()
+    }
+}
+
+impl<G, K: Hash + Clone, V> ExactSizeIterator for IntoGroups<G, K, V> {}
+
+impl<G, K: Hash + Clone, V> std::iter::FusedIterator for IntoGroups<G, K, V> {}
+
+impl<G, K, V> crate::BilevelMapOps for BilevelMap<G, K, V>
+where
+    G: Hash + Eq + Copy,
+    K: Hash + Eq,
+{
+    type Group = G;
+    type Key = K;
+    type Value = V;
+
+    fn add_or_get_pair(&mut self, g: G, k: K) -> &mut V {
+        self.add_or_get_owned(g, k)
+    }
+
+    fn iter_pairs(&self) -> impl Iterator<Item = (&G, &K, &V)> {
+        self.inner.iter()
+    }
+
+    fn group_sizes(&self) -> impl Iterator<Item = (&G, usize)> {
+        self.inner.group_sizes()
+    }
+
+    fn group_len(&self, g: &G) -> usize {
+        self.inner.group_len(g)
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn len(&self) -> usize {
+        self.inner.len()
+    }
+}
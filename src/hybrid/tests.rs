@@ -146,4 +146,367 @@ pub fn test_map() {
             assert!(!set.contains(&g));
         }
     }
-}
\ No newline at end of file
+}
+#[test]
+pub fn test_regroup() {
+    let mut map: BilevelMap<i32, String, u32> = BilevelMap::new();
+    for (g, k) in [(2, "1"), (3, "1"), (4, "2"), (5, "1"), (5, "2")] {
+        *map.add_or_get(g, k) += 1;
+    }
+    // Collapse the groups by parity, summing the counts.
+    let regrouped = map.regroup_by(|g| g % 2, |v, w| *v += w);
+    let mut result: Vec<_> = regrouped.iter()
+        .map(|(g, k, &v)| (g, k.as_str(), v))
+        .collect();
+    result.sort();
+    assert_eq!(result, [(0, "1", 1), (0, "2", 1), (1, "1", 2), (1, "2", 1)]);
+}
+
+#[test]
+pub fn test_dedup_groups() {
+    let mut set: BilevelSet<i32, String> = BilevelSet::new();
+    let mut map: BilevelMap<i32, String, u32> = BilevelMap::new();
+    for (g, k) in [(1, "1"), (1, "2"), (2, "2"), (2, "1"), (3, "1"), (4, "1"), (4, "2"), (4, "2")] {
+        set.insert(g, k);
+        *map.add_or_get(g, k) += 1;
+    }
+    let mut classes = set.dedup_groups();
+    classes.iter_mut().for_each(|class| class.sort());
+    assert_eq!(classes, [vec![1, 2, 4]]);
+    // Group 4 has a different count for key 2.
+    let mut classes = map.dedup_groups_with_values();
+    classes.iter_mut().for_each(|class| class.sort());
+    assert_eq!(classes, [vec![1, 2]]);
+}
+
+#[test]
+pub fn test_map_values() {
+    use std::num::NonZeroUsize;
+    let mut map: BilevelMap<i32, String, u32> = BilevelMap::new();
+    for (g, k) in [(1, "1"), (1, "1"), (1, "2"), (2, "1")] {
+        *map.add_or_get(g, k) += 1;
+    }
+    let mapped = map.map_values(|g, k, v| format!("{}:{}={}", g, k, v));
+    let mut result: Vec<_> = mapped.iter().map(|(_, _, v)| v.as_str()).collect();
+    result.sort();
+    assert_eq!(result, ["1:1=2", "1:2=1", "2:1=1"]);
+    // The new collection can be given a constructor of its own, for payload
+    // types without a default.
+    let mut mapped = mapped.map_values_with(|_, _, v| NonZeroUsize::new(v.len()).unwrap(), || NonZeroUsize::MIN);
+    assert_eq!(*mapped.add_or_get(3, "1"), NonZeroUsize::MIN);
+    assert_eq!(mapped.len(), 4);
+}
+
+#[test]
+pub fn test_flat() {
+    let flat = std::collections::HashMap::from([
+        ((1, "1".to_owned()), 2),
+        ((1, "2".to_owned()), 1),
+        ((2, "1".to_owned()), 1),
+    ]);
+    let map: BilevelMap<i32, String, u32> = BilevelMap::from_flat(flat.clone());
+    let mut result: Vec<_> = map.iter().map(|(g, k, &v)| (g, k.as_str(), v)).collect();
+    result.sort();
+    assert_eq!(result, [(1, "1", 2), (1, "2", 1), (2, "1", 1)]);
+    assert_eq!(map.into_flat(), flat);
+}
+
+#[test]
+pub fn test_nested() {
+    use std::collections::HashMap;
+    let grouped = HashMap::from([
+        (1, HashSet::from(["1".to_owned(), "2".to_owned()])),
+        (2, HashSet::from(["1".to_owned()])),
+    ]);
+    let set = BilevelSet::from(grouped.clone());
+    assert_eq!(set.iter().count(), 3);
+    assert_eq!(set.into_grouped(), grouped);
+    let nested = HashMap::from([
+        (1, HashMap::from([("1".to_owned(), 2), ("2".to_owned(), 1)])),
+        (2, HashMap::from([("1".to_owned(), 1)])),
+    ]);
+    let map: BilevelMap<i32, String, u32> = BilevelMap::from(nested.clone());
+    assert_eq!(map.iter().count(), 3);
+    assert_eq!(map.into_nested(), nested);
+}
+
+#[test]
+pub fn test_sorted() {
+    let mut set: BilevelSet<i32, String> = BilevelSet::new();
+    let mut map: BilevelMap<i32, String, u32> = BilevelMap::new();
+    for (g, k) in [(2, "b"), (1, "c"), (1, "b"), (2, "a")] {
+        set.insert(g, k);
+        *map.add_or_get(g, k) += 1;
+    }
+    let expected = [(1, "b"), (1, "c"), (2, "a"), (2, "b")];
+    assert!(set.iter_sorted().map(|(g, k)| (g, k.as_str())).eq(expected));
+    assert!(map.iter_sorted().map(|(g, k, _)| (g, k.as_str())).eq(expected));
+    let result: Vec<_> = set.into_iter_sorted().collect();
+    assert!(result.iter().map(|(g, k)| (*g, k.as_str())).eq(expected));
+    let result: Vec<_> = map.into_iter_sorted().collect();
+    assert!(result.iter().map(|(g, k, _)| (*g, k.as_str())).eq(expected));
+}
+
+#[test]
+pub fn test_send_sync() {
+    // Fails to compile if the collections cannot be shared with, or moved
+    // into, worker threads.
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<BilevelSet<u32, String>>();
+    assert_send_sync::<BilevelMap<u32, String, u64>>();
+}
+
+#[cfg(feature = "counter")]
+#[test]
+pub fn test_counter() {
+    let mut counts: CounterMap<i32, String, u32> = CounterMap::new();
+    let mut map: BilevelMap<i32, String, u32> = BilevelMap::new();
+    for (g, k) in [(2, "b"), (1, "c"), (1, "b"), (2, "a"), (2, "b"), (1, "c"), (2, "b")] {
+        *counts.add_or_get(g, k) += 1;
+        *map.add_or_get(g, k) += 1;
+    }
+    // Verify that the counts agree with those of a map.
+    let mut expected: Vec<_> = map.iter().collect();
+    expected.sort();
+    let mut result: Vec<_> = counts.iter().collect();
+    result.sort();
+    assert_eq!(result, expected);
+}
+
+#[test]
+pub fn test_ids() {
+    let mut map: BilevelMap<i32, String, u32> = BilevelMap::new();
+    for (g, k) in [(1, "a"), (2, "b"), (2, "a")] {
+        *map.add_or_get(g, k) += 1;
+    }
+    assert!(map.id_mapping().map(|(i, k)| (i.index(), k.as_str())).eq([(0, "a"), (1, "b")]));
+    // Verify that each id resolves to the key listed with it.
+    for (id, g, i, k, _) in map.iter_with_ids() {
+        assert_eq!(map.resolve_group(id), Some(g));
+        assert_eq!(map.resolve_agg_key(i), Some(k));
+    }
+    assert_eq!(map.iter_with_ids().count(), 3);
+}
+
+#[cfg(feature = "serde")]
+#[test]
+pub fn test_serde() {
+    let mut set: BilevelSet<i32, String> = BilevelSet::new();
+    set.insert(1, "a");
+    assert_eq!(serde_json::to_string(&set).unwrap(), r#"{"1":["a"]}"#);
+    let map: BilevelMap<i32, String, u32> = serde_json::from_str(r#"{"1":{"a":3},"2":{"a":1}}"#).unwrap();
+    assert!(map.id_mapping().map(|(_, k)| k).eq(["a"]));
+    let mut result: Vec<_> = map.iter().map(|(g, k, &v)| (g, k.as_str(), v)).collect();
+    result.sort();
+    assert_eq!(result, [(1, "a", 3), (2, "a", 1)]);
+    assert_eq!(serde_json::to_string(&map.regroup_by(|_| 0, |v, w| *v += w)).unwrap(), r#"{"0":{"a":4}}"#);
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+pub fn test_snapshot() {
+    let mut set: BilevelSet<i32, String> = BilevelSet::new();
+    for (g, k) in [(1, "a"), (1, "b"), (2, "a")] {
+        set.insert(g, k);
+    }
+    let mut buf = Vec::new();
+    set.write_snapshot(&mut buf).unwrap();
+    let mut restored: BilevelSet<i32, String> = BilevelSet::read_snapshot(&buf[..]).unwrap();
+    assert!(restored.iter_sorted().eq(set.iter_sorted()));
+    // The interned keys are restored, so known keys are found again.
+    assert!(!restored.insert(2, "a"));
+    assert!(restored.id_mapping().eq(set.id_mapping()));
+}
+
+#[cfg(feature = "replay")]
+#[test]
+pub fn test_replay() {
+    use crate::{Logged, Replay};
+    let mut logged = Logged::new(BilevelSet::<i32, String>::new(), Vec::new());
+    for (g, k) in [(1, "a"), (1, "b"), (2, "a"), (1, "a")] {
+        logged.insert(g, k.to_string());
+    }
+    let (set, log) = logged.into_parts();
+    // Every mutation is logged, even if it has no effect.
+    assert_eq!(log.len(), 4);
+    let mut rebuilt = BilevelSet::new();
+    rebuilt.replay(log);
+    assert!(rebuilt.iter_sorted().eq(set.iter_sorted()));
+}
+
+#[cfg(feature = "snapshot")]
+#[test]
+pub fn test_delta() {
+    let mut central: BilevelMap<i32, String, u32> = BilevelMap::new();
+    for worker_keys in [["a", "b"], ["b", "c"]] {
+        let mut worker: BilevelMap<i32, String, u32> = BilevelMap::new();
+        for k in worker_keys {
+            *worker.add_or_get(1, k) += 1;
+        }
+        central.apply_delta(&worker.take_delta().unwrap(), |v, w| *v += w).unwrap();
+    }
+    let mut result: Vec<_> = central.iter().map(|(g, k, v)| (g, k.as_str(), *v)).collect();
+    result.sort();
+    assert_eq!(result, [(1, "a", 1), (1, "b", 2), (1, "c", 1)]);
+}
+
+#[cfg(feature = "tenant")]
+#[test]
+pub fn test_tenanted() {
+    use crate::Tenanted;
+    let mut agg: Tenanted<&str, BilevelMap<i32, String, u32>> = Tenanted::new(BilevelMap::new);
+    *agg.tenant_mut("acme").add_or_get(1, "a") += 1;
+    *agg.tenant_mut("acme").add_or_get(1, "b") += 1;
+    *agg.tenant_mut("initech").add_or_get(1, "a") += 5;
+    let mut acme: Vec<_> = agg.iter_tenant(&"acme").map(|(g, k, v)| (g, k.as_str(), *v)).collect();
+    acme.sort();
+    assert_eq!(acme, [(1, "a", 1), (1, "b", 1)]);
+    assert_eq!(agg.iter_tenant(&"unknown").count(), 0);
+    assert!(agg.memory_usage(&"acme") > 0);
+    assert_eq!(agg.memory_usage(&"unknown"), 0);
+    assert_eq!(agg.memory_by_tenant().count(), 2);
+    agg.remove_tenant(&"initech");
+    assert!(agg.tenants().eq([&"acme"]));
+}
+
+#[cfg(feature = "tenant")]
+#[test]
+pub fn test_tenant_quota() {
+    use crate::{Quota, QuotaExceeded, Tenanted};
+    let mut agg: Tenanted<&str, BilevelSet<i32, String>> = Tenanted::new(BilevelSet::new);
+    agg.set_default_quota(Quota { pairs: Some(2), ..Quota::default() });
+    agg.set_quota("big", Quota::default());
+    {
+        // The quota is checked on each insert, not only when the tenant is
+        // borrowed.
+        let mut small = agg.try_tenant_mut("small").unwrap();
+        let inserted = ["a", "b", "c"].map(|k| small.insert(1, k.to_owned()));
+        assert_eq!(inserted, [Ok(true), Ok(true), Err(QuotaExceeded::Pairs)]);
+        assert_eq!(small.len(), 2);
+    }
+    for k in ["a", "b", "c"] {
+        agg.try_tenant_mut("big").unwrap().insert(1, k.to_owned()).unwrap();
+    }
+    assert_eq!(agg.try_tenant_mut("small").err(), Some(QuotaExceeded::Pairs));
+    let small = agg.metrics(&"small");
+    assert_eq!((small.pairs, small.rejections), (2, 2));
+    let big = agg.metrics(&"big");
+    assert_eq!((big.groups, big.pairs, big.rejections), (1, 3, 0));
+    // A tenant with no data has nothing to exceed.
+    agg.set_quota("new", Quota { groups: Some(1), ..Quota::default() });
+    assert!(agg.try_tenant_mut("new").is_ok());
+}
+
+/// A payload with no default, counting values into buckets whose bounds
+/// are only known at run time.
+#[derive(Clone, Debug, PartialEq)]
+struct Histogram {
+    bounds: std::sync::Arc<[u32]>,
+    counts: Vec<u32>,
+}
+
+impl Histogram {
+    fn new(bounds: std::sync::Arc<[u32]>) -> Self {
+        Self { counts: vec![0; bounds.len() + 1], bounds }
+    }
+
+    fn record(&mut self, value: u32) {
+        let i = self.bounds.iter().take_while(|&&b| value >= b).count();
+        self.counts[i] += 1;
+    }
+}
+
+#[test]
+pub fn test_new_with() {
+    // The bounds are read from configuration.
+    let bounds: std::sync::Arc<[u32]> = "10,100,1000".split(',').map(|b| b.parse().unwrap()).collect();
+    let latency = move || Histogram::new(bounds.clone());
+    let mut a = BilevelMap::new_with(latency.clone());
+    let mut b = BilevelMap::with_capacity_and(Capacity { groups: 4, per_group: 4, agg_keys: 4 }, latency);
+    for (g, k, value) in [(1, "a", 5), (1, "a", 50), (1, "b", 5000), (2, "a", 500)] {
+        a.add_or_get(g, k).record(value);
+        b.add_or_get(g, k).record(value);
+    }
+    assert_eq!(a.add_or_get(1, "a").counts, [1, 1, 0, 0]);
+    assert_eq!(b.add_or_get(1, "b").counts, [0, 0, 0, 1]);
+    // Derived collections keep the constructor.
+    let mut regrouped = b.regroup_by(|_| 0, |a, b| {
+        a.counts.iter_mut().zip(b.counts).for_each(|(a, b)| *a += b)
+    });
+    assert_eq!(regrouped.add_or_get(0, "a").counts, [1, 1, 1, 0]);
+    regrouped.add_or_get(0, "c").record(100);
+    assert_eq!(regrouped.add_or_get(0, "c").counts, [0, 0, 1, 0]);
+}
+
+#[test]
+pub fn test_add_or_get_with_key() {
+    let mut map: BilevelMap<u32, String, String> = BilevelMap::new();
+    for (g, k) in [(1, "ab"), (1, "ab"), (2, "c")] {
+        map.add_or_get_with_key(g, k, |g, k| format!("{g}:{k}")).push('!');
+    }
+    assert!(map.iter_sorted().map(|(_, _, v)| v.as_str()).eq(["1:ab!!", "2:c!"]));
+}
+
+#[test]
+pub fn test_extend_with() {
+    let mut map: BilevelMap<u8, String, Vec<u32>> = BilevelMap::new();
+    map.extend_with([(1, "a".to_owned(), vec![1]), (1, "a".to_owned(), vec![2])], |a, b| a.extend(b));
+    assert!(map.iter().map(|(g, k, v)| (g, k.as_str(), v.as_slice())).eq([(1, "a", [1, 2].as_slice())]));
+}
+
+#[test]
+pub fn test_top_k() {
+    let mut map: BilevelMap<u8, String, Vec<u32>> = BilevelMap::new();
+    map.add_or_get(1, "a").extend([1, 2, 3]);
+    map.add_or_get(1, "b").extend([1]);
+    map.add_or_get(1, "c").extend([1, 2]);
+    // Rank by the number of values kept for each pair.
+    let top: Vec<_> = map.top_k(1, 2, Vec::len).into_iter().map(|(k, _)| k.as_str()).collect();
+    assert_eq!(top, ["a", "c"]);
+    assert_eq!(map.top_k_all(5, Vec::len).count(), 3);
+}
+
+#[test]
+pub fn test_group_sizes() {
+    let mut map: BilevelMap<u8, String, u32> = BilevelMap::new();
+    *map.add_or_get(1, "a") += 1;
+    *map.add_or_get(1, "a") += 1;
+    *map.add_or_get(1, "b") += 1;
+    assert!(map.group_sizes().eq([(1, 2)]));
+    assert_eq!(map.size_histogram(&[1, 2]), [0, 1, 0]);
+}
+
+#[test]
+pub fn test_heavy_hitters() {
+    let mut map: BilevelMap<u8, String, u32> = BilevelMap::new();
+    *map.add_or_get(1, "a") += 4;
+    *map.add_or_get(2, "a") += 6;
+    *map.add_or_get(2, "b") += 1;
+    let top: Vec<_> = map.heavy_hitters(2).into_iter().map(|(g, k, v)| (g, k.as_str(), *v)).collect();
+    assert_eq!(top, [(2, "a", 6), (1, "a", 4)]);
+}
+
+#[test]
+pub fn test_for_each_group() {
+    let mut map: BilevelMap<u32, String, u32> = BilevelMap::new();
+    for (g, k, v) in [(1, "a", 2), (2, "b", 1), (1, "b", 3)] {
+        *map.add_or_get(g, k) += v;
+    }
+    // Write a line per group, with the total and the keys in order.
+    let mut report = Vec::new();
+    map.for_each_group(|g, pairs| {
+        let mut pairs: Vec<_> = pairs.collect();
+        pairs.sort();
+        let total: u32 = pairs.iter().map(|(_, v)| **v).sum();
+        let keys: Vec<_> = pairs.iter().map(|(k, _)| k.as_str()).collect();
+        report.push(format!("{g}: {total} {}", keys.join(",")));
+    });
+    report.sort();
+    assert_eq!(report, ["1: 5 a,b", "2: 1 b"]);
+    let mut set: BilevelSet<u32, String> = BilevelSet::new();
+    set.insert(1, "a");
+    set.insert(1, "b");
+    let mut sizes = Vec::new();
+    set.for_each_group(|g, keys| sizes.push((g, keys.len())));
+    assert_eq!(sizes, [(1, 2)]);
+}
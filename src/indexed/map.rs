@@ -0,0 +1,178 @@
+use std::hash::Hash;
+use indexmap::{IndexMap, map::Entry};
+
+/// A collection of distinct pairs (g, k) grouped by g, kept in the order
+/// they were first found, with a payload associated with each pair.
+/// 
+/// As pairs are found, they are added if not already present.
+/// When the collection is iterated over, the pairs are listed by group,
+/// with the groups in the order their first pair was found, and the pairs
+/// within each group in the order they were found.
+/// 
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// V is the type of the payload.
+#[derive(Clone)]
+pub struct BilevelMap<G, K, V> {
+    data: IndexMap<G, IndexMap<K, V>>,
+    per_group: usize,
+}
+
+impl<G: Hash + Eq, K: Hash + Eq, V: Default> BilevelMap<G, K, V> {
+    /// Create a new collection.
+    /// 
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    pub fn new() -> Self {
+        Self {
+            data: IndexMap::new(),
+            per_group: 4,
+        }
+    }
+
+    /// Create a new collection with the specified capacity.
+    /// 
+    /// groups: The number of groups to allocate space for.
+    /// per_group: The number of items to allocate capacity for when a new
+    ///     group key is found.
+    pub fn with_capacity(groups: usize, per_group: usize) -> Self {
+        Self {
+            data: IndexMap::with_capacity(groups),
+            per_group,
+        }
+    }
+
+    /// Get a mutable reference to the payload for the specified key pair.
+    /// 
+    /// If the key pair is currently not present, the default payload is inserted.
+    pub fn add_or_get(&mut self, g: G, k: K) -> &mut V {
+        self.data.entry(g)
+            .or_insert_with(|| IndexMap::with_capacity(self.per_group))
+            .entry(k)
+            .or_default()
+    }
+
+    /// List the payloads for the pairs currently in the collection,
+    /// without consuming the collection or the payloads.
+    /// 
+    /// Pairs are listed in the order they were first found.
+    pub fn iter(&self) -> impl Iterator<Item = (&G, &K, &V)> {
+        self.data.iter()
+            .flat_map(|(g, inner)| inner.iter().map(move |(k, v)| (g, k, v)))
+    }
+}
+
+impl<G: Hash + Eq, K: Hash + Eq, V: Default> Default for BilevelMap<G, K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: Hash + Eq + Clone, K: Hash + Eq, V: Default> BilevelMap<G, K, V> {
+    /// List and consume the payloads for the pairs in the collection,
+    /// consuming the collection.
+    /// 
+    /// Pairs are listed in the order they were first found.
+    pub fn into_iter(self) -> impl Iterator<Item = (G, K, V)> {
+        self.data.into_iter()
+            .flat_map(|(g, inner)| inner.into_iter().map(move |(k, v)| (g.clone(), k, v)))
+    }
+}
+
+impl<G: Hash + Eq, K: Hash + Eq, V> BilevelMap<G, K, V> {
+    /// Add rows of key pairs and payloads to the collection.
+    /// 
+    /// combine: folds the payload of a row into the payload already present
+    ///     when the collection already holds its pair, including a pair
+    ///     found earlier in the same rows.
+    pub fn extend_with<F>(&mut self, rows: impl IntoIterator<Item = (G, K, V)>, mut combine: F)
+    where
+        F: FnMut(&mut V, V),
+    {
+        for (g, k, v) in rows {
+            match self.data.entry(g)
+                .or_insert_with(|| IndexMap::with_capacity(self.per_group))
+                .entry(k) {
+                Entry::Occupied(mut e) => combine(e.get_mut(), v),
+                Entry::Vacant(e) => { e.insert(v); },
+            }
+        }
+    }
+}
+
+impl<G, K, V> std::fmt::Debug for BilevelMap<G, K, V>
+where
+    G: std::fmt::Debug,
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+{
+    /// Format the collection as a map from each group key to a map of its
+    /// aggregation keys and payloads, listing only the first few groups,
+    /// and keys of each group, of a large collection.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::debug::debug_map(f, self.data.iter())
+    }
+}
+
+impl<G: Hash + Eq, K: Hash + Eq, V: PartialEq> PartialEq for BilevelMap<G, K, V> {
+    /// Check whether two collections hold the same groups, with the same
+    /// pairs and payloads, whatever the order in which they were found.
+    fn eq(&self, other: &Self) -> bool {
+        self.data == other.data
+    }
+}
+
+impl<G: Hash + Eq, K: Hash + Eq, V: Eq> Eq for BilevelMap<G, K, V> {}
+
+#[cfg(feature = "serde")]
+impl<G, K, V> serde::Serialize for BilevelMap<G, K, V>
+where
+    G: Hash + Eq + serde::Serialize,
+    K: Hash + Eq + serde::Serialize,
+    V: serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.data.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, G, K, V> serde::Deserialize<'de> for BilevelMap<G, K, V>
+where
+    G: Hash + Eq + serde::Deserialize<'de>,
+    K: Hash + Eq + serde::Deserialize<'de>,
+    V: serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        IndexMap::deserialize(deserializer).map(|data| Self { data, per_group: 4 })
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<G: Hash + Eq + Send + Sync, K: Hash + Eq + Send + Sync, V: Default> BilevelMap<G, K, V> {
+    /// List the pairs currently in the collection in parallel, splitting
+    /// the work between threads by group.
+    /// 
+    /// Pairs are listed in the order they were first found if collected into an ordered collection.
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (&G, &K, &V)>
+    where
+        V: Sync,
+    {
+        use rayon::prelude::*;
+        self.data.par_iter()
+            .flat_map_iter(|(g, inner)| inner.iter().map(move |(k, v)| (g, k, v)))
+    }
+
+    /// List the payloads for the pairs currently in the collection in
+    /// parallel, for modification, splitting the work between threads
+    /// by group.
+    pub fn par_iter_mut(&mut self) -> impl rayon::iter::ParallelIterator<Item = (&G, &K, &mut V)>
+    where
+        V: Send,
+    {
+        use rayon::prelude::*;
+        self.data.par_iter_mut()
+            .flat_map_iter(|(g, inner)| inner.iter_mut().map(move |(k, v)| (&*g, k, v)))
+    }
+}
+
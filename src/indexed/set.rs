@@ -0,0 +1,134 @@
+use std::hash::Hash;
+use indexmap::{IndexMap, IndexSet};
+
+/// A collection of distinct pairs (g, k) grouped by g, kept in the order
+/// they were first found.
+/// 
+/// As pairs are found, they are added if not already present.
+/// When the collection is iterated over, the pairs are listed by group,
+/// with the groups in the order their first pair was found, and the pairs
+/// within each group in the order they were found.
+/// 
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+#[derive(Clone)]
+pub struct BilevelSet<G, K> {
+    data: IndexMap<G, IndexSet<K>>,
+    per_group: usize,
+}
+
+impl<G: Hash + Eq, K: Hash + Eq> BilevelSet<G, K> {
+    /// Create a new collection.
+    /// 
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    pub fn new() -> Self {
+        Self {
+            data: IndexMap::new(),
+            per_group: 4,
+        }
+    }
+
+    /// Create a new collection with the specified capacity.
+    /// 
+    /// groups: The number of groups to allocate space for.
+    /// per_group: The number of items to allocate capacity for when a new
+    ///     group key is found.
+    pub fn with_capacity(groups: usize, per_group: usize) -> Self {
+        Self {
+            data: IndexMap::with_capacity(groups),
+            per_group,
+        }
+    }
+
+    /// Insert a key pair found into the collection.
+    /// 
+    /// g: the group key.
+    /// k: the remaining key.
+    /// 
+    /// Return false if the key was already present, otherwise true.
+    pub fn insert(&mut self, g: G, k: K) -> bool {
+        self.data.entry(g)
+            .or_insert_with(|| IndexSet::with_capacity(self.per_group))
+            .insert(k)
+    }
+
+    /// List the pairs currently in the collection without consuming
+    /// the collection.
+    /// 
+    /// Pairs are listed in the order they were first found.
+    pub fn iter(&self) -> impl Iterator<Item = (&G, &K)> {
+        self.data.iter()
+            .flat_map(|(g, inner)| inner.iter().map(move |k| (g, k)))
+    }
+}
+
+impl<G: Hash + Eq, K: Hash + Eq> Default for BilevelSet<G, K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: Hash + Eq + Clone, K: Hash + Eq> BilevelSet<G, K> {
+    /// List the pairs in the collection and consume the collection.
+    /// 
+    /// Pairs are listed in the order they were first found.
+    pub fn into_iter(self) -> impl Iterator<Item = (G, K)> {
+        self.data.into_iter()
+            .flat_map(|(g, inner)| inner.into_iter().map(move |k| (g.clone(), k)))
+    }
+}
+
+impl<G: std::fmt::Debug, K: std::fmt::Debug> std::fmt::Debug for BilevelSet<G, K> {
+    /// Format the collection as a map from each group key to the set of
+    /// its aggregation keys, listing only the first few groups, and keys
+    /// of each group, of a large collection.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::debug::debug_set(f, self.data.iter())
+    }
+}
+
+impl<G: Hash + Eq, K: Hash + Eq> PartialEq for BilevelSet<G, K> {
+    /// Check whether two collections hold the same groups, with the same
+    /// aggregation keys, whatever the order in which they were found.
+    fn eq(&self, other: &Self) -> bool {
+        self.data == other.data
+    }
+}
+
+impl<G: Hash + Eq, K: Hash + Eq> Eq for BilevelSet<G, K> {}
+
+#[cfg(feature = "serde")]
+impl<G, K> serde::Serialize for BilevelSet<G, K>
+where
+    G: Hash + Eq + serde::Serialize,
+    K: Hash + Eq + serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.data.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, G, K> serde::Deserialize<'de> for BilevelSet<G, K>
+where
+    G: Hash + Eq + serde::Deserialize<'de>,
+    K: Hash + Eq + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        IndexMap::deserialize(deserializer).map(|data| Self { data, per_group: 4 })
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<G: Hash + Eq + Send + Sync, K: Hash + Eq + Send + Sync> BilevelSet<G, K> {
+    /// List the pairs currently in the collection in parallel, splitting
+    /// the work between threads by group.
+    /// 
+    /// Pairs are listed in the order they were first found if collected into an ordered collection.
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (&G, &K)> {
+        use rayon::prelude::*;
+        self.data.par_iter()
+            .flat_map_iter(|(g, inner)| inner.iter().map(move |k| (g, k)))
+    }
+}
@@ -0,0 +1,71 @@
+use super::*;
+
+#[test]
+pub fn test_set() {
+    let mut set = BilevelSet::new();
+    for (i, (g, k)) in [(3, "b"), (1, "b"), (3, "a"), (1, "a"), (3, "b")].into_iter().enumerate() {
+        // Verify that insertion returns false on duplicates. otherwise true.
+        assert_eq!(set.insert(g, k), i != 4);
+    }
+    // Verify that the pairs are listed in the order they were found.
+    assert!(set.iter().eq([(&3, &"b"), (&3, &"a"), (&1, &"b"), (&1, &"a")]));
+    assert!(set.into_iter().eq([(3, "b"), (3, "a"), (1, "b"), (1, "a")]));
+}
+
+#[test]
+pub fn test_map() {
+    let mut map: BilevelMap<u32, &str, u32> = BilevelMap::with_capacity(2, 2);
+    for (g, k) in [(3, "b"), (1, "b"), (3, "a"), (1, "a"), (3, "b")] {
+        *map.add_or_get(g, k) += 1;
+    }
+    // Verify that the pairs are listed in the order they were found,
+    // with the correct counts.
+    assert!(map.iter().eq([(&3, &"b", &2), (&3, &"a", &1), (&1, &"b", &1), (&1, &"a", &1)]));
+    assert!(map.into_iter().eq([(3, "b", 2), (3, "a", 1), (1, "b", 1), (1, "a", 1)]));
+}
+
+#[test]
+pub fn test_send_sync() {
+    // Fails to compile if the collections cannot be shared with, or moved
+    // into, worker threads.
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<BilevelSet<String, String>>();
+    assert_send_sync::<BilevelMap<String, String, u64>>();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+pub fn test_serde() {
+    let json = r#"{"2":{"b":1,"a":3},"1":{"a":2}}"#;
+    let map: BilevelMap<u32, String, u32> = serde_json::from_str(json).unwrap();
+    // The order found in the input is preserved.
+    assert_eq!(serde_json::to_string(&map).unwrap(), json);
+    let set: BilevelSet<u32, String> = serde_json::from_str(r#"{"1":["b","a"]}"#).unwrap();
+    assert_eq!(serde_json::to_string(&set).unwrap(), r#"{"1":["b","a"]}"#);
+}
+
+#[cfg(feature = "rayon")]
+#[test]
+pub fn test_par_iter() {
+    use rayon::prelude::*;
+    let mut map = BilevelMap::<&str, u32, u32>::new();
+    for g in ["b", "a", "c"] {
+        for k in (0..50).rev() {
+            *map.add_or_get(g, k) += k;
+        }
+    }
+    map.par_iter_mut().for_each(|(_, _, v)| *v *= 2);
+    // Collecting preserves the order found.
+    let pairs: Vec<_> = map.par_iter().collect();
+    assert!(pairs.into_iter().eq(map.iter()));
+    assert!(map.iter().all(|(_, k, v)| *v == 2 * k));
+}
+
+#[test]
+pub fn test_extend_with() {
+    let mut map: BilevelMap<u8, u8, u32> = BilevelMap::new();
+    *map.add_or_get(2, 1) += 1;
+    map.extend_with([(1, 1, 2), (2, 1, 4), (1, 1, 3)], |a, b| *a += b);
+    // The pairs stay in the order they were first found.
+    assert!(map.iter().map(|(g, k, v)| (*g, *k, *v)).eq([(2, 1, 5), (1, 1, 5)]));
+}
@@ -0,0 +1,65 @@
+/// A row that can be split into a group key and an aggregation key, so
+/// that it can be added to a collection by insert_row or add_or_get_row.
+/// 
+/// This can be derived for a struct, marking the fields of the group key
+/// with #[group] and those of the aggregation key with #[agg].
+/// 
+/// # Examples
+/// ```
+/// use bilevel_aggregator::{BilevelKey, core::BilevelMap};
+/// 
+/// #[derive(BilevelKey)]
+/// struct Request {
+///     #[group]
+///     region: String,
+///     #[group]
+///     host: String,
+///     #[agg]
+///     status: u16,
+///     bytes: u64,
+/// }
+/// 
+/// let row = Request { region: "eu".into(), host: "web1".into(), status: 200, bytes: 512 };
+/// let mut map = BilevelMap::<(String, String), u16, u64>::new();
+/// *map.add_or_get_row(&row) += row.bytes;
+/// assert_eq!(row.group(), ("eu".to_string(), "web1".to_string()));
+/// ```
+/// 
+/// A field can be in the group key or the aggregation key, but not both:
+/// ```compile_fail
+/// use bilevel_aggregator::BilevelKey;
+/// 
+/// #[derive(BilevelKey)]
+/// struct Request {
+///     #[group]
+///     #[agg]
+///     host: String,
+///     #[agg]
+///     status: u16,
+/// }
+/// ```
+/// 
+/// And the attributes take no arguments:
+/// ```compile_fail
+/// use bilevel_aggregator::BilevelKey;
+/// 
+/// #[derive(BilevelKey)]
+/// struct Request {
+///     #[group(name = "host")]
+///     host: String,
+///     #[agg]
+///     status: u16,
+/// }
+/// ```
+pub trait BilevelKey {
+    /// The type of the group key.
+    type Group;
+    /// The type of the aggregation key.
+    type Agg;
+
+    /// Get the group key of the row.
+    fn group(&self) -> Self::Group;
+
+    /// Get the aggregation key of the row.
+    fn agg(&self) -> Self::Agg;
+}
@@ -17,17 +17,273 @@
 //! 
 //! (Note: Each module is a feature with the same name.)
 //! 
+//! - core  Use the versions in this module to choose how the aggregation keys
+//!         are stored. The copy, hybrid and borrow versions are built on
+//!         these.
 //! - copy  Use the versions in this module where both the group key and the
-//!         aggregation key are copy types.
+//!         aggregation key are copy types, or are cheap to clone.
 //! - borrow Use the versions in this module where the group key is a copy
 //!         type but the aggregation key is not.
 //! - hybrid Use the versions in this module where neither key is a copy type.  
+//! - bytes Use the versions in this module where the aggregation key is a
+//!         byte string, such as a hash or a hardware address.
+//! - path  Use the versions in this module to group by path and aggregate by
+//!         OS string, such as files grouped by directory and extension.
+//! - text  Use the versions in this module where each key is a fixed number
+//!         of strings, such as fields of a log line.
+//! - stats Use this module to keep summary statistics, such as the mean and
+//!         variance, or estimated percentiles, of the numbers observed for
+//!         each pair.
+//! - approx Use this module to estimate the number of distinct aggregation
+//!         keys of each group, or the count of each pair, in fixed memory per
+//!         group, where the keys are too many to keep.
+//! - ordered Use the versions in this module where the pairs must be listed
+//!         in order, or groups must be selected by a range of group keys.
+//! - indexed Use the versions in this module where the pairs must be listed
+//!         in the order they were first found.
+//! - sync  Use the versions in this module where many threads must add to the
+//!         same map at once, or read snapshots of a map while it is added to.
+//! - pipeline Use this module to aggregate a delimited file, such as a CSV
+//!         file or a log file, in parallel, and write the results out.
+//!
+//! Other features add to the collections of the modules above:
+//!
+//! - serde Serialize and deserialize every set and map as a nested map.
+//! - snapshot Write and read compact binary snapshots, and take deltas of the
+//!         maps to merge into a central map.
+//! - json  Write collections as JSON, and build maps from JSON Lines.
+//! - csv   Write collections as delimited text, one row per pair.
+//! - arrow Convert maps into Arrow RecordBatches.
+//! - parquet Write maps to Parquet files.
+//! - polars Convert maps to and from Polars DataFrames.
+//! - sqlite Write maps to SQLite tables, and build them from queries.
+//! - spill Aggregate more than fits in memory with a SpillingMap.
+//! - frozen Write collections in a read-only format, to be queried where
+//!         they lie by FrozenMap.
+//! - replay Record the mutations of a collection with Logged, to be replayed.
+//! - tenant Serve several tenants from one aggregator with Tenanted.
+//! - derive Derive BilevelKey for the rows added by insert_row and
+//!         add_or_get_row.
+//! - rayon List and modify collections in parallel, and build maps in
+//!         parallel with par_extend or a ShardedCollector.
 
-#[cfg(any(feature = "hybrid", feature = "borrow"))]
+// The lists in the crate docs align each description after the module
+// or feature name rather than indenting it as a Markdown list continuation.
+#![allow(clippy::doc_overindented_list_items)]
+
+#[cfg(feature = "core")]
+use std::collections::HashMap;
+#[cfg(any(feature = "core", feature = "sync", feature = "approx"))]
 use std::hash::{Hash, Hasher, DefaultHasher};
 
+#[cfg(any(feature = "bytes", feature = "text"))]
+mod arena;
+#[cfg(feature = "arrow")]
+mod arrow;
+#[cfg(feature = "core")]
+mod compact;
+#[cfg(all(feature = "csv", feature = "core"))]
+mod csv;
+#[cfg(any(feature = "core", feature = "ordered", feature = "indexed", feature = "sync", feature = "approx"))]
+mod debug;
+mod derived;
+#[cfg(feature = "core")]
+mod heap;
+#[cfg(feature = "core")]
+mod ops;
+#[cfg(feature = "derive")]
+mod key;
+#[cfg(all(feature = "serde", feature = "core"))]
+mod serde_support;
+#[cfg(all(feature = "snapshot", feature = "core"))]
+mod snapshot;
+#[cfg(feature = "frozen")]
+mod frozen;
+#[cfg(feature = "spill")]
+mod spill;
+#[cfg(feature = "sqlite")]
+mod sqlite;
+#[cfg(feature = "parquet")]
+mod parquet;
+#[cfg(feature = "polars")]
+mod polars;
+#[cfg(feature = "replay")]
+mod replay;
+#[cfg(feature = "tenant")]
+mod tenant;
+#[cfg(any(feature = "core", feature = "sync"))]
+// The sync maps use only part of what the core collections need.
+#[cfg_attr(not(feature = "core"), allow(dead_code))]
+mod small;
+#[cfg(feature = "core")]
+mod table;
+#[cfg(feature = "rayon")]
+mod parallel;
+
+#[cfg(feature = "arrow")]
+pub use arrow::ArrowValue;
+#[cfg(all(feature = "csv", feature = "core"))]
+pub use csv::{Column, CsvOptions};
+#[cfg(feature = "parquet")]
+pub use parquet::ParquetOptions;
+#[cfg(feature = "polars")]
+pub use polars::PolarsValue;
+#[cfg(feature = "frozen")]
+pub use frozen::{FrozenError, FrozenMap, FrozenValue};
+#[cfg(feature = "spill")]
+pub use spill::{LostRun, SpillOptions, SpillingMap};
+#[cfg(feature = "sqlite")]
+pub use sqlite::sqlite_row;
+#[cfg(all(feature = "snapshot", feature = "core"))]
+pub use snapshot::{MissingGroups, SnapshotError};
+#[cfg(feature = "core")]
+pub use compact::CompactStr;
+#[cfg(feature = "core")]
+pub use heap::HeapSize;
+#[cfg(feature = "core")]
+pub use ops::{BilevelMapOps, BilevelSetOps};
+#[cfg(feature = "core")]
+pub use table::TableOptions;
+#[cfg(feature = "replay")]
+pub use replay::{Logged, Mutation, MutationSink, Replay};
+#[cfg(feature = "tenant")]
+pub use tenant::{Quota, QuotaExceeded, TenantData, TenantMetrics, TenantMut, Tenanted};
+#[cfg(feature = "rayon")]
+pub use parallel::{Shard, ShardedCollector};
+#[cfg(feature = "derive")]
+pub use key::BilevelKey;
+#[cfg(feature = "derive")]
+pub use bilevel_aggregator_derive::BilevelKey;
+
+pub use derived::Derived;
+#[cfg(feature = "core")]
+pub use core::aggregate_by;
+
+// Let the derived implementations refer to this crate by name from
+// within it, as they do from other crates.
+#[cfg(feature = "derive")]
+extern crate self as bilevel_aggregator;
+
+/// Generic implementations, with the strategy for storing the
+/// aggregation keys chosen by a type parameter, on which the copy,
+/// hybrid and borrow versions are built.
+/// 
+/// Inline keeps each aggregation key in every group where it appears,
+/// and Interned, the default, keeps a single copy of each key.
+/// 
+/// The hash of each group key is kept with the group, so that a long
+/// key, such as a URL, is not hashed again as the collection grows.
+/// The keys of each small group are held inline, in a short array
+/// searched in order, and moved into a hash table only once the group
+/// grows past a few keys, so that many tiny groups take little memory.
+/// 
+/// Several collections can share a KeyInterner, so that a key found in
+/// each, such as a URL, is kept once for all of them. Each group has a
+/// GroupId, and with interned keys each aggregation key has an AggKeyId,
+/// so that pairs can be passed to other systems, and inserted, as compact
+/// ids rather than copies of the keys. String keys can be held as
+/// CompactStr, which keeps a short string within the key itself, and is
+/// searched by &str all the same.
+/// 
+/// The sets and maps of this module, and of the copy, hybrid and borrow
+/// modules, implement BilevelSetOps and BilevelMapOps, so that code that
+/// fills or reads them can be written once for all. Their iterators are
+/// named types, which know how many pairs remain. They estimate the
+/// memory they hold with estimated_heap_bytes, counting the memory their
+/// keys and payloads own through HeapSize, render themselves as a text
+/// table with render_table, and format with Debug as a nested map that
+/// lists only the first few groups of a large collection.
+/// 
+/// BilevelMultiset counts how many times each pair is inserted, and
+/// BilevelVecMap keeps every value pushed for each pair.
+/// TrilevelMap groups by two nested group keys before the aggregation
+/// key, keeping a BilevelMap for each outer group. Builder aggregates
+/// rows fed to it in batches, counting the rows and duplicates found,
+/// and aggregate_by builds a BilevelMap from any iterator of rows in one
+/// call.
+/// 
+/// BoundedMap holds no more than a fixed number of groups, passing the
+/// least recently updated group to a callback to make room for each new
+/// group, so that an unbounded space of keys can be aggregated in bounded
+/// memory. WindowedMap records when each group was last updated, so that
+/// the groups older than a window of time can be swept out.
+/// StreamingAggregator reads input sorted by group key, holding only the
+/// current group, so that input larger than memory can be aggregated.
+/// A finished map can be frozen into a read-only FrozenBilevelMap, which
+/// holds the groups and pairs in sorted slices, taking less memory and
+/// reading faster, with a minimal perfect hash of the pairs if asked.
+/// 
+/// # Examples
+/// ```
+/// use bilevel_aggregator::core::{BilevelMap, Inline};
+/// 
+/// let mut map = BilevelMap::<String, u16, usize, Inline>::new();
+/// *map.add_or_get("web1", &200) += 1;
+/// *map.add_or_get("web1", &500) += 1;
+/// *map.add_or_get("web1", &200) += 1;
+/// for (g, k, v) in map.iter_sorted() {
+///     println!("{}, {}, {}", g, k, v)
+/// }
+/// ```
+/// The results will be grouped by g, in order:
+/// 
+/// web1, 200, 2
+/// web1, 500, 1
+/// 
+#[cfg(feature = "core")]
+pub mod core {
+    mod aggregate;
+    mod bounded;
+    mod builder;
+    mod csr;
+    mod frozen;
+    mod groups;
+    mod keys;
+    mod map;
+    mod metrics;
+    mod multiset;
+    mod recency;
+    mod set;
+    mod streaming;
+    mod trilevel;
+    mod vec_map;
+    mod windowed;
+
+    pub use aggregate::aggregate_by;
+    pub use bounded::{BoundedMap, Eviction};
+    pub use builder::{BuildStats, Builder};
+    pub use csr::CsrMatrix;
+    pub use frozen::FrozenBilevelMap;
+    pub use groups::GroupId;
+    pub(crate) use groups::GroupTable;
+    pub use keys::{AggKeyId, DefaultHashing, IdentityHasher, IdentityHashing, Inline, Interned, KeyHasher, KeyInterner, KeyStore, SharedKey};
+    pub use map::{BilevelMap, GroupIntoIter, GroupIter, IntoGroups, MapIntoIter, MapIter, MapIterMut};
+    pub use metrics::GroupMetrics;
+    pub use multiset::BilevelMultiset;
+    pub use set::{BilevelSet, GroupKeys, SetIntoIter, SetIter};
+    pub use streaming::StreamingAggregator;
+    pub use trilevel::TrilevelMap;
+    pub use vec_map::BilevelVecMap;
+    pub use windowed::WindowedMap;
+
+    #[cfg(test)]
+    pub mod tests;
+}
+
 /// Implementations where both the group key and the aggregation key
-/// are copy types.
+/// are copy types, or other types that are cheap to clone, such as
+/// Arc<str>, held by value in each group.
+/// 
+/// Keys are returned by value, as clones. The keys are hashed with
+/// SipHash unless another hasher is given, such as IdentityHashing, which
+/// takes an integer id as its own hash.
+/// 
+/// Where the aggregation keys are drawn from a small domain known in
+/// advance, such as status codes, BilevelDenseSet and BilevelDenseMap
+/// hold the keys of each group in a fixed-size bitset or array, with no
+/// hash table each. With the roaring feature, a BilevelBitmapSet holds
+/// the u32 aggregation keys of each group in a roaring bitmap, for sets
+/// of many dense ids per group.
 /// 
 /// # Examples
 /// ```
@@ -70,19 +326,31 @@ use std::hash::{Hash, Hasher, DefaultHasher};
 /// 
 #[cfg(feature = "copy")]
 pub mod copy {
+    #[cfg(feature = "roaring")]
+    mod bitmap;
+    mod dense;
     mod map;
     mod set;
 
-    pub use map::BilevelMap;
-    pub use set::BilevelSet;
+    #[cfg(feature = "roaring")]
+    pub use bitmap::BilevelBitmapSet;
+    pub use dense::{BilevelDenseMap, BilevelDenseSet, DenseKey};
+    pub use map::{BilevelMap, GroupIter, IntoGroups, MapIntoIter, MapIter, MapIterMut};
+    pub use set::{BilevelSet, GroupKeys, SetIntoIter, SetIter};
 
     #[cfg(test)]
+    #[allow(clippy::match_like_matches_macro)]
     pub mod tests;
 }
 
 /// Implementations where the group key is a copy type but the
 /// aggregation key is not.
 /// 
+/// These collections cannot be pivoted, so they have neither pivot nor
+/// a cached pivoted view: the aggregation key would become a group key,
+/// which must be a copy type. Use the borrow collections to group by
+/// the aggregation key as well.
+/// 
 /// # Examples
 /// ```
 /// use bilevel_aggregator::hybrid::BilevelSet;
@@ -126,11 +394,16 @@ pub mod copy {
 pub mod hybrid {
     mod map;
     mod set;
+    #[cfg(feature = "counter")]
+    mod counter;
 
-    pub use map::BilevelMap;
-    pub use set::BilevelSet;
+    pub use map::{BilevelMap, GroupIter, IntoGroups, MapIntoIter, MapIter, MapIterMut};
+    pub use set::{BilevelSet, GroupKeys, SetIntoIter, SetIter};
+    #[cfg(feature = "counter")]
+    pub use counter::CounterMap;
 
     #[cfg(test)]
+    #[allow(clippy::match_like_matches_macro)]
     pub mod tests;
 }
 
@@ -181,6 +454,43 @@ pub mod borrow {
     mod map;
     mod set;
 
+    pub use map::{BilevelMap, GroupIter, IntoGroups, MapIntoIter, MapIter, MapIterMut};
+    pub use set::{BilevelSet, GroupKeys, SetIntoIter, SetIter};
+
+    #[cfg(test)]
+    #[allow(clippy::match_like_matches_macro)]
+    pub mod tests;
+}
+
+/// Implementations where the aggregation key is a byte string, such as
+/// a hash or a hardware address, and the group key is of any type, as in
+/// the borrow module.
+/// 
+/// The aggregation keys are kept once each, end to end in a single buffer,
+/// rather than each in an allocation of its own.
+/// 
+/// # Examples
+/// ```
+/// use bilevel_aggregator::bytes::BilevelMap;
+/// 
+/// let mut map = BilevelMap::<String, usize>::new();
+/// *map.add_or_get("eth0", &[0x00, 0x1a, 0x2b]) += 1;
+/// *map.add_or_get("eth0", &[0xff, 0x00]) += 1;
+/// *map.add_or_get("eth0", &[0x00, 0x1a, 0x2b]) += 1;
+/// for (g, k, v) in map.iter_sorted() {
+///     println!("{}, {:02x?}, {}", g, k, v)
+/// }
+/// ```
+/// The results will be grouped by g, in order:
+/// 
+/// eth0, [00, 1a, 2b], 2
+/// eth0, [ff, 00], 1
+/// 
+#[cfg(feature = "bytes")]
+pub mod bytes {
+    mod map;
+    mod set;
+
     pub use map::BilevelMap;
     pub use set::BilevelSet;
 
@@ -188,7 +498,363 @@ pub mod borrow {
     pub mod tests;
 }
 
+/// Implementations where the group key is a path and the aggregation key
+/// is an OS string, such as files grouped by directory and aggregated by
+/// extension.
+/// 
+/// The keys are kept as PathBuf and OsString, so no names are lost to
+/// conversion to UTF-8.
+/// 
+/// # Examples
+/// ```
+/// use bilevel_aggregator::path::BilevelMap;
+/// use std::path::Path;
+/// 
+/// let mut map = BilevelMap::<u64>::new();
+/// *map.add_or_get_file(Path::new("logs/a.log")) += 10;
+/// *map.add_or_get_file(Path::new("logs/b.log")) += 20;
+/// *map.add_or_get_file(Path::new("logs/c.gz")) += 5;
+/// for (g, k, v) in map.iter_sorted() {
+///     println!("{}, {}, {}", g.display(), k.to_string_lossy(), v)
+/// }
+/// ```
+/// The results will be grouped by g, in order:
+/// 
+/// logs, gz, 5
+/// logs, log, 30
+/// 
+#[cfg(feature = "path")]
+pub mod path {
+    mod map;
+    mod set;
+
+    pub use map::BilevelMap;
+    pub use set::BilevelSet;
+
+    #[cfg(test)]
+    pub mod tests;
+}
+
+/// Implementations where each key is an array of strings, with the
+/// number of strings in the group key and the aggregation key fixed at
+/// compile time.
+/// 
+/// Each distinct string is kept once, and the keys are kept as arrays of
+/// string ids, so wide keys with many repeated strings stay small. The
+/// strings are kept end to end in an arena rather than in an allocation
+/// each, so that tens of millions of short keys take little allocator
+/// work.
+/// Lines of delimited text, such as tab-separated logs, can be split into
+/// keys and inserted in one call by insert_delimited or extend_from_lines.
+/// A Normalizer can be set to trim, collapse whitespace in or case-fold
+/// each string as it is inserted. BilevelMapDyn and BilevelSetDyn take
+/// the number of strings in each key at run time instead.
+/// 
+/// # Examples
+/// ```
+/// use bilevel_aggregator::text::BilevelMap;
+/// 
+/// let mut map = BilevelMap::<2, 1, usize>::new();
+/// *map.add_or_get(["us-east", "prod"], ["api"]) += 1;
+/// *map.add_or_get(["us-east", "prod"], ["web"]) += 1;
+/// *map.add_or_get(["us-east", "prod"], ["api"]) += 1;
+/// for (g, k, v) in map.iter_sorted() {
+///     println!("{}/{}, {}, {}", g[0], g[1], k[0], v)
+/// }
+/// ```
+/// The results will be grouped by g, in order:
+/// 
+/// us-east/prod, api, 2
+/// us-east/prod, web, 1
+/// 
+#[cfg(feature = "text")]
+pub mod text {
+    mod components;
+    mod delimited;
+    mod map;
+    mod map_dyn;
+    mod normalize;
+    mod set;
+    mod set_dyn;
+
+    pub use delimited::FieldCountError;
+    pub use map::BilevelMap;
+    pub use map_dyn::BilevelMapDyn;
+    pub use normalize::Normalizer;
+    pub use set::BilevelSet;
+    pub use set_dyn::BilevelSetDyn;
+
+    #[cfg(test)]
+    pub mod tests;
+}
+
+/// Summary statistics of the numbers observed for each pair, such as
+/// request latencies, kept without keeping the numbers themselves.
+/// 
+/// Stats can also be used as the payload of any BilevelMap, folding in
+/// each number with observe.
+/// 
+/// With the quantile feature, BilevelQuantiles keeps a TDigest sketch of
+/// the numbers observed for each pair, from which quantiles such as p50,
+/// p95 and p99 can be estimated in bounded memory.
+/// 
+/// # Examples
+/// ```
+/// use bilevel_aggregator::stats::BilevelStats;
+/// 
+/// let mut stats = BilevelStats::<String, String>::new();
+/// stats.observe("api", "GET", 12.0);
+/// stats.observe("api", "GET", 18.0);
+/// stats.observe("api", "POST", 40.0);
+/// for (g, k, s) in stats.iter_sorted() {
+///     println!("{}, {}, {}, {:.1}", g, k, s.count(), s.mean().unwrap())
+/// }
+/// ```
+/// The results will be grouped by g, in order:
+/// 
+/// api, GET, 2, 15.0
+/// api, POST, 1, 40.0
+/// 
+#[cfg(feature = "stats")]
+pub mod stats {
+    #[cfg(feature = "quantile")]
+    mod digest;
+    mod map;
+    #[cfg(feature = "quantile")]
+    mod quantiles;
+    mod summary;
+
+    #[cfg(feature = "quantile")]
+    pub use digest::TDigest;
+    pub use map::BilevelStats;
+    #[cfg(feature = "quantile")]
+    pub use quantiles::BilevelQuantiles;
+    pub use summary::Stats;
+
+    #[cfg(test)]
+    pub mod tests;
+}
+
+/// Estimates of the number of distinct aggregation keys of each group,
+/// kept as a HyperLogLog sketch per group rather than as the keys
+/// themselves.
+/// 
+/// BilevelCountMin similarly estimates the count of each pair from a
+/// count-min sketch per group, keeping only the most frequent aggregation
+/// keys of each group, so that memory stays bounded however many distinct
+/// keys are found.
+/// 
+/// # Examples
+/// ```
+/// use bilevel_aggregator::approx::BilevelDistinct;
+/// 
+/// let mut distinct = BilevelDistinct::<String, u64>::new();
+/// for user in 0..1000 {
+///     distinct.insert("2024-01-01", &user);
+///     distinct.insert("2024-01-02", &(user % 10));
+/// }
+/// for (g, n) in distinct.iter_sorted() {
+///     println!("{}, {}", g, n)
+/// }
+/// ```
+/// The estimates will be listed in order of the group key, within about
+/// 1% of the exact counts:
+/// 
+/// 2024-01-01, 1000
+/// 2024-01-02, 10
+/// 
+#[cfg(feature = "approx")]
+pub mod approx {
+    mod count_map;
+    mod count_min;
+    mod distinct;
+    mod hll;
+
+    pub use count_map::BilevelCountMin;
+    pub use count_min::CountMinSketch;
+    pub use distinct::BilevelDistinct;
+    pub use hll::{HyperLogLog, MAX_PRECISION, MIN_PRECISION};
+
+    #[cfg(test)]
+    pub mod tests;
+}
+
+/// Implementations that keep the pairs ordered by group key and then by
+/// aggregation key, where both keys are ordered types.
+/// 
+/// # Examples
+/// ```
+/// use bilevel_aggregator::ordered::BilevelMap;
+/// 
+/// let mut map = BilevelMap::<&str, usize, usize>::new();
+/// *map.add_or_get("2024-01-02", 2) += 1;
+/// *map.add_or_get("2024-01-01", 1) += 1;
+/// *map.add_or_get("2024-01-03", 2) += 1;
+/// *map.add_or_get("2024-01-02", 2) += 1;
+/// for (g, k, v) in map.range("2024-01-02"..) {
+///     println!("{}, {}, {}", g, k, v)
+/// }
+/// ```
+/// The results will be listed in order, starting at the second day:
+/// 
+/// 2024-01-02, 2, 2
+/// 2024-01-03, 2, 1
+/// 
+#[cfg(feature = "ordered")]
+pub mod ordered {
+    mod map;
+    mod set;
+
+    pub use map::BilevelMap;
+    pub use set::BilevelSet;
+
+    #[cfg(test)]
+    pub mod tests;
+}
+
+/// Implementations that keep the pairs in the order they were first found.
+/// 
+/// # Examples
+/// ```
+/// use bilevel_aggregator::indexed::BilevelSet;
+/// 
+/// let mut set = BilevelSet::new();
+/// set.insert("b", 2);
+/// set.insert("a", 1);
+/// set.insert("b", 1);
+/// set.insert("b", 2);
+/// for (g, k) in set.iter() {
+///     println!("{}, {}", g, k)
+/// }
+/// ```
+/// The results will be de-duplicated, and grouped by g, in the order
+/// found:
+/// 
+/// b, 2
+/// b, 1
+/// a, 1
+/// 
+#[cfg(feature = "indexed")]
+pub mod indexed {
+    mod map;
+    mod set;
+
+    pub use map::BilevelMap;
+    pub use set::BilevelSet;
+
+    #[cfg(test)]
+    pub mod tests;
+}
+
+/// Implementations that many threads can add to at once, with the
+/// groups divided between separately locked shards. As in the core
+/// collections, the keys of each small group are held inline.
+/// 
+/// The collections of the other modules are Send and Sync whenever their
+/// key and payload types are, so they can be moved into, or read from,
+/// worker threads, but must be locked to be added to by several threads.
+/// 
+/// # Examples
+/// ```
+/// use bilevel_aggregator::sync::BilevelMap;
+/// use std::thread;
+/// 
+/// let map = BilevelMap::<&str, u32, usize>::new();
+/// thread::scope(|s| {
+///     for t in 0..4 {
+///         let map = &map;
+///         s.spawn(move || map.add_or_get("requests", t % 2, |v| *v += 1));
+///     }
+/// });
+/// assert_eq!(map.get(&"requests", &0, |v| *v), Some(2));
+/// ```
+/// 
+/// Where the payloads are counts, BilevelCounter lets pairs already
+/// present be counted without waiting for other threads.
+/// ```
+/// use bilevel_aggregator::sync::BilevelCounter;
+/// use std::thread;
+/// 
+/// let counter = BilevelCounter::new();
+/// thread::scope(|s| {
+///     for t in 0..4 {
+///         let counter = &counter;
+///         s.spawn(move || counter.increment("requests", t % 2));
+///     }
+/// });
+/// assert_eq!(counter.get(&"requests", &0), Some(2));
+/// ```
+/// 
+/// Where a map must be read while it is added to, such as by a dashboard
+/// polling a live aggregator, SnapshotMap hands out snapshots that share
+/// its groups, copying a group only when it changes while shared.
+/// ```
+/// use bilevel_aggregator::sync::SnapshotMap;
+/// 
+/// let map = SnapshotMap::<&str, u32, usize>::new();
+/// map.add_or_get("requests", 200, |v| *v += 1);
+/// let snapshot = map.snapshot();
+/// map.add_or_get("requests", 200, |v| *v += 1);
+/// assert_eq!(snapshot.get(&"requests", &200), Some(&1));
+/// assert_eq!(map.snapshot().get(&"requests", &200), Some(&2));
+/// ```
+#[cfg(feature = "sync")]
+pub mod sync {
+    mod counter;
+    mod cow;
+    mod map;
+
+    pub use counter::BilevelCounter;
+    pub use cow::{MapSnapshot, SnapshotMap};
+    pub use map::BilevelMap;
+
+    #[cfg(test)]
+    pub mod tests;
+}
+
+/// A configurable pipeline that reads the rows of a delimited source in
+/// chunks, optionally groups them into windows of time, aggregates them in
+/// parallel, and writes the results to CSV, in the Prometheus text format
+/// or, with the parquet feature, to Parquet.
+/// 
+/// # Examples
+/// ```
+/// use bilevel_aggregator::pipeline::{Pipeline, PipelineConfig, write_csv};
+/// 
+/// let log = "web1,200\nweb1,500\nweb1,200\n";
+/// let pipeline = Pipeline::new(PipelineConfig::default());
+/// let results = pipeline.run(log.as_bytes()).unwrap();
+/// write_csv(&results, std::io::stdout()).unwrap();
+/// ```
+/// The rows are counted by host and status:
+/// 
+/// group,key,value
+/// web1,200,2
+/// web1,500,1
+/// 
+#[cfg(feature = "pipeline")]
+pub mod pipeline {
+    mod config;
+    mod results;
+    mod run;
+    mod sink;
+
+    pub use config::{PipelineConfig, PipelineError, Window};
+    pub use results::PipelineResults;
+    pub use run::Pipeline;
+    pub use sink::{write_csv, write_prometheus};
+    #[cfg(feature = "parquet")]
+    pub use sink::write_parquet;
+
+    #[cfg(test)]
+    pub mod tests;
+}
+
 /// The capacity dimensions of a BilateralSet of BilateralTree.
+/// 
+/// The default is the capacity a new collection starts with: no groups
+/// or aggregation keys, and a few items for each new group key found.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
 pub struct Capacity {
     /// The number of groups to allocate space for.
     pub groups: usize,
@@ -199,9 +865,161 @@ pub struct Capacity {
     pub agg_keys: usize,
 }
 
-#[cfg(any(feature = "hybrid", feature = "borrow"))]
+impl Capacity {
+    /// Start building a capacity, from the default for the dimensions
+    /// left unspecified.
+    pub fn builder() -> CapacityBuilder {
+        CapacityBuilder { capacity: Self::default() }
+    }
+}
+
+impl Default for Capacity {
+    fn default() -> Self {
+        Self { groups: 0, per_group: 4, agg_keys: 0 }
+    }
+}
+
+/// Builds a Capacity a dimension at a time.
+#[derive(Clone, Copy, Debug)]
+pub struct CapacityBuilder {
+    capacity: Capacity,
+}
+
+impl CapacityBuilder {
+    /// Set the number of groups to allocate space for.
+    pub fn groups(mut self, groups: usize) -> Self {
+        self.capacity.groups = groups;
+        self
+    }
+
+    /// Set the number of items to allocate capacity for when a new group
+    /// key is found.
+    pub fn per_group(mut self, per_group: usize) -> Self {
+        self.capacity.per_group = per_group;
+        self
+    }
+
+    /// Set the number of distinct aggregation keys to allocate space for.
+    pub fn agg_keys(mut self, agg_keys: usize) -> Self {
+        self.capacity.agg_keys = agg_keys;
+        self
+    }
+
+    /// Get the capacity built.
+    pub fn build(self) -> Capacity {
+        self.capacity
+    }
+}
+
+#[cfg(any(feature = "core", feature = "sync", feature = "approx"))]
 fn hash<T: Hash + ?Sized>(t: &T) -> u64 {
     let mut s = DefaultHasher::new();
     t.hash(&mut s);
     s.finish()
-}
\ No newline at end of file
+}
+
+/// Estimate the memory allocated for a hash table of the specified
+/// capacity holding entries of type E.
+#[cfg(feature = "core")]
+fn table_size<E>(capacity: usize) -> usize {
+    // Allow a control byte per entry, as the standard tables use.
+    capacity * (std::mem::size_of::<E>() + 1)
+}
+
+/// Combine the hashes of the items of a group, independently of the
+/// order in which they are listed.
+#[cfg(feature = "core")]
+fn fingerprint<T: Hash>(items: impl Iterator<Item = T>) -> u64 {
+    items.fold(0, |acc, t| acc.wrapping_add(hash(&t)))
+}
+
+/// Partition groups into classes of groups with identical contents.
+/// 
+/// Groups are first bucketed by fingerprint, so the full comparison is
+/// only made between groups whose fingerprints collide.
+/// Only classes with more than one member are returned.
+#[cfg(feature = "core")]
+fn equivalence_classes<G, T>(
+    groups: impl Iterator<Item = (G, T)>,
+    fingerprint: impl Fn(&T) -> u64,
+    eq: impl Fn(&T, &T) -> bool,
+) -> Vec<Vec<G>> {
+    let mut buckets: HashMap<u64, Vec<(T, Vec<G>)>> = HashMap::new();
+    for (g, t) in groups {
+        let bucket = buckets.entry(fingerprint(&t)).or_default();
+        match bucket.iter_mut().find(|(r, _)| eq(r, &t)) {
+            Some((_, class)) => class.push(g),
+            None => bucket.push((t, vec![g])),
+        }
+    }
+    buckets.into_values()
+        .flat_map(|bucket| bucket.into_iter().map(|(_, class)| class))
+        .filter(|class| class.len() > 1)
+        .collect()
+}
+/// Count the sizes falling into each bucket of a histogram.
+/// 
+/// buckets: the greatest size counted in each bucket, in ascending order.
+///     A final bucket counts the sizes greater than the last of these.
+#[cfg(feature = "core")]
+fn size_histogram(sizes: impl Iterator<Item = usize>, buckets: &[usize]) -> Vec<usize> {
+    let mut counts = vec![0; buckets.len() + 1];
+    for size in sizes {
+        counts[buckets.partition_point(|&b| b < size)] += 1;
+    }
+    counts
+}
+
+/// An item ranked by a value computed from it, ordered by that value alone.
+#[cfg(feature = "core")]
+struct Ranked<B, T>(B, T);
+
+#[cfg(feature = "core")]
+impl<B: Ord, T> PartialEq for Ranked<B, T> {
+    fn eq(&self, other: &Self) -> bool {
+        self.0 == other.0
+    }
+}
+
+#[cfg(feature = "core")]
+impl<B: Ord, T> Eq for Ranked<B, T> {}
+
+#[cfg(feature = "core")]
+impl<B: Ord, T> PartialOrd for Ranked<B, T> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+#[cfg(feature = "core")]
+impl<B: Ord, T> Ord for Ranked<B, T> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.0.cmp(&other.0)
+    }
+}
+
+/// Select the n items with the greatest values computed by the specified
+/// function, greatest first.
+/// 
+/// Only n items are kept at once, in a heap whose least item is replaced
+/// by each greater item found, so the items are never sorted in full.
+#[cfg(feature = "core")]
+fn top_n<T, B: Ord>(items: impl Iterator<Item = T>, n: usize, mut by: impl FnMut(&T) -> B) -> Vec<T> {
+    use std::{cmp::Reverse, collections::BinaryHeap};
+    if n == 0 {
+        return Vec::new();
+    }
+    let mut heap = BinaryHeap::with_capacity(n);
+    for t in items {
+        let b = by(&t);
+        if heap.len() < n {
+            heap.push(Reverse(Ranked(b, t)));
+        } else if let Some(mut least) = heap.peek_mut() {
+            if b > least.0.0 {
+                *least = Reverse(Ranked(b, t));
+            }
+        }
+    }
+    // The heap is ordered in reverse, so its ascending order is greatest first.
+    heap.into_sorted_vec().into_iter().map(|Reverse(Ranked(_, t))| t).collect()
+}
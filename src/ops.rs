@@ -0,0 +1,83 @@
+/// The operations shared by the sets of the core, copy, hybrid and borrow
+/// modules, so that code such as a report writer or a merger can be
+/// written once for any of them.
+///
+/// Keys are given by value, to be stored if new, and listed by reference,
+/// whether the set returns them by value or by reference itself.
+pub trait BilevelSetOps {
+    /// The type of the group key.
+    type Group;
+    /// The type of the aggregation key.
+    type Key;
+
+    /// Insert a key pair, if not already present.
+    ///
+    /// Return false if the pair was already present, otherwise true.
+    fn insert_pair(&mut self, g: Self::Group, k: Self::Key) -> bool;
+
+    /// List the pairs currently in the collection, grouped by g.
+    fn iter_pairs(&self) -> impl Iterator<Item = (&Self::Group, &Self::Key)>;
+
+    /// List the group keys currently in the collection, with the number
+    /// of aggregation keys in each group.
+    fn group_sizes(&self) -> impl Iterator<Item = (&Self::Group, usize)>;
+
+    /// Count the pairs of the specified group, which is 0 if the group is
+    /// not present.
+    fn group_len(&self, g: &Self::Group) -> usize;
+
+    /// Count the groups currently in the collection.
+    fn group_count(&self) -> usize;
+
+    /// Count the pairs currently in the collection.
+    fn len(&self) -> usize;
+
+    /// Check whether the collection holds no pairs, although it may hold
+    /// empty groups.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
+
+/// The operations shared by the maps of the core, copy, hybrid and borrow
+/// modules, so that code such as a report writer or a merger can be
+/// written once for any of them.
+///
+/// Keys are given by value, to be stored if new, and listed by reference,
+/// whether the map returns them by value or by reference itself.
+pub trait BilevelMapOps {
+    /// The type of the group key.
+    type Group;
+    /// The type of the aggregation key.
+    type Key;
+    /// The type of the payload.
+    type Value;
+
+    /// Get a mutable reference to the payload for the specified key pair,
+    /// inserting a new payload if the pair is not present.
+    fn add_or_get_pair(&mut self, g: Self::Group, k: Self::Key) -> &mut Self::Value;
+
+    /// List the payloads for the pairs currently in the collection,
+    /// grouped by g.
+    fn iter_pairs(&self) -> impl Iterator<Item = (&Self::Group, &Self::Key, &Self::Value)>;
+
+    /// List the group keys currently in the collection, with the number
+    /// of aggregation keys in each group.
+    fn group_sizes(&self) -> impl Iterator<Item = (&Self::Group, usize)>;
+
+    /// Count the pairs of the specified group, which is 0 if the group is
+    /// not present.
+    fn group_len(&self, g: &Self::Group) -> usize;
+
+    /// Count the groups currently in the collection.
+    fn group_count(&self) -> usize;
+
+    /// Count the pairs currently in the collection.
+    fn len(&self) -> usize;
+
+    /// Check whether the collection holds no pairs, although it may hold
+    /// empty groups.
+    fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+}
@@ -0,0 +1,166 @@
+use std::{collections::{BTreeMap, btree_map::Entry}, ops::RangeBounds};
+
+/// A collection of distinct pairs (g, k) grouped by g, kept in order,
+/// with a payload associated with each pair.
+/// 
+/// As pairs are found, they are added if not already present.
+/// When the collection is iterated over, the pairs are listed by group,
+/// in order of g and then of k.
+/// 
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// V is the type of the payload.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BilevelMap<G, K, V> {
+    data: BTreeMap<G, BTreeMap<K, V>>,
+}
+
+impl<G: Ord, K: Ord, V: Default> BilevelMap<G, K, V> {
+    /// Create a new collection.
+    pub fn new() -> Self {
+        Self { data: BTreeMap::new() }
+    }
+
+    /// Get a mutable reference to the payload for the specified key pair.
+    /// 
+    /// If the key pair is currently not present, the default payload is inserted.
+    pub fn add_or_get(&mut self, g: G, k: K) -> &mut V {
+        self.data.entry(g)
+            .or_default()
+            .entry(k)
+            .or_default()
+    }
+
+    /// List the payloads for the pairs currently in the collection,
+    /// without consuming the collection or the payloads.
+    /// 
+    /// Pairs are listed in order, and can be listed from the last with
+    /// rev.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&G, &K, &V)> {
+        self.data.iter()
+            .flat_map(|(g, inner)| inner.iter().map(move |(k, v)| (g, k, v)))
+    }
+
+    /// List the groups in order, with the aggregation keys of each, in
+    /// order, and the payload of each.
+    /// 
+    /// Both can be listed from the last with rev, such as to take the
+    /// last few groups without listing the others.
+    pub fn iter_groups(&self) -> impl DoubleEndedIterator<Item = (&G, impl DoubleEndedIterator<Item = (&K, &V)>)> {
+        self.data.iter().map(|(g, inner)| (g, inner.iter()))
+    }
+
+    /// List the payloads for the pairs whose group keys fall within the
+    /// specified range, without consuming the collection or the payloads.
+    /// 
+    /// Pairs are listed in order, and can be listed from the last with
+    /// rev.
+    pub fn range<R: RangeBounds<G>>(&self, range: R) -> impl DoubleEndedIterator<Item = (&G, &K, &V)> {
+        self.data.range(range)
+            .flat_map(|(g, inner)| inner.iter().map(move |(k, v)| (g, k, v)))
+    }
+}
+
+impl<G: Ord, K: Ord, V: Default> Default for BilevelMap<G, K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: Ord + Clone, K: Ord, V: Default> BilevelMap<G, K, V> {
+    /// List and consume the payloads for the pairs in the collection,
+    /// consuming the collection.
+    /// 
+    /// Pairs are listed in order, and can be listed from the last with
+    /// rev.
+    pub fn into_iter(self) -> impl DoubleEndedIterator<Item = (G, K, V)> {
+        self.data.into_iter()
+            .flat_map(|(g, inner)| inner.into_iter().map(move |(k, v)| (g.clone(), k, v)))
+    }
+}
+
+impl<G: Ord, K: Ord, V> BilevelMap<G, K, V> {
+    /// Add rows of key pairs and payloads to the collection.
+    /// 
+    /// combine: folds the payload of a row into the payload already present
+    ///     when the collection already holds its pair, including a pair
+    ///     found earlier in the same rows.
+    pub fn extend_with<F>(&mut self, rows: impl IntoIterator<Item = (G, K, V)>, mut combine: F)
+    where
+        F: FnMut(&mut V, V),
+    {
+        for (g, k, v) in rows {
+            match self.data.entry(g).or_default().entry(k) {
+                Entry::Occupied(mut e) => combine(e.get_mut(), v),
+                Entry::Vacant(e) => { e.insert(v); },
+            }
+        }
+    }
+}
+
+impl<G, K, V> std::fmt::Debug for BilevelMap<G, K, V>
+where
+    G: std::fmt::Debug,
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+{
+    /// Format the collection as a map from each group key to a map of its
+    /// aggregation keys and payloads, listing only the first few groups,
+    /// and keys of each group, of a large collection.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::debug::debug_map(f, self.data.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<G, K, V> serde::Serialize for BilevelMap<G, K, V>
+where
+    G: serde::Serialize,
+    K: serde::Serialize,
+    V: serde::Serialize,
+{
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.data.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, G, K, V> serde::Deserialize<'de> for BilevelMap<G, K, V>
+where
+    G: Ord + serde::Deserialize<'de>,
+    K: Ord + serde::Deserialize<'de>,
+    V: serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        BTreeMap::deserialize(deserializer).map(|data| Self { data })
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<G: Ord + Send + Sync, K: Ord + Send + Sync, V: Default> BilevelMap<G, K, V> {
+    /// List the pairs currently in the collection in parallel, splitting
+    /// the work between threads by group.
+    /// 
+    /// Pairs are listed in order if collected into an ordered collection.
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (&G, &K, &V)>
+    where
+        V: Sync,
+    {
+        use rayon::prelude::*;
+        self.data.par_iter()
+            .flat_map_iter(|(g, inner)| inner.iter().map(move |(k, v)| (g, k, v)))
+    }
+
+    /// List the payloads for the pairs currently in the collection in
+    /// parallel, for modification, splitting the work between threads
+    /// by group.
+    pub fn par_iter_mut(&mut self) -> impl rayon::iter::ParallelIterator<Item = (&G, &K, &mut V)>
+    where
+        V: Send,
+    {
+        use rayon::prelude::*;
+        self.data.par_iter_mut()
+            .flat_map_iter(|(g, inner)| inner.iter_mut().map(move |(k, v)| (g, k, v)))
+    }
+}
+
@@ -0,0 +1,119 @@
+use std::{collections::{BTreeMap, BTreeSet}, ops::RangeBounds};
+
+/// A collection of distinct pairs (g, k) grouped by g, kept in order.
+/// 
+/// As pairs are found, they are added if not already present.
+/// When the collection is iterated over, the pairs are listed by group,
+/// in order of g and then of k.
+/// 
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BilevelSet<G, K> {
+    data: BTreeMap<G, BTreeSet<K>>,
+}
+
+impl<G: Ord, K: Ord> BilevelSet<G, K> {
+    /// Create a new collection.
+    pub fn new() -> Self {
+        Self { data: BTreeMap::new() }
+    }
+
+    /// Insert a key pair found into the collection.
+    /// 
+    /// g: the group key.
+    /// k: the remaining key.
+    /// 
+    /// Return false if the key was already present, otherwise true.
+    pub fn insert(&mut self, g: G, k: K) -> bool {
+        self.data.entry(g)
+            .or_default()
+            .insert(k)
+    }
+
+    /// List the pairs currently in the collection without consuming
+    /// the collection.
+    /// 
+    /// Pairs are listed in order, and can be listed from the last with
+    /// rev.
+    pub fn iter(&self) -> impl DoubleEndedIterator<Item = (&G, &K)> {
+        self.data.iter()
+            .flat_map(|(g, inner)| inner.iter().map(move |k| (g, k)))
+    }
+
+    /// List the groups in order, with the aggregation keys of each, in
+    /// order.
+    /// 
+    /// Both can be listed from the last with rev, such as to take the
+    /// last few groups without listing the others.
+    pub fn iter_groups(&self) -> impl DoubleEndedIterator<Item = (&G, impl DoubleEndedIterator<Item = &K>)> {
+        self.data.iter().map(|(g, inner)| (g, inner.iter()))
+    }
+
+    /// List the pairs whose group keys fall within the specified range,
+    /// without consuming the collection.
+    /// 
+    /// Pairs are listed in order, and can be listed from the last with
+    /// rev.
+    pub fn range<R: RangeBounds<G>>(&self, range: R) -> impl DoubleEndedIterator<Item = (&G, &K)> {
+        self.data.range(range)
+            .flat_map(|(g, inner)| inner.iter().map(move |k| (g, k)))
+    }
+}
+
+impl<G: Ord, K: Ord> Default for BilevelSet<G, K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: Ord + Clone, K: Ord> BilevelSet<G, K> {
+    /// List the pairs in the collection and consume the collection.
+    /// 
+    /// Pairs are listed in order, and can be listed from the last with
+    /// rev.
+    pub fn into_iter(self) -> impl DoubleEndedIterator<Item = (G, K)> {
+        self.data.into_iter()
+            .flat_map(|(g, inner)| inner.into_iter().map(move |k| (g.clone(), k)))
+    }
+}
+
+impl<G: std::fmt::Debug, K: std::fmt::Debug> std::fmt::Debug for BilevelSet<G, K> {
+    /// Format the collection as a map from each group key to the set of
+    /// its aggregation keys, listing only the first few groups, and keys
+    /// of each group, of a large collection.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::debug::debug_set(f, self.data.iter())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<G: serde::Serialize, K: serde::Serialize> serde::Serialize for BilevelSet<G, K> {
+    fn serialize<S: serde::Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        self.data.serialize(serializer)
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, G, K> serde::Deserialize<'de> for BilevelSet<G, K>
+where
+    G: Ord + serde::Deserialize<'de>,
+    K: Ord + serde::Deserialize<'de>,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        BTreeMap::deserialize(deserializer).map(|data| Self { data })
+    }
+}
+
+#[cfg(feature = "rayon")]
+impl<G: Ord + Send + Sync, K: Ord + Send + Sync> BilevelSet<G, K> {
+    /// List the pairs currently in the collection in parallel, splitting
+    /// the work between threads by group.
+    /// 
+    /// Pairs are listed in order if collected into an ordered collection.
+    pub fn par_iter(&self) -> impl rayon::iter::ParallelIterator<Item = (&G, &K)> {
+        use rayon::prelude::*;
+        self.data.par_iter()
+            .flat_map_iter(|(g, inner)| inner.iter().map(move |k| (g, k)))
+    }
+}
@@ -0,0 +1,85 @@
+use super::*;
+
+#[test]
+pub fn test_set() {
+    let mut set = BilevelSet::new();
+    for (i, (g, k)) in [(3, "b"), (1, "b"), (2, "a"), (1, "a"), (3, "b")].into_iter().enumerate() {
+        // Verify that insertion returns false on duplicates. otherwise true.
+        assert_eq!(set.insert(g, k), i != 4);
+    }
+    // Verify that the pairs are listed in order.
+    assert!(set.iter().eq([(&1, &"a"), (&1, &"b"), (&2, &"a"), (&3, &"b")]));
+    assert!(set.range(2..).eq([(&2, &"a"), (&3, &"b")]));
+    assert!(set.into_iter().eq([(1, "a"), (1, "b"), (2, "a"), (3, "b")]));
+}
+
+#[test]
+pub fn test_map() {
+    let mut map: BilevelMap<u32, &str, u32> = BilevelMap::new();
+    for (g, k) in [(3, "b"), (1, "b"), (2, "a"), (1, "a"), (3, "b")] {
+        *map.add_or_get(g, k) += 1;
+    }
+    // Verify that the pairs are listed in order, with the correct counts.
+    assert!(map.iter().eq([(&1, &"a", &1), (&1, &"b", &1), (&2, &"a", &1), (&3, &"b", &2)]));
+    assert!(map.range(..=2).eq([(&1, &"a", &1), (&1, &"b", &1), (&2, &"a", &1)]));
+    assert!(map.into_iter().eq([(1, "a", 1), (1, "b", 1), (2, "a", 1), (3, "b", 2)]));
+}
+
+#[test]
+pub fn test_send_sync() {
+    // Fails to compile if the collections cannot be shared with, or moved
+    // into, worker threads.
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<BilevelSet<String, String>>();
+    assert_send_sync::<BilevelMap<String, String, u64>>();
+}
+
+#[cfg(feature = "serde")]
+#[test]
+pub fn test_serde() {
+    let mut map: BilevelMap<u32, String, u32> = BilevelMap::new();
+    *map.add_or_get(2, "b".to_owned()) += 1;
+    *map.add_or_get(1, "a".to_owned()) += 2;
+    let json = serde_json::to_string(&map).unwrap();
+    assert_eq!(json, r#"{"1":{"a":2},"2":{"b":1}}"#);
+    let set: BilevelSet<u32, String> = serde_json::from_str(r#"{"1":["b","a"]}"#).unwrap();
+    assert_eq!(serde_json::to_string(&set).unwrap(), r#"{"1":["a","b"]}"#);
+}
+
+#[test]
+pub fn test_extend_with() {
+    let mut map: BilevelMap<u8, u8, u32> = BilevelMap::new();
+    *map.add_or_get(2, 1) += 1;
+    map.extend_with([(2, 1, 4), (1, 1, 2), (1, 1, 3)], |a, b| *a += b);
+    assert!(map.iter().map(|(g, k, v)| (*g, *k, *v)).eq([(1, 1, 5), (2, 1, 5)]));
+}
+
+#[test]
+pub fn test_debug() {
+    let mut set = BilevelSet::new();
+    set.insert(2, "b");
+    set.insert(1, "b");
+    set.insert(1, "a");
+    assert_eq!(format!("{:?}", set), r#"{1: {"a", "b"}, 2: {"b"}}"#);
+}
+
+#[test]
+pub fn test_rev() {
+    let mut map: BilevelMap<&str, u32, u32> = BilevelMap::new();
+    for day in ["2024-01-01", "2024-01-02", "2024-01-03", "2024-01-04"] {
+        *map.add_or_get(day, 1) += 1;
+        *map.add_or_get(day, 2) += 2;
+    }
+    // The last two days, latest first, without listing the others.
+    let last: Vec<_> = map.iter_groups().rev().take(2).map(|(g, pairs)| (*g, pairs.count())).collect();
+    assert_eq!(last, [("2024-01-04", 2), ("2024-01-03", 2)]);
+    assert_eq!(map.iter().next_back(), Some((&"2024-01-04", &2, &2)));
+    assert!(map.range(.."2024-01-02").rev().eq([(&"2024-01-01", &2, &2), (&"2024-01-01", &1, &1)]));
+    let mut set = BilevelSet::new();
+    for (g, k) in [(1, "a"), (2, "b"), (2, "c")] {
+        set.insert(g, k);
+    }
+    let (g, keys) = set.iter_groups().next_back().unwrap();
+    assert_eq!((g, keys.rev().collect::<Vec<_>>()), (&2, vec![&"c", &"b"]));
+    assert!(set.into_iter().rev().eq([(2, "c"), (2, "b"), (1, "a")]));
+}
@@ -0,0 +1,64 @@
+use rayon::iter::{IntoParallelIterator, ParallelIterator};
+
+/// A collection that can be built in shards, one per worker, and the
+/// shards merged at the end.
+pub trait Shard: Sized {
+    /// The type of the payload.
+    type Payload;
+
+    /// Merge another shard into this one, consuming it.
+    ///
+    /// combine: folds a payload from the other shard into the payload
+    ///     already present when both shards hold the same pair.
+    fn merge_shard<F>(&mut self, other: Self, combine: F)
+    where
+        F: FnMut(&mut Self::Payload, Self::Payload);
+}
+
+/// Reduces a parallel iterator of rows into a single collection, without
+/// a lock on the collection.
+///
+/// Each piece of work that rayon hands to a worker thread folds its rows
+/// into a collection of its own, and the collections are merged pairwise
+/// as the pieces of work complete.
+///
+/// C is the type of the collection.
+/// F is the type of the function folding a row into a collection.
+/// M is the type of the function combining payloads when merging.
+pub struct ShardedCollector<C, F, M> {
+    constructor: fn() -> C,
+    fold: F,
+    combine: M,
+}
+
+impl<C: Shard + Send, F, M> ShardedCollector<C, F, M> {
+    /// Create a new collector.
+    ///
+    /// constructor: creates the collection for each worker, such as
+    ///     BilevelMap::new.
+    /// fold: folds a row into a collection.
+    /// combine: folds a payload from one collection into the payload
+    ///     already present in another, when both hold the same pair.
+    pub fn new(constructor: fn() -> C, fold: F, combine: M) -> Self {
+        Self { constructor, fold, combine }
+    }
+
+    /// Reduce the rows into a single collection.
+    pub fn collect<T, I>(&self, rows: I) -> C
+    where
+        I: IntoParallelIterator<Item = T>,
+        F: Fn(&mut C, T) + Sync,
+        M: Fn(&mut C::Payload, C::Payload) + Sync,
+    {
+        rows.into_par_iter()
+            .fold(self.constructor, |mut shard, row| {
+                (self.fold)(&mut shard, row);
+                shard
+            })
+            .reduce_with(|mut a, b| {
+                a.merge_shard(b, &self.combine);
+                a
+            })
+            .unwrap_or_else(self.constructor)
+    }
+}
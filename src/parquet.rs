@@ -0,0 +1,51 @@
+use std::{fs::File, path::Path};
+use parquet::{arrow::ArrowWriter, errors::ParquetError, file::properties::WriterProperties};
+use crate::{ArrowValue, arrow};
+
+/// How write_parquet lays out its file.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct ParquetOptions {
+    /// Whether to start a new row group for each group, so that a reader
+    /// can skip to a group by the statistics of the row groups.
+    pub row_group_per_group: bool,
+    /// The greatest number of rows in a row group. A group with more rows
+    /// is split across row groups.
+    pub max_row_group_size: usize,
+}
+
+impl Default for ParquetOptions {
+    /// Write row groups of up to a million rows, regardless of groups.
+    fn default() -> Self {
+        Self { row_group_per_group: false, max_row_group_size: 1024 * 1024 }
+    }
+}
+
+/// Write the rows of each group listed to a Parquet file, with group, key
+/// and value columns.
+pub(crate) fn write_groups<'a, G, K, V, I>(
+    path: &Path,
+    options: &ParquetOptions,
+    groups: impl Iterator<Item = I>,
+) -> Result<(), ParquetError>
+where
+    G: ArrowValue + 'a,
+    K: ArrowValue + 'a,
+    V: ArrowValue + 'a,
+    I: Iterator<Item = (&'a G, &'a K, &'a V)>,
+{
+    let properties = WriterProperties::builder()
+        .set_max_row_group_size(options.max_row_group_size.max(1))
+        .build();
+    let mut writer = ArrowWriter::try_new(File::create(path)?, arrow::schema::<G, K, V>(), Some(properties))?;
+    if options.row_group_per_group {
+        for rows in groups {
+            writer.write(&arrow::record_batch(rows)?)?;
+            // Close the row group, so that the next group starts a new one.
+            writer.flush()?;
+        }
+    } else {
+        writer.write(&arrow::record_batch(groups.flatten())?)?;
+    }
+    writer.close()?;
+    Ok(())
+}
@@ -0,0 +1,140 @@
+use std::{cmp::Ordering, ffi::{OsStr, OsString}, path::{Path, PathBuf}};
+use crate::{Capacity, core::{self, Interned}};
+
+/// A collection of distinct pairs (g, k) grouped by g, with a payload
+/// associated with each pair, where the group key is a path and the
+/// aggregation key is an OS string, such as a file extension.
+/// 
+/// As pairs are found, they are added if not already present.
+/// When the collection is iterated over, the pairs are listed by group.
+/// 
+/// The keys are kept as PathBuf and OsString, so paths that are not
+/// valid UTF-8 are kept exactly.
+/// 
+/// V is the type of the payload.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BilevelMap<V> {
+    /// Keep a single copy of each aggregation key, rather than one in
+    /// each group where it appears.
+    inner: core::BilevelMap<PathBuf, OsString, V, Interned<OsString>>,
+}
+
+impl<V: Default> BilevelMap<V> {
+    /// Create a new collection, with the default payload for each new pair.
+    /// 
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    pub fn new() -> Self {
+        Self { inner: core::BilevelMap::new() }
+    }
+
+    /// Create a new collection with the specified capacity, with the
+    /// default payload for each new pair.
+    pub fn with_capacity(capacity: Capacity) -> Self {
+        Self { inner: core::BilevelMap::with_capacity(capacity) }
+    }
+}
+
+impl<V: Default> Default for BilevelMap<V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<V> BilevelMap<V> {
+    /// Create a new collection.
+    /// 
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    /// 
+    /// constructor: A constructor for the payload of each new pair.
+    pub fn new_with(constructor: impl Fn() -> V + Send + Sync + 'static) -> Self {
+        Self { inner: core::BilevelMap::new_with(constructor) }
+    }
+
+    /// Create a new collection with the specified capacity.
+    /// 
+    /// constructor: A constructor for the payload of each new pair.
+    pub fn with_capacity_and(capacity: Capacity, constructor: impl Fn() -> V + Send + Sync + 'static) -> Self {
+        Self { inner: core::BilevelMap::with_capacity_and(capacity, constructor) }
+    }
+
+    /// Get a mutable reference to the payload for the specified key pair.
+    /// 
+    /// If the key pair is currently not present, a new payload is inserted.
+    pub fn add_or_get(&mut self, g: &Path, k: &OsStr) -> &mut V {
+        self.inner.add_or_get(g, k)
+    }
+
+    /// Get a mutable reference to the payload for the directory and
+    /// extension of a file.
+    /// 
+    /// A file with no directory is grouped under the empty path, and a
+    /// file with no extension is listed with the empty extension.
+    pub fn add_or_get_file(&mut self, file: &Path) -> &mut V {
+        let (g, k) = super::set::split_file(file);
+        self.add_or_get(g, k)
+    }
+
+    /// List the payloads for the pairs currently in the collection,
+    /// without consuming the collection or the payloads.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &OsStr, &V)> {
+        self.inner.iter().map(|(g, k, v)| (g.as_path(), k.as_os_str(), v))
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&Path, &OsStr, &V)> {
+        self.iter_sorted_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)))
+    }
+
+    /// List the pairs currently in the collection, sorted using the
+    /// specified comparison function.
+    pub fn iter_sorted_by<'a, F>(&'a self, compare: F) -> impl Iterator<Item = (&'a Path, &'a OsStr, &'a V)> + 'a
+    where
+        F: FnMut(&(&'a Path, &'a OsStr, &'a V), &(&'a Path, &'a OsStr, &'a V)) -> Ordering + 'a,
+    {
+        let mut pairs: Vec<_> = self.iter().collect();
+        pairs.sort_unstable_by(compare);
+        pairs.into_iter()
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    /// Count the pairs currently in the collection.
+    pub fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+}
+
+impl<V: std::fmt::Debug> std::fmt::Debug for BilevelMap<V> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+#[cfg(feature = "tenant")]
+impl<V> crate::TenantData for BilevelMap<V> {
+    type Item<'a> = (&'a Path, &'a OsStr, &'a V) where Self: 'a;
+
+    fn pairs(&self) -> impl Iterator<Item = (&Path, &OsStr, &V)> {
+        self.iter()
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        crate::TenantData::memory_usage(&self.inner)
+    }
+}
@@ -0,0 +1,127 @@
+use std::{cmp::Ordering, ffi::{OsStr, OsString}, path::{Path, PathBuf}};
+use crate::{Capacity, core::{self, Interned}};
+
+/// A collection of distinct pairs (g, k) grouped by g, where the group
+/// key is a path and the aggregation key is an OS string, such as a file
+/// extension.
+/// 
+/// As pairs are found, they are added if not already present.
+/// When the collection is iterated over, the pairs are listed by group.
+/// 
+/// The keys are kept as PathBuf and OsString, so paths that are not
+/// valid UTF-8 are kept exactly.
+#[derive(Clone, PartialEq, Eq)]
+pub struct BilevelSet {
+    /// Keep a single copy of each aggregation key, rather than one in
+    /// each group where it appears.
+    inner: core::BilevelSet<PathBuf, OsString, Interned<OsString>>,
+}
+
+impl BilevelSet {
+    /// Create a new collection.
+    /// 
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    pub fn new() -> Self {
+        Self { inner: core::BilevelSet::new() }
+    }
+
+    /// Create a new collection with the specified capacity.
+    pub fn with_capacity(capacity: Capacity) -> Self {
+        Self { inner: core::BilevelSet::with_capacity(capacity) }
+    }
+
+    /// Insert a key pair found into the collection.
+    /// 
+    /// g: the group key, such as a directory.
+    /// k: the remaining key, such as a file extension.
+    /// 
+    /// Return false if the key was already present, otherwise true.
+    pub fn insert(&mut self, g: &Path, k: &OsStr) -> bool {
+        self.inner.insert(g, k)
+    }
+
+    /// Insert the directory and extension of a file as a key pair.
+    /// 
+    /// A file with no directory is grouped under the empty path, and a
+    /// file with no extension is listed with the empty extension.
+    /// 
+    /// Return false if the key was already present, otherwise true.
+    pub fn insert_file(&mut self, file: &Path) -> bool {
+        let (g, k) = split_file(file);
+        self.insert(g, k)
+    }
+
+    /// List the pairs currently in the collection without consuming
+    /// the collection.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn iter(&self) -> impl Iterator<Item = (&Path, &OsStr)> {
+        self.inner.iter().map(|(g, k)| (g.as_path(), k.as_os_str()))
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&Path, &OsStr)> {
+        self.iter_sorted_by(Ord::cmp)
+    }
+
+    /// List the pairs currently in the collection, sorted using the
+    /// specified comparison function.
+    pub fn iter_sorted_by<'a, F>(&'a self, compare: F) -> impl Iterator<Item = (&'a Path, &'a OsStr)> + 'a
+    where
+        F: FnMut(&(&'a Path, &'a OsStr), &(&'a Path, &'a OsStr)) -> Ordering + 'a,
+    {
+        let mut pairs: Vec<_> = self.iter().collect();
+        pairs.sort_unstable_by(compare);
+        pairs.into_iter()
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    /// Count the pairs currently in the collection.
+    pub fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+}
+
+impl Default for BilevelSet {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl std::fmt::Debug for BilevelSet {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+#[cfg(feature = "tenant")]
+impl crate::TenantData for BilevelSet {
+    type Item<'a> = (&'a Path, &'a OsStr) where Self: 'a;
+
+    fn pairs(&self) -> impl Iterator<Item = (&Path, &OsStr)> {
+        self.iter()
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        crate::TenantData::memory_usage(&self.inner)
+    }
+}
+
+/// Split a file into its directory and extension.
+pub(super) fn split_file(file: &Path) -> (&Path, &OsStr) {
+    (file.parent().unwrap_or(Path::new("")), file.extension().unwrap_or_default())
+}
@@ -0,0 +1,39 @@
+use std::{ffi::OsStr, path::Path};
+use super::*;
+
+#[test]
+pub fn test_set() {
+    let mut set = BilevelSet::new();
+    for (i, file) in ["src/lib.rs", "src/main.rs", "README.md", "src/a/b", "src/lib.rs"].into_iter().enumerate() {
+        // Files of the same directory and extension are one pair.
+        assert_eq!(set.insert_file(Path::new(file)), i != 1 && i != 4);
+    }
+    assert!(set.insert(Path::new("src"), OsStr::new("toml")));
+    let expected = [("", "md"), ("src", "rs"), ("src", "toml"), ("src/a", "")];
+    assert!(set.iter_sorted().eq(expected.map(|(g, k)| (Path::new(g), OsStr::new(k)))));
+    assert_eq!(set.group_count(), 3);
+}
+
+#[test]
+pub fn test_map() {
+    let mut map: BilevelMap<u64> = BilevelMap::new();
+    for (file, size) in [("a/x.log", 10), ("a/y.log", 20), ("b/z.gz", 5), ("a/w.gz", 1)] {
+        *map.add_or_get_file(Path::new(file)) += size;
+    }
+    let expected = [("a", "gz", 1), ("a", "log", 30), ("b", "gz", 5)];
+    assert!(map.iter_sorted().map(|(g, k, v)| (g, k, *v))
+        .eq(expected.map(|(g, k, v)| (Path::new(g), OsStr::new(k), v))));
+}
+
+#[cfg(unix)]
+#[test]
+pub fn test_non_utf8() {
+    use std::os::unix::ffi::OsStrExt;
+    // Names that are not valid UTF-8 are kept exactly.
+    let name = OsStr::from_bytes(b"f\xffo.d\xfft");
+    let mut map: BilevelMap<u32> = BilevelMap::new();
+    *map.add_or_get_file(&Path::new("dir").join(name)) += 1;
+    let (g, k, _) = map.iter().next().unwrap();
+    assert_eq!(g, Path::new("dir"));
+    assert_eq!(k.as_bytes(), b"d\xfft");
+}
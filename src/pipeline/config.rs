@@ -0,0 +1,101 @@
+use std::{error::Error, fmt, io};
+
+/// How rows are grouped into windows of time.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct Window {
+    /// The field holding the time of each row, as a whole number, such
+    /// as seconds since the epoch.
+    pub field: usize,
+    /// The width of each window, in the units of the time field.
+    pub width: u64,
+}
+
+/// The configuration of a Pipeline.
+/// 
+/// Fields are numbered from zero.
+#[derive(Clone, Debug, PartialEq, Eq)]
+pub struct PipelineConfig {
+    /// The character separating the fields of each row.
+    pub delimiter: char,
+    /// Whether the first line is a header, to be skipped.
+    pub skip_header: bool,
+    /// The fields forming the group key, in order.
+    pub group_fields: Vec<usize>,
+    /// The field forming the aggregation key.
+    pub key_field: usize,
+    /// The field holding a whole number to be summed for each pair, or
+    /// None to count the rows of each pair.
+    pub value_field: Option<usize>,
+    /// How rows are grouped into windows of time, if at all. The groups of
+    /// each window are kept apart in the results.
+    pub window: Option<Window>,
+    /// The number of lines read and parsed at a time, which bounds the
+    /// memory held for the source.
+    pub chunk_size: usize,
+}
+
+impl Default for PipelineConfig {
+    fn default() -> Self {
+        Self {
+            delimiter: ',',
+            skip_header: false,
+            group_fields: vec![0],
+            key_field: 1,
+            value_field: None,
+            window: None,
+            chunk_size: 64 * 1024,
+        }
+    }
+}
+
+/// An error running a pipeline.
+#[derive(Debug)]
+pub enum PipelineError {
+    /// The source or sink failed.
+    Io(io::Error),
+    /// A row of the source could not be parsed.
+    Parse {
+        /// The number of the line holding the row, counting from one.
+        line: usize,
+        /// What was wrong with the row.
+        message: String,
+    },
+    /// The Parquet sink failed.
+    #[cfg(feature = "parquet")]
+    Parquet(parquet::errors::ParquetError),
+}
+
+impl fmt::Display for PipelineError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "pipeline I/O failed: {}", e),
+            Self::Parse { line, message } => write!(f, "line {}: {}", line, message),
+            #[cfg(feature = "parquet")]
+            Self::Parquet(e) => write!(f, "pipeline Parquet sink failed: {}", e),
+        }
+    }
+}
+
+impl Error for PipelineError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Parse { .. } => None,
+            #[cfg(feature = "parquet")]
+            Self::Parquet(e) => Some(e),
+        }
+    }
+}
+
+impl From<io::Error> for PipelineError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+#[cfg(feature = "parquet")]
+impl From<parquet::errors::ParquetError> for PipelineError {
+    fn from(e: parquet::errors::ParquetError) -> Self {
+        Self::Parquet(e)
+    }
+}
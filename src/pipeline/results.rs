@@ -0,0 +1,94 @@
+use crate::core::{BilevelMap, Inline, WindowedMap};
+
+/// The start of the window of a group, and the group key, which together
+/// key the groups of the results. Without windows, every group is in the
+/// window starting at 0.
+type WindowGroup = (u64, String);
+
+/// A row parsed from the source of a pipeline.
+pub(super) struct Row {
+    /// The start of the window holding the row, or 0 without windows.
+    pub window: u64,
+    /// The time of the row, or 0 without windows.
+    pub time: u64,
+    pub group: String,
+    pub key: String,
+    pub value: u64,
+}
+
+/// The results of a Pipeline: the value of each pair of each group, with
+/// the groups of each window of time kept apart if the pipeline has
+/// windows.
+///
+/// The groups are held in a WindowedMap along with the time of the latest
+/// row of each, so that a pipeline fed one source after another, such as
+/// the files of a rotated log, can evict the windows it has finished with
+/// rather than holding every window it has seen.
+pub struct PipelineResults {
+    map: WindowedMap<WindowGroup, String, u64, u64>,
+    windowed: bool,
+}
+
+impl PipelineResults {
+    /// Create empty results.
+    ///
+    /// windowed: whether the groups are kept by window of time.
+    pub fn new(windowed: bool) -> Self {
+        Self { map: WindowedMap::new(), windowed }
+    }
+
+    /// Check whether the groups are kept by window of time.
+    pub fn is_windowed(&self) -> bool {
+        self.windowed
+    }
+
+    /// List the pairs, sorted by window, group key and aggregation key,
+    /// with the start of the window of each, or None without windows.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (Option<u64>, &str, &str, u64)> {
+        let windowed = self.windowed;
+        self.map.get().iter_sorted()
+            .map(move |((window, g), k, &v)| (windowed.then_some(*window), g.as_str(), k.as_str(), v))
+    }
+
+    /// Get the groups, keyed by the start of their window and their group
+    /// key.
+    pub fn get(&self) -> &BilevelMap<WindowGroup, String, u64, Inline> {
+        self.map.get()
+    }
+
+    /// Count the pairs.
+    pub fn len(&self) -> usize {
+        self.map.get().len()
+    }
+
+    /// Check whether there are no pairs.
+    pub fn is_empty(&self) -> bool {
+        self.map.get().is_empty()
+    }
+
+    /// Remove every group whose latest row is earlier than the cutoff, such
+    /// as the groups of the windows ending before it, and return them as
+    /// results of their own, to be written out.
+    pub fn evict_older_than(&mut self, cutoff: u64) -> PipelineResults {
+        let mut evicted = PipelineResults::new(self.windowed);
+        for (g, pairs) in self.map.evict_older_than(cutoff) {
+            let at = g.0;
+            for (k, v) in pairs {
+                *evicted.map.add_or_get_at(&g, &k, at) += v;
+            }
+        }
+        evicted
+    }
+
+    /// Add the value of a row to its pair.
+    pub(super) fn add(&mut self, row: Row) {
+        let Row { window, time, group, key, value } = row;
+        *self.map.add_or_get_at(&(window, group), &key, time) += value;
+    }
+}
+
+impl std::fmt::Debug for PipelineResults {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.map, f)
+    }
+}
@@ -0,0 +1,106 @@
+use std::{fs::File, io::{BufRead, BufReader}, path::Path};
+use rayon::prelude::*;
+use super::{PipelineConfig, PipelineError, PipelineResults, results::Row};
+
+/// Aggregates the rows of a delimited source, such as a CSV file or a
+/// log file, as configured.
+/// 
+/// Each group key is made of the configured group fields, joined by the
+/// delimiter, and each aggregation key is the configured key field. With
+/// windows of time, the groups of each window are kept apart.
+/// The source is read in chunks of lines, and the rows of each chunk are
+/// parsed in parallel, so the memory held for the source is bounded by
+/// the size of a chunk however large the source is.
+pub struct Pipeline {
+    config: PipelineConfig,
+}
+
+impl Pipeline {
+    /// Create a new pipeline with the specified configuration.
+    pub fn new(config: PipelineConfig) -> Self {
+        Self { config }
+    }
+
+    /// Get the configuration of the pipeline.
+    pub fn config(&self) -> &PipelineConfig {
+        &self.config
+    }
+
+    /// Aggregate the rows of the file at the specified path.
+    pub fn run_file(&self, path: impl AsRef<Path>) -> Result<PipelineResults, PipelineError> {
+        self.run(BufReader::new(File::open(path)?))
+    }
+
+    /// Aggregate the rows read from a source.
+    /// 
+    /// Blank lines are ignored.
+    pub fn run(&self, source: impl BufRead) -> Result<PipelineResults, PipelineError> {
+        let mut results = PipelineResults::new(self.config.window.is_some());
+        self.run_into(source, &mut results)?;
+        Ok(results)
+    }
+
+    /// Aggregate the rows read from a source into results, such as those
+    /// of the sources read before.
+    /// 
+    /// Blank lines are ignored. If a row cannot be parsed, the rows of the
+    /// chunks before it have already been added to the results, but none
+    /// of its own chunk.
+    pub fn run_into(&self, source: impl BufRead, results: &mut PipelineResults) -> Result<(), PipelineError> {
+        let chunk_size = self.config.chunk_size.max(1);
+        let skip = usize::from(self.config.skip_header);
+        let mut lines = source.lines().enumerate().skip(skip);
+        let mut chunk = Vec::new();
+        loop {
+            chunk.clear();
+            for (i, line) in lines.by_ref().take(chunk_size) {
+                chunk.push((i + 1, line?));
+            }
+            if chunk.is_empty() {
+                return Ok(());
+            }
+            let rows = chunk.par_iter()
+                .filter(|(_, line)| !line.trim().is_empty())
+                .map(|(n, line)| self.parse(line).map_err(|message| PipelineError::Parse { line: *n, message }))
+                .collect::<Result<Vec<_>, _>>()?;
+            for row in rows {
+                results.add(row);
+            }
+        }
+    }
+
+    /// Split a row into its window, group key, aggregation key and value.
+    fn parse(&self, line: &str) -> Result<Row, String> {
+        let config = &self.config;
+        let fields: Vec<&str> = line.split(config.delimiter).map(str::trim).collect();
+        let field = |i: usize| {
+            fields.get(i).copied()
+                .ok_or_else(|| format!("expected at least {} fields, found {}", i + 1, fields.len()))
+        };
+        let number = |i: usize| {
+            field(i)?.parse::<u64>().map_err(|e| format!("field {}: {}", i, e))
+        };
+        let (window, time) = match &config.window {
+            Some(window) => {
+                let time = number(window.field)?;
+                (time - time % window.width.max(1), time)
+            },
+            None => (0, 0),
+        };
+        let mut group = Vec::with_capacity(config.group_fields.len());
+        for &i in &config.group_fields {
+            group.push(field(i)?);
+        }
+        let value = match config.value_field {
+            Some(i) => number(i)?,
+            None => 1,
+        };
+        Ok(Row {
+            window,
+            time,
+            group: group.join(&config.delimiter.to_string()),
+            key: field(config.key_field)?.to_string(),
+            value,
+        })
+    }
+}
@@ -0,0 +1,80 @@
+use std::io::Write;
+use super::{PipelineError, PipelineResults};
+
+/// Write the results of a pipeline as CSV, with a header, listing the
+/// start of the window if the pipeline has windows, then the group key,
+/// aggregation key and value of each pair, in sorted order.
+pub fn write_csv(results: &PipelineResults, mut writer: impl Write) -> Result<(), PipelineError> {
+    if results.is_windowed() {
+        writeln!(writer, "window,group,key,value")?;
+    } else {
+        writeln!(writer, "group,key,value")?;
+    }
+    for (window, g, k, v) in results.iter_sorted() {
+        if let Some(window) = window {
+            write!(writer, "{},", window)?;
+        }
+        writeln!(writer, "{},{},{}", csv_field(g), csv_field(k), v)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write the results of a pipeline in the Prometheus text format, as a
+/// gauge with the start of the window, if the pipeline has windows, the
+/// group key and the aggregation key as labels.
+pub fn write_prometheus(results: &PipelineResults, metric: &str, mut writer: impl Write) -> Result<(), PipelineError> {
+    writeln!(writer, "# TYPE {} gauge", metric)?;
+    for (window, g, k, v) in results.iter_sorted() {
+        write!(writer, "{}{{", metric)?;
+        if let Some(window) = window {
+            write!(writer, "window=\"{}\",", window)?;
+        }
+        writeln!(writer, "group=\"{}\",key=\"{}\"}} {}", label_value(g), label_value(k), v)?;
+    }
+    writer.flush()?;
+    Ok(())
+}
+
+/// Write the results of a pipeline as Parquet, with a window column if
+/// the pipeline has windows, then group, key and value columns, in
+/// sorted order.
+#[cfg(feature = "parquet")]
+pub fn write_parquet(results: &PipelineResults, writer: impl Write + Send) -> Result<(), PipelineError> {
+    use std::sync::Arc;
+    use arrow_array::{ArrayRef, RecordBatch, StringArray, UInt64Array};
+    use arrow_schema::{DataType, Field, Schema};
+
+    let mut fields = Vec::with_capacity(4);
+    let mut columns: Vec<ArrayRef> = Vec::with_capacity(4);
+    if results.is_windowed() {
+        fields.push(Field::new("window", DataType::UInt64, false));
+        columns.push(Arc::new(UInt64Array::from_iter_values(results.iter_sorted().filter_map(|(w, ..)| w))));
+    }
+    fields.push(Field::new("group", DataType::Utf8, false));
+    columns.push(Arc::new(StringArray::from_iter_values(results.iter_sorted().map(|(_, g, ..)| g))));
+    fields.push(Field::new("key", DataType::Utf8, false));
+    columns.push(Arc::new(StringArray::from_iter_values(results.iter_sorted().map(|(_, _, k, _)| k))));
+    fields.push(Field::new("value", DataType::UInt64, false));
+    columns.push(Arc::new(UInt64Array::from_iter_values(results.iter_sorted().map(|(.., v)| v))));
+    let batch = RecordBatch::try_new(Arc::new(Schema::new(fields)), columns)
+        .map_err(parquet::errors::ParquetError::from)?;
+    let mut writer = parquet::arrow::ArrowWriter::try_new(writer, batch.schema(), None)?;
+    writer.write(&batch)?;
+    writer.close()?;
+    Ok(())
+}
+
+/// Quote a CSV field if it contains a comma, quote or line break.
+fn csv_field(s: &str) -> String {
+    if s.contains([',', '"', '\n', '\r']) {
+        format!("\"{}\"", s.replace('"', "\"\""))
+    } else {
+        s.to_string()
+    }
+}
+
+/// Escape a Prometheus label value.
+fn label_value(s: &str) -> String {
+    s.replace('\\', "\\\\").replace('"', "\\\"").replace('\n', "\\n")
+}
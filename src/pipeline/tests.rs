@@ -0,0 +1,105 @@
+use super::*;
+
+const LOG: &str = "\
+time,host,status
+100,web1,200
+130,web1,500
+170,web2,200
+190,web1,200
+230,web1,200
+";
+
+#[test]
+pub fn test_pipeline() {
+    let pipeline = Pipeline::new(PipelineConfig {
+        skip_header: true,
+        group_fields: vec![1],
+        key_field: 2,
+        window: Some(Window { field: 0, width: 100 }),
+        ..PipelineConfig::default()
+    });
+    let results = pipeline.run(LOG.as_bytes()).unwrap();
+    let mut csv = Vec::new();
+    write_csv(&results, &mut csv).unwrap();
+    assert_eq!(String::from_utf8(csv).unwrap(), "\
+window,group,key,value
+100,web1,200,2
+100,web1,500,1
+100,web2,200,1
+200,web1,200,1
+");
+    let mut prometheus = Vec::new();
+    write_prometheus(&results, "requests", &mut prometheus).unwrap();
+    let prometheus = String::from_utf8(prometheus).unwrap();
+    assert!(prometheus.starts_with("# TYPE requests gauge\n"));
+    assert!(prometheus.contains("requests{window=\"100\",group=\"web1\",key=\"500\"} 1\n"));
+}
+
+#[test]
+pub fn test_pipeline_errors() {
+    let pipeline = Pipeline::new(PipelineConfig {
+        value_field: Some(2),
+        ..PipelineConfig::default()
+    });
+    let results = pipeline.run("a,x,3\n\na,x,4\n".as_bytes()).unwrap();
+    assert!(results.iter_sorted().eq([(None, "a", "x", 7)]));
+    assert!(matches!(
+        pipeline.run("a,x,3\na,x\n".as_bytes()),
+        Err(PipelineError::Parse { line: 2, .. })
+    ));
+    assert!(matches!(
+        pipeline.run("a,x,-1\n".as_bytes()),
+        Err(PipelineError::Parse { line: 1, .. })
+    ));
+}
+
+#[test]
+pub fn test_pipeline_chunks() {
+    let config = PipelineConfig {
+        skip_header: true,
+        group_fields: vec![1],
+        key_field: 2,
+        window: Some(Window { field: 0, width: 100 }),
+        ..PipelineConfig::default()
+    };
+    let whole = Pipeline::new(config.clone()).run(LOG.as_bytes()).unwrap();
+    let pipeline = Pipeline::new(PipelineConfig { chunk_size: 2, ..config });
+    let chunked = pipeline.run(LOG.as_bytes()).unwrap();
+    assert!(chunked.iter_sorted().eq(whole.iter_sorted()));
+    let mut results = PipelineResults::new(true);
+    pipeline.run_into(LOG.as_bytes(), &mut results).unwrap();
+    assert!(matches!(
+        pipeline.run_into("time,host,status\n300,web1,200\n310,web1,200\n320,web1\n".as_bytes(), &mut results),
+        Err(PipelineError::Parse { line: 4, .. })
+    ));
+    // The rows of the chunk before the error were added.
+    assert_eq!(results.len(), 5);
+
+    let evicted = results.evict_older_than(300);
+    assert!(evicted.iter_sorted().map(|(w, ..)| w).eq([Some(100), Some(100), Some(100), Some(200)]));
+    assert!(results.iter_sorted().eq([(Some(300), "web1", "200", 2)]));
+}
+
+#[cfg(feature = "parquet")]
+#[test]
+pub fn test_pipeline_parquet() {
+    use parquet::file::reader::{FileReader, SerializedFileReader};
+    let pipeline = Pipeline::new(PipelineConfig {
+        skip_header: true,
+        group_fields: vec![1],
+        key_field: 2,
+        window: Some(Window { field: 0, width: 100 }),
+        ..PipelineConfig::default()
+    });
+    let results = pipeline.run(LOG.as_bytes()).unwrap();
+    let path = std::env::temp_dir().join(format!("bilevel-pipeline-{}.parquet", std::process::id()));
+    write_parquet(&results, std::fs::File::create(&path).unwrap()).unwrap();
+    let reader = SerializedFileReader::new(std::fs::File::open(&path).unwrap()).unwrap();
+    std::fs::remove_file(&path).unwrap();
+    let metadata = reader.metadata();
+    assert_eq!(metadata.file_metadata().num_rows(), 4);
+    let columns = metadata.file_metadata().schema_descr().columns().iter()
+        .map(|c| c.name().to_string())
+        .collect::<Vec<_>>();
+    assert_eq!(columns, ["window", "group", "key", "value"]);
+}
@@ -0,0 +1,170 @@
+use polars::prelude::*;
+
+/// A type whose values can be read from, and written to, the columns of a
+/// Polars DataFrame.
+///
+/// A tuple of such types takes a column for each of its fields, so that
+/// a composite key can be read from several columns.
+pub trait PolarsValue: Sized {
+    /// The number of columns a value takes.
+    const WIDTH: usize = 1;
+
+    /// Read the value of each row from the columns, in order.
+    ///
+    /// A column that holds nulls, or cannot be cast to the type of the
+    /// value, is an error.
+    fn from_columns(columns: &[&Column]) -> PolarsResult<Vec<Self>>;
+
+    /// Build the columns of the values, with the names listed.
+    fn to_columns(names: &[PlSmallStr], values: Vec<&Self>) -> Vec<Column>;
+}
+
+/// Cast a column to the specified type, as long as it holds no nulls.
+fn cast(column: &Column, dtype: &DataType) -> PolarsResult<Column> {
+    polars_ensure!(column.null_count() == 0, ComputeError: "column {} holds nulls", column.name());
+    column.strict_cast(dtype)
+}
+
+macro_rules! primitive_polars_value {
+    ($($t:ty => $method:ident, $dtype:expr;)*) => {
+        $(
+            impl PolarsValue for $t {
+                fn from_columns(columns: &[&Column]) -> PolarsResult<Vec<Self>> {
+                    let column = cast(columns[0], &$dtype)?;
+                    Ok(column.$method()?.into_no_null_iter().collect())
+                }
+
+                fn to_columns(names: &[PlSmallStr], values: Vec<&Self>) -> Vec<Column> {
+                    vec![Column::new(names[0].clone(), values.into_iter().copied().collect::<Vec<_>>())]
+                }
+            }
+        )*
+    };
+}
+
+primitive_polars_value! {
+    i32 => i32, DataType::Int32;
+    i64 => i64, DataType::Int64;
+    u32 => u32, DataType::UInt32;
+    u64 => u64, DataType::UInt64;
+    f32 => f32, DataType::Float32;
+    f64 => f64, DataType::Float64;
+    bool => bool, DataType::Boolean;
+}
+
+impl PolarsValue for usize {
+    fn from_columns(columns: &[&Column]) -> PolarsResult<Vec<Self>> {
+        let column = cast(columns[0], &DataType::UInt64)?;
+        Ok(column.u64()?.into_no_null_iter().map(|v| v as usize).collect())
+    }
+
+    fn to_columns(names: &[PlSmallStr], values: Vec<&Self>) -> Vec<Column> {
+        vec![Column::new(names[0].clone(), values.into_iter().map(|&v| v as u64).collect::<Vec<_>>())]
+    }
+}
+
+impl PolarsValue for String {
+    fn from_columns(columns: &[&Column]) -> PolarsResult<Vec<Self>> {
+        let column = cast(columns[0], &DataType::String)?;
+        Ok(column.str()?.into_no_null_iter().map(str::to_owned).collect())
+    }
+
+    fn to_columns(names: &[PlSmallStr], values: Vec<&Self>) -> Vec<Column> {
+        vec![Column::new(names[0].clone(), values.into_iter().map(String::as_str).collect::<Vec<_>>())]
+    }
+}
+
+/// Split the first n items off a slice.
+fn take<'a, T>(items: &mut &'a [T], n: usize) -> &'a [T] {
+    let (head, rest) = items.split_at(n);
+    *items = rest;
+    head
+}
+
+macro_rules! tuple_polars_value {
+    ($(($($t:ident $v:ident $i:tt),*);)*) => {
+        $(
+            impl<$($t: PolarsValue),*> PolarsValue for ($($t,)*) {
+                const WIDTH: usize = 0 $(+ $t::WIDTH)*;
+
+                fn from_columns(mut columns: &[&Column]) -> PolarsResult<Vec<Self>> {
+                    $(let mut $v = $t::from_columns(take(&mut columns, $t::WIDTH))?.into_iter();)*
+                    Ok(std::iter::from_fn(|| Some(($($v.next()?,)*))).collect())
+                }
+
+                fn to_columns(mut names: &[PlSmallStr], values: Vec<&Self>) -> Vec<Column> {
+                    let mut columns = Vec::with_capacity(Self::WIDTH);
+                    $(columns.extend($t::to_columns(take(&mut names, $t::WIDTH), values.iter().map(|v| &v.$i).collect()));)*
+                    columns
+                }
+            }
+        )*
+    };
+}
+
+tuple_polars_value! {
+    (A a 0, B b 1);
+    (A a 0, B b 1, C c 2);
+    (A a 0, B b 1, C c 2, D d 3);
+}
+
+/// Read the group, key and value of each row of a DataFrame.
+pub(crate) fn read_rows<G, K, V>(
+    df: &DataFrame,
+    group_cols: &[&str],
+    key_cols: &[&str],
+    value_col: &str,
+) -> PolarsResult<impl Iterator<Item = (G, K, V)>>
+where
+    G: PolarsValue,
+    K: PolarsValue,
+    V: PolarsValue,
+{
+    polars_ensure!(
+        group_cols.len() == G::WIDTH,
+        ShapeMismatch: "expected {} group columns, found {}", G::WIDTH, group_cols.len()
+    );
+    polars_ensure!(
+        key_cols.len() == K::WIDTH,
+        ShapeMismatch: "expected {} key columns, found {}", K::WIDTH, key_cols.len()
+    );
+    polars_ensure!(V::WIDTH == 1, ShapeMismatch: "expected a value of a single column");
+    let columns = |names: &[&str]| names.iter().map(|&name| df.column(name)).collect::<PolarsResult<Vec<_>>>();
+    let groups = G::from_columns(&columns(group_cols)?)?;
+    let keys = K::from_columns(&columns(key_cols)?)?;
+    let values = V::from_columns(&columns(&[value_col])?)?;
+    Ok(groups.into_iter().zip(keys).zip(values).map(|((g, k), v)| (g, k, v)))
+}
+
+/// Build a DataFrame with group, key and value columns from the rows
+/// listed.
+///
+/// A group or key of several columns is written to columns numbered
+/// from 0, such as group_0 and group_1.
+pub(crate) fn data_frame<'a, G, K, V>(
+    rows: impl Iterator<Item = (&'a G, &'a K, &'a V)>,
+) -> PolarsResult<DataFrame>
+where
+    G: PolarsValue + 'a,
+    K: PolarsValue + 'a,
+    V: PolarsValue + 'a,
+{
+    let (mut groups, mut keys, mut values) = (Vec::new(), Vec::new(), Vec::new());
+    for (g, k, v) in rows {
+        groups.push(g);
+        keys.push(k);
+        values.push(v);
+    }
+    let mut columns = G::to_columns(&names("group", G::WIDTH), groups);
+    columns.extend(K::to_columns(&names("key", K::WIDTH), keys));
+    columns.extend(V::to_columns(&names("value", V::WIDTH), values));
+    DataFrame::new(columns)
+}
+
+/// Name the columns of a value of the specified width.
+fn names(prefix: &str, width: usize) -> Vec<PlSmallStr> {
+    match width {
+        1 => vec![prefix.into()],
+        _ => (0..width).map(|i| format!("{prefix}_{i}").into()).collect(),
+    }
+}
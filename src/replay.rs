@@ -0,0 +1,174 @@
+/// A mutation of a collection, as recorded in a replay log.
+///
+/// Replaying the mutations recorded for a collection, in order, into an
+/// empty collection rebuilds it exactly.
+///
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// V is the type of the payload, or () for sets.
+#[derive(Clone, Debug, PartialEq, Eq)]
+#[cfg_attr(feature = "serde", derive(serde::Serialize, serde::Deserialize))]
+pub enum Mutation<G, K, V> {
+    /// The group g was declared, so that it is listed even while empty.
+    DeclareGroup(G),
+    /// The pair (g, k) was inserted, if not already present.
+    Insert(G, K),
+    /// The delta was added to the payload of the pair (g, k), inserting
+    /// the pair with a new payload first if not already present.
+    Update(G, K, V),
+    /// The pair (g, k) was removed, if present, leaving its group.
+    Remove(G, K),
+    /// The group g was removed, along with its pairs, if present.
+    RemoveGroup(G),
+}
+
+impl<G, K, V> Mutation<G, K, V> {
+    /// Refer to the keys and delta of the mutation.
+    pub fn as_ref(&self) -> Mutation<&G, &K, &V> {
+        match self {
+            Mutation::DeclareGroup(g) => Mutation::DeclareGroup(g),
+            Mutation::Insert(g, k) => Mutation::Insert(g, k),
+            Mutation::Update(g, k, v) => Mutation::Update(g, k, v),
+            Mutation::Remove(g, k) => Mutation::Remove(g, k),
+            Mutation::RemoveGroup(g) => Mutation::RemoveGroup(g),
+        }
+    }
+}
+
+impl<G, K, V> Mutation<&G, &K, &V>
+where
+    G: Clone,
+    K: Clone,
+    V: Clone,
+{
+    /// Copy the keys and delta of a mutation referring to them.
+    pub fn to_owned(&self) -> Mutation<G, K, V> {
+        match *self {
+            Mutation::DeclareGroup(g) => Mutation::DeclareGroup(g.clone()),
+            Mutation::Insert(g, k) => Mutation::Insert(g.clone(), k.clone()),
+            Mutation::Update(g, k, v) => Mutation::Update(g.clone(), k.clone(), v.clone()),
+            Mutation::Remove(g, k) => Mutation::Remove(g.clone(), k.clone()),
+            Mutation::RemoveGroup(g) => Mutation::RemoveGroup(g.clone()),
+        }
+    }
+}
+
+/// A destination for the mutations of a collection, such as an
+/// append-only audit log.
+///
+/// Mutations are passed by reference to the keys and delta, so that the
+/// sink can choose whether and how to copy them.
+pub trait MutationSink<G, K, V> {
+    /// Record a mutation.
+    fn record(&mut self, mutation: Mutation<&G, &K, &V>);
+}
+
+impl<G: Clone, K: Clone, V: Clone> MutationSink<G, K, V> for Vec<Mutation<G, K, V>> {
+    fn record(&mut self, mutation: Mutation<&G, &K, &V>) {
+        self.push(mutation.to_owned())
+    }
+}
+
+/// A collection that mutations can be applied to.
+pub trait Replay<G, K, V> {
+    /// Apply a mutation to the collection.
+    fn apply(&mut self, mutation: Mutation<&G, &K, &V>);
+
+    /// Apply a sequence of mutations to the collection, in order.
+    fn replay(&mut self, mutations: impl IntoIterator<Item = Mutation<G, K, V>>) {
+        for mutation in mutations {
+            self.apply(mutation.as_ref());
+        }
+    }
+}
+
+/// A collection whose mutations are all recorded to a sink before they
+/// are applied.
+///
+/// The collection can be read through get, but can only be modified
+/// through this wrapper, so that no mutation goes unrecorded.
+pub struct Logged<C, S> {
+    inner: C,
+    sink: S,
+}
+
+impl<C, S> Logged<C, S> {
+    /// Wrap a collection, recording its mutations to the specified sink.
+    ///
+    /// The collection is normally empty, so that replaying the log
+    /// rebuilds it.
+    pub fn new(inner: C, sink: S) -> Self {
+        Self { inner, sink }
+    }
+
+    /// Get the collection.
+    pub fn get(&self) -> &C {
+        &self.inner
+    }
+
+    /// Get the sink.
+    pub fn sink(&self) -> &S {
+        &self.sink
+    }
+
+    /// Unwrap the collection and the sink.
+    pub fn into_parts(self) -> (C, S) {
+        (self.inner, self.sink)
+    }
+
+    /// Record a mutation, then apply it to the collection.
+    pub fn apply<G, K, V>(&mut self, mutation: Mutation<G, K, V>)
+    where
+        C: Replay<G, K, V>,
+        S: MutationSink<G, K, V>,
+    {
+        let mutation = mutation.as_ref();
+        self.sink.record(mutation.clone());
+        self.inner.apply(mutation);
+    }
+
+    /// Declare a group, so that it is listed even while empty.
+    pub fn declare_group<G, K, V>(&mut self, g: G)
+    where
+        C: Replay<G, K, V>,
+        S: MutationSink<G, K, V>,
+    {
+        self.apply(Mutation::DeclareGroup(g))
+    }
+
+    /// Insert a key pair found into the collection.
+    pub fn insert<G, K, V>(&mut self, g: G, k: K)
+    where
+        C: Replay<G, K, V>,
+        S: MutationSink<G, K, V>,
+    {
+        self.apply(Mutation::Insert(g, k))
+    }
+
+    /// Add a delta to the payload for a key pair.
+    pub fn update<G, K, V>(&mut self, g: G, k: K, delta: V)
+    where
+        C: Replay<G, K, V>,
+        S: MutationSink<G, K, V>,
+    {
+        self.apply(Mutation::Update(g, k, delta))
+    }
+
+    /// Remove a key pair, if present, leaving its group.
+    pub fn remove<G, K, V>(&mut self, g: G, k: K)
+    where
+        C: Replay<G, K, V>,
+        S: MutationSink<G, K, V>,
+    {
+        self.apply(Mutation::Remove(g, k))
+    }
+
+    /// Remove a group, along with its pairs, if present.
+    pub fn remove_group<G, K, V>(&mut self, g: G)
+    where
+        C: Replay<G, K, V>,
+        S: MutationSink<G, K, V>,
+    {
+        self.apply(Mutation::RemoveGroup(g))
+    }
+}
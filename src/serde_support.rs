@@ -0,0 +1,143 @@
+use serde::{Serialize, Serializer};
+
+/// A sequence serialized from the items listed by a function, such as the
+/// aggregation keys of a group looked up from their ids.
+pub(crate) struct SeqOf<F>(pub F);
+
+impl<F, I> Serialize for SeqOf<F>
+where
+    F: Fn() -> I,
+    I: IntoIterator,
+    I::Item: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_seq((self.0)())
+    }
+}
+
+/// A map serialized from the entries listed by a function, such as the
+/// payloads of a group keyed by aggregation keys looked up from their ids.
+pub(crate) struct MapOf<F>(pub F);
+
+impl<F, I, K, V> Serialize for MapOf<F>
+where
+    F: Fn() -> I,
+    I: IntoIterator<Item = (K, V)>,
+    K: Serialize,
+    V: Serialize,
+{
+    fn serialize<S: Serializer>(&self, serializer: S) -> Result<S::Ok, S::Error> {
+        serializer.collect_map((self.0)())
+    }
+}
+
+/// A pair written by to_json_with_ids, with the ids of its group and
+/// aggregation key. The payload is left out for a set.
+#[cfg(feature = "json")]
+#[derive(Serialize)]
+pub(crate) struct IdRow<'a, G, K, V> {
+    pub group_id: crate::core::GroupId,
+    pub group: &'a G,
+    pub key_id: crate::core::AggKeyId,
+    pub key: &'a K,
+    #[serde(skip_serializing_if = "Option::is_none")]
+    pub value: Option<&'a V>,
+}
+
+/// Write the pairs of a map as a JSON object, from each group key to an
+/// object from each aggregation key to its payload, with the groups and
+/// the pairs of each group in order.
+#[cfg(feature = "json")]
+pub(crate) fn write_json_map<'a, G, K, V>(
+    writer: impl std::io::Write,
+    groups: impl Iterator<Item = (&'a G, Vec<(&'a K, &'a V)>)>,
+) -> Result<(), serde_json::Error>
+where
+    G: Ord + Serialize + 'a,
+    K: Ord + Serialize + 'a,
+    V: Serialize + 'a,
+{
+    let mut groups: Vec<_> = groups.collect();
+    groups.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    for (_, pairs) in &mut groups {
+        pairs.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    }
+    let groups = &groups;
+    serde_json::to_writer(writer, &MapOf(|| {
+        groups.iter().map(|(g, pairs)| (g, MapOf(move || pairs.iter().copied())))
+    }))
+}
+
+/// Write the pairs of a set as a JSON object, from each group key to an
+/// array of its aggregation keys, with the groups and the keys of each
+/// group in order.
+#[cfg(feature = "json")]
+pub(crate) fn write_json_set<'a, G, K>(
+    writer: impl std::io::Write,
+    groups: impl Iterator<Item = (&'a G, Vec<&'a K>)>,
+) -> Result<(), serde_json::Error>
+where
+    G: Ord + Serialize + 'a,
+    K: Ord + Serialize + 'a,
+{
+    let mut groups: Vec<_> = groups.collect();
+    groups.sort_unstable_by(|a, b| a.0.cmp(b.0));
+    for (_, keys) in &mut groups {
+        keys.sort_unstable();
+    }
+    let groups = &groups;
+    serde_json::to_writer(writer, &MapOf(|| {
+        groups.iter().map(|(g, keys)| (g, SeqOf(move || keys.iter())))
+    }))
+}
+
+/// Read the group key, aggregation key and payload of each line of JSON
+/// Lines text, passing them to a function, skipping blank lines.
+///
+/// An error in a line is reported with the number of the line.
+#[cfg(feature = "json")]
+pub(crate) fn read_jsonl<G, K, V>(
+    reader: impl std::io::BufRead,
+    group_fields: &[&str],
+    key_fields: &[&str],
+    value_field: &str,
+    mut f: impl FnMut(G, K, V),
+) -> Result<(), serde_json::Error>
+where
+    G: serde::de::DeserializeOwned,
+    K: serde::de::DeserializeOwned,
+    V: serde::de::DeserializeOwned,
+{
+    for (i, line) in reader.lines().enumerate() {
+        let line = line.map_err(serde_json::Error::io)?;
+        if line.trim().is_empty() {
+            continue;
+        }
+        let row = || -> Result<_, serde_json::Error> {
+            let row: serde_json::Value = serde_json::from_str(&line)?;
+            Ok((field(&row, group_fields)?, field(&row, key_fields)?, field(&row, &[value_field])?))
+        };
+        let (g, k, v) = row().map_err(|e| serde::de::Error::custom(format_args!("line {}: {e}", i + 1)))?;
+        f(g, k, v);
+    }
+    Ok(())
+}
+
+/// Deserialize the value of a field of a JSON object, or an array of the
+/// values of several fields, such as to read a tuple.
+///
+/// A field name starting with / is a JSON pointer to a nested field.
+#[cfg(feature = "json")]
+fn field<T: serde::de::DeserializeOwned>(row: &serde_json::Value, names: &[&str]) -> Result<T, serde_json::Error> {
+    let get = |name: &str| {
+        let value = if name.starts_with('/') { row.pointer(name) } else { row.get(name) };
+        value.ok_or_else(|| serde::de::Error::custom(format_args!("missing field {name}")))
+    };
+    match names {
+        [name] => T::deserialize(get(name)?),
+        _ => {
+            let values = names.iter().map(|name| get(name).cloned()).collect::<Result<_, _>>()?;
+            T::deserialize(serde_json::Value::Array(values))
+        },
+    }
+}
@@ -0,0 +1,521 @@
+use std::{borrow::Borrow, hash::{BuildHasher, Hash, RandomState}, mem::size_of};
+use hashbrown::{Equivalent, HashMap, HashSet, hash_map, hash_set};
+use smallvec::SmallVec;
+
+/// The number of keys of a group held within the group itself, with no
+/// allocation of their own.
+const INLINE: usize = 4;
+
+/// The number of keys past which a group moves its keys into a hash
+/// table, as searching a list of them takes longer than hashing.
+pub(crate) const PROMOTE_AT: usize = 8;
+
+/// The aggregation keys of a group, held in a short list while the group
+/// is small, and in a hash table once it grows past PROMOTE_AT keys.
+///
+/// Most groups of many aggregations have only a few keys, so this saves
+/// the hash table, and the allocation, that each would otherwise take.
+///
+/// H is the hasher for the keys once they are moved into a hash table.
+#[derive(Clone, Debug)]
+pub(crate) enum SmallSet<T, H = RandomState> {
+    Inline(SmallVec<[T; INLINE]>),
+    Hashed(HashSet<T, H>),
+}
+
+impl<T: Hash + Eq, H: BuildHasher + Default> SmallSet<T, H> {
+    /// Create a set with space for the specified number of keys, which
+    /// is held in a hash table from the start if it exceeds PROMOTE_AT.
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        match capacity {
+            n if n > PROMOTE_AT => Self::Hashed(HashSet::with_capacity_and_hasher(n, H::default())),
+            n => Self::Inline(SmallVec::with_capacity(n)),
+        }
+    }
+
+    /// Add a key, if not already present, returning whether it was added.
+    pub(crate) fn insert(&mut self, t: T) -> bool {
+        match self {
+            Self::Inline(list) if list.contains(&t) => false,
+            Self::Inline(list) if list.len() < PROMOTE_AT => {
+                list.push(t);
+                true
+            },
+            Self::Inline(list) => {
+                let mut set: HashSet<_, H> = list.drain(..).collect();
+                set.insert(t);
+                *self = Self::Hashed(set);
+                true
+            },
+            Self::Hashed(set) => set.insert(t),
+        }
+    }
+
+    /// Check whether a key is present.
+    pub(crate) fn contains(&self, t: &T) -> bool {
+        match self {
+            Self::Inline(list) => list.contains(t),
+            Self::Hashed(set) => set.contains(t),
+        }
+    }
+
+    /// Remove a key, found by any form that is equivalent to it, returning
+    /// whether it was present.
+    pub(crate) fn remove<Q>(&mut self, t: &Q) -> bool
+    where
+        Q: Hash + Equivalent<T> + ?Sized,
+    {
+        match self {
+            Self::Inline(list) => match list.iter().position(|o| t.equivalent(o)) {
+                Some(i) => {
+                    list.remove(i);
+                    true
+                },
+                None => false,
+            },
+            Self::Hashed(set) => set.remove(t),
+        }
+    }
+
+    /// Reserve space for at least the specified number of additional keys,
+    /// moving them into a hash table if there would be more than
+    /// PROMOTE_AT.
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        match self {
+            Self::Inline(list) if list.len() + additional > PROMOTE_AT => {
+                let mut set = HashSet::with_capacity_and_hasher(list.len() + additional, H::default());
+                set.extend(list.drain(..));
+                *self = Self::Hashed(set);
+            },
+            Self::Inline(list) => list.reserve(additional),
+            Self::Hashed(set) => set.reserve(additional),
+        }
+    }
+
+    /// List the keys.
+    pub(crate) fn iter(&self) -> SetIter<'_, T> {
+        match self {
+            Self::Inline(list) => Either::Inline(list.iter()),
+            Self::Hashed(set) => Either::Hashed(set.iter()),
+        }
+    }
+
+    /// Count the keys.
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Self::Inline(list) => list.len(),
+            Self::Hashed(set) => set.len(),
+        }
+    }
+
+    /// Check whether there are no keys.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Estimate the memory allocated for the keys, in bytes, not counting
+    /// any memory the keys own themselves.
+    pub(crate) fn allocated(&self) -> usize {
+        match self {
+            Self::Inline(list) if list.spilled() => list.capacity() * size_of::<T>(),
+            Self::Inline(_) => 0,
+            // Allow a control byte per entry, as the standard tables use.
+            Self::Hashed(set) => set.capacity() * (size_of::<T>() + 1),
+        }
+    }
+}
+
+impl<T: Hash + Eq, H> Default for SmallSet<T, H> {
+    fn default() -> Self {
+        Self::Inline(SmallVec::new())
+    }
+}
+
+impl<T: Hash + Eq, H: BuildHasher + Default> PartialEq for SmallSet<T, H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|t| other.contains(t))
+    }
+}
+
+impl<T: Hash + Eq, H: BuildHasher + Default> Eq for SmallSet<T, H> {}
+
+impl<T: Hash + Eq, H: BuildHasher + Default> FromIterator<T> for SmallSet<T, H> {
+    fn from_iter<I: IntoIterator<Item = T>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut set = Self::with_capacity(iter.size_hint().0);
+        for t in iter {
+            set.insert(t);
+        }
+        set
+    }
+}
+
+impl<T, H> IntoIterator for SmallSet<T, H> {
+    type Item = T;
+    type IntoIter = SetIntoIter<T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self {
+            Self::Inline(list) => Either::Inline(list.into_iter()),
+            Self::Hashed(set) => Either::Hashed(set.into_iter()),
+        }
+    }
+}
+
+impl<'a, T: Hash + Eq, H: BuildHasher + Default> IntoIterator for &'a SmallSet<T, H> {
+    type Item = &'a T;
+    type IntoIter = SetIter<'a, T>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Lists the keys of a SmallSet.
+pub(crate) type SetIter<'a, T> = Either<std::slice::Iter<'a, T>, hash_set::Iter<'a, T>>;
+
+/// Lists the keys of a SmallSet, consuming it.
+pub(crate) type SetIntoIter<T> = Either<smallvec::IntoIter<[T; INLINE]>, hash_set::IntoIter<T>>;
+
+/// The aggregation keys of a group and their payloads, held in a short
+/// list while the group is small, and in a hash table once it grows past
+/// PROMOTE_AT keys.
+///
+/// H is the hasher for the keys once they are moved into a hash table.
+#[derive(Clone, Debug)]
+pub(crate) enum SmallMap<K, V, H = RandomState> {
+    Inline(SmallVec<[(K, V); INLINE]>),
+    Hashed(HashMap<K, V, H>),
+}
+
+/// A key of a SmallMap, which may or may not be present.
+pub(crate) enum Entry<'a, K, V, H = RandomState> {
+    /// The payload of a key that is present.
+    Occupied(&'a mut V),
+    /// A key that is not present, to which a payload can be given.
+    Vacant(VacantEntry<'a, K, V, H>),
+}
+
+/// A key not present in a SmallMap.
+pub(crate) enum VacantEntry<'a, K, V, H = RandomState> {
+    Inline(&'a mut SmallMap<K, V, H>, K),
+    Hashed(hash_map::VacantEntry<'a, K, V, H>),
+}
+
+impl<K: Hash + Eq, V, H: BuildHasher + Default> SmallMap<K, V, H> {
+    /// Create a map with space for the specified number of keys, which
+    /// is held in a hash table from the start if it exceeds PROMOTE_AT.
+    pub(crate) fn with_capacity(capacity: usize) -> Self {
+        match capacity {
+            n if n > PROMOTE_AT => Self::Hashed(HashMap::with_capacity_and_hasher(n, H::default())),
+            n => Self::Inline(SmallVec::with_capacity(n)),
+        }
+    }
+
+    /// Find the entry of a key, to get or give its payload.
+    pub(crate) fn entry(&mut self, k: K) -> Entry<'_, K, V, H> {
+        let position = match &*self {
+            Self::Inline(list) => list.iter().position(|(o, _)| *o == k),
+            Self::Hashed(_) => None,
+        };
+        match (position, self) {
+            (_, Self::Hashed(map)) => match map.entry(k) {
+                hash_map::Entry::Occupied(e) => Entry::Occupied(e.into_mut()),
+                hash_map::Entry::Vacant(e) => Entry::Vacant(VacantEntry::Hashed(e)),
+            },
+            (Some(i), Self::Inline(list)) => Entry::Occupied(&mut list[i].1),
+            (None, map) => Entry::Vacant(VacantEntry::Inline(map, k)),
+        }
+    }
+
+    /// Give a key a payload, returning the payload it had, if any.
+    pub(crate) fn insert(&mut self, k: K, v: V) -> Option<V> {
+        match self.entry(k) {
+            Entry::Occupied(old) => Some(std::mem::replace(old, v)),
+            Entry::Vacant(e) => {
+                e.insert(v);
+                None
+            },
+        }
+    }
+
+    /// Remove a key, found by any form that is equivalent to it, returning
+    /// its payload, if present.
+    pub(crate) fn remove<Q>(&mut self, k: &Q) -> Option<V>
+    where
+        Q: Hash + Equivalent<K> + ?Sized,
+    {
+        match self {
+            Self::Inline(list) => list.iter().position(|(o, _)| k.equivalent(o)).map(|i| list.remove(i).1),
+            Self::Hashed(map) => map.remove(k),
+        }
+    }
+
+    /// Get the payload of a key, if present, looked up by any form the key
+    /// borrows as.
+    pub(crate) fn get<Q>(&self, k: &Q) -> Option<&V>
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        match self {
+            Self::Inline(list) => list.iter().find(|(o, _)| o.borrow() == k).map(|(_, v)| v),
+            Self::Hashed(map) => map.get(k),
+        }
+    }
+
+    /// Check whether a key is present.
+    pub(crate) fn contains_key<Q>(&self, k: &Q) -> bool
+    where
+        K: Borrow<Q>,
+        Q: Hash + Eq + ?Sized,
+    {
+        self.get(k).is_some()
+    }
+
+    /// Reserve space for at least the specified number of additional keys,
+    /// moving them into a hash table if there would be more than
+    /// PROMOTE_AT.
+    pub(crate) fn reserve(&mut self, additional: usize) {
+        match self {
+            Self::Inline(list) if list.len() + additional > PROMOTE_AT => {
+                let mut map = HashMap::with_capacity_and_hasher(list.len() + additional, H::default());
+                map.extend(list.drain(..));
+                *self = Self::Hashed(map);
+            },
+            Self::Inline(list) => list.reserve(additional),
+            Self::Hashed(map) => map.reserve(additional),
+        }
+    }
+
+    /// List the keys.
+    pub(crate) fn keys(&self) -> impl Iterator<Item = &K> {
+        self.iter().map(|(k, _)| k)
+    }
+
+    /// List the payloads.
+    pub(crate) fn values(&self) -> impl Iterator<Item = &V> {
+        self.iter().map(|(_, v)| v)
+    }
+
+    /// Count the keys.
+    pub(crate) fn len(&self) -> usize {
+        match self {
+            Self::Inline(list) => list.len(),
+            Self::Hashed(map) => map.len(),
+        }
+    }
+
+    /// Check whether there are no keys.
+    pub(crate) fn is_empty(&self) -> bool {
+        self.len() == 0
+    }
+
+    /// Estimate the memory allocated for the keys and payloads, in bytes,
+    /// not counting any memory they own themselves.
+    pub(crate) fn allocated(&self) -> usize {
+        match self {
+            Self::Inline(list) if list.spilled() => list.capacity() * size_of::<(K, V)>(),
+            Self::Inline(_) => 0,
+            // Allow a control byte per entry, as the standard tables use.
+            Self::Hashed(map) => map.capacity() * (size_of::<(K, V)>() + 1),
+        }
+    }
+}
+
+impl<K, V, H> SmallMap<K, V, H> {
+    /// List the keys and their payloads.
+    pub(crate) fn iter(&self) -> MapIter<'_, K, V> {
+        match self {
+            Self::Inline(list) => Either::Inline(list.iter().map(|(k, v)| (k, v))),
+            Self::Hashed(map) => Either::Hashed(map.iter()),
+        }
+    }
+
+    /// List the keys and mutable references to their payloads.
+    pub(crate) fn iter_mut(&mut self) -> MapIterMut<'_, K, V> {
+        match self {
+            Self::Inline(list) => Either::Inline(list.iter_mut().map(|(k, v)| (&*k, v))),
+            Self::Hashed(map) => Either::Hashed(map.iter_mut()),
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V, H: BuildHasher + Default> Entry<'a, K, V, H> {
+    /// Get the payload of the key, giving it the payload computed if it
+    /// is not present.
+    pub(crate) fn or_insert_with(self, f: impl FnOnce() -> V) -> &'a mut V {
+        match self {
+            Entry::Occupied(v) => v,
+            Entry::Vacant(e) => e.insert(f()),
+        }
+    }
+}
+
+impl<'a, K: Hash + Eq, V, H: BuildHasher + Default> VacantEntry<'a, K, V, H> {
+    /// Get the key.
+    pub(crate) fn key(&self) -> &K {
+        match self {
+            VacantEntry::Inline(_, k) => k,
+            VacantEntry::Hashed(e) => e.key(),
+        }
+    }
+
+    /// Give the key a payload, moving the keys into a hash table if there
+    /// would be more than PROMOTE_AT.
+    pub(crate) fn insert(self, v: V) -> &'a mut V {
+        match self {
+            VacantEntry::Hashed(e) => e.insert(v),
+            VacantEntry::Inline(map, k) => {
+                if let SmallMap::Inline(list) = map {
+                    if list.len() >= PROMOTE_AT {
+                        *map = SmallMap::Hashed(list.drain(..).collect());
+                    }
+                }
+                match map {
+                    SmallMap::Inline(list) => {
+                        list.push((k, v));
+                        &mut list.last_mut().expect("the pair was just added").1
+                    },
+                    SmallMap::Hashed(hashed) => hashed.entry(k).or_insert(v),
+                }
+            },
+        }
+    }
+}
+
+impl<K: Hash + Eq, V, H> Default for SmallMap<K, V, H> {
+    fn default() -> Self {
+        Self::Inline(SmallVec::new())
+    }
+}
+
+impl<K: Hash + Eq, V: PartialEq, H: BuildHasher + Default> PartialEq for SmallMap<K, V, H> {
+    fn eq(&self, other: &Self) -> bool {
+        self.len() == other.len() && self.iter().all(|(k, v)| other.get(k) == Some(v))
+    }
+}
+
+impl<K: Hash + Eq, V: Eq, H: BuildHasher + Default> Eq for SmallMap<K, V, H> {}
+
+impl<K: Hash + Eq, V, H: BuildHasher + Default> FromIterator<(K, V)> for SmallMap<K, V, H> {
+    fn from_iter<I: IntoIterator<Item = (K, V)>>(iter: I) -> Self {
+        let iter = iter.into_iter();
+        let mut map = Self::with_capacity(iter.size_hint().0);
+        for (k, v) in iter {
+            map.insert(k, v);
+        }
+        map
+    }
+}
+
+impl<K, V, H> IntoIterator for SmallMap<K, V, H> {
+    type Item = (K, V);
+    type IntoIter = MapIntoIter<K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        match self {
+            Self::Inline(list) => Either::Inline(list.into_iter()),
+            Self::Hashed(map) => Either::Hashed(map.into_iter()),
+        }
+    }
+}
+
+impl<'a, K, V, H> IntoIterator for &'a SmallMap<K, V, H> {
+    type Item = (&'a K, &'a V);
+    type IntoIter = MapIter<'a, K, V>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        self.iter()
+    }
+}
+
+/// Lists the keys and payloads of a SmallMap.
+pub(crate) type MapIter<'a, K, V> = Either<
+    std::iter::Map<std::slice::Iter<'a, (K, V)>, fn(&'a (K, V)) -> (&'a K, &'a V)>,
+    hash_map::Iter<'a, K, V>,
+>;
+
+/// Lists the keys of a SmallMap and mutable references to their payloads.
+pub(crate) type MapIterMut<'a, K, V> = Either<
+    std::iter::Map<std::slice::IterMut<'a, (K, V)>, fn(&'a mut (K, V)) -> (&'a K, &'a mut V)>,
+    hash_map::IterMut<'a, K, V>,
+>;
+
+/// Lists the keys and payloads of a SmallMap, consuming it.
+pub(crate) type MapIntoIter<K, V> = Either<smallvec::IntoIter<[(K, V); INLINE]>, hash_map::IntoIter<K, V>>;
+
+/// Lists the items of a SmallSet or SmallMap, whichever way it holds them.
+pub(crate) enum Either<A, B> {
+    Inline(A),
+    Hashed(B),
+}
+
+impl<A, B, T> Iterator for Either<A, B>
+where
+    A: Iterator<Item = T>,
+    B: Iterator<Item = T>,
+{
+    type Item = T;
+
+    fn next(&mut self) -> Option<T> {
+        match self {
+            Either::Inline(a) => a.next(),
+            Either::Hashed(b) => b.next(),
+        }
+    }
+
+    fn size_hint(&self) -> (usize, Option<usize>) {
+        match self {
+            Either::Inline(a) => a.size_hint(),
+            Either::Hashed(b) => b.size_hint(),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<T: serde::Serialize, H> serde::Serialize for SmallSet<T, H> {
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        // Written as a sequence, as a HashSet is, so that either reads the
+        // other.
+        match self {
+            Self::Inline(list) => serializer.collect_seq(list.iter()),
+            Self::Hashed(set) => serializer.collect_seq(set.iter()),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, T, H> serde::Deserialize<'de> for SmallSet<T, H>
+where
+    T: serde::Deserialize<'de> + Hash + Eq,
+    H: BuildHasher + Default,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        Vec::<T>::deserialize(deserializer).map(|list| list.into_iter().collect())
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<K: serde::Serialize, V: serde::Serialize, H> serde::Serialize for SmallMap<K, V, H> {
+    fn serialize<Ser: serde::Serializer>(&self, serializer: Ser) -> Result<Ser::Ok, Ser::Error> {
+        // Written as a map, as a HashMap is, so that either reads the
+        // other.
+        match self {
+            Self::Inline(list) => serializer.collect_map(list.iter().map(|(k, v)| (k, v))),
+            Self::Hashed(map) => serializer.collect_map(map.iter()),
+        }
+    }
+}
+
+#[cfg(feature = "serde")]
+impl<'de, K, V, H> serde::Deserialize<'de> for SmallMap<K, V, H>
+where
+    K: serde::Deserialize<'de> + Hash + Eq,
+    V: serde::Deserialize<'de>,
+    H: BuildHasher + Default,
+{
+    fn deserialize<D: serde::Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        std::collections::HashMap::<K, V>::deserialize(deserializer).map(|map| map.into_iter().collect())
+    }
+}
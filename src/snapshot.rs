@@ -0,0 +1,216 @@
+use std::{error::Error, fmt, io::{self, Read, Write}, marker::PhantomData};
+use serde::{Deserialize, Deserializer, Serialize, de::{DeserializeOwned, SeqAccess, Visitor}};
+
+/// Identifies a snapshot written by this crate.
+const MAGIC: &[u8; 4] = b"BLVL";
+
+/// Identifies a delta written by this crate.
+const DELTA_MAGIC: &[u8; 4] = b"BLVD";
+
+/// The version of the snapshot format, to be incremented whenever the
+/// format changes.
+const VERSION: u8 = 1;
+
+/// The most capacity for the pairs of each new group that a snapshot may
+/// ask for, beyond which it is taken to be corrupt, so that a damaged
+/// snapshot cannot make the first new group fail to allocate.
+const MAX_PER_GROUP: usize = 1 << 20;
+
+/// An error reading or writing a snapshot.
+#[derive(Debug)]
+pub enum SnapshotError {
+    /// The underlying reader or writer failed.
+    Io(io::Error),
+    /// The data read is not a snapshot.
+    BadMagic,
+    /// The snapshot was written in a format version this build cannot read.
+    UnsupportedVersion(u8),
+    /// The snapshot could not be encoded or decoded.
+    Encoding(bincode::Error),
+    /// The snapshot decoded, but its contents are inconsistent.
+    Corrupt,
+}
+
+impl fmt::Display for SnapshotError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Io(e) => write!(f, "snapshot I/O failed: {}", e),
+            Self::BadMagic => write!(f, "not a snapshot"),
+            Self::UnsupportedVersion(v) => write!(f, "unsupported snapshot version {}", v),
+            Self::Encoding(e) => write!(f, "snapshot encoding failed: {}", e),
+            Self::Corrupt => write!(f, "snapshot is corrupt"),
+        }
+    }
+}
+
+impl Error for SnapshotError {
+    fn source(&self) -> Option<&(dyn Error + 'static)> {
+        match self {
+            Self::Io(e) => Some(e),
+            Self::Encoding(e) => Some(e),
+            _ => None,
+        }
+    }
+}
+
+impl From<io::Error> for SnapshotError {
+    fn from(e: io::Error) -> Self {
+        Self::Io(e)
+    }
+}
+
+impl From<bincode::Error> for SnapshotError {
+    fn from(e: bincode::Error) -> Self {
+        Self::Encoding(e)
+    }
+}
+
+/// A report of the groups that could not be read back from a snapshot
+/// by read_snapshot_partial.
+#[derive(Debug)]
+pub struct MissingGroups {
+    /// The number of groups the snapshot was written with.
+    pub expected: usize,
+    /// The number of groups read back before the error.
+    pub recovered: usize,
+    /// The error that stopped the groups from being read.
+    pub error: SnapshotError,
+}
+
+impl MissingGroups {
+    /// The number of groups that could not be read back.
+    pub fn missing(&self) -> usize {
+        self.expected - self.recovered
+    }
+}
+
+/// The most groups to allocate room for before reading them.
+const MAX_PREALLOCATED: usize = 4096;
+
+/// A list of groups read from a snapshot, keeping those read before any
+/// error rather than failing.
+/// 
+/// The groups must be the last part of the snapshot, since nothing can
+/// be read after the error.
+pub(crate) struct Lenient<T> {
+    items: Vec<T>,
+    expected: usize,
+    error: Option<String>,
+}
+
+impl<T> Lenient<T> {
+    /// Get the groups read, and the report of those missing, if any.
+    pub(crate) fn finish(self) -> (Vec<T>, Option<MissingGroups>) {
+        let report = self.error.map(|e| MissingGroups {
+            expected: self.expected,
+            recovered: self.items.len(),
+            error: SnapshotError::Encoding(bincode::ErrorKind::Custom(e).into()),
+        });
+        (self.items, report)
+    }
+}
+
+impl<'de, T: Deserialize<'de>> Deserialize<'de> for Lenient<T> {
+    fn deserialize<D: Deserializer<'de>>(deserializer: D) -> Result<Self, D::Error> {
+        // Bincode encodes maps and sequences alike, so groups written as
+        // a map can be read back as a sequence of pairs.
+        deserializer.deserialize_seq(LenientVisitor(PhantomData))
+    }
+}
+
+struct LenientVisitor<T>(PhantomData<T>);
+
+impl<'de, T: Deserialize<'de>> Visitor<'de> for LenientVisitor<T> {
+    type Value = Lenient<T>;
+
+    fn expecting(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        write!(f, "a sequence of groups")
+    }
+
+    fn visit_seq<A: SeqAccess<'de>>(self, mut seq: A) -> Result<Self::Value, A::Error> {
+        let expected = seq.size_hint().unwrap_or(0);
+        // The length is read from the snapshot, and may be damaged too, so
+        // only so much is allocated ahead of the groups actually read.
+        let mut items = Vec::with_capacity(expected.min(MAX_PREALLOCATED));
+        loop {
+            match seq.next_element() {
+                Ok(Some(item)) => items.push(item),
+                Ok(None) => return Ok(Lenient { items, expected, error: None }),
+                Err(e) => return Ok(Lenient { items, expected, error: Some(e.to_string()) }),
+            }
+        }
+    }
+}
+
+/// Write the header and contents of a snapshot.
+pub(crate) fn write<T: Serialize>(writer: impl Write, contents: &T) -> Result<(), SnapshotError> {
+    write_framed(MAGIC, writer, contents)
+}
+
+/// Check the header of a snapshot, and read its contents.
+pub(crate) fn read<T: DeserializeOwned>(reader: impl Read) -> Result<T, SnapshotError> {
+    read_framed(MAGIC, reader)
+}
+
+/// Encode the changes since the last delta.
+pub(crate) fn write_delta<T: Serialize>(changes: &[T]) -> Result<Vec<u8>, SnapshotError> {
+    let mut buf = Vec::new();
+    write_framed(DELTA_MAGIC, &mut buf, &changes)?;
+    Ok(buf)
+}
+
+/// Decode the changes of a delta written by write_delta.
+pub(crate) fn read_delta<T: DeserializeOwned>(delta: &[u8]) -> Result<Vec<T>, SnapshotError> {
+    read_framed(DELTA_MAGIC, delta)
+}
+
+/// Write a header with the specified magic, and the contents.
+fn write_framed<T: Serialize>(magic: &[u8; 4], mut writer: impl Write, contents: &T) -> Result<(), SnapshotError> {
+    writer.write_all(magic)?;
+    writer.write_all(&[VERSION])?;
+    bincode::serialize_into(&mut writer, contents)?;
+    writer.flush()?;
+    Ok(())
+}
+
+/// Check for a header with the specified magic, and read the contents.
+fn read_framed<T: DeserializeOwned>(magic: &[u8; 4], mut reader: impl Read) -> Result<T, SnapshotError> {
+    let mut header = [0; 5];
+    reader.read_exact(&mut header)?;
+    if &header[..4] != magic {
+        return Err(SnapshotError::BadMagic);
+    }
+    if header[4] != VERSION {
+        return Err(SnapshotError::UnsupportedVersion(header[4]));
+    }
+    Ok(bincode::deserialize_from(reader)?)
+}
+
+/// Rebuild the table of groups read from a snapshot, checking that the
+/// group keys are distinct, that the contents of each group are valid,
+/// and that the capacity for each new group is one a collection could
+/// have been given.
+///
+/// per_group: the capacity for the pairs of each new group, as written.
+/// hash: the hash of a group key, as the collection hashes it.
+pub(crate) fn group_table<G: Eq, T>(
+    per_group: usize,
+    group_list: Vec<(G, T)>,
+    hash: fn(&G) -> u64,
+    valid: impl Fn(&T) -> bool,
+) -> Result<crate::core::GroupTable<(G, T)>, SnapshotError> {
+    if per_group > MAX_PER_GROUP {
+        return Err(SnapshotError::Corrupt);
+    }
+    let mut groups = crate::core::GroupTable::with_capacity(group_list.len());
+    for (g, inner) in group_list {
+        if !valid(&inner) {
+            return Err(SnapshotError::Corrupt);
+        }
+        if groups.find(hash(&g), |(o, _)| *o == g).is_some() {
+            return Err(SnapshotError::Corrupt);
+        }
+        groups.insert_unique(hash(&g), (g, inner));
+    }
+    Ok(groups)
+}
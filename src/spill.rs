@@ -0,0 +1,354 @@
+use std::{
+    cmp::{Ordering, Reverse},
+    collections::{BinaryHeap, VecDeque, binary_heap::PeekMut},
+    fs::File,
+    hash::Hash,
+    io::{BufReader, BufWriter, Seek, Write},
+    marker::PhantomData,
+    path::PathBuf,
+};
+use serde::{Serialize, de::DeserializeOwned};
+
+use crate::{HeapSize, SnapshotError, core::BilevelMap};
+
+/// Options for spilling a SpillingMap to disk.
+#[derive(Clone, Debug)]
+pub struct SpillOptions {
+    /// The memory the collection may hold, in bytes, as estimated by
+    /// estimated_heap_bytes, before its pairs are spilled to a file.
+    pub memory_budget: usize,
+    /// The directory to create the files in. Each file is deleted as soon
+    /// as it is closed.
+    pub dir: PathBuf,
+}
+
+impl Default for SpillOptions {
+    fn default() -> Self {
+        Self { memory_budget: 256 << 20, dir: std::env::temp_dir() }
+    }
+}
+
+/// A BilevelMap that spills its pairs to a temporary file, as a run
+/// sorted by key, whenever it holds more than a memory budget, so that
+/// more pairs can be aggregated than fit in memory.
+///
+/// Finishing the aggregation merges the runs, combining the payloads of
+/// any pair spilled more than once, and lists the groups in order of their
+/// keys.
+///
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// V is the type of the payload.
+/// F combines the payloads of a pair found in more than one run.
+pub struct SpillingMap<G, K: Hash, V, F> {
+    inner: BilevelMap<G, K, V>,
+    options: SpillOptions,
+    combine: F,
+    runs: Vec<File>,
+    /// The number of pairs at which the memory held is next checked.
+    next_check: usize,
+}
+
+impl<G, K, V, F> SpillingMap<G, K, V, F>
+where
+    G: Hash + Ord + Clone + HeapSize + Serialize + DeserializeOwned,
+    K: Hash + Ord + Clone + HeapSize + Serialize + DeserializeOwned,
+    V: Default + HeapSize + Serialize + DeserializeOwned,
+    F: FnMut(&mut V, V),
+{
+    /// Create a new collection, with the default payload for each new pair.
+    ///
+    /// combine: folds the payload of a pair from a later run into the
+    ///     payload of the same pair from an earlier run.
+    pub fn new(options: SpillOptions, combine: F) -> Self {
+        Self { inner: BilevelMap::new(), options, combine, runs: Vec::new(), next_check: 0 }
+    }
+
+    /// Get a mutable reference to the payload for the specified key pair.
+    ///
+    /// If the collection holds more than the memory budget, its pairs are
+    /// spilled first. If the key pair is not present in memory, a new
+    /// payload is inserted, even if the pair was spilled earlier, and the
+    /// two are combined when the aggregation is finished.
+    pub fn add_or_get<GRef, KRef>(&mut self, g: &GRef, k: &KRef) -> Result<&mut V, SnapshotError>
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+        KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        if self.inner.pair_count() >= self.next_check {
+            self.check()?;
+        }
+        Ok(self.inner.add_or_get(g, k))
+    }
+
+    /// Spill the pairs held in memory to a new run, if there are any.
+    pub fn spill(&mut self) -> Result<(), SnapshotError> {
+        self.next_check = 0;
+        if self.inner.is_empty() {
+            return Ok(());
+        }
+        let pairs = std::mem::take(&mut self.inner);
+        let mut file = tempfile::tempfile_in(&self.options.dir)?;
+        let mut writer = BufWriter::new(&mut file);
+        bincode::serialize_into(&mut writer, &(pairs.pair_count() as u64))?;
+        for pair in pairs.into_iter_sorted() {
+            bincode::serialize_into(&mut writer, &pair)?;
+        }
+        writer.flush()?;
+        drop(writer);
+        file.rewind()?;
+        self.runs.push(file);
+        Ok(())
+    }
+
+    /// Get the collection of the pairs held in memory, which have not yet
+    /// been spilled.
+    pub fn get(&self) -> &BilevelMap<G, K, V> {
+        &self.inner
+    }
+
+    /// Count the runs spilled so far.
+    pub fn spilled_runs(&self) -> usize {
+        self.runs.len()
+    }
+
+    /// Finish the aggregation, merging the runs spilled with the pairs
+    /// held in memory.
+    ///
+    /// Return each group in order of the group keys, with the aggregation
+    /// key and payload of each of its pairs, in order of the aggregation
+    /// keys. Reading a run can fail, which drops the rest of that run
+    /// from the merge, reported as a LostRun in place of the next group,
+    /// while the other runs are still merged.
+    pub fn finish(self) -> impl Iterator<Item = Result<Group<G, K, V>, LostRun>> {
+        let Self { inner, combine, runs: files, .. } = self;
+        let mut runs = Vec::with_capacity(files.len() + 1);
+        let mut lost = VecDeque::new();
+        for (run, file) in files.into_iter().enumerate() {
+            let mut reader = BufReader::new(file);
+            match bincode::deserialize_from(&mut reader) {
+                Ok(expected) => runs.push(Run::File(RunReader { reader, run, expected, read: 0, pair: PhantomData })),
+                Err(e) => lost.push_back(LostRun { run, expected: None, recovered: 0, error: e.into() }),
+            }
+        }
+        let mut memory: Vec<_> = inner.into_iter().collect();
+        memory.sort_unstable_by(|a, b| (&a.0, &a.1).cmp(&(&b.0, &b.1)));
+        runs.push(Run::Memory(memory.into_iter()));
+        Groups { merge: Merge::new(runs, combine, lost), pending: None }
+    }
+
+    /// Spill the pairs if the collection holds more than the memory
+    /// budget.
+    fn check(&mut self) -> Result<(), SnapshotError> {
+        if self.inner.estimated_heap_bytes() > self.options.memory_budget {
+            return self.spill();
+        }
+        // Check again once the pairs have grown by an eighth, so that the
+        // cost of the estimate is spread over the pairs added.
+        let pairs = self.inner.pair_count();
+        self.next_check = pairs + (pairs / 8).max(1);
+        Ok(())
+    }
+}
+
+/// A group, with the aggregation key and payload of each of its pairs.
+type Group<G, K, V> = (G, Vec<(K, V)>);
+
+/// A report of a run spilled by a SpillingMap that could not be read back
+/// in full, the rest of whose pairs are missing from the groups listed.
+#[derive(Debug)]
+pub struct LostRun {
+    /// The position of the run among those spilled, the first being 0.
+    pub run: usize,
+    /// The number of pairs the run was written with, or None if even that
+    /// could not be read.
+    pub expected: Option<u64>,
+    /// The number of pairs read back before the error.
+    pub recovered: u64,
+    /// The error that stopped the run from being read.
+    pub error: SnapshotError,
+}
+
+impl LostRun {
+    /// The number of pairs that could not be read back, if known.
+    pub fn missing(&self) -> Option<u64> {
+        self.expected.map(|expected| expected - self.recovered)
+    }
+}
+
+/// Reads back the pairs of a run from its file.
+struct RunReader<G, K, V> {
+    reader: BufReader<File>,
+    run: usize,
+    expected: u64,
+    read: u64,
+    pair: PhantomData<(G, K, V)>,
+}
+
+impl<G, K, V> Iterator for RunReader<G, K, V>
+where
+    G: DeserializeOwned,
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    type Item = Result<(G, K, V), LostRun>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.read == self.expected {
+            return None;
+        }
+        let pair = bincode::deserialize_from(&mut self.reader).map_err(|e| LostRun {
+            run: self.run,
+            expected: Some(self.expected),
+            recovered: self.read,
+            error: e.into(),
+        });
+        self.read += 1;
+        Some(pair)
+    }
+}
+
+/// A run of pairs sorted by key, spilled to a file or held in memory.
+enum Run<G, K, V> {
+    File(RunReader<G, K, V>),
+    Memory(std::vec::IntoIter<(G, K, V)>),
+}
+
+impl<G, K, V> Iterator for Run<G, K, V>
+where
+    G: DeserializeOwned,
+    K: DeserializeOwned,
+    V: DeserializeOwned,
+{
+    type Item = Result<(G, K, V), LostRun>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        match self {
+            Run::File(reader) => reader.next(),
+            Run::Memory(pairs) => pairs.next().map(Ok),
+        }
+    }
+}
+
+/// The next pair of a run, ordered by key and then by the run, so that
+/// the payloads of a pair are combined in the order of the runs.
+struct Head<G, K, V> {
+    g: G,
+    k: K,
+    v: V,
+    run: usize,
+}
+
+impl<G: Ord, K: Ord, V> Ord for Head<G, K, V> {
+    fn cmp(&self, other: &Self) -> Ordering {
+        (&self.g, &self.k, self.run).cmp(&(&other.g, &other.k, other.run))
+    }
+}
+
+impl<G: Ord, K: Ord, V> PartialOrd for Head<G, K, V> {
+    fn partial_cmp(&self, other: &Self) -> Option<Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<G: Ord, K: Ord, V> PartialEq for Head<G, K, V> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cmp(other) == Ordering::Equal
+    }
+}
+
+impl<G: Ord, K: Ord, V> Eq for Head<G, K, V> {}
+
+/// Merges sorted runs into a single sorted sequence of pairs, combining
+/// the payloads of each pair found in more than one run.
+struct Merge<G, K, V, F> {
+    runs: Vec<Run<G, K, V>>,
+    heads: BinaryHeap<Reverse<Head<G, K, V>>>,
+    combine: F,
+    /// The runs that failed to be read, and so were dropped from the
+    /// merge, not yet reported.
+    lost: VecDeque<LostRun>,
+}
+
+impl<G, K, V, F> Merge<G, K, V, F>
+where
+    G: Ord + DeserializeOwned,
+    K: Ord + DeserializeOwned,
+    V: DeserializeOwned,
+    F: FnMut(&mut V, V),
+{
+    fn new(runs: Vec<Run<G, K, V>>, combine: F, lost: VecDeque<LostRun>) -> Self {
+        let heads = BinaryHeap::with_capacity(runs.len());
+        let mut merge = Self { runs, heads, combine, lost };
+        for run in 0..merge.runs.len() {
+            merge.advance(run);
+        }
+        merge
+    }
+
+    /// Read the next pair of a run, if any.
+    ///
+    /// A run that fails to be read is not read again, and its pairs not
+    /// yet read are lost, while the other runs are merged as before.
+    fn advance(&mut self, run: usize) {
+        match self.runs[run].next() {
+            Some(Ok((g, k, v))) => self.heads.push(Reverse(Head { g, k, v, run })),
+            Some(Err(lost)) => self.lost.push_back(lost),
+            None => (),
+        }
+    }
+
+    /// Get the next pair, with its payloads combined.
+    fn next_pair(&mut self) -> Option<(G, K, V)> {
+        let Reverse(Head { g, k, mut v, run }) = self.heads.pop()?;
+        self.advance(run);
+        loop {
+            let Some(top) = self.heads.peek_mut() else { break };
+            if top.0.g != g || top.0.k != k {
+                break;
+            }
+            let Reverse(next) = PeekMut::pop(top);
+            (self.combine)(&mut v, next.v);
+            self.advance(next.run);
+        }
+        Some((g, k, v))
+    }
+}
+
+/// Lists the merged pairs by group.
+struct Groups<G, K, V, F> {
+    merge: Merge<G, K, V, F>,
+    /// The first pair of the next group, read while finding the end of
+    /// the group before.
+    pending: Option<(G, K, V)>,
+}
+
+impl<G, K, V, F> Iterator for Groups<G, K, V, F>
+where
+    G: Ord + DeserializeOwned,
+    K: Ord + DeserializeOwned,
+    V: DeserializeOwned,
+    F: FnMut(&mut V, V),
+{
+    type Item = Result<Group<G, K, V>, LostRun>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        // A run lost while the group before was read is reported first.
+        if let Some(lost) = self.merge.lost.pop_front() {
+            return Some(Err(lost));
+        }
+        let (g, k, v) = match self.pending.take() {
+            Some(pair) => pair,
+            None => self.merge.next_pair()?,
+        };
+        let mut pairs = vec![(k, v)];
+        while let Some((g2, k, v)) = self.merge.next_pair() {
+            if g2 != g {
+                self.pending = Some((g2, k, v));
+                break;
+            }
+            pairs.push((k, v));
+        }
+        Some(Ok((g, pairs)))
+    }
+}
@@ -0,0 +1,52 @@
+use rusqlite::{Connection, Row, ToSql, types::FromSql};
+
+/// Read the group key, aggregation key and payload from the first three
+/// columns of a row, such as a row of a table written by to_sqlite.
+///
+/// This can be given to from_sqlite as the mapping of a query such as
+/// SELECT "group", "key", "value" FROM hits.
+pub fn sqlite_row<G: FromSql, K: FromSql, V: FromSql>(row: &Row) -> rusqlite::Result<(G, K, V)> {
+    Ok((row.get(0)?, row.get(1)?, row.get(2)?))
+}
+
+/// Quote a name for use as an SQL identifier.
+fn quote(name: &str) -> String {
+    format!("\"{}\"", name.replace('"', "\"\""))
+}
+
+/// Insert one row for each group key, aggregation key and payload listed
+/// into a table with group, key and value columns, creating the table if
+/// it does not exist.
+///
+/// The rows are inserted in a single transaction, so either all of them
+/// are written or none.
+pub(crate) fn write_rows<'a>(
+    conn: &Connection,
+    table: &str,
+    rows: impl Iterator<Item = [&'a dyn ToSql; 3]>,
+) -> rusqlite::Result<()> {
+    let table = quote(table);
+    let tx = conn.unchecked_transaction()?;
+    tx.execute(&format!("CREATE TABLE IF NOT EXISTS {table} (\"group\", \"key\", \"value\")"), [])?;
+    {
+        let mut insert = tx.prepare(&format!("INSERT INTO {table} (\"group\", \"key\", \"value\") VALUES (?1, ?2, ?3)"))?;
+        for row in rows {
+            insert.execute(row)?;
+        }
+    }
+    tx.commit()
+}
+
+/// Run a query, passing each row of its results to a function.
+pub(crate) fn read_rows(
+    conn: &Connection,
+    query: &str,
+    mut f: impl FnMut(&Row) -> rusqlite::Result<()>,
+) -> rusqlite::Result<()> {
+    let mut statement = conn.prepare(query)?;
+    let mut rows = statement.query([])?;
+    while let Some(row) = rows.next()? {
+        f(row)?;
+    }
+    Ok(())
+}
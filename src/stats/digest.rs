@@ -0,0 +1,208 @@
+use std::f64::consts::PI;
+
+/// The default compression of a TDigest, which keeps the estimates of
+/// extreme quantiles such as p99 within a small fraction of a percent.
+const DEFAULT_COMPRESSION: f64 = 100.0;
+
+/// The greatest compression of a TDigest. Beyond this, the clusters and
+/// the buffer would take more memory than the estimates are worth.
+const MAX_COMPRESSION: f64 = 100_000.0;
+
+/// A cluster of nearby numbers, kept as their mean and count.
+#[derive(Clone, Copy, Debug, PartialEq)]
+struct Centroid {
+    mean: f64,
+    weight: f64,
+}
+
+/// A sketch of the distribution of a series of numbers, from which any
+/// quantile, such as the median or p99, can be estimated, in memory that
+/// does not grow with the length of the series.
+/// 
+/// The numbers are clustered by the merging t-digest method, with small
+/// clusters near the extremes, so that the estimates of extreme quantiles
+/// are the most accurate.
+#[derive(Clone, Debug, PartialEq)]
+pub struct TDigest {
+    compression: f64,
+    /// The clusters, in order of their means.
+    centroids: Vec<Centroid>,
+    /// The numbers observed since the clusters were last updated.
+    buffer: Vec<Centroid>,
+    count: u64,
+    min: f64,
+    max: f64,
+}
+
+impl Default for TDigest {
+    fn default() -> Self {
+        Self::with_compression(DEFAULT_COMPRESSION)
+    }
+}
+
+impl TDigest {
+    /// Create a sketch of an empty series, with the default compression.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Create a sketch of an empty series with the specified compression.
+    /// 
+    /// compression: Roughly the greatest number of clusters kept. Greater
+    ///     values give more accurate estimates in more memory. It is
+    ///     clamped to the range 1 to 100,000, and NaN is taken as the
+    ///     default.
+    pub fn with_compression(compression: f64) -> Self {
+        let compression = if compression.is_nan() { DEFAULT_COMPRESSION } else { compression };
+        Self {
+            compression: compression.clamp(1.0, MAX_COMPRESSION),
+            centroids: Vec::new(),
+            buffer: Vec::new(),
+            count: 0,
+            min: f64::INFINITY,
+            max: f64::NEG_INFINITY,
+        }
+    }
+
+    /// Add a number to the series.
+    /// 
+    /// NaN is ignored.
+    pub fn observe(&mut self, x: f64) {
+        if x.is_nan() {
+            return;
+        }
+        self.count += 1;
+        self.min = self.min.min(x);
+        self.max = self.max.max(x);
+        self.buffer.push(Centroid { mean: x, weight: 1.0 });
+        if self.buffer.len() >= self.buffer_limit() {
+            self.compress();
+        }
+    }
+
+    /// Combine the sketch of another series, as if its numbers had been
+    /// observed here.
+    pub fn merge(&mut self, other: &TDigest) {
+        self.count += other.count;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+        self.buffer.extend_from_slice(&other.centroids);
+        self.buffer.extend_from_slice(&other.buffer);
+        self.compress();
+    }
+
+    /// The number of numbers observed.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The least number observed, if any.
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    /// The greatest number observed, if any.
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    /// Estimate the quantile q of the numbers observed, such as 0.5 for
+    /// the median or 0.99 for p99, if any were observed.
+    /// 
+    /// q is clamped to the range 0 to 1, so that 0 gives the least number
+    /// observed and 1 the greatest. The numbers observed since the last
+    /// estimate are first merged into the clusters, as flush does, so a
+    /// run of estimates merges them only once.
+    pub fn quantile(&mut self, q: f64) -> Option<f64> {
+        self.flush();
+        self.estimate(q)
+    }
+
+    /// Merge the numbers observed since the clusters were last updated
+    /// into the clusters, so that quantiles can be estimated without
+    /// changing the sketch.
+    pub fn flush(&mut self) {
+        self.compress();
+    }
+
+    /// Estimate the quantile q of the numbers merged into the clusters by
+    /// the last flush, as quantile does, without changing the sketch, such
+    /// as through a shared reference. The numbers observed since are left
+    /// out, other than as the least or greatest number, so flush first
+    /// after observing more.
+    pub fn estimate(&self, q: f64) -> Option<f64> {
+        if self.centroids.is_empty() {
+            return None;
+        }
+        let q = q.clamp(0.0, 1.0);
+        let centroids = &self.centroids;
+        let total: f64 = centroids.iter().map(|c| c.weight).sum();
+        let target = q * total;
+        if centroids.len() == 1 || q == 0.0 || q == 1.0 {
+            return Some(if q == 0.0 { self.min } else if q == 1.0 { self.max } else { centroids[0].mean });
+        }
+        // Each cluster is taken to be centered on its mean, and the
+        // quantiles between the centers are interpolated.
+        let first = centroids[0];
+        if target < first.weight / 2.0 {
+            return Some(self.min + (first.mean - self.min) * target / (first.weight / 2.0));
+        }
+        let mut before = 0.0;
+        for pair in centroids.windows(2) {
+            let left = before + pair[0].weight / 2.0;
+            let right = before + pair[0].weight + pair[1].weight / 2.0;
+            if target < right {
+                let t = (target - left) / (right - left);
+                return Some(pair[0].mean + t * (pair[1].mean - pair[0].mean));
+            }
+            before += pair[0].weight;
+        }
+        let last = centroids[centroids.len() - 1];
+        let t = ((target - (total - last.weight / 2.0)) / (last.weight / 2.0)).min(1.0);
+        Some(last.mean + t * (self.max - last.mean))
+    }
+
+    /// The number of numbers to buffer before updating the clusters.
+    fn buffer_limit(&self) -> usize {
+        (self.compression as usize) * 5
+    }
+
+    /// Merge the buffered numbers into the clusters, merging neighboring
+    /// clusters as far as the scale function allows.
+    fn compress(&mut self) {
+        if self.buffer.is_empty() {
+            return;
+        }
+        let mut all = std::mem::take(&mut self.centroids);
+        all.append(&mut self.buffer);
+        all.sort_unstable_by(|a, b| a.mean.total_cmp(&b.mean));
+        let total: f64 = all.iter().map(|c| c.weight).sum();
+        let mut result = Vec::with_capacity(self.compression as usize * 2);
+        let mut before = 0.0;
+        let mut limit = total * self.next_quantile(0.0);
+        let mut current = all[0];
+        for &c in &all[1..] {
+            if before + current.weight + c.weight <= limit {
+                let weight = current.weight + c.weight;
+                current.mean += (c.mean - current.mean) * c.weight / weight;
+                current.weight = weight;
+            } else {
+                before += current.weight;
+                result.push(current);
+                limit = total * self.next_quantile(before / total);
+                current = c;
+            }
+        }
+        result.push(current);
+        self.centroids = result;
+    }
+
+    /// Find the greatest quantile that a cluster starting at quantile q
+    /// may reach, by the scale function k(q) = d / 2π · asin(2q - 1).
+    fn next_quantile(&self, q: f64) -> f64 {
+        let scale = self.compression / (2.0 * PI);
+        let k = scale * (2.0 * q - 1.0).asin() + 1.0;
+        let k = k.min(self.compression / 4.0);
+        ((k / scale).sin() + 1.0) / 2.0
+    }
+}
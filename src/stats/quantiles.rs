@@ -0,0 +1,157 @@
+use std::{cmp::Ordering, hash::Hash};
+use crate::{Capacity, core::{self, Interned, KeyStore}};
+use super::TDigest;
+
+/// A collection of distinct pairs (g, k) grouped by g, with a sketch of
+/// the distribution of the numbers observed for each pair, from which
+/// quantiles such as p50, p95 and p99 can be estimated.
+/// 
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// S is the strategy for storing the aggregation keys.
+pub struct BilevelQuantiles<G, K, S = Interned<K>>
+where
+    S: KeyStore<K>,
+{
+    inner: core::BilevelMap<G, K, TDigest, S>,
+}
+
+impl<G: Hash, K, S: KeyStore<K>> BilevelQuantiles<G, K, S> {
+    /// Create a new collection, with the default compression for the
+    /// sketch of each pair.
+    /// 
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    pub fn new() -> Self {
+        Self { inner: core::BilevelMap::new() }
+    }
+
+    /// Create a new collection with the specified capacity, with the
+    /// default compression for the sketch of each pair.
+    pub fn with_capacity(capacity: Capacity) -> Self {
+        Self { inner: core::BilevelMap::with_capacity(capacity) }
+    }
+
+    /// Add a number to the sketch of the specified key pair.
+    pub fn observe<GRef, KRef>(&mut self, g: &GRef, k: &KRef, x: f64)
+    where
+        GRef: ToOwned<Owned = G> + PartialEq<G> + Hash + ?Sized,
+        KRef: ToOwned<Owned = K> + PartialEq<K> + Hash + ?Sized,
+    {
+        self.inner.add_or_get(g, k).observe(x);
+    }
+
+    /// List the pairs currently in the collection, with the sketch of
+    /// each.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn iter(&self) -> impl Iterator<Item = (&G, &K, &TDigest)> {
+        self.inner.iter()
+    }
+
+    /// List the pairs currently in the collection, with the estimate of
+    /// the quantile q of each, such as 0.99 for p99.
+    /// 
+    /// The sketches are flushed first, as flush does.
+    /// Pairs are grouped by g.
+    pub fn iter_quantile(&mut self, q: f64) -> impl Iterator<Item = (&G, &K, f64)> {
+        self.flush();
+        // Every sketch in the collection has at least one number.
+        self.inner.iter().filter_map(move |(g, k, d)| Some((g, k, d.estimate(q)?)))
+    }
+
+    /// Merge the numbers observed for each pair into the clusters of its
+    /// sketch, as TDigest::flush does, so that the estimates of several
+    /// quantiles merge them only once.
+    pub fn flush(&mut self) {
+        for (_, _, d) in self.inner.iter_mut() {
+            d.flush();
+        }
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (&G, &K, &TDigest)>
+    where
+        G: Ord,
+        K: Ord,
+    {
+        self.inner.iter_sorted()
+    }
+
+    /// List the pairs currently in the collection, sorted using the
+    /// specified comparison function.
+    pub fn iter_sorted_by<'a, F>(&'a self, compare: F) -> impl Iterator<Item = (&'a G, &'a K, &'a TDigest)> + 'a
+    where
+        F: FnMut(&(&'a G, &'a K, &'a TDigest), &(&'a G, &'a K, &'a TDigest)) -> Ordering + 'a,
+    {
+        self.inner.iter_sorted_by(compare)
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    /// Count the pairs currently in the collection.
+    pub fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    /// Convert into a map from each pair to its sketch, consuming the
+    /// collection.
+    pub fn into_map(self) -> core::BilevelMap<G, K, TDigest, S> {
+        self.inner
+    }
+}
+
+impl<G: Hash, K, S: KeyStore<K>> Default for BilevelQuantiles<G, K, S> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G, K, S> BilevelQuantiles<G, K, S>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    S: KeyStore<K>,
+{
+    /// Merge the sketches of another collection into this one, consuming
+    /// it, such as one gathered on another worker.
+    pub fn merge(&mut self, other: Self) {
+        self.inner.merge(other.inner, |a, b| a.merge(&b));
+    }
+}
+
+impl<G, K, S> std::fmt::Debug for BilevelQuantiles<G, K, S>
+where
+    G: Hash + std::fmt::Debug,
+    K: std::fmt::Debug,
+    S: KeyStore<K>,
+{
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.inner, f)
+    }
+}
+
+#[cfg(feature = "tenant")]
+impl<G: Hash, K, S: KeyStore<K>> crate::TenantData for BilevelQuantiles<G, K, S> {
+    type Item<'a> = (&'a G, &'a K, &'a TDigest) where Self: 'a;
+
+    fn pairs(&self) -> impl Iterator<Item = (&G, &K, &TDigest)> {
+        self.iter()
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        crate::TenantData::memory_usage(&self.inner)
+    }
+}
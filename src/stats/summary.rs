@@ -0,0 +1,99 @@
+/// Summary statistics of a series of numbers, updated as each number is
+/// observed without keeping the numbers.
+/// 
+/// The mean and variance are updated by Welford's method, which stays
+/// accurate for long series where summing squares would not.
+#[derive(Clone, Copy, Debug, Default, PartialEq)]
+pub struct Stats {
+    count: u64,
+    sum: f64,
+    min: f64,
+    max: f64,
+    mean: f64,
+    /// The sum of the squared differences from the mean.
+    m2: f64,
+}
+
+impl Stats {
+    /// Create statistics of an empty series.
+    pub fn new() -> Self {
+        Self::default()
+    }
+
+    /// Add a number to the series.
+    pub fn observe(&mut self, x: f64) {
+        if self.count == 0 {
+            self.min = x;
+            self.max = x;
+        } else {
+            self.min = self.min.min(x);
+            self.max = self.max.max(x);
+        }
+        self.count += 1;
+        self.sum += x;
+        let delta = x - self.mean;
+        self.mean += delta / self.count as f64;
+        self.m2 += delta * (x - self.mean);
+    }
+
+    /// Combine the statistics of another series, as if its numbers had
+    /// been observed here.
+    pub fn merge(&mut self, other: &Stats) {
+        if other.count == 0 {
+            return;
+        }
+        if self.count == 0 {
+            *self = *other;
+            return;
+        }
+        let count = self.count + other.count;
+        let delta = other.mean - self.mean;
+        self.m2 += other.m2 + delta * delta * (self.count as f64 * other.count as f64 / count as f64);
+        self.mean += delta * other.count as f64 / count as f64;
+        self.count = count;
+        self.sum += other.sum;
+        self.min = self.min.min(other.min);
+        self.max = self.max.max(other.max);
+    }
+
+    /// The number of numbers observed.
+    pub fn count(&self) -> u64 {
+        self.count
+    }
+
+    /// The sum of the numbers observed.
+    pub fn sum(&self) -> f64 {
+        self.sum
+    }
+
+    /// The least number observed, if any.
+    pub fn min(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.min)
+    }
+
+    /// The greatest number observed, if any.
+    pub fn max(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.max)
+    }
+
+    /// The mean of the numbers observed, if any.
+    pub fn mean(&self) -> Option<f64> {
+        (self.count > 0).then_some(self.mean)
+    }
+
+    /// The population variance of the numbers observed, if any.
+    pub fn variance(&self) -> Option<f64> {
+        (self.count > 0).then(|| self.m2 / self.count as f64)
+    }
+
+    /// The sample variance of the numbers observed, if there are at
+    /// least two.
+    pub fn sample_variance(&self) -> Option<f64> {
+        (self.count > 1).then(|| self.m2 / (self.count - 1) as f64)
+    }
+
+    /// The population standard deviation of the numbers observed, if any.
+    pub fn std_dev(&self) -> Option<f64> {
+        self.variance().map(f64::sqrt)
+    }
+}
@@ -0,0 +1,97 @@
+use super::*;
+
+#[test]
+pub fn test_stats() {
+    let mut stats = Stats::new();
+    assert_eq!((stats.min(), stats.mean(), stats.variance()), (None, None, None));
+    for x in [2.0, 4.0, 4.0, 4.0, 5.0, 5.0, 7.0, 9.0] {
+        stats.observe(x);
+    }
+    assert_eq!(stats.count(), 8);
+    assert_eq!(stats.sum(), 40.0);
+    assert_eq!((stats.min(), stats.max()), (Some(2.0), Some(9.0)));
+    assert_eq!(stats.mean(), Some(5.0));
+    assert_eq!(stats.variance(), Some(4.0));
+    assert_eq!(stats.std_dev(), Some(2.0));
+    assert!((stats.sample_variance().unwrap() - 32.0 / 7.0).abs() < 1e-12);
+}
+
+#[test]
+pub fn test_merge() {
+    let xs = [1.5, -3.0, 8.25, 0.0, 2.0, 11.0, 4.5];
+    let mut all = Stats::new();
+    let mut a = Stats::new();
+    let mut b = Stats::new();
+    for (i, &x) in xs.iter().enumerate() {
+        all.observe(x);
+        if i < 3 { a.observe(x) } else { b.observe(x) }
+    }
+    a.merge(&b);
+    assert_eq!(a.count(), all.count());
+    assert_eq!((a.min(), a.max()), (all.min(), all.max()));
+    assert!((a.mean().unwrap() - all.mean().unwrap()).abs() < 1e-12);
+    assert!((a.variance().unwrap() - all.variance().unwrap()).abs() < 1e-12);
+}
+
+#[test]
+pub fn test_bilevel_stats() {
+    let mut map: BilevelStats<String, String> = BilevelStats::new();
+    let mut other: BilevelStats<String, String> = BilevelStats::new();
+    map.observe("api", "GET", 10.0);
+    map.observe("api", "GET", 30.0);
+    other.observe("api", "GET", 20.0);
+    other.observe("api", "POST", 5.0);
+    map.merge(other);
+    let result: Vec<_> = map.iter_sorted()
+        .map(|(g, k, s)| (g.as_str(), k.as_str(), s.count(), s.mean().unwrap()))
+        .collect();
+    assert_eq!(result, [("api", "GET", 3, 20.0), ("api", "POST", 1, 5.0)]);
+}
+
+#[cfg(feature = "quantile")]
+#[test]
+pub fn test_digest() {
+    let mut digest = TDigest::new();
+    assert_eq!(digest.quantile(0.5), None);
+    // Observe the numbers out of order, so that the clusters must be sorted.
+    for i in 0..10_000 {
+        digest.observe(((i * 7_919) % 10_000 + 1) as f64);
+    }
+    assert_eq!(digest.count(), 10_000);
+    assert_eq!((digest.quantile(0.0), digest.quantile(1.0)), (Some(1.0), Some(10_000.0)));
+    for (q, expected) in [(0.5, 5_000.0), (0.95, 9_500.0), (0.99, 9_900.0), (0.999, 9_990.0)] {
+        let estimate = digest.quantile(q).unwrap();
+        assert!((estimate - expected).abs() < 0.002 * 10_000.0, "q {} estimated {}", q, estimate);
+    }
+    // Once flushed, estimates are read through a shared reference.
+    digest.observe(20_000.0);
+    digest.flush();
+    let digest = &digest;
+    assert_eq!(digest.estimate(1.0), Some(20_000.0));
+
+    // Compressions beyond the range are clamped rather than allocated.
+    for compression in [f64::INFINITY, f64::NAN, -1.0, 1e300] {
+        let mut digest = TDigest::with_compression(compression);
+        for i in 0..1_000 {
+            digest.observe(i as f64);
+        }
+        assert!((digest.quantile(0.5).unwrap() - 500.0).abs() < 50.0, "compression {}", compression);
+    }
+}
+
+#[cfg(feature = "quantile")]
+#[test]
+pub fn test_quantiles() {
+    let mut a: BilevelQuantiles<String, String> = BilevelQuantiles::new();
+    let mut b: BilevelQuantiles<String, String> = BilevelQuantiles::new();
+    for i in 1..=100 {
+        let target = if i % 2 == 0 { &mut a } else { &mut b };
+        target.observe("api", "GET", i as f64);
+    }
+    b.observe("api", "POST", 7.0);
+    a.merge(b);
+    let result: Vec<_> = a.iter_quantile(0.5).map(|(g, k, x)| (g.as_str(), k.as_str(), x)).collect();
+    let get = result.iter().find(|r| r.1 == "GET").unwrap();
+    assert!((get.2 - 50.5).abs() <= 1.0);
+    assert!(result.contains(&("api", "POST", 7.0)));
+}
@@ -0,0 +1,140 @@
+use std::{borrow::Borrow, collections::HashMap, hash::Hash, sync::{RwLock, atomic::{AtomicU64, Ordering}}, thread};
+use crate::{hash, small::SmallMap};
+
+/// The groups held in one shard.
+type Shard<G, K> = RwLock<HashMap<G, SmallMap<K, AtomicU64>>>;
+
+/// A collection of distinct pairs (g, k) grouped by g, with a count
+/// associated with each pair, that many threads can count into at once.
+/// 
+/// The groups are divided between a number of shards, each behind a
+/// read-write lock of its own. Counting a pair already present only needs
+/// the shared lock, so threads counting known pairs never wait for each
+/// other; only adding a new pair takes the exclusive lock of its shard.
+/// 
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+pub struct BilevelCounter<G, K> {
+    shards: Box<[Shard<G, K>]>,
+    per_group: usize,
+}
+
+impl<G: Hash + Eq, K: Hash + Eq> BilevelCounter<G, K> {
+    /// Create a new collection.
+    /// 
+    /// A few shards are created for each thread the system can run at
+    /// once.
+    pub fn new() -> Self {
+        let threads = thread::available_parallelism().map_or(4, |n| n.get());
+        Self::with_shards(threads * 4)
+    }
+
+    /// Create a new collection with the specified number of shards.
+    /// 
+    /// At least one shard is always created.
+    pub fn with_shards(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| RwLock::new(HashMap::new())).collect(),
+            per_group: 4,
+        }
+    }
+
+    /// Find the shard holding a group, by any form its key borrows as,
+    /// which hashes as the key does.
+    fn shard<Q: Hash + ?Sized>(&self, g: &Q) -> &Shard<G, K> {
+        &self.shards[hash(g) as usize % self.shards.len()]
+    }
+
+    /// Add one to the count for the specified key pair.
+    pub fn increment(&self, g: G, k: K) {
+        self.add(g, k, 1)
+    }
+
+    /// Add to the count for the specified key pair.
+    /// 
+    /// If the key pair is currently not present, it is added with a count
+    /// of zero first.
+    pub fn add(&self, g: G, k: K, n: u64) {
+        let shard = self.shard(&g);
+        {
+            let groups = shard.read().unwrap();
+            if let Some(count) = groups.get(&g).and_then(|inner| inner.get(&k)) {
+                count.fetch_add(n, Ordering::Relaxed);
+                return;
+            }
+        }
+        // Escalate to the exclusive lock to add the pair. Another thread
+        // may have added it meanwhile, so it is looked up again.
+        let mut groups = shard.write().unwrap();
+        groups.entry(g)
+            .or_insert_with(|| SmallMap::with_capacity(self.per_group))
+            .entry(k)
+            .or_insert_with(AtomicU64::default)
+            .fetch_add(n, Ordering::Relaxed);
+    }
+
+    /// Get the count for the specified key pair, if present.
+    /// 
+    /// The keys can be given in any form they borrow as, such as &str for
+    /// String keys.
+    pub fn get<GQ, KQ>(&self, g: &GQ, k: &KQ) -> Option<u64>
+    where
+        G: Borrow<GQ>,
+        K: Borrow<KQ>,
+        GQ: Hash + Eq + ?Sized,
+        KQ: Hash + Eq + ?Sized,
+    {
+        let groups = self.shard(g).read().unwrap();
+        groups.get(g)
+            .and_then(|inner| inner.get(k))
+            .map(|count| count.load(Ordering::Relaxed))
+    }
+
+    /// Call a function for each pair currently in the collection, with
+    /// its count.
+    /// 
+    /// Pairs are grouped by g. Each shard is locked in turn while its
+    /// pairs are listed, so counts made meanwhile may or may not be seen.
+    pub fn for_each(&self, mut f: impl FnMut(&G, &K, u64)) {
+        for shard in self.shards.iter() {
+            let groups = shard.read().unwrap();
+            for (g, inner) in groups.iter() {
+                for (k, count) in inner {
+                    f(g, k, count.load(Ordering::Relaxed));
+                }
+            }
+        }
+    }
+}
+
+impl<G: Hash + Eq, K: Hash + Eq> Default for BilevelCounter<G, K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: Hash + Eq + Clone, K: Hash + Eq> BilevelCounter<G, K> {
+    /// List the counts for the pairs in the collection, consuming the
+    /// collection.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn into_iter(self) -> impl Iterator<Item = (G, K, u64)> {
+        self.shards.into_vec().into_iter()
+            .flat_map(|shard| shard.into_inner().unwrap())
+            .flat_map(|(g, inner)| {
+                inner.into_iter().map(move |(k, count)| (g.clone(), k, count.into_inner()))
+            })
+    }
+}
+
+impl<G: std::fmt::Debug, K: std::fmt::Debug> std::fmt::Debug for BilevelCounter<G, K> {
+    /// Format the collection as a map from each group key to a map of its
+    /// aggregation keys and counts, listing only the first few groups, and
+    /// keys of each group, of a large collection.
+    /// 
+    /// Every shard is locked for reading while the collection is formatted.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let shards: Vec<_> = self.shards.iter().map(|shard| shard.read().unwrap()).collect();
+        crate::debug::debug_map(f, shards.iter().flat_map(|shard| shard.iter()))
+    }
+}
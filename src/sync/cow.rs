@@ -0,0 +1,178 @@
+use std::{borrow::Borrow, collections::HashMap, hash::Hash, sync::{Arc, Mutex}};
+use crate::small::SmallMap;
+
+/// The groups of a SnapshotMap, each shared between the map and any
+/// snapshots taken before the group was last changed.
+type Groups<G, K, V> = HashMap<G, Arc<SmallMap<K, V>>>;
+
+/// A collection of distinct pairs (g, k) grouped by g, with a payload
+/// associated with each pair, that can be read through snapshots while
+/// it is being added to.
+/// 
+/// A snapshot shares the groups of the collection rather than copying
+/// them, so taking one only clones a reference. While a snapshot is held,
+/// the first change to the collection copies its table of groups, with a
+/// reference to each group rather than the group itself, and the first
+/// change to each group copies that group alone. So a dashboard can poll
+/// a live aggregator without pausing ingestion, at the cost of a copy of
+/// each group changed between polls.
+/// 
+/// The collection is behind a single lock, held only while a pair is
+/// added or a snapshot is taken, so that each snapshot holds the
+/// contents at one moment.
+/// 
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// V is the type of the payload.
+pub struct SnapshotMap<G, K, V> {
+    groups: Mutex<Arc<Groups<G, K, V>>>,
+    per_group: usize,
+}
+
+/// The contents of a SnapshotMap at the moment the snapshot was taken,
+/// which can be read, and sent to other threads, however the collection
+/// changes afterwards.
+/// 
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// V is the type of the payload.
+pub struct MapSnapshot<G, K, V> {
+    groups: Arc<Groups<G, K, V>>,
+}
+
+impl<G, K, V> SnapshotMap<G, K, V> {
+    /// Create a new collection.
+    pub fn new() -> Self {
+        Self {
+            groups: Mutex::new(Arc::new(HashMap::new())),
+            per_group: 4,
+        }
+    }
+
+    /// Take a snapshot of the pairs currently in the collection.
+    /// 
+    /// The lock is held only to clone a reference to the groups, so the
+    /// snapshot can be read for as long as needed without holding up the
+    /// threads adding to the collection.
+    pub fn snapshot(&self) -> MapSnapshot<G, K, V> {
+        MapSnapshot { groups: Arc::clone(&self.groups.lock().unwrap()) }
+    }
+}
+
+impl<G, K, V> Default for SnapshotMap<G, K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G, K, V> SnapshotMap<G, K, V>
+where
+    G: Hash + Eq + Clone,
+    K: Hash + Eq + Clone,
+    V: Default + Clone,
+{
+    /// Call a function with a mutable reference to the payload for the
+    /// specified key pair, and return its result.
+    /// 
+    /// If the key pair is currently not present, the default payload is
+    /// inserted. If a snapshot still shares the group, the group is copied
+    /// first, leaving the snapshot unchanged. The collection is locked
+    /// while the function runs, so the function should be brief.
+    pub fn add_or_get<R>(&self, g: G, k: K, f: impl FnOnce(&mut V) -> R) -> R {
+        let mut groups = self.groups.lock().unwrap();
+        let group = Arc::make_mut(&mut groups)
+            .entry(g)
+            .or_insert_with(|| Arc::new(SmallMap::with_capacity(self.per_group)));
+        let v = Arc::make_mut(group).entry(k).or_insert_with(V::default);
+        f(v)
+    }
+}
+
+impl<G: Hash + Eq, K: Hash + Eq, V> MapSnapshot<G, K, V> {
+    /// Get a reference to the payload for the specified key pair, if
+    /// present.
+    /// 
+    /// The keys can be given in any form they borrow as, such as &str for
+    /// String keys.
+    pub fn get<GQ, KQ>(&self, g: &GQ, k: &KQ) -> Option<&V>
+    where
+        G: Borrow<GQ>,
+        K: Borrow<KQ>,
+        GQ: Hash + Eq + ?Sized,
+        KQ: Hash + Eq + ?Sized,
+    {
+        self.groups.get(g).and_then(|inner| inner.get(k))
+    }
+
+    /// Check whether the specified group is present.
+    pub fn contains_group<GQ>(&self, g: &GQ) -> bool
+    where
+        G: Borrow<GQ>,
+        GQ: Hash + Eq + ?Sized,
+    {
+        self.groups.contains_key(g)
+    }
+
+    /// List the group keys in the snapshot, with the number of
+    /// aggregation keys in each group.
+    pub fn group_sizes(&self) -> impl Iterator<Item = (&G, usize)> {
+        self.groups.iter().map(|(g, inner)| (g, inner.len()))
+    }
+
+    /// Count the pairs in the snapshot.
+    pub fn len(&self) -> usize {
+        self.groups.values().map(|inner| inner.len()).sum()
+    }
+}
+
+impl<G, K, V> MapSnapshot<G, K, V> {
+    /// List the payloads for the pairs in the snapshot.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn iter(&self) -> impl Iterator<Item = (&G, &K, &V)> {
+        self.groups.iter().flat_map(|(g, inner)| inner.iter().map(move |(k, v)| (g, k, v)))
+    }
+
+    /// Count the groups in the snapshot.
+    pub fn group_count(&self) -> usize {
+        self.groups.len()
+    }
+
+    /// Check whether the snapshot holds no pairs.
+    pub fn is_empty(&self) -> bool {
+        self.groups.is_empty()
+    }
+}
+
+impl<G, K, V> Clone for MapSnapshot<G, K, V> {
+    /// Clone a reference to the snapshot, without copying its contents.
+    fn clone(&self) -> Self {
+        Self { groups: Arc::clone(&self.groups) }
+    }
+}
+
+impl<G, K, V> std::fmt::Debug for SnapshotMap<G, K, V>
+where
+    G: std::fmt::Debug,
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+{
+    /// Format the collection as its current snapshot would be formatted.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        std::fmt::Debug::fmt(&self.snapshot(), f)
+    }
+}
+
+impl<G, K, V> std::fmt::Debug for MapSnapshot<G, K, V>
+where
+    G: std::fmt::Debug,
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+{
+    /// Format the snapshot as a map from each group key to a map of its
+    /// aggregation keys and payloads, listing only the first few groups,
+    /// and keys of each group, of a large snapshot.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        crate::debug::debug_map(f, self.groups.iter().map(|(g, inner)| (g, &**inner)))
+    }
+}
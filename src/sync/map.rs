@@ -0,0 +1,128 @@
+use std::{borrow::Borrow, collections::HashMap, hash::Hash, sync::Mutex, thread};
+use crate::{hash, small::SmallMap};
+
+/// The groups held in one shard.
+type Shard<G, K, V> = Mutex<HashMap<G, SmallMap<K, V>>>;
+
+/// A collection of distinct pairs (g, k) grouped by g, with a payload
+/// associated with each pair, that many threads can add to at once.
+/// 
+/// The groups are divided between a number of shards, each behind a lock
+/// of its own, so that threads only contend when they touch groups in
+/// the same shard.
+/// 
+/// G is the type of the group key.
+/// K is the type of the remaining key.
+/// V is the type of the payload.
+pub struct BilevelMap<G, K, V> {
+    shards: Box<[Shard<G, K, V>]>,
+    per_group: usize,
+}
+
+impl<G: Hash + Eq, K: Hash + Eq, V: Default> BilevelMap<G, K, V> {
+    /// Create a new collection.
+    /// 
+    /// A few shards are created for each thread the system can run at
+    /// once.
+    pub fn new() -> Self {
+        let threads = thread::available_parallelism().map_or(4, |n| n.get());
+        Self::with_shards(threads * 4)
+    }
+
+    /// Create a new collection with the specified number of shards.
+    /// 
+    /// At least one shard is always created.
+    pub fn with_shards(shards: usize) -> Self {
+        Self {
+            shards: (0..shards.max(1)).map(|_| Mutex::new(HashMap::new())).collect(),
+            per_group: 4,
+        }
+    }
+
+    /// Find the shard holding a group, by any form its key borrows as,
+    /// which hashes as the key does.
+    fn shard<Q: Hash + ?Sized>(&self, g: &Q) -> &Shard<G, K, V> {
+        &self.shards[hash(g) as usize % self.shards.len()]
+    }
+
+    /// Call a function with a mutable reference to the payload for the
+    /// specified key pair, and return its result.
+    /// 
+    /// If the key pair is currently not present, the default payload is
+    /// inserted. The shard holding the group is locked while the function
+    /// runs, so the function should be brief.
+    pub fn add_or_get<R>(&self, g: G, k: K, f: impl FnOnce(&mut V) -> R) -> R {
+        let mut shard = self.shard(&g).lock().unwrap();
+        let v = shard.entry(g)
+            .or_insert_with(|| SmallMap::with_capacity(self.per_group))
+            .entry(k)
+            .or_insert_with(V::default);
+        f(v)
+    }
+
+    /// Call a function with a reference to the payload for the specified
+    /// key pair, if present, and return its result.
+    /// 
+    /// The keys can be given in any form they borrow as, such as &str for
+    /// String keys.
+    pub fn get<GQ, KQ, R>(&self, g: &GQ, k: &KQ, f: impl FnOnce(&V) -> R) -> Option<R>
+    where
+        G: Borrow<GQ>,
+        K: Borrow<KQ>,
+        GQ: Hash + Eq + ?Sized,
+        KQ: Hash + Eq + ?Sized,
+    {
+        let shard = self.shard(g).lock().unwrap();
+        shard.get(g).and_then(|inner| inner.get(k)).map(f)
+    }
+
+    /// Call a function for each pair currently in the collection.
+    /// 
+    /// Pairs are grouped by g. Each shard is locked in turn while its
+    /// pairs are listed, so pairs added meanwhile may or may not be seen.
+    pub fn for_each(&self, mut f: impl FnMut(&G, &K, &V)) {
+        for shard in self.shards.iter() {
+            let shard = shard.lock().unwrap();
+            for (g, inner) in shard.iter() {
+                for (k, v) in inner {
+                    f(g, k, v);
+                }
+            }
+        }
+    }
+}
+
+impl<G: Hash + Eq, K: Hash + Eq, V: Default> Default for BilevelMap<G, K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<G: Hash + Eq + Clone, K: Hash + Eq, V: Default> BilevelMap<G, K, V> {
+    /// List and consume the payloads for the pairs in the collection,
+    /// consuming the collection.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn into_iter(self) -> impl Iterator<Item = (G, K, V)> {
+        self.shards.into_vec().into_iter()
+            .flat_map(|shard| shard.into_inner().unwrap())
+            .flat_map(|(g, inner)| inner.into_iter().map(move |(k, v)| (g.clone(), k, v)))
+    }
+}
+
+impl<G, K, V> std::fmt::Debug for BilevelMap<G, K, V>
+where
+    G: std::fmt::Debug,
+    K: std::fmt::Debug,
+    V: std::fmt::Debug,
+{
+    /// Format the collection as a map from each group key to a map of its
+    /// aggregation keys and payloads, listing only the first few groups,
+    /// and keys of each group, of a large collection.
+    /// 
+    /// Every shard is locked while the collection is formatted.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let shards: Vec<_> = self.shards.iter().map(|shard| shard.lock().unwrap()).collect();
+        crate::debug::debug_map(f, shards.iter().flat_map(|shard| shard.iter()))
+    }
+}
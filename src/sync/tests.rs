@@ -0,0 +1,102 @@
+use std::thread;
+
+use super::*;
+
+#[test]
+pub fn test_map() {
+    let map: BilevelMap<u32, u32, u64> = BilevelMap::with_shards(3);
+    thread::scope(|s| {
+        for t in 0..4 {
+            let map = &map;
+            s.spawn(move || {
+                for i in 0..1000 {
+                    map.add_or_get(i % 10, (i + t) % 2, |v| *v += 1);
+                }
+            });
+        }
+    });
+    assert_eq!(map.get(&1, &0, |v| *v), Some(200));
+    assert_eq!(map.get(&10, &0, |v| *v), None);
+    let mut total = 0;
+    map.for_each(|_, _, v| total += v);
+    assert_eq!(total, 4000);
+    let mut pairs: Vec<_> = map.into_iter().collect();
+    pairs.sort();
+    assert_eq!(pairs.len(), 20);
+    assert_eq!(pairs[0], (0, 0, 200));
+}
+
+#[test]
+pub fn test_send_sync() {
+    // Fails to compile if the collection cannot be shared with, or moved
+    // into, worker threads.
+    fn assert_send_sync<T: Send + Sync>() {}
+    assert_send_sync::<BilevelMap<String, String, u64>>();
+    assert_send_sync::<BilevelCounter<String, String>>();
+    assert_send_sync::<SnapshotMap<String, String, u64>>();
+    assert_send_sync::<MapSnapshot<String, String, u64>>();
+}
+
+#[test]
+pub fn test_counter() {
+    let counter: BilevelCounter<u32, u32> = BilevelCounter::with_shards(2);
+    thread::scope(|s| {
+        for t in 0..4 {
+            let counter = &counter;
+            s.spawn(move || {
+                for i in 0..1000 {
+                    counter.increment(i % 10, (i + t) % 2);
+                }
+            });
+        }
+    });
+    counter.add(1, 0, 5);
+    assert_eq!(counter.get(&1, &0), Some(205));
+    assert_eq!(counter.get(&10, &0), None);
+    let mut total = 0;
+    counter.for_each(|_, _, n| total += n);
+    assert_eq!(total, 4005);
+    assert_eq!(counter.into_iter().count(), 20);
+}
+
+#[test]
+pub fn test_borrowed_lookup() {
+    let map: BilevelMap<String, String, u64> = BilevelMap::with_shards(2);
+    map.add_or_get("u1".to_string(), "a".to_string(), |v| *v += 3);
+    assert_eq!(map.get("u1", "a", |v| *v), Some(3));
+    assert_eq!(map.get("u1", "b", |v| *v), None);
+    let counter: BilevelCounter<String, String> = BilevelCounter::with_shards(2);
+    counter.increment("u1".to_string(), "a".to_string());
+    assert_eq!(counter.get("u1", "a"), Some(1));
+}
+
+#[test]
+pub fn test_snapshot_map() {
+    let map: SnapshotMap<u32, u32, u64> = SnapshotMap::new();
+    map.add_or_get(1, 0, |v| *v += 1);
+    map.add_or_get(2, 0, |v| *v += 1);
+    let before = map.snapshot();
+    thread::scope(|s| {
+        // Snapshots are read while the writers keep adding.
+        let reader = s.spawn(|| (0..100).map(|_| map.snapshot().len()).max());
+        for t in 0..4 {
+            let map = &map;
+            s.spawn(move || {
+                for i in 0..1000 {
+                    map.add_or_get(i % 10, (i + t) % 2, |v| *v += 1);
+                }
+            });
+        }
+        assert!(reader.join().unwrap() <= Some(20));
+    });
+    // The earlier snapshot is unchanged by the writes after it.
+    assert_eq!(before.len(), 2);
+    assert_eq!(before.get(&1, &0), Some(&1));
+    let after = map.snapshot();
+    assert_eq!(after.get(&1, &0), Some(&201));
+    assert_eq!(after.group_count(), 10);
+    assert_eq!(after.iter().map(|(_, _, v)| v).sum::<u64>(), 4002);
+    assert!(after.group_sizes().all(|(_, n)| n == 2));
+    assert!(after.contains_group(&9) && !before.contains_group(&9));
+}
+
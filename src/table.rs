@@ -0,0 +1,80 @@
+use std::fmt::{Display, Write};
+
+/// Options for rendering a collection as a text table by render_table.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct TableOptions {
+    /// The number of spaces before each aggregation key, under the heading
+    /// of its group.
+    pub indent: usize,
+    /// The number of spaces between the key and value columns.
+    pub gap: usize,
+    /// The greatest number of keys to list for each group, or None to list
+    /// them all. The number of keys left out is noted after the last.
+    pub max_rows: Option<usize>,
+    /// Whether to leave a blank line between groups.
+    pub spaced: bool,
+}
+
+impl Default for TableOptions {
+    /// Indent keys by 2 spaces, with a gap of 2 spaces before the values,
+    /// listing every key, with groups separated by blank lines.
+    fn default() -> Self {
+        Self { indent: 2, gap: 2, max_rows: None, spaced: true }
+    }
+}
+
+/// The text of an aggregation key and its value, if any.
+type Row = (String, Option<String>);
+
+/// Render groups as headed sections, with the keys of every group left
+/// aligned in one column, and the values, if any, right aligned in another.
+///
+/// The groups, and the keys of each group, are listed in the order of
+/// their text, so that the same contents are always rendered the same way.
+pub(crate) fn render<'a, G, K, V, I>(options: &TableOptions, groups: impl Iterator<Item = (&'a G, I)>) -> String
+where
+    G: Display + ?Sized + 'a,
+    K: Display + ?Sized + 'a,
+    V: Display + ?Sized + 'a,
+    I: Iterator<Item = (&'a K, Option<&'a V>)>,
+{
+    let mut groups: Vec<(String, Vec<Row>)> = groups
+        .map(|(g, rows)| {
+            let mut rows: Vec<_> = rows.map(|(k, v)| (k.to_string(), v.map(V::to_string))).collect();
+            rows.sort_unstable();
+            (g.to_string(), rows)
+        })
+        .collect();
+    groups.sort_unstable();
+    let shown = |rows: &[_]| options.max_rows.map_or(rows.len(), |n| n.min(rows.len()));
+    let widths = |column: fn(&Row) -> usize| {
+        groups.iter().flat_map(|(_, rows)| rows[..shown(rows)].iter().map(column)).max().unwrap_or(0)
+    };
+    let key_width = widths(|(k, _)| k.chars().count());
+    let value_width = widths(|(_, v)| v.as_ref().map_or(0, |v| v.chars().count()));
+    let mut table = String::new();
+    for (i, (g, rows)) in groups.iter().enumerate() {
+        if i > 0 && options.spaced {
+            table.push('\n');
+        }
+        table.push_str(g);
+        table.push('\n');
+        let n = shown(rows);
+        for (k, v) in &rows[..n] {
+            let indent = options.indent;
+            match v {
+                Some(v) => {
+                    let gap = options.gap;
+                    let _ = writeln!(table, "{:indent$}{k:<key_width$}{:gap$}{v:>value_width$}", "", "");
+                },
+                None => {
+                    let _ = writeln!(table, "{:indent$}{k}", "");
+                },
+            }
+        }
+        if n < rows.len() {
+            let _ = writeln!(table, "{:indent$}... {} more", "", rows.len() - n, indent = options.indent);
+        }
+    }
+    table
+}
@@ -0,0 +1,271 @@
+use std::{collections::HashMap, error::Error, fmt, hash::Hash, ops::Deref};
+
+/// A collection that can hold the data of one tenant of a Tenanted
+/// aggregator.
+pub trait TenantData {
+    /// The type of the pairs listed by the collection.
+    type Item<'a> where Self: 'a;
+
+    /// List the pairs currently in the collection.
+    fn pairs(&self) -> impl Iterator<Item = Self::Item<'_>>;
+
+    /// Count the groups currently in the collection.
+    fn group_count(&self) -> usize;
+
+    /// Count the pairs currently in the collection.
+    fn pair_count(&self) -> usize;
+
+    /// Estimate the memory allocated by the collection, in bytes.
+    ///
+    /// The estimate counts the space allocated for the keys and payloads,
+    /// but not any memory they own themselves, such as the contents of
+    /// strings.
+    fn memory_usage(&self) -> usize;
+}
+
+/// Limits on the data kept for a tenant.
+/// 
+/// A limit of None means there is no limit.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Quota {
+    /// The greatest number of groups.
+    pub groups: Option<usize>,
+    /// The greatest number of pairs.
+    pub pairs: Option<usize>,
+    /// The greatest estimated memory usage, in bytes.
+    pub memory: Option<usize>,
+}
+
+/// The limit of a Quota that a tenant has reached.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub enum QuotaExceeded {
+    /// The tenant has reached its limit on groups.
+    Groups,
+    /// The tenant has reached its limit on pairs.
+    Pairs,
+    /// The tenant has reached its limit on memory.
+    Memory,
+}
+
+impl fmt::Display for QuotaExceeded {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self {
+            Self::Groups => write!(f, "tenant group quota exceeded"),
+            Self::Pairs => write!(f, "tenant pair quota exceeded"),
+            Self::Memory => write!(f, "tenant memory quota exceeded"),
+        }
+    }
+}
+
+impl Error for QuotaExceeded {}
+
+/// The usage of a tenant, and the number of times it was refused access
+/// for exceeding its quota.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct TenantMetrics {
+    /// The number of groups.
+    pub groups: usize,
+    /// The number of pairs.
+    pub pairs: usize,
+    /// The estimated memory usage, in bytes.
+    pub memory: usize,
+    /// The number of times access or a change was refused for exceeding
+    /// the quota.
+    pub rejections: u64,
+}
+
+/// An aggregator serving several tenants, with the data of each tenant
+/// kept in a collection of its own.
+///
+/// The tenant is stored once for its whole collection, rather than in
+/// each key, and every access names the tenant, so no query can see the
+/// data of another tenant.
+///
+/// T is the type of the tenant.
+/// C is the type of the collection kept for each tenant.
+pub struct Tenanted<T, C> {
+    tenants: HashMap<T, Tenant<C>>,
+    constructor: fn() -> C,
+    default_quota: Quota,
+    quotas: HashMap<T, Quota>,
+}
+
+/// The data of a tenant, with the number of times it was refused.
+struct Tenant<C> {
+    data: C,
+    rejections: u64,
+}
+
+/// The collection of a tenant of a Tenanted aggregator, borrowed by
+/// try_tenant_mut, through which pairs can be added only while the tenant
+/// is within its quota.
+///
+/// The quota is checked before each pair is added, so a tenant can never
+/// hold more groups or pairs than its limits, and can exceed its limit on
+/// memory by at most the memory taken by the last pair added. Each
+/// refusal is counted in the metrics of the tenant.
+///
+/// The collection can be read through the borrow, as it derefs to C.
+pub struct TenantMut<'a, C> {
+    data: &'a mut C,
+    quota: Quota,
+    rejections: &'a mut u64,
+}
+
+impl<C: TenantData> TenantMut<'_, C> {
+    /// Check that the tenant has not reached any limit of its quota,
+    /// counting a refusal if it has.
+    fn check(&mut self) -> Result<(), QuotaExceeded> {
+        let c = &*self.data;
+        let exceeded = if self.quota.groups.is_some_and(|n| c.group_count() >= n) {
+            Some(QuotaExceeded::Groups)
+        } else if self.quota.pairs.is_some_and(|n| c.pair_count() >= n) {
+            Some(QuotaExceeded::Pairs)
+        } else if self.quota.memory.is_some_and(|n| c.memory_usage() >= n) {
+            Some(QuotaExceeded::Memory)
+        } else {
+            None
+        };
+        match exceeded {
+            Some(e) => {
+                *self.rejections += 1;
+                Err(e)
+            },
+            None => Ok(()),
+        }
+    }
+}
+
+#[cfg(feature = "core")]
+impl<C: TenantData + crate::BilevelSetOps> TenantMut<'_, C> {
+    /// Insert a key pair, if not already present, unless the tenant has
+    /// reached any limit of its quota.
+    /// 
+    /// Return false if the pair was already present, otherwise true.
+    pub fn insert(&mut self, g: C::Group, k: C::Key) -> Result<bool, QuotaExceeded> {
+        self.check()?;
+        Ok(self.data.insert_pair(g, k))
+    }
+}
+
+#[cfg(feature = "core")]
+impl<C: TenantData + crate::BilevelMapOps> TenantMut<'_, C> {
+    /// Get a mutable reference to the payload for the specified key pair,
+    /// inserting a new payload if the pair is not present, unless the
+    /// tenant has reached any limit of its quota.
+    pub fn add_or_get(&mut self, g: C::Group, k: C::Key) -> Result<&mut C::Value, QuotaExceeded> {
+        self.check()?;
+        Ok(self.data.add_or_get_pair(g, k))
+    }
+}
+
+impl<C> Deref for TenantMut<'_, C> {
+    type Target = C;
+
+    fn deref(&self) -> &C {
+        self.data
+    }
+}
+
+impl<T: Hash + Eq, C: TenantData> Tenanted<T, C> {
+    /// Create a new aggregator with no tenants.
+    ///
+    /// constructor: creates the collection for each new tenant found,
+    ///     such as BilevelMap::new.
+    pub fn new(constructor: fn() -> C) -> Self {
+        Self {
+            tenants: HashMap::new(),
+            constructor,
+            default_quota: Quota::default(),
+            quotas: HashMap::new(),
+        }
+    }
+
+    /// Set the quota for tenants that have no quota of their own.
+    pub fn set_default_quota(&mut self, quota: Quota) {
+        self.default_quota = quota;
+    }
+
+    /// Set the quota for the specified tenant.
+    pub fn set_quota(&mut self, t: T, quota: Quota) {
+        self.quotas.insert(t, quota);
+    }
+
+    /// Get the quota that applies to the specified tenant.
+    pub fn quota(&self, t: &T) -> Quota {
+        self.quotas.get(t).copied().unwrap_or(self.default_quota)
+    }
+
+    /// Get the collection of the specified tenant for modification,
+    /// creating it if the tenant is new, unless the tenant has reached
+    /// any limit of its quota.
+    /// 
+    /// The quota is checked again before each pair is added through the
+    /// returned TenantMut. A tenant that has reached a limit is refused
+    /// even for changes that would not add to its usage, until its data is
+    /// reduced or its quota raised. Each refusal is counted in the metrics
+    /// of the tenant.
+    /// 
+    /// Checking a limit on groups or pairs takes constant time, but
+    /// checking a limit on memory takes time in proportion to the number
+    /// of groups of the tenant.
+    pub fn try_tenant_mut(&mut self, t: T) -> Result<TenantMut<'_, C>, QuotaExceeded> {
+        let quota = self.quota(&t);
+        let constructor = self.constructor;
+        let tenant = self.tenants.entry(t).or_insert_with(|| Tenant { data: constructor(), rejections: 0 });
+        let mut data = TenantMut { data: &mut tenant.data, quota, rejections: &mut tenant.rejections };
+        data.check()?;
+        Ok(data)
+    }
+
+    /// Get the usage and rejection count of the specified tenant.
+    pub fn metrics(&self, t: &T) -> TenantMetrics {
+        self.tenants.get(t).map_or_else(TenantMetrics::default, |tenant| TenantMetrics {
+            groups: tenant.data.group_count(),
+            pairs: tenant.data.pair_count(),
+            memory: tenant.data.memory_usage(),
+            rejections: tenant.rejections,
+        })
+    }
+
+    /// Get the collection of the specified tenant for modification,
+    /// creating it if the tenant is new.
+    /// 
+    /// The quota of the tenant is not enforced; use try_tenant_mut to
+    /// enforce it.
+    pub fn tenant_mut(&mut self, t: T) -> &mut C {
+        let constructor = self.constructor;
+        &mut self.tenants.entry(t).or_insert_with(|| Tenant { data: constructor(), rejections: 0 }).data
+    }
+
+    /// Get the collection of the specified tenant, if it has any data.
+    pub fn tenant(&self, t: &T) -> Option<&C> {
+        self.tenants.get(t).map(|tenant| &tenant.data)
+    }
+
+    /// Remove a tenant and its data, returning its collection.
+    pub fn remove_tenant(&mut self, t: &T) -> Option<C> {
+        self.tenants.remove(t).map(|tenant| tenant.data)
+    }
+
+    /// List the tenants currently holding data.
+    pub fn tenants(&self) -> impl Iterator<Item = &T> {
+        self.tenants.keys()
+    }
+
+    /// List the pairs of the specified tenant only.
+    pub fn iter_tenant(&self, t: &T) -> impl Iterator<Item = C::Item<'_>> {
+        self.tenants.get(t).into_iter().flat_map(|tenant| tenant.data.pairs())
+    }
+
+    /// Estimate the memory allocated for the data of the specified tenant,
+    /// in bytes, as TenantData::memory_usage does.
+    pub fn memory_usage(&self, t: &T) -> usize {
+        self.tenants.get(t).map_or(0, |tenant| tenant.data.memory_usage())
+    }
+
+    /// Estimate the memory allocated for the data of each tenant.
+    pub fn memory_by_tenant(&self) -> impl Iterator<Item = (&T, usize)> {
+        self.tenants.iter().map(|(t, tenant)| (t, tenant.data.memory_usage()))
+    }
+}
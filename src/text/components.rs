@@ -0,0 +1,68 @@
+use crate::arena::StrArena;
+use super::Normalizer;
+
+/// The string components of the keys of a text collection.
+///
+/// Each distinct string is kept once, whether it appears in the group
+/// key or the aggregation key, and is given an index as its id, so a key
+/// is kept as an array of ids.
+///
+/// The strings are kept end to end in an arena, rather than allocated
+/// one by one, since there can be tens of millions of short strings.
+///
+/// Each component is normalized before it is looked up.
+#[derive(Clone)]
+pub(super) struct Components {
+    strings: StrArena,
+    normalizer: Normalizer,
+}
+
+impl Components {
+    /// Create an empty table with space for the specified number of
+    /// distinct strings.
+    pub fn with_capacity(capacity: usize) -> Self {
+        Self { strings: StrArena::with_capacity(capacity), normalizer: Normalizer::default() }
+    }
+
+    /// Find the ids of the components of a key, adding any that are new.
+    pub fn intern<const N: usize>(&mut self, key: [&str; N]) -> [usize; N] {
+        key.map(|c| self.strings.intern(&self.normalizer.normalize(c)))
+    }
+
+    /// Find the ids of the components of a key of any width, adding any
+    /// that are new.
+    pub fn intern_slice(&mut self, key: &[&str]) -> Vec<usize> {
+        key.iter().map(|c| self.strings.intern(&self.normalizer.normalize(c))).collect()
+    }
+
+    /// Get the options applied to components as they are interned.
+    pub fn normalizer(&self) -> Normalizer {
+        self.normalizer
+    }
+
+    /// Set the options applied to components as they are interned.
+    pub fn set_normalizer(&mut self, normalizer: Normalizer) {
+        self.normalizer = normalizer;
+    }
+
+    /// Look up the components of a key by their ids.
+    pub fn resolve<const N: usize>(&self, ids: &[usize; N]) -> [&str; N] {
+        ids.map(|i| self.strings.key(i))
+    }
+
+    /// Look up the components of a key of any width by their ids.
+    pub fn resolve_slice(&self, ids: &[usize]) -> Vec<&str> {
+        ids.iter().map(|&i| self.strings.key(i)).collect()
+    }
+
+    /// List the distinct strings, in order of their ids.
+    pub fn iter(&self) -> impl ExactSizeIterator<Item = &str> {
+        self.strings.iter()
+    }
+
+    /// Estimate the memory allocated by the table, in bytes, including the
+    /// contents of the strings, which are held in the arena.
+    pub fn memory_usage(&self) -> usize {
+        self.strings.memory_usage()
+    }
+}
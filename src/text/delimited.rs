@@ -0,0 +1,59 @@
+use std::{error::Error, fmt};
+
+/// An error splitting a delimited line into the components of a key pair,
+/// where the line has the wrong number of fields.
+#[derive(Clone, Copy, Debug, PartialEq, Eq)]
+pub struct FieldCountError {
+    /// The index of the line among the lines given, counting from 0,
+    /// or None if a single line was given.
+    pub line: Option<usize>,
+    /// The number of fields of a key pair.
+    pub expected: usize,
+    /// The number of fields found in the line.
+    pub found: usize,
+}
+
+impl fmt::Display for FieldCountError {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        if let Some(line) = self.line {
+            write!(f, "line {}: ", line)?;
+        }
+        write!(f, "expected {} fields, found {}", self.expected, self.found)
+    }
+}
+
+impl Error for FieldCountError {}
+
+/// Split a line into the G components of the group key followed by the
+/// K components of the aggregation key.
+pub(super) fn split<const G: usize, const K: usize>(
+    line: &str,
+    delimiter: char,
+) -> Result<([&str; G], [&str; K]), FieldCountError> {
+    let mut g = [""; G];
+    let mut k = [""; K];
+    let mut found = 0;
+    for field in line.split(delimiter) {
+        if found < G {
+            g[found] = field;
+        } else if found < G + K {
+            k[found - G] = field;
+        }
+        found += 1;
+    }
+    if found == G + K {
+        Ok((g, k))
+    } else {
+        Err(FieldCountError { line: None, expected: G + K, found })
+    }
+}
+
+/// Split a line into the specified number of fields.
+pub(super) fn split_dyn(line: &str, delimiter: char, expected: usize) -> Result<Vec<&str>, FieldCountError> {
+    let fields: Vec<_> = line.split(delimiter).collect();
+    if fields.len() == expected {
+        Ok(fields)
+    } else {
+        Err(FieldCountError { line: None, expected, found: fields.len() })
+    }
+}
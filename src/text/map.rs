@@ -0,0 +1,236 @@
+use std::{cmp::Ordering, collections::HashMap};
+use crate::{Capacity, HeapSize, core::{self, Inline}};
+use super::{Normalizer, components::Components, delimited::{self, FieldCountError}};
+
+/// A collection of distinct pairs (g, k) grouped by g, with a payload
+/// associated with each pair, where each key is an array of strings.
+/// 
+/// As pairs are found, they are added if not already present.
+/// When the collection is iterated over, the pairs are listed by group.
+/// 
+/// Each distinct string component is kept once, whether it appears in
+/// the group key or the aggregation key, and the keys are kept as arrays
+/// of component ids, so keys that repeat the same few components take
+/// little more space than their ids.
+/// 
+/// G is the number of components of the group key.
+/// K is the number of components of the aggregation key.
+/// V is the type of the payload.
+#[derive(Clone)]
+pub struct BilevelMap<const G: usize, const K: usize, V> {
+    components: Components,
+    inner: core::BilevelMap<[usize; G], [usize; K], V, Inline>,
+}
+
+impl<const G: usize, const K: usize, V: Default> BilevelMap<G, K, V> {
+    /// Create a new collection, with the default payload for each new pair.
+    /// 
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    pub fn new() -> Self {
+        Self::with_capacity(Capacity::default())
+    }
+
+    /// Create a new collection with the specified capacity, with the
+    /// default payload for each new pair.
+    /// 
+    /// The capacity for aggregation keys is taken as the capacity for
+    /// distinct string components.
+    pub fn with_capacity(capacity: Capacity) -> Self {
+        Self {
+            components: Components::with_capacity(capacity.agg_keys),
+            inner: core::BilevelMap::with_capacity(capacity),
+        }
+    }
+}
+
+impl<const G: usize, const K: usize, V: Default> Default for BilevelMap<G, K, V> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const G: usize, const K: usize, V> BilevelMap<G, K, V> {
+    /// Create a new collection.
+    /// 
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    /// 
+    /// constructor: A constructor for the payload of each new pair.
+    pub fn new_with(constructor: impl Fn() -> V + Send + Sync + 'static) -> Self {
+        Self::with_capacity_and(Capacity::default(), constructor)
+    }
+
+    /// Create a new collection with the specified capacity.
+    /// 
+    /// The capacity for aggregation keys is taken as the capacity for
+    /// distinct string components.
+    /// 
+    /// constructor: A constructor for the payload of each new pair.
+    pub fn with_capacity_and(capacity: Capacity, constructor: impl Fn() -> V + Send + Sync + 'static) -> Self {
+        Self {
+            components: Components::with_capacity(capacity.agg_keys),
+            inner: core::BilevelMap::with_capacity_and(capacity, constructor),
+        }
+    }
+
+    /// Get a mutable reference to the payload for the specified key pair.
+    /// 
+    /// If the key pair is currently not present, a new payload is inserted.
+    pub fn add_or_get(&mut self, g: [&str; G], k: [&str; K]) -> &mut V {
+        let g = self.components.intern(g);
+        let k = self.components.intern(k);
+        self.inner.add_or_get(&g, &k)
+    }
+
+    /// Get a mutable reference to the payload for the key pair found in
+    /// a delimited line, such as a line of a tab-separated file, which must
+    /// have G + K fields: the components of the group key followed by those
+    /// of the aggregation key.
+    /// 
+    /// If the key pair is currently not present, a new payload is inserted.
+    pub fn insert_delimited(&mut self, line: &str, delimiter: char) -> Result<&mut V, FieldCountError> {
+        let (g, k) = delimited::split(line, delimiter)?;
+        Ok(self.add_or_get(g, k))
+    }
+
+    /// Update the payloads for the key pairs found in delimited lines, as
+    /// insert_delimited does, such as the lines given by str::lines.
+    /// 
+    /// The lines before the first line with the wrong number of fields
+    /// are applied, and the rest are not.
+    /// 
+    /// update: Called with the payload for the key pair of each line.
+    pub fn extend_from_lines<L: AsRef<str>>(
+        &mut self,
+        lines: impl IntoIterator<Item = L>,
+        delimiter: char,
+        mut update: impl FnMut(&mut V),
+    ) -> Result<(), FieldCountError> {
+        for (i, line) in lines.into_iter().enumerate() {
+            let v = self.insert_delimited(line.as_ref(), delimiter)
+                .map_err(|e| FieldCountError { line: Some(i), ..e })?;
+            update(v);
+        }
+        Ok(())
+    }
+
+    /// List the payloads for the pairs currently in the collection,
+    /// without consuming the collection or the payloads.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn iter(&self) -> impl Iterator<Item = ([&str; G], [&str; K], &V)> {
+        let components = &self.components;
+        self.inner.iter()
+            .map(move |(g, k, v)| (components.resolve(g), components.resolve(k), v))
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = ([&str; G], [&str; K], &V)> {
+        self.iter_sorted_by(|a, b| (a.0, a.1).cmp(&(b.0, b.1)))
+    }
+
+    /// List the pairs currently in the collection, sorted using the
+    /// specified comparison function.
+    pub fn iter_sorted_by<'a, F>(&'a self, compare: F) -> impl Iterator<Item = ([&'a str; G], [&'a str; K], &'a V)> + 'a
+    where
+        F: FnMut(&([&'a str; G], [&'a str; K], &'a V), &([&'a str; G], [&'a str; K], &'a V)) -> Ordering + 'a,
+    {
+        let mut pairs: Vec<_> = self.iter().collect();
+        pairs.sort_unstable_by(compare);
+        pairs.into_iter()
+    }
+
+    /// Get the options applied to the string components of keys as they
+    /// are inserted.
+    pub fn normalizer(&self) -> Normalizer {
+        self.components.normalizer()
+    }
+
+    /// Set the options applied to the string components of keys as they
+    /// are inserted, so that keys differing only in the ways chosen are
+    /// aggregated together.
+    /// 
+    /// The keys already in the collection are not changed, so the
+    /// normalizer is best set before any keys are inserted.
+    pub fn set_normalizer(&mut self, normalizer: Normalizer) {
+        self.components.set_normalizer(normalizer);
+    }
+
+    /// List the distinct string components, in order of their ids.
+    pub fn components(&self) -> impl ExactSizeIterator<Item = &str> {
+        self.components.iter()
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    /// Count the pairs currently in the collection.
+    pub fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    /// Estimate the memory held by the collection, in bytes, including
+    /// the contents of the string components, and the memory owned by the
+    /// payloads.
+    /// 
+    /// This walks every group, so takes time in proportion to the number
+    /// of pairs.
+    pub fn estimated_heap_bytes(&self) -> usize
+    where
+        V: HeapSize,
+    {
+        self.components.memory_usage() + self.inner.estimated_heap_bytes()
+    }
+}
+
+impl<const G: usize, const K: usize, V: std::fmt::Debug> std::fmt::Debug for BilevelMap<G, K, V> {
+    /// Format the collection as a map from each group key to a map of its
+    /// aggregation keys and payloads, each key an array of strings, listing
+    /// only the first few groups, and keys of each group, of a large
+    /// collection.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let components = &self.components;
+        crate::debug::debug_map(f, self.inner.iter_groups().map(|(g, pairs)| {
+            (components.resolve(g), pairs.map(|(k, v)| (components.resolve(k), v)))
+        }))
+    }
+}
+
+impl<const G: usize, const K: usize, V: PartialEq> PartialEq for BilevelMap<G, K, V> {
+    /// Check whether two collections hold the same pairs and payloads,
+    /// whatever their order and the ids given to the strings of their keys.
+    fn eq(&self, other: &Self) -> bool {
+        if self.group_count() != other.group_count() || self.pair_count() != other.pair_count() {
+            return false;
+        }
+        let theirs: HashMap<_, _> = other.iter().map(|(g, k, v)| ((g, k), v)).collect();
+        self.iter().all(|(g, k, v)| theirs.get(&(g, k)) == Some(&v))
+    }
+}
+
+impl<const G: usize, const K: usize, V: Eq> Eq for BilevelMap<G, K, V> {}
+
+#[cfg(feature = "tenant")]
+impl<const G: usize, const K: usize, V> crate::TenantData for BilevelMap<G, K, V> {
+    type Item<'a> = ([&'a str; G], [&'a str; K], &'a V) where Self: 'a;
+
+    fn pairs(&self) -> impl Iterator<Item = ([&str; G], [&str; K], &V)> {
+        self.iter()
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.components.memory_usage() + crate::TenantData::memory_usage(&self.inner)
+    }
+}
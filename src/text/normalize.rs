@@ -0,0 +1,61 @@
+use std::borrow::Cow;
+
+/// Options for cleaning up the string components of text keys as they
+/// are inserted, so that strings differing only in the ways chosen are
+/// aggregated as the same component.
+/// 
+/// By default, components are kept as they are given.
+#[derive(Clone, Copy, Debug, Default, PartialEq, Eq)]
+pub struct Normalizer {
+    /// Remove whitespace from the start and end of each component.
+    pub trim: bool,
+    /// Replace each run of whitespace within a component by a single space.
+    pub collapse_whitespace: bool,
+    /// Convert each component to lowercase.
+    pub case_fold: bool,
+}
+
+impl Normalizer {
+    /// Apply the options chosen to a component.
+    /// 
+    /// The component is borrowed rather than copied if it is unchanged.
+    pub fn normalize<'a>(&self, component: &'a str) -> Cow<'a, str> {
+        let mut result = Cow::Borrowed(component);
+        if self.trim {
+            result = match result {
+                Cow::Borrowed(s) => Cow::Borrowed(s.trim()),
+                Cow::Owned(s) => Cow::Owned(s.trim().to_owned()),
+            };
+        }
+        if self.collapse_whitespace && has_whitespace_run(&result) {
+            let mut collapsed = String::with_capacity(result.len());
+            let mut in_run = false;
+            for c in result.chars() {
+                if c.is_whitespace() {
+                    if !in_run {
+                        collapsed.push(' ');
+                    }
+                    in_run = true;
+                } else {
+                    collapsed.push(c);
+                    in_run = false;
+                }
+            }
+            result = Cow::Owned(collapsed);
+        }
+        if self.case_fold && result.chars().any(char::is_uppercase) {
+            result = Cow::Owned(result.to_lowercase());
+        }
+        result
+    }
+}
+
+/// Check whether a string has whitespace other than single spaces.
+fn has_whitespace_run(s: &str) -> bool {
+    let mut previous = false;
+    s.chars().any(|c| {
+        let run = c.is_whitespace() && (previous || c != ' ');
+        previous = c.is_whitespace();
+        run
+    })
+}
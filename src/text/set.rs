@@ -0,0 +1,201 @@
+use std::{cmp::Ordering, collections::HashSet};
+use crate::{Capacity, core::{self, Inline}};
+use super::{Normalizer, components::Components, delimited::{self, FieldCountError}};
+
+/// A collection of distinct pairs (g, k) grouped by g, where each key is
+/// an array of strings.
+/// 
+/// As pairs are found, they are added if not already present.
+/// When the collection is iterated over, the pairs are listed by group.
+/// 
+/// Each distinct string component is kept once, whether it appears in
+/// the group key or the aggregation key, and the keys are kept as arrays
+/// of component ids.
+/// 
+/// G is the number of components of the group key.
+/// K is the number of components of the aggregation key.
+#[derive(Clone)]
+pub struct BilevelSet<const G: usize, const K: usize> {
+    components: Components,
+    inner: core::BilevelSet<[usize; G], [usize; K], Inline>,
+}
+
+impl<const G: usize, const K: usize> BilevelSet<G, K> {
+    /// Create a new collection.
+    /// 
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    pub fn new() -> Self {
+        Self::with_capacity(Capacity::default())
+    }
+
+    /// Create a new collection with the specified capacity.
+    /// 
+    /// The capacity for aggregation keys is taken as the capacity for
+    /// distinct string components.
+    pub fn with_capacity(capacity: Capacity) -> Self {
+        Self {
+            components: Components::with_capacity(capacity.agg_keys),
+            inner: core::BilevelSet::with_capacity(capacity),
+        }
+    }
+
+    /// Insert a key pair found into the collection.
+    /// 
+    /// g: the group key.
+    /// k: the remaining key.
+    /// 
+    /// Return false if the key was already present, otherwise true.
+    pub fn insert(&mut self, g: [&str; G], k: [&str; K]) -> bool {
+        let g = self.components.intern(g);
+        let k = self.components.intern(k);
+        self.inner.insert(&g, &k)
+    }
+
+    /// Insert a key pair found in a delimited line, such as a line of a
+    /// tab-separated file, which must have G + K fields: the components
+    /// of the group key followed by those of the aggregation key.
+    /// 
+    /// Return false if the key was already present, otherwise true.
+    pub fn insert_delimited(&mut self, line: &str, delimiter: char) -> Result<bool, FieldCountError> {
+        let (g, k) = delimited::split(line, delimiter)?;
+        Ok(self.insert(g, k))
+    }
+
+    /// Insert the key pairs found in delimited lines, as insert_delimited
+    /// does, such as the lines given by str::lines.
+    /// 
+    /// The lines before the first line with the wrong number of fields
+    /// are inserted, and the rest are not.
+    pub fn extend_from_lines<L: AsRef<str>>(
+        &mut self,
+        lines: impl IntoIterator<Item = L>,
+        delimiter: char,
+    ) -> Result<(), FieldCountError> {
+        for (i, line) in lines.into_iter().enumerate() {
+            self.insert_delimited(line.as_ref(), delimiter)
+                .map_err(|e| FieldCountError { line: Some(i), ..e })?;
+        }
+        Ok(())
+    }
+
+    /// List the pairs currently in the collection without consuming
+    /// the collection.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn iter(&self) -> impl Iterator<Item = ([&str; G], [&str; K])> {
+        let components = &self.components;
+        self.inner.iter()
+            .map(move |(g, k)| (components.resolve(g), components.resolve(k)))
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = ([&str; G], [&str; K])> {
+        self.iter_sorted_by(Ord::cmp)
+    }
+
+    /// List the pairs currently in the collection, sorted using the
+    /// specified comparison function.
+    pub fn iter_sorted_by<'a, F>(&'a self, compare: F) -> impl Iterator<Item = ([&'a str; G], [&'a str; K])> + 'a
+    where
+        F: FnMut(&([&'a str; G], [&'a str; K]), &([&'a str; G], [&'a str; K])) -> Ordering + 'a,
+    {
+        let mut pairs: Vec<_> = self.iter().collect();
+        pairs.sort_unstable_by(compare);
+        pairs.into_iter()
+    }
+
+    /// Get the options applied to the string components of keys as they
+    /// are inserted.
+    pub fn normalizer(&self) -> Normalizer {
+        self.components.normalizer()
+    }
+
+    /// Set the options applied to the string components of keys as they
+    /// are inserted, so that keys differing only in the ways chosen are
+    /// aggregated together.
+    /// 
+    /// The keys already in the collection are not changed, so the
+    /// normalizer is best set before any keys are inserted.
+    pub fn set_normalizer(&mut self, normalizer: Normalizer) {
+        self.components.set_normalizer(normalizer);
+    }
+
+    /// List the distinct string components, in order of their ids.
+    pub fn components(&self) -> impl ExactSizeIterator<Item = &str> {
+        self.components.iter()
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    /// Count the pairs currently in the collection.
+    pub fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    /// Estimate the memory held by the collection, in bytes, including
+    /// the contents of the string components.
+    /// 
+    /// This walks every group, so takes time in proportion to the number
+    /// of pairs.
+    pub fn estimated_heap_bytes(&self) -> usize {
+        self.components.memory_usage() + self.inner.estimated_heap_bytes()
+    }
+}
+
+impl<const G: usize, const K: usize> Default for BilevelSet<G, K> {
+    fn default() -> Self {
+        Self::new()
+    }
+}
+
+impl<const G: usize, const K: usize> std::fmt::Debug for BilevelSet<G, K> {
+    /// Format the collection as a map from each group key to the set of
+    /// its aggregation keys, each key an array of strings, listing only
+    /// the first few groups, and keys of each group, of a large collection.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let components = &self.components;
+        crate::debug::debug_set(f, self.inner.iter_groups().map(|(g, keys)| {
+            (components.resolve(g), keys.map(|k| components.resolve(k)))
+        }))
+    }
+}
+
+impl<const G: usize, const K: usize> PartialEq for BilevelSet<G, K> {
+    /// Check whether two collections hold the same pairs, whatever their
+    /// order and the ids given to the strings of their keys.
+    fn eq(&self, other: &Self) -> bool {
+        if self.group_count() != other.group_count() || self.pair_count() != other.pair_count() {
+            return false;
+        }
+        let theirs: HashSet<_> = other.iter().collect();
+        self.iter().all(|pair| theirs.contains(&pair))
+    }
+}
+
+impl<const G: usize, const K: usize> Eq for BilevelSet<G, K> {}
+
+#[cfg(feature = "tenant")]
+impl<const G: usize, const K: usize> crate::TenantData for BilevelSet<G, K> {
+    type Item<'a> = ([&'a str; G], [&'a str; K]) where Self: 'a;
+
+    fn pairs(&self) -> impl Iterator<Item = ([&str; G], [&str; K])> {
+        self.iter()
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.components.memory_usage() + crate::TenantData::memory_usage(&self.inner)
+    }
+}
@@ -0,0 +1,225 @@
+use std::{cmp::Ordering, collections::HashSet};
+use crate::{Capacity, core::{self, Inline}};
+use super::{Normalizer, components::Components, delimited::{self, FieldCountError}};
+
+/// A collection of distinct pairs (g, k) grouped by g, where each key is
+/// a list of strings, with the number of strings in each key chosen when
+/// the collection is created rather than at compile time.
+/// 
+/// This suits tools that read the layout of their data at run time, such
+/// as from a configuration file. Where the widths are known at compile
+/// time, BilevelSet avoids allocating a list for each key listed.
+/// 
+/// As pairs are found, they are added if not already present.
+/// When the collection is iterated over, the pairs are listed by group.
+/// 
+/// Each distinct string component is kept once, whether it appears in
+/// the group key or the aggregation key, and the keys are kept as lists
+/// of component ids.
+#[derive(Clone)]
+pub struct BilevelSetDyn {
+    group_fields: usize,
+    agg_fields: usize,
+    components: Components,
+    inner: core::BilevelSet<Vec<usize>, Vec<usize>, Inline>,
+}
+
+impl BilevelSetDyn {
+    /// Create a new collection.
+    /// 
+    /// No initial capacity is allocated, and capacity for a few items
+    /// is allocated for each new group key found.
+    /// 
+    /// group_fields: The number of components of the group key.
+    /// agg_fields: The number of components of the aggregation key.
+    pub fn new(group_fields: usize, agg_fields: usize) -> Self {
+        Self::with_capacity(group_fields, agg_fields, Capacity::default())
+    }
+
+    /// Create a new collection with the specified capacity.
+    /// 
+    /// The capacity for aggregation keys is taken as the capacity for
+    /// distinct string components.
+    /// 
+    /// group_fields: The number of components of the group key.
+    /// agg_fields: The number of components of the aggregation key.
+    pub fn with_capacity(group_fields: usize, agg_fields: usize, capacity: Capacity) -> Self {
+        Self {
+            group_fields,
+            agg_fields,
+            components: Components::with_capacity(capacity.agg_keys),
+            inner: core::BilevelSet::with_capacity(capacity),
+        }
+    }
+
+    /// Get the number of components of the group key.
+    pub fn group_fields(&self) -> usize {
+        self.group_fields
+    }
+
+    /// Get the number of components of the aggregation key.
+    pub fn agg_fields(&self) -> usize {
+        self.agg_fields
+    }
+
+    /// Insert a key pair found into the collection.
+    /// 
+    /// g: the group key, of group_fields components.
+    /// k: the remaining key, of agg_fields components.
+    /// 
+    /// Return false if the key was already present, otherwise true, or an
+    /// error if either key has the wrong number of components.
+    pub fn insert(&mut self, g: &[&str], k: &[&str]) -> Result<bool, FieldCountError> {
+        for (key, expected) in [(g, self.group_fields), (k, self.agg_fields)] {
+            if key.len() != expected {
+                return Err(FieldCountError { line: None, expected, found: key.len() });
+            }
+        }
+        let g = self.components.intern_slice(g);
+        let k = self.components.intern_slice(k);
+        Ok(self.inner.insert(&g, &k))
+    }
+
+    /// Insert a key pair found in a delimited line, such as a line of a
+    /// tab-separated file, which must have group_fields + agg_fields
+    /// fields: the components of the group key followed by those of the
+    /// aggregation key.
+    /// 
+    /// Return false if the key was already present, otherwise true.
+    pub fn insert_delimited(&mut self, line: &str, delimiter: char) -> Result<bool, FieldCountError> {
+        let fields = delimited::split_dyn(line, delimiter, self.group_fields + self.agg_fields)?;
+        let (g, k) = fields.split_at(self.group_fields);
+        self.insert(g, k)
+    }
+
+    /// Insert the key pairs found in delimited lines, as insert_delimited
+    /// does, such as the lines given by str::lines.
+    /// 
+    /// The lines before the first line with the wrong number of fields
+    /// are inserted, and the rest are not.
+    pub fn extend_from_lines<L: AsRef<str>>(
+        &mut self,
+        lines: impl IntoIterator<Item = L>,
+        delimiter: char,
+    ) -> Result<(), FieldCountError> {
+        for (i, line) in lines.into_iter().enumerate() {
+            self.insert_delimited(line.as_ref(), delimiter)
+                .map_err(|e| FieldCountError { line: Some(i), ..e })?;
+        }
+        Ok(())
+    }
+
+    /// List the pairs currently in the collection without consuming
+    /// the collection.
+    /// 
+    /// Pairs are grouped by g.
+    pub fn iter(&self) -> impl Iterator<Item = (Vec<&str>, Vec<&str>)> {
+        let components = &self.components;
+        self.inner.iter()
+            .map(move |(g, k)| (components.resolve_slice(g), components.resolve_slice(k)))
+    }
+
+    /// List the pairs currently in the collection, sorted by the group
+    /// key and then by the aggregation key.
+    pub fn iter_sorted(&self) -> impl Iterator<Item = (Vec<&str>, Vec<&str>)> {
+        self.iter_sorted_by(Ord::cmp)
+    }
+
+    /// List the pairs currently in the collection, sorted using the
+    /// specified comparison function.
+    pub fn iter_sorted_by<'a, F>(&'a self, compare: F) -> impl Iterator<Item = (Vec<&'a str>, Vec<&'a str>)> + 'a
+    where
+        F: FnMut(&(Vec<&'a str>, Vec<&'a str>), &(Vec<&'a str>, Vec<&'a str>)) -> Ordering + 'a,
+    {
+        let mut pairs: Vec<_> = self.iter().collect();
+        pairs.sort_unstable_by(compare);
+        pairs.into_iter()
+    }
+
+    /// Get the options applied to the string components of keys as they
+    /// are inserted.
+    pub fn normalizer(&self) -> Normalizer {
+        self.components.normalizer()
+    }
+
+    /// Set the options applied to the string components of keys as they
+    /// are inserted, so that keys differing only in the ways chosen are
+    /// aggregated together.
+    /// 
+    /// The keys already in the collection are not changed, so the
+    /// normalizer is best set before any keys are inserted.
+    pub fn set_normalizer(&mut self, normalizer: Normalizer) {
+        self.components.set_normalizer(normalizer);
+    }
+
+    /// List the distinct string components, in order of their ids.
+    pub fn components(&self) -> impl ExactSizeIterator<Item = &str> {
+        self.components.iter()
+    }
+
+    /// Count the groups currently in the collection.
+    pub fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    /// Count the pairs currently in the collection.
+    pub fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    /// Estimate the memory held by the collection, in bytes, including
+    /// the contents of the string components.
+    /// 
+    /// This walks every group, so takes time in proportion to the number
+    /// of pairs.
+    pub fn estimated_heap_bytes(&self) -> usize {
+        self.components.memory_usage() + self.inner.estimated_heap_bytes()
+    }
+}
+
+impl std::fmt::Debug for BilevelSetDyn {
+    /// Format the collection as a map from each group key to the set of
+    /// its aggregation keys, each key a list of strings, listing only the
+    /// first few groups, and keys of each group, of a large collection.
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        let components = &self.components;
+        crate::debug::debug_set(f, self.inner.iter_groups().map(|(g, keys)| {
+            (components.resolve_slice(g), keys.map(|k| components.resolve_slice(k)))
+        }))
+    }
+}
+
+impl PartialEq for BilevelSetDyn {
+    /// Check whether two collections hold the same pairs, whatever their
+    /// order and the ids given to the strings of their keys.
+    fn eq(&self, other: &Self) -> bool {
+        if self.group_count() != other.group_count() || self.pair_count() != other.pair_count() {
+            return false;
+        }
+        let theirs: HashSet<_> = other.iter().collect();
+        self.iter().all(|pair| theirs.contains(&pair))
+    }
+}
+
+impl Eq for BilevelSetDyn {}
+
+#[cfg(feature = "tenant")]
+impl crate::TenantData for BilevelSetDyn {
+    type Item<'a> = (Vec<&'a str>, Vec<&'a str>) where Self: 'a;
+
+    fn pairs(&self) -> impl Iterator<Item = (Vec<&str>, Vec<&str>)> {
+        self.iter()
+    }
+
+    fn group_count(&self) -> usize {
+        self.inner.group_count()
+    }
+
+    fn pair_count(&self) -> usize {
+        self.inner.pair_count()
+    }
+
+    fn memory_usage(&self) -> usize {
+        self.components.memory_usage() + crate::TenantData::memory_usage(&self.inner)
+    }
+}
@@ -0,0 +1,206 @@
+use super::*;
+use crate::Capacity;
+
+#[test]
+pub fn test_map() {
+    let mut a: BilevelMap<2, 1, u32> = BilevelMap::new();
+    let mut b: BilevelMap<2, 1, u32> = BilevelMap::with_capacity(Capacity {
+        groups: 2,
+        per_group: 2,
+        agg_keys: 8,
+    });
+    let test_data = [
+        (["us-east", "prod"], ["api"]),
+        (["us-east", "prod"], ["web"]),
+        (["us-west", "prod"], ["api"]),
+        (["us-east", "prod"], ["api"]),
+    ];
+    for (g, k) in test_data {
+        *a.add_or_get(g, k) += 1;
+        *b.add_or_get(g, k) += 1;
+    }
+    let expected = [
+        (["us-east", "prod"], ["api"], 2),
+        (["us-east", "prod"], ["web"], 1),
+        (["us-west", "prod"], ["api"], 1),
+    ];
+    assert!(a.iter_sorted().map(|(g, k, v)| (g, k, *v)).eq(expected));
+    assert!(b.iter_sorted().map(|(g, k, v)| (g, k, *v)).eq(expected));
+    assert_eq!(a.group_count(), 2);
+    assert_eq!(a.pair_count(), 3);
+}
+
+#[test]
+pub fn test_components() {
+    let mut map: BilevelMap<1, 1, u32> = BilevelMap::new();
+    *map.add_or_get(["api"], ["api"]) += 1;
+    *map.add_or_get(["web"], ["api"]) += 1;
+    // Each string is kept once, wherever it appears.
+    assert!(map.components().eq(["api", "web"]));
+
+    // The strings are kept end to end, and read back whole.
+    let mut set: BilevelSet<1, 2> = BilevelSet::new();
+    for i in 0..1_000 {
+        set.insert(["größe"], [&format!("k{i}"), "ü"]);
+    }
+    assert_eq!(set.components().len(), 1_002);
+    assert!(set.iter().all(|(g, [k, u])| g == ["größe"] && k.starts_with('k') && u == "ü"));
+}
+
+#[test]
+pub fn test_new_with() {
+    let mut map: BilevelMap<1, 1, Vec<u32>> = BilevelMap::new_with(|| Vec::with_capacity(4));
+    map.add_or_get(["a"], ["b"]).push(1);
+    map.add_or_get(["a"], ["b"]).push(2);
+    assert!(map.iter().map(|(_, _, v)| v.as_slice()).eq([[1, 2].as_slice()]));
+}
+
+#[test]
+pub fn test_set() {
+    let mut set: BilevelSet<1, 2> = BilevelSet::new();
+    let test_data = [
+        (["prod"], ["us-east", "api"]),
+        (["prod"], ["us-west", "api"]),
+        (["dev"], ["us-east", "api"]),
+        (["prod"], ["us-east", "api"]),
+    ];
+    for (i, (g, k)) in test_data.into_iter().enumerate() {
+        assert_eq!(set.insert(g, k), i != 3);
+    }
+    let expected = [
+        (["dev"], ["us-east", "api"]),
+        (["prod"], ["us-east", "api"]),
+        (["prod"], ["us-west", "api"]),
+    ];
+    assert!(set.iter_sorted().eq(expected));
+    assert_eq!(set.group_count(), 2);
+    assert_eq!(set.components().len(), 5);
+}
+
+#[cfg(feature = "tenant")]
+#[test]
+pub fn test_memory_usage() {
+    use crate::TenantData;
+    let mut text: BilevelMap<2, 1, u32> = BilevelMap::new();
+    let mut owned: crate::core::BilevelMap<[String; 2], [String; 1], u32, crate::core::Inline> =
+        crate::core::BilevelMap::new();
+    for i in 0..100 {
+        let host = format!("host{}", i % 10);
+        *text.add_or_get(["prod", &host], ["api"]) += 1;
+        *owned.add_or_get(&["prod".to_owned(), host], &["api".to_owned()]) += 1;
+    }
+    // The repeated components are kept once, and the keys as ids.
+    assert_eq!(text.components().len(), 12);
+    assert!(text.memory_usage() < owned.memory_usage());
+}
+
+#[test]
+pub fn test_delimited() {
+    let mut map: BilevelMap<2, 1, u32> = BilevelMap::new();
+    *map.insert_delimited("us-east\tprod\tapi", '\t').unwrap() += 1;
+    let log = "us-east\tprod\tapi\nus-east\tprod\tweb\nus-west\tprod\n";
+    let error = map.extend_from_lines(log.lines(), '\t', |v| *v += 1).unwrap_err();
+    assert_eq!(error, FieldCountError { line: Some(2), expected: 3, found: 2 });
+    assert_eq!(error.to_string(), "line 2: expected 3 fields, found 2");
+    // The lines before the error were applied.
+    let expected = [(["us-east", "prod"], ["api"], 2), (["us-east", "prod"], ["web"], 1)];
+    assert!(map.iter_sorted().map(|(g, k, v)| (g, k, *v)).eq(expected));
+
+    let mut set: BilevelSet<1, 1> = BilevelSet::new();
+    assert_eq!(set.insert_delimited("a,b", ','), Ok(true));
+    assert_eq!(set.insert_delimited("a,b", ','), Ok(false));
+    assert_eq!(
+        set.insert_delimited("a,b,c", ','),
+        Err(FieldCountError { line: None, expected: 2, found: 3 }),
+    );
+    set.extend_from_lines(["c,d", "a,d"], ',').unwrap();
+    assert_eq!(set.pair_count(), 3);
+}
+
+#[test]
+pub fn test_normalizer() {
+    let mut map: BilevelMap<1, 1, u32> = BilevelMap::new();
+    map.set_normalizer(Normalizer { trim: true, collapse_whitespace: true, case_fold: true });
+    for (g, k) in [("Foo ", "New  York"), ("foo", "new york"), (" FOO", "New\tYork")] {
+        *map.add_or_get([g], [k]) += 1;
+    }
+    assert!(map.iter().map(|(g, k, v)| (g, k, *v)).eq([(["foo"], ["new york"], 3)]));
+
+    let normalizer = Normalizer { collapse_whitespace: true, ..Normalizer::default() };
+    assert_eq!(normalizer.normalize(" a  b\t\nc "), " a b c ");
+    assert!(matches!(normalizer.normalize("a b"), std::borrow::Cow::Borrowed("a b")));
+}
+
+#[test]
+pub fn test_set_dyn() {
+    let mut set = BilevelSetDyn::new(2, 1);
+    assert_eq!(set.insert(&["us-east", "prod"], &["api"]), Ok(true));
+    assert_eq!(set.insert(&["us-east", "prod"], &["api"]), Ok(false));
+    assert_eq!(
+        set.insert(&["us-east"], &["api"]),
+        Err(FieldCountError { line: None, expected: 2, found: 1 }),
+    );
+    let error = set.extend_from_lines(["us-west,prod,api", "us-west,prod,web,x"], ',').unwrap_err();
+    assert_eq!(error, FieldCountError { line: Some(1), expected: 3, found: 4 });
+    let expected = [
+        (vec!["us-east", "prod"], vec!["api"]),
+        (vec!["us-west", "prod"], vec!["api"]),
+    ];
+    assert!(set.iter_sorted().eq(expected));
+    assert_eq!((set.group_fields(), set.agg_fields()), (2, 1));
+    assert_eq!(set.components().len(), 4);
+}
+
+#[test]
+pub fn test_map_dyn() {
+    let mut map: BilevelMapDyn<usize> = BilevelMapDyn::new(2, 1);
+    map.set_normalizer(Normalizer { case_fold: true, ..Normalizer::default() });
+    *map.add_or_get(&["us-east", "prod"], &["api"]).unwrap() += 1;
+    *map.add_or_get(&["US-East", "prod"], &["API"]).unwrap() += 1;
+    assert_eq!(
+        map.add_or_get(&["us-east", "prod"], &[]).map(|v| *v),
+        Err(FieldCountError { line: None, expected: 1, found: 0 }),
+    );
+    let error = map.extend_from_lines(["us-west,prod,web", "us-west,prod"], ',', |v| *v += 5).unwrap_err();
+    assert_eq!(error, FieldCountError { line: Some(1), expected: 3, found: 2 });
+    let expected = [
+        (vec!["us-east", "prod"], vec!["api"], &2),
+        (vec!["us-west", "prod"], vec!["web"], &5),
+    ];
+    assert!(map.iter_sorted().eq(expected));
+    assert_eq!((map.group_fields(), map.agg_fields()), (2, 1));
+    assert_eq!(map.clone(), map);
+}
+
+#[test]
+pub fn test_debug() {
+    let mut map = BilevelMap::<2, 1, usize>::new();
+    *map.add_or_get(["us-east", "prod"], ["api"]) += 2;
+    assert_eq!(format!("{:?}", map), r#"{["us-east", "prod"]: {["api"]: 2}}"#);
+}
+
+#[test]
+pub fn test_clone_eq() {
+    let mut a = BilevelMap::<2, 1, usize>::new();
+    *a.add_or_get(["us-east", "prod"], ["api"]) += 2;
+    *a.add_or_get(["us-west", "prod"], ["web"]) += 1;
+    let mut b = BilevelMap::<2, 1, usize>::new();
+    *b.add_or_get(["us-west", "prod"], ["web"]) += 1;
+    *b.add_or_get(["us-east", "prod"], ["api"]) += 2;
+    assert_eq!(a, b);
+    let mut c = a.clone();
+    assert_eq!(a, c);
+    *c.add_or_get(["us-east", "prod"], ["api"]) += 1;
+    assert_ne!(a, c);
+}
+
+#[test]
+pub fn test_estimated_heap_bytes() {
+    let mut set = BilevelSet::<1, 1>::new();
+    let empty = set.estimated_heap_bytes();
+    let component = "x".repeat(1_000);
+    set.insert([&component], [&component]);
+    // The component is kept once, though it appears in both keys.
+    let bytes = set.estimated_heap_bytes() - empty;
+    assert!((1_000..2_000).contains(&bytes), "{bytes}");
+}
@@ -0,0 +1,47 @@
+use std::{io::Write, process::{Command, Output, Stdio}};
+
+/// Run the bilevel binary with the arguments given, writing the input to
+/// its standard input.
+fn bilevel(args: &[&str], input: &str) -> Output {
+    let mut child = Command::new(env!("CARGO_BIN_EXE_bilevel"))
+        .args(args)
+        .stdin(Stdio::piped())
+        .stdout(Stdio::piped())
+        .stderr(Stdio::piped())
+        .spawn()
+        .unwrap();
+    child.stdin.take().unwrap().write_all(input.as_bytes()).unwrap();
+    child.wait_with_output().unwrap()
+}
+
+const LOG: &str = "\
+time,host,status
+1,web1,200
+2,web1,200
+3,WEB1 ,404
+4,web2,200
+";
+
+#[test]
+pub fn test_distinct() {
+    let output = bilevel(&["-d", ",", "--skip-header", "-g", "2", "-k", "3"], LOG);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "WEB1 ,404\nweb1,200\nweb2,200\n");
+}
+
+#[test]
+pub fn test_count() {
+    let args = ["-d", ",", "--skip-header", "--trim", "--ignore-case", "-g", "2", "-k", "3", "--count"];
+    let output = bilevel(&args, LOG);
+    assert!(output.status.success());
+    assert_eq!(String::from_utf8(output.stdout).unwrap(), "web1,200,2\nweb1,404,1\nweb2,200,1\n");
+}
+
+#[test]
+pub fn test_errors() {
+    let output = bilevel(&["-d", ",", "-g", "2", "-k", "5"], LOG);
+    assert_eq!(output.status.code(), Some(1));
+    assert!(String::from_utf8(output.stderr).unwrap().starts_with("bilevel: line 1: expected at least 5 fields"));
+    let output = bilevel(&["-g", "0", "-k", "1"], LOG);
+    assert_eq!(output.status.code(), Some(2));
+}